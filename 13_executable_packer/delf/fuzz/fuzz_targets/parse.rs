@@ -0,0 +1,81 @@
+//! Parsing a file, then reading anything from it, must never panic (or
+//! overflow the stack), whatever the input: headers, symbols and their
+//! versions and hash tables, relocations, notes and core dumps, call frame
+//! information and DWARF.
+//!
+//! Run with `cargo +nightly fuzz run parse` from `delf/`.
+#![no_main]
+
+use delf::{Addr, CfiEntry, File, FileContents};
+use libfuzzer_sys::fuzz_target;
+
+/// Bounds the work done per input, so slow inputs don't hide crashes
+const MAX_LOOKUPS: usize = 16;
+
+fuzz_target!(|input: &[u8]| {
+    let Ok((_, contents)) = FileContents::parse(input) else {
+        return;
+    };
+    let file = File { input, contents };
+    let _ = file.validate();
+    for sh in &file.section_headers {
+        let _ = file.section_name(sh);
+        let _ = file.section_slice(sh);
+    }
+    for ph in &file.program_headers {
+        let _ = file.segment_slice(ph);
+    }
+
+    let _ = file.read_rela_entries();
+    let _ = file.read_jmp_rel_entries();
+    let _ = file.read_relr_vaddrs();
+    let _ = file.read_symtab_entries();
+    let _ = file.read_versym_entries();
+    let _ = file.read_verdef_entries();
+    let _ = file.read_verneed_entries();
+    let _ = file.read_dynsym_versions();
+    if let Ok(lookup) = file.symbol_lookup() {
+        let names: Vec<_> = lookup
+            .syms()
+            .iter()
+            .take(MAX_LOOKUPS)
+            .map(|sym| file.dynstr_entry(sym.name).to_vec())
+            .collect();
+        for name in names.iter().map(Vec::as_slice).chain([&b"main"[..]]) {
+            let _ = lookup.lookup(name);
+            let _ = lookup.lookup_all(name).count();
+        }
+    }
+
+    for note in file.notes() {
+        let _ = note.gnu();
+    }
+    let _ = file.read_core();
+
+    // addresses worth looking up: the entry point, and where FDEs start
+    let mut pcs = vec![file.entry_point];
+    if let Ok(Some(eh_frame)) = file.eh_frame() {
+        for entry in eh_frame.entries().take(MAX_LOOKUPS) {
+            if let Ok(CfiEntry::Fde(fde)) = entry {
+                pcs.push(fde.initial_location);
+                let _ = fde.unwind_row(fde.initial_location);
+            }
+        }
+    }
+    let hdr = file.eh_frame_hdr().ok().flatten();
+    for &pc in &pcs {
+        if let Some(hdr) = &hdr {
+            let _ = hdr.lookup(pc);
+        }
+        if let Ok(Some(fde)) = file.find_fde(pc) {
+            let _ = fde.unwind_row(Addr(pc.0.wrapping_add(1)));
+        }
+    }
+
+    if let Ok(Some(dwarf)) = file.dwarf() {
+        let _ = dwarf.line_programs();
+        for &pc in &pcs {
+            let _ = dwarf.locate(pc);
+        }
+    }
+});
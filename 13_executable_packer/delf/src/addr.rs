@@ -2,10 +2,10 @@ use std::fmt;
 
 use crate::parse;
 use derive_more::{Add, Sub};
-use nom::{Parser as _, combinator, number::complete::le_u64};
+use nom::{Parser as _, combinator};
 
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Add, Sub, Hash)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Add, Sub, Hash)]
 pub struct Addr(pub u64);
 
 impl fmt::Debug for Addr {
@@ -72,7 +72,7 @@ impl Addr {
         }
     }
 
-    pub fn parse(i: parse::Input) -> parse::Result<Self> {
-        combinator::map(le_u64, From::from).parse(i)
+    pub fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| combinator::map(ctx.word(), From::from).parse(i)
     }
 }
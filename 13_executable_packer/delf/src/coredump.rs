@@ -0,0 +1,505 @@
+use std::ops::Range;
+
+use crate::{Addr, File, Machine, Note, Registers, Type, parse};
+use nom::{Parser as _, bytes::complete::take, multi};
+
+// Types of the notes owned by "CORE"
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x4649_4c45;
+
+/// The x86-64 `user_regs_struct`, as saved in `NT_PRSTATUS` notes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UserRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+impl UserRegs {
+    pub fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, regs) = multi::count(ctx.u64(), 27).parse(i)?;
+            let [
+                r15,
+                r14,
+                r13,
+                r12,
+                rbp,
+                rbx,
+                r11,
+                r10,
+                r9,
+                r8,
+                rax,
+                rcx,
+                rdx,
+                rsi,
+                rdi,
+                orig_rax,
+                rip,
+                cs,
+                eflags,
+                rsp,
+                ss,
+                fs_base,
+                gs_base,
+                ds,
+                es,
+                fs,
+                gs,
+            ] = regs[..]
+            else {
+                unreachable!()
+            };
+            let res = Self {
+                r15,
+                r14,
+                r13,
+                r12,
+                rbp,
+                rbx,
+                r11,
+                r10,
+                r9,
+                r8,
+                rax,
+                rcx,
+                rdx,
+                rsi,
+                rdi,
+                orig_rax,
+                rip,
+                cs,
+                eflags,
+                rsp,
+                ss,
+                fs_base,
+                gs_base,
+                ds,
+                es,
+                fs,
+                gs,
+            };
+            Ok((i, res))
+        }
+    }
+
+    /// Returns the registers in DWARF numbering, to unwind the thread's stack
+    pub fn dwarf(&self) -> Registers {
+        let regs = [
+            self.rax, self.rdx, self.rcx, self.rbx, self.rsi, self.rdi, self.rbp, self.rsp,
+            self.r8, self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15, self.rip,
+        ];
+        Registers(regs.map(Some))
+    }
+}
+
+/// A thread of a dumped process, from its `NT_PRSTATUS` note
+#[derive(Debug, Clone, Copy)]
+pub struct CoreThread {
+    pub pid: i32,
+    /// The signal the thread received, or 0
+    pub signal: u16,
+    pub registers: UserRegs,
+}
+
+impl CoreThread {
+    // Elf64_Prstatus: siginfo (signo, code, errno), cursig, padding,
+    // sigpend, sighold, pid, ppid, pgrp, sid, four timevals, then registers
+    fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, _siginfo) = take(12usize).parse(i)?;
+            let (i, signal) = ctx.u16()(i)?;
+            let (i, _) = take(2 + 16usize).parse(i)?;
+            let (i, pid) = ctx.u32()(i)?;
+            let (i, _) = take(12 + 64usize).parse(i)?;
+            let (i, registers) = UserRegs::parse(ctx)(i)?;
+            let res = Self {
+                pid: pid as i32,
+                signal,
+                registers,
+            };
+            Ok((i, res))
+        }
+    }
+}
+
+/// Process information, from the `NT_PRPSINFO` note
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessInfo<'a> {
+    /// State letter, as in `/proc/<pid>/stat` (e.g. 'R', 'S')
+    pub state: u8,
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: i32,
+    pub ppid: i32,
+    /// Name of the executable, truncated to 15 bytes
+    pub fname: &'a [u8],
+    /// Start of the command line, arguments separated by spaces, truncated
+    /// to 79 bytes
+    pub psargs: &'a [u8],
+}
+
+impl<'a> ProcessInfo<'a> {
+    // Elf64_Prpsinfo: state, sname, zomb, nice, padding, flag, uid, gid,
+    // pid, ppid, pgrp, sid, fname[16], psargs[80]
+    fn parse(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, (_state, state)) = (ctx.u8(), ctx.u8()).parse(i)?;
+            let (i, _) = take(2 + 4 + 8usize).parse(i)?;
+            let (i, (uid, gid, pid, ppid)) =
+                (ctx.u32(), ctx.u32(), ctx.u32(), ctx.u32()).parse(i)?;
+            let (i, _) = take(8usize).parse(i)?;
+            let (i, (fname, psargs)) = (take(16usize), take(80usize)).parse(i)?;
+            let res = Self {
+                state,
+                uid,
+                gid,
+                pid: pid as i32,
+                ppid: ppid as i32,
+                fname: until_nul(fname),
+                psargs: until_nul(psargs),
+            };
+            Ok((i, res))
+        }
+    }
+}
+
+fn until_nul(s: &[u8]) -> &[u8] {
+    s.split(|&b| b == 0).next().unwrap_or_default()
+}
+
+/// A file mapped in the dumped process, from the `NT_FILE` note
+#[derive(Debug, Clone)]
+pub struct FileMapping<'a> {
+    pub range: Range<Addr>,
+    /// Offset in the file, in bytes
+    pub offset: Addr,
+    pub path: &'a [u8],
+}
+
+/// An entry of the auxiliary vector (`AT_*`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuxvEntry {
+    pub r#type: u64,
+    pub value: u64,
+}
+
+impl AuxvEntry {
+    /// `AT_ENTRY`: the entry point of the executable
+    pub const ENTRY: u64 = 9;
+    /// `AT_BASE`: the base address of the dynamic loader
+    pub const BASE: u64 = 7;
+}
+
+/// The state of a process, as recorded in the notes of a core dump
+#[derive(Debug, Clone, Default)]
+pub struct Core<'a> {
+    /// The thread that received the fatal signal comes first
+    pub threads: Vec<CoreThread>,
+    pub process: Option<ProcessInfo<'a>>,
+    pub files: Vec<FileMapping<'a>>,
+    pub auxv: Vec<AuxvEntry>,
+}
+
+impl<'a> Core<'a> {
+    pub fn auxv_value(&self, r#type: u64) -> Option<u64> {
+        self.auxv
+            .iter()
+            .find(|entry| entry.r#type == r#type)
+            .map(|entry| entry.value)
+    }
+
+    /// Returns the file mapped at `addr`, if any
+    pub fn file_containing(&self, addr: Addr) -> Option<&FileMapping<'a>> {
+        self.files.iter().find(|file| file.range.contains(&addr))
+    }
+
+    fn read_note(&mut self, note: &Note<'a>, ctx: parse::Ctx) -> parse::Result<'a, ()> {
+        let i = note.desc;
+        match note.r#type {
+            NT_PRSTATUS => {
+                let (_, thread) = CoreThread::parse(ctx)(i)?;
+                self.threads.push(thread);
+            }
+            NT_PRPSINFO => {
+                let (_, process) = ProcessInfo::parse(ctx)(i)?;
+                self.process = Some(process);
+            }
+            NT_AUXV => {
+                let n = i.len() / (2 * ctx.word_size());
+                let (_, entries) = multi::count((ctx.word(), ctx.word()), n).parse(i)?;
+                self.auxv = entries
+                    .into_iter()
+                    .take_while(|&(r#type, _)| r#type != 0)
+                    .map(|(r#type, value)| AuxvEntry { r#type, value })
+                    .collect();
+            }
+            NT_FILE => {
+                let (i, (count, page_size)) = (ctx.word(), ctx.word()).parse(i)?;
+                let (mut i, ranges) =
+                    multi::count((ctx.word(), ctx.word(), ctx.word()), count as usize).parse(i)?;
+                for (start, end, page) in ranges {
+                    let path;
+                    (i, path) = crate::dwarf::cstr(i)?;
+                    self.files.push(FileMapping {
+                        range: Addr(start)..Addr(end),
+                        offset: Addr(page * page_size),
+                        path,
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok((i, ()))
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Reads the notes of an x86-64 core dump
+    pub fn read_core(&self) -> Result<Core<'_>, ReadCoreError> {
+        if self.r#type != Type::Core {
+            return Err(ReadCoreError::NotACore(self.r#type));
+        }
+        if self.machine != Machine::X86_64 {
+            return Err(ReadCoreError::UnsupportedMachine(self.machine));
+        }
+        let ctx = self.ctx();
+        let mut core = Core::default();
+        for note in self.notes().filter(|note| note.name == b"CORE") {
+            match core.read_note(&note, ctx) {
+                Ok(_) => {}
+                Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                    return Err(ReadCoreError::ParsingError(format!("{err:?}")));
+                }
+                Err(_) => unreachable!(),
+            }
+        }
+        Ok(core)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadCoreError {
+    #[error("Not a core dump: {0:?}")]
+    NotACore(Type),
+    #[error("Core dumps of {0:?} are not supported")]
+    UnsupportedMachine(Machine),
+    #[error("Parsing error: {0}")]
+    ParsingError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends a "CORE" note, with 4-byte alignment like the kernel's
+    fn push_note(out: &mut Vec<u8>, r#type: u32, desc: &[u8]) {
+        for word in [5, desc.len() as u32, r#type] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.extend_from_slice(b"CORE\0\0\0\0");
+        out.extend_from_slice(desc);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+
+    /// A core dump with one thread, its process info, auxiliary vector and
+    /// mapped files, and a page of stack at `STACK`
+    fn synthetic_core() -> Vec<u8> {
+        const STACK: u64 = 0x7ffc_0000;
+        let mut notes = Vec::new();
+
+        let mut prstatus = vec![0; 12];
+        prstatus.extend_from_slice(&11u16.to_le_bytes());
+        prstatus.resize(32, 0);
+        prstatus.extend_from_slice(&1234u32.to_le_bytes());
+        prstatus.resize(112, 0);
+        let mut regs = [0u64; 27];
+        (regs[16], regs[19]) = (0x40_1234, STACK + 0x100);
+        for reg in regs {
+            prstatus.extend_from_slice(&reg.to_le_bytes());
+        }
+        prstatus.resize(336, 0);
+        push_note(&mut notes, NT_PRSTATUS, &prstatus);
+
+        let mut prpsinfo = vec![0, b'R'];
+        prpsinfo.resize(16, 0);
+        for word in [1000u32, 100, 1234, 1] {
+            prpsinfo.extend_from_slice(&word.to_le_bytes());
+        }
+        prpsinfo.resize(40, 0);
+        prpsinfo.extend_from_slice(b"fake\0");
+        prpsinfo.resize(56, 0);
+        prpsinfo.extend_from_slice(b"fake --crash\0");
+        prpsinfo.resize(136, 0);
+        push_note(&mut notes, NT_PRPSINFO, &prpsinfo);
+
+        let mut auxv = Vec::new();
+        for word in [AuxvEntry::ENTRY, 0x40_1000, AuxvEntry::BASE, 0, 0, 0] {
+            auxv.extend_from_slice(&word.to_le_bytes());
+        }
+        push_note(&mut notes, NT_AUXV, &auxv);
+
+        let mut files = Vec::new();
+        for word in [1u64, 0x1000, 0x40_0000, 0x40_2000, 3] {
+            files.extend_from_slice(&word.to_le_bytes());
+        }
+        files.extend_from_slice(b"/usr/bin/fake\0");
+        push_note(&mut notes, NT_FILE, &files);
+
+        // header, two program headers, the notes, then the stack contents
+        let notes_offset = 64 + 2 * 56;
+        let stack_offset = (notes_offset + notes.len()) as u64;
+        let mut out = b"\x7fELF\x02\x01\x01".to_vec();
+        out.resize(16, 0);
+        out.extend_from_slice(&4u16.to_le_bytes());
+        out.extend_from_slice(&62u16.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+        for word in [0u64, 64, 0] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.extend_from_slice(&0u32.to_le_bytes());
+        for half in [64u16, 56, 2, 64, 0, 0] {
+            out.extend_from_slice(&half.to_le_bytes());
+        }
+        let phdrs: [(u32, u32, [u64; 6]); 2] = [
+            (4, 4, [notes_offset as u64, 0, 0, notes.len() as u64, 0, 4]),
+            (1, 6, [stack_offset, STACK, 0, 0x200, 0x1000, 0x1000]),
+        ];
+        for (r#type, flags, words) in phdrs {
+            out.extend_from_slice(&r#type.to_le_bytes());
+            out.extend_from_slice(&flags.to_le_bytes());
+            for word in words {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        out.extend(notes);
+        out.resize(out.len() + 0x200, 0xaa);
+        out
+    }
+
+    #[test]
+    fn read_synthetic_core() {
+        let file = File::parse_or_print_error(synthetic_core()).unwrap();
+        let core = file.read_core().unwrap();
+
+        assert_eq!(core.threads.len(), 1);
+        let thread = &core.threads[0];
+        assert_eq!((thread.pid, thread.signal), (1234, 11));
+        assert_eq!(thread.registers.rip, 0x40_1234);
+
+        let process = core.process.unwrap();
+        assert_eq!(process.state, b'R');
+        assert_eq!((process.uid, process.gid), (1000, 100));
+        assert_eq!((process.pid, process.ppid), (1234, 1));
+        assert_eq!(process.fname, b"fake");
+        assert_eq!(process.psargs, b"fake --crash");
+
+        assert_eq!(core.auxv_value(AuxvEntry::ENTRY), Some(0x40_1000));
+        assert_eq!(core.auxv.len(), 2);
+        let mapping = core.file_containing(Addr(thread.registers.rip)).unwrap();
+        assert_eq!(mapping.path, b"/usr/bin/fake");
+        assert_eq!(mapping.offset, Addr(0x3000));
+        assert!(core.file_containing(Addr(0x40_2000)).is_none());
+
+        let stack = file.mem_slice(Addr(thread.registers.rsp), 8).unwrap();
+        assert_eq!(stack, [0xaa; 8]);
+
+        // a status note too short for its registers is an error, not a panic
+        let mut truncated = synthetic_core();
+        truncated[64 + 2 * 56 + 4..][..4].copy_from_slice(&100u32.to_le_bytes());
+        let file = File::parse_or_print_error(truncated).unwrap();
+        assert!(matches!(
+            file.read_core(),
+            Err(ReadCoreError::ParsingError(_))
+        ));
+    }
+
+    #[test]
+    fn read_shell_core() {
+        // a shell that kills itself, leaving a `core` file in the current directory
+        let dir = std::env::temp_dir().join(format!("delf-core-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let status = std::process::Command::new("sh")
+            .args(["-c", "ulimit -c unlimited; kill -ABRT $$"])
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        let path = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("core")
+            });
+        let Some(path) = path else {
+            // core dumps are disabled or piped to a handler
+            std::fs::remove_dir_all(&dir).ok();
+            return;
+        };
+        let file = File::parse_or_print_error(std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(!status.success());
+
+        let core = file.read_core().unwrap();
+        assert_eq!(core.threads.len(), 1);
+        let thread = &core.threads[0];
+        assert_eq!(thread.signal, 6);
+        let process = core.process.unwrap();
+        assert_eq!(process.pid, thread.pid);
+        assert_eq!(
+            process.psargs.trim_ascii_end(),
+            b"sh -c ulimit -c unlimited; kill -ABRT $$"
+        );
+
+        // the faulting thread was running code from a mapped file
+        let rip = Addr(thread.registers.rip);
+        let mapping = core.file_containing(rip).expect("file mapping rip");
+        assert!(mapping.path.starts_with(b"/"));
+        let entry = core.auxv_value(AuxvEntry::ENTRY).expect("AT_ENTRY");
+        assert!(core.file_containing(Addr(entry)).is_some());
+
+        // the stack was dumped
+        let rsp = Addr(thread.registers.rsp);
+        assert!(file.mem_slice(rsp, 8).is_some());
+
+        let mut other = file;
+        other.contents.r#type = Type::Exec;
+        assert!(matches!(
+            other.read_core(),
+            Err(ReadCoreError::NotACore(Type::Exec))
+        ));
+    }
+}
@@ -0,0 +1,1356 @@
+use std::{collections::HashMap, fmt, ops::Range};
+
+use crate::{Addr, File, parse};
+use nom::{
+    Parser as _,
+    bytes::complete::{tag, take, take_until},
+    combinator::map,
+};
+
+/// `SHF_COMPRESSED`: the section starts with an `Elf_Chdr` and holds zlib or
+/// zstd data
+const SHF_COMPRESSED: u64 = 0x800;
+
+// DW_TAG_*
+const TAG_SUBPROGRAM: u64 = 0x2e;
+const TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+
+// DW_AT_*
+const AT_NAME: u64 = 0x03;
+const AT_STMT_LIST: u64 = 0x10;
+const AT_LOW_PC: u64 = 0x11;
+const AT_HIGH_PC: u64 = 0x12;
+const AT_COMP_DIR: u64 = 0x1b;
+const AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const AT_SPECIFICATION: u64 = 0x47;
+const AT_RANGES: u64 = 0x55;
+const AT_CALL_COLUMN: u64 = 0x57;
+const AT_CALL_FILE: u64 = 0x58;
+const AT_CALL_LINE: u64 = 0x59;
+const AT_LINKAGE_NAME: u64 = 0x6e;
+const AT_STR_OFFSETS_BASE: u64 = 0x72;
+const AT_ADDR_BASE: u64 = 0x73;
+const AT_RNGLISTS_BASE: u64 = 0x74;
+const AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+
+// DW_FORM_*
+const FORM_ADDR: u64 = 0x01;
+const FORM_BLOCK2: u64 = 0x03;
+const FORM_BLOCK4: u64 = 0x04;
+const FORM_DATA2: u64 = 0x05;
+const FORM_DATA4: u64 = 0x06;
+const FORM_DATA8: u64 = 0x07;
+const FORM_STRING: u64 = 0x08;
+const FORM_BLOCK: u64 = 0x09;
+const FORM_BLOCK1: u64 = 0x0a;
+const FORM_DATA1: u64 = 0x0b;
+const FORM_FLAG: u64 = 0x0c;
+const FORM_SDATA: u64 = 0x0d;
+const FORM_STRP: u64 = 0x0e;
+const FORM_UDATA: u64 = 0x0f;
+const FORM_REF_ADDR: u64 = 0x10;
+const FORM_REF1: u64 = 0x11;
+const FORM_REF2: u64 = 0x12;
+const FORM_REF4: u64 = 0x13;
+const FORM_REF8: u64 = 0x14;
+const FORM_REF_UDATA: u64 = 0x15;
+const FORM_INDIRECT: u64 = 0x16;
+const FORM_SEC_OFFSET: u64 = 0x17;
+const FORM_EXPRLOC: u64 = 0x18;
+const FORM_FLAG_PRESENT: u64 = 0x19;
+const FORM_STRX: u64 = 0x1a;
+const FORM_ADDRX: u64 = 0x1b;
+const FORM_REF_SUP4: u64 = 0x1c;
+const FORM_STRP_SUP: u64 = 0x1d;
+const FORM_DATA16: u64 = 0x1e;
+const FORM_LINE_STRP: u64 = 0x1f;
+const FORM_REF_SIG8: u64 = 0x20;
+const FORM_IMPLICIT_CONST: u64 = 0x21;
+const FORM_LOCLISTX: u64 = 0x22;
+const FORM_RNGLISTX: u64 = 0x23;
+const FORM_REF_SUP8: u64 = 0x24;
+const FORM_STRX1: u64 = 0x25;
+const FORM_STRX2: u64 = 0x26;
+const FORM_STRX3: u64 = 0x27;
+const FORM_STRX4: u64 = 0x28;
+const FORM_ADDRX1: u64 = 0x29;
+const FORM_ADDRX2: u64 = 0x2a;
+const FORM_ADDRX3: u64 = 0x2b;
+const FORM_ADDRX4: u64 = 0x2c;
+const FORM_GNU_ADDR_INDEX: u64 = 0x1f01;
+const FORM_GNU_STR_INDEX: u64 = 0x1f02;
+const FORM_GNU_REF_ALT: u64 = 0x1f20;
+const FORM_GNU_STRP_ALT: u64 = 0x1f21;
+
+// DW_LNCT_*
+const LNCT_PATH: u64 = 0x1;
+const LNCT_DIRECTORY_INDEX: u64 = 0x2;
+
+/// The DWARF sections of a file. Missing sections are empty.
+#[derive(Debug, Clone, Copy, Default)]
+struct Sections<'a> {
+    info: &'a [u8],
+    abbrev: &'a [u8],
+    line: &'a [u8],
+    str: &'a [u8],
+    line_str: &'a [u8],
+    ranges: &'a [u8],
+    rnglists: &'a [u8],
+    addr: &'a [u8],
+    str_offsets: &'a [u8],
+}
+
+/// The debug information of a file: enough of `.debug_info` and `.debug_line`
+/// to map addresses to functions and source lines
+#[derive(Debug, Clone, Copy)]
+pub struct Dwarf<'a> {
+    ctx: parse::Ctx,
+    sections: Sections<'a>,
+}
+
+/// A position in a source file. Columns are 0 when unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u64,
+    pub column: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if self.column != 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// One level of the (possibly inlined) call stack at an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function: Option<String>,
+    pub location: Option<SourceLocation>,
+}
+
+/// A row of the line number table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRow {
+    pub address: Addr,
+    /// Index into the program's file table: 1-based before DWARF 5, 0-based since
+    pub file: u64,
+    pub line: u64,
+    pub column: u64,
+    pub is_stmt: bool,
+    /// Marks the first address past the end of a sequence
+    pub end_sequence: bool,
+}
+
+/// A decoded `.debug_line` program
+#[derive(Debug, Clone)]
+pub struct LineProgram {
+    pub version: u16,
+    /// Full paths of the file table entries, in table order
+    pub files: Vec<String>,
+    pub rows: Vec<LineRow>,
+}
+
+impl LineProgram {
+    /// Returns the path of a `file` register value
+    pub fn file(&self, index: u64) -> Option<&str> {
+        let index = if self.version >= 5 {
+            index
+        } else {
+            index.checked_sub(1)?
+        };
+        self.files.get(index as usize).map(String::as_str)
+    }
+
+    /// Returns the row describing `addr`: the last row at or before it,
+    /// within a sequence that covers it
+    pub fn find_row(&self, addr: Addr) -> Option<&LineRow> {
+        self.rows
+            .windows(2)
+            .find(|w| !w[0].end_sequence && w[0].address <= addr && addr < w[1].address)
+            .map(|w| &w[0])
+    }
+
+    fn location(&self, file: u64, line: u64, column: u64) -> SourceLocation {
+        SourceLocation {
+            file: self.file(file).unwrap_or("??").to_owned(),
+            line,
+            column,
+        }
+    }
+}
+
+/// Offset and address sizes of a unit
+#[derive(Debug, Clone, Copy)]
+struct Encoding {
+    ctx: parse::Ctx,
+    version: u16,
+    /// 4 for 32-bit DWARF, 8 for 64-bit DWARF
+    offset_size: u8,
+    address_size: u8,
+}
+
+impl Encoding {
+    fn offset<'a>(&self) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, u64> + use<'a> {
+        sized(self.ctx, self.offset_size)
+    }
+
+    fn address<'a>(&self) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, u64> + use<'a> {
+        sized(self.ctx, self.address_size)
+    }
+}
+
+/// Parses an unsigned integer of 1 to 8 bytes
+pub(crate) fn sized<'a>(
+    ctx: parse::Ctx,
+    size: u8,
+) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, u64> + use<'a> {
+    move |i| match size {
+        1 => map(ctx.u8(), u64::from).parse(i),
+        2 => map(ctx.u16(), u64::from).parse(i),
+        4 => map(ctx.u32(), u64::from).parse(i),
+        8 => ctx.u64()(i),
+        _ => {
+            let (rest, bytes) = take(size).parse(i)?;
+            let fold = |acc: u64, &b: &u8| (acc << 8) | u64::from(b);
+            let val = match ctx.endianness {
+                crate::Endianness::Little => bytes.iter().rev().fold(0, fold),
+                crate::Endianness::Big => bytes.iter().fold(0, fold),
+            };
+            Ok((rest, val))
+        }
+    }
+}
+
+pub(crate) fn uleb128(i: parse::Input) -> parse::Result<u64> {
+    let mut val = 0;
+    for (n, &byte) in i.iter().enumerate() {
+        if n < 10 {
+            val |= u64::from(byte & 0x7f) << (7 * n);
+        }
+        if byte & 0x80 == 0 {
+            return Ok((&i[n + 1..], val));
+        }
+    }
+    Err(nom::Err::Error(parse::Error::from_string(
+        i,
+        "truncated LEB128",
+    )))
+}
+
+pub(crate) fn sleb128(i: parse::Input) -> parse::Result<i64> {
+    let mut val = 0i64;
+    for (n, &byte) in i.iter().enumerate() {
+        let shift = 7 * n as u32;
+        if shift < 64 {
+            val |= i64::from(byte & 0x7f) << shift;
+        }
+        if byte & 0x80 == 0 {
+            if shift + 7 < 64 && byte & 0x40 != 0 {
+                val |= -1 << (shift + 7);
+            }
+            return Ok((&i[n + 1..], val));
+        }
+    }
+    Err(nom::Err::Error(parse::Error::from_string(
+        i,
+        "truncated LEB128",
+    )))
+}
+
+/// Parses a null-terminated string, without its terminator
+pub(crate) fn cstr(i: parse::Input<'_>) -> parse::Result<'_, &[u8]> {
+    let (i, s) = take_until(&b"\0"[..]).parse(i)?;
+    let (i, _) = tag(&b"\0"[..]).parse(i)?;
+    Ok((i, s))
+}
+
+/// Parses a unit's initial length, returning it along with the offset size
+pub(crate) fn initial_length<'a>(
+    ctx: parse::Ctx,
+) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, (u64, u8)> + use<'a> {
+    move |i| {
+        let (i, len) = ctx.u32()(i)?;
+        if len == 0xffff_ffff {
+            map(ctx.u64(), |len| (len, 8)).parse(i)
+        } else {
+            Ok((i, (u64::from(len), 4)))
+        }
+    }
+}
+
+/// A unit of `.debug_info` or `.debug_line`
+struct RawUnit<'a> {
+    /// The whole unit, length field included
+    data: &'a [u8],
+    /// The unit without its length field
+    contents: &'a [u8],
+    offset_size: u8,
+}
+
+/// Splits a unit off the start of `i`
+fn split_unit<'a>(ctx: parse::Ctx, i: parse::Input<'a>) -> parse::Result<'a, RawUnit<'a>> {
+    let (rest, (len, offset_size)) = initial_length(ctx)(i)?;
+    let (rest, contents) = take(len).parse(rest)?;
+    let res = RawUnit {
+        data: &i[..i.len() - rest.len()],
+        contents,
+        offset_size,
+    };
+    Ok((rest, res))
+}
+
+/// An attribute value. Strings, addresses and references are resolved by
+/// the unit they belong to.
+#[derive(Debug, Clone, Copy)]
+enum Value<'a> {
+    Addr(u64),
+    AddrIndex(u64),
+    Udata(u64),
+    Sdata(i64),
+    Str(&'a [u8]),
+    /// Offset into `.debug_str`
+    Strp(u64),
+    /// Offset into `.debug_line_str`
+    LineStrp(u64),
+    StrIndex(u64),
+    /// Offset from the start of the current unit
+    UnitRef(u64),
+    /// Offset from the start of `.debug_info`
+    InfoRef(u64),
+    SecOffset(u64),
+    RngListIndex(u64),
+    Flag,
+    /// Blocks, expressions and references to other files
+    Other,
+}
+
+impl Value<'_> {
+    fn udata(&self) -> Option<u64> {
+        match *self {
+            Self::Udata(x) | Self::SecOffset(x) => Some(x),
+            Self::Sdata(x) => Some(x as u64),
+            _ => None,
+        }
+    }
+}
+
+fn value<'a>(
+    enc: Encoding,
+    form: u64,
+    implicit_const: i64,
+) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Value<'a>> {
+    move |i| {
+        let ctx = enc.ctx;
+        let block = |i: parse::Input<'a>, len: u64| {
+            let (i, _) = take(len).parse(i)?;
+            Ok((i, Value::Other))
+        };
+        match form {
+            FORM_ADDR => map(enc.address(), Value::Addr).parse(i),
+            FORM_BLOCK1 => ctx.u8()(i).and_then(|(i, len)| block(i, len.into())),
+            FORM_BLOCK2 => ctx.u16()(i).and_then(|(i, len)| block(i, len.into())),
+            FORM_BLOCK4 => ctx.u32()(i).and_then(|(i, len)| block(i, len.into())),
+            FORM_BLOCK | FORM_EXPRLOC => uleb128(i).and_then(|(i, len)| block(i, len)),
+            FORM_DATA1 => map(sized(ctx, 1), Value::Udata).parse(i),
+            FORM_DATA2 => map(sized(ctx, 2), Value::Udata).parse(i),
+            FORM_DATA4 => map(sized(ctx, 4), Value::Udata).parse(i),
+            FORM_DATA8 => map(sized(ctx, 8), Value::Udata).parse(i),
+            FORM_DATA16 => block(i, 16),
+            FORM_SDATA => map(sleb128, Value::Sdata).parse(i),
+            FORM_UDATA => map(uleb128, Value::Udata).parse(i),
+            FORM_STRING => map(cstr, Value::Str).parse(i),
+            FORM_STRP => map(enc.offset(), Value::Strp).parse(i),
+            FORM_LINE_STRP => map(enc.offset(), Value::LineStrp).parse(i),
+            FORM_STRX | FORM_GNU_STR_INDEX => map(uleb128, Value::StrIndex).parse(i),
+            FORM_STRX1 => map(sized(ctx, 1), Value::StrIndex).parse(i),
+            FORM_STRX2 => map(sized(ctx, 2), Value::StrIndex).parse(i),
+            FORM_STRX3 => map(sized(ctx, 3), Value::StrIndex).parse(i),
+            FORM_STRX4 => map(sized(ctx, 4), Value::StrIndex).parse(i),
+            FORM_ADDRX | FORM_GNU_ADDR_INDEX => map(uleb128, Value::AddrIndex).parse(i),
+            FORM_ADDRX1 => map(sized(ctx, 1), Value::AddrIndex).parse(i),
+            FORM_ADDRX2 => map(sized(ctx, 2), Value::AddrIndex).parse(i),
+            FORM_ADDRX3 => map(sized(ctx, 3), Value::AddrIndex).parse(i),
+            FORM_ADDRX4 => map(sized(ctx, 4), Value::AddrIndex).parse(i),
+            FORM_REF1 => map(sized(ctx, 1), Value::UnitRef).parse(i),
+            FORM_REF2 => map(sized(ctx, 2), Value::UnitRef).parse(i),
+            FORM_REF4 => map(sized(ctx, 4), Value::UnitRef).parse(i),
+            FORM_REF8 => map(sized(ctx, 8), Value::UnitRef).parse(i),
+            FORM_REF_UDATA => map(uleb128, Value::UnitRef).parse(i),
+            // DWARF 2 used address-sized references
+            FORM_REF_ADDR if enc.version <= 2 => map(enc.address(), Value::InfoRef).parse(i),
+            FORM_REF_ADDR => map(enc.offset(), Value::InfoRef).parse(i),
+            FORM_REF_SIG8 => block(i, 8),
+            FORM_REF_SUP4 => block(i, 4),
+            FORM_REF_SUP8 => block(i, 8),
+            FORM_STRP_SUP | FORM_GNU_REF_ALT | FORM_GNU_STRP_ALT => {
+                block(i, enc.offset_size.into())
+            }
+            FORM_SEC_OFFSET => map(enc.offset(), Value::SecOffset).parse(i),
+            FORM_FLAG => map(ctx.u8(), |_| Value::Flag).parse(i),
+            FORM_FLAG_PRESENT => Ok((i, Value::Flag)),
+            FORM_IMPLICIT_CONST => Ok((i, Value::Sdata(implicit_const))),
+            FORM_LOCLISTX => map(uleb128, |_| Value::Other).parse(i),
+            FORM_RNGLISTX => map(uleb128, Value::RngListIndex).parse(i),
+            FORM_INDIRECT => {
+                let (i, form) = uleb128(i)?;
+                // an indirect form naming itself could recurse forever
+                if form == FORM_INDIRECT {
+                    return Err(nom::Err::Failure(parse::Error::from_string(
+                        i,
+                        "Nested DW_FORM_indirect",
+                    )));
+                }
+                value(enc, form, implicit_const)(i)
+            }
+            _ => Err(nom::Err::Failure(parse::Error::from_string(
+                i,
+                format!("Unknown DW_FORM 0x{form:x}"),
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AttributeSpec {
+    name: u64,
+    form: u64,
+    implicit_const: i64,
+}
+
+#[derive(Debug, Clone)]
+struct Abbrev {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<AttributeSpec>,
+}
+
+/// Parses an abbreviation table, up to its null entry
+fn abbrevs(i: parse::Input) -> parse::Result<HashMap<u64, Abbrev>> {
+    let mut res = HashMap::new();
+    let (mut i, mut code) = uleb128(i)?;
+    while code != 0 {
+        let (rest, (tag, has_children)) = (uleb128, nom::number::complete::u8).parse(i)?;
+        i = rest;
+        let mut attrs = Vec::new();
+        loop {
+            let (rest, (name, form)) = (uleb128, uleb128).parse(i)?;
+            i = rest;
+            if name == 0 && form == 0 {
+                break;
+            }
+            let mut implicit_const = 0;
+            if form == FORM_IMPLICIT_CONST {
+                (i, implicit_const) = sleb128(i)?;
+            }
+            attrs.push(AttributeSpec {
+                name,
+                form,
+                implicit_const,
+            });
+        }
+        res.insert(
+            code,
+            Abbrev {
+                tag,
+                has_children: has_children != 0,
+                attrs,
+            },
+        );
+        (i, code) = uleb128(i)?;
+    }
+    Ok((i, res))
+}
+
+/// A debugging information entry
+#[derive(Debug, Clone)]
+struct Die<'a> {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<(u64, Value<'a>)>,
+}
+
+impl<'a> Die<'a> {
+    fn attr(&self, name: u64) -> Option<Value<'a>> {
+        self.attrs.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+    }
+}
+
+/// A compilation unit of `.debug_info`
+struct Unit<'a> {
+    sections: Sections<'a>,
+    enc: Encoding,
+    /// Offset of the unit header in `.debug_info`
+    offset: usize,
+    /// The whole unit, header included: `DW_FORM_ref*` offsets are relative to it
+    data: &'a [u8],
+    /// Offset of the first entry in `data`
+    entries: usize,
+    abbrevs: HashMap<u64, Abbrev>,
+    /// The `DW_TAG_compile_unit` entry
+    root: Die<'a>,
+    str_offsets_base: u64,
+    addr_base: u64,
+    rnglists_base: u64,
+}
+
+impl<'a> Unit<'a> {
+    /// Parses the entry at `offset` in the unit, returning the offset of the
+    /// next one. Null entries, which end a list of children, are `None`.
+    fn entry(&self, offset: usize) -> Result<(usize, Option<Die<'a>>), DwarfError> {
+        let input = self.data.get(offset..).unwrap_or_default();
+        let (mut i, code) = uleb128(input)?;
+        if code == 0 {
+            return Ok((offset + input.len() - i.len(), None));
+        }
+        let abbrev = self
+            .abbrevs
+            .get(&code)
+            .ok_or(DwarfError::AbbrevNotFound(code))?;
+        let mut attrs = Vec::with_capacity(abbrev.attrs.len());
+        for spec in &abbrev.attrs {
+            let (rest, val) = value(self.enc, spec.form, spec.implicit_const)(i)?;
+            i = rest;
+            attrs.push((spec.name, val));
+        }
+        let die = Die {
+            tag: abbrev.tag,
+            has_children: abbrev.has_children,
+            attrs,
+        };
+        Ok((offset + input.len() - i.len(), Some(die)))
+    }
+
+    fn string(&self, val: Value<'a>) -> Option<&'a [u8]> {
+        let at = |section: &'a [u8], offset: u64| {
+            cstr(section.get(offset as usize..)?).ok().map(|(_, s)| s)
+        };
+        match val {
+            Value::Str(s) => Some(s),
+            Value::Strp(offset) => at(self.sections.str, offset),
+            Value::LineStrp(offset) => at(self.sections.line_str, offset),
+            Value::StrIndex(index) => {
+                let offset = index
+                    .checked_mul(u64::from(self.enc.offset_size))?
+                    .checked_add(self.str_offsets_base)?;
+                let i = self.sections.str_offsets.get(offset as usize..)?;
+                let (_, offset) = self.enc.offset()(i).ok()?;
+                at(self.sections.str, offset)
+            }
+            _ => None,
+        }
+    }
+
+    fn address(&self, val: Value<'a>) -> Option<u64> {
+        match val {
+            Value::Addr(addr) => Some(addr),
+            Value::AddrIndex(index) => {
+                let offset = index
+                    .checked_mul(u64::from(self.enc.address_size))?
+                    .checked_add(self.addr_base)?;
+                let i = self.sections.addr.get(offset as usize..)?;
+                self.enc.address()(i).ok().map(|(_, addr)| addr)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the address ranges covered by an entry, from `DW_AT_low_pc`
+    /// and `DW_AT_high_pc` or from `DW_AT_ranges`
+    fn ranges(&self, die: &Die<'a>) -> Result<Vec<Range<u64>>, DwarfError> {
+        let low = die.attr(AT_LOW_PC).and_then(|v| self.address(v));
+        if let (Some(low), Some(high)) = (low, die.attr(AT_HIGH_PC)) {
+            let high = match high {
+                Value::Udata(len) => add_offset(low, len)?,
+                val => self.address(val).unwrap_or(low),
+            };
+            let range = low..high;
+            return Ok(vec![range]);
+        }
+        let offset = match die.attr(AT_RANGES) {
+            Some(Value::RngListIndex(index)) => {
+                let at = index
+                    .checked_mul(u64::from(self.enc.offset_size))
+                    .ok_or(DwarfError::AddressOverflow)?;
+                let at = add_offset(self.rnglists_base, at)?;
+                let i = self
+                    .sections
+                    .rnglists
+                    .get(at as usize..)
+                    .unwrap_or_default();
+                let (_, offset) = self.enc.offset()(i)?;
+                add_offset(self.rnglists_base, offset)?
+            }
+            Some(val) => match val.udata() {
+                Some(offset) => offset,
+                None => return Ok(vec![]),
+            },
+            None => return Ok(vec![]),
+        };
+        let base = self
+            .root
+            .attr(AT_LOW_PC)
+            .and_then(|v| self.address(v))
+            .unwrap_or_default();
+        if self.enc.version >= 5 {
+            self.rnglist(offset, base)
+        } else {
+            self.debug_ranges(offset, base)
+        }
+    }
+
+    /// Reads a DWARF 2-4 range list from `.debug_ranges`
+    fn debug_ranges(&self, offset: u64, mut base: u64) -> Result<Vec<Range<u64>>, DwarfError> {
+        let mut i = self
+            .sections
+            .ranges
+            .get(offset as usize..)
+            .unwrap_or_default();
+        let max = u64::MAX >> (64 - 8 * u32::from(self.enc.address_size));
+        let mut res = Vec::new();
+        loop {
+            let (rest, (begin, end)) = (self.enc.address(), self.enc.address()).parse(i)?;
+            i = rest;
+            match (begin, end) {
+                (0, 0) => return Ok(res),
+                (begin, end) if begin == max => base = end,
+                (begin, end) => res.push(add_offset(base, begin)?..add_offset(base, end)?),
+            }
+        }
+    }
+
+    /// Reads a DWARF 5 range list from `.debug_rnglists`
+    fn rnglist(&self, offset: u64, mut base: u64) -> Result<Vec<Range<u64>>, DwarfError> {
+        let mut i = self
+            .sections
+            .rnglists
+            .get(offset as usize..)
+            .unwrap_or_default();
+        let addrx = |index| {
+            self.address(Value::AddrIndex(index))
+                .ok_or_else(|| DwarfError::ParsingError(format!("Bad address index {index}")))
+        };
+        let mut res = Vec::new();
+        loop {
+            let (rest, kind) = nom::number::complete::u8(i)?;
+            i = rest;
+            // DW_RLE_*
+            match kind {
+                0 => return Ok(res),
+                1 => {
+                    let (rest, index) = uleb128(i)?;
+                    (i, base) = (rest, addrx(index)?);
+                }
+                2 => {
+                    let (rest, (start, end)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    res.push(addrx(start)?..addrx(end)?);
+                }
+                3 => {
+                    let (rest, (start, len)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    let start = addrx(start)?;
+                    res.push(start..add_offset(start, len)?);
+                }
+                4 => {
+                    let (rest, (start, end)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    res.push(add_offset(base, start)?..add_offset(base, end)?);
+                }
+                5 => (i, base) = self.enc.address()(i)?,
+                6 => {
+                    let (rest, (start, end)) = (self.enc.address(), self.enc.address()).parse(i)?;
+                    i = rest;
+                    res.push(start..end);
+                }
+                7 => {
+                    let (rest, (start, len)) = (self.enc.address(), uleb128).parse(i)?;
+                    i = rest;
+                    res.push(start..add_offset(start, len)?);
+                }
+                _ => {
+                    return Err(DwarfError::ParsingError(format!(
+                        "Unknown range list entry kind {kind}"
+                    )));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Dwarf<'a> {
+    /// Parses the unit whose header is at `offset` in `.debug_info`
+    fn unit(&self, offset: usize) -> Result<Unit<'a>, DwarfError> {
+        let ctx = self.ctx;
+        let input = self.sections.info.get(offset..).unwrap_or_default();
+        let (
+            _,
+            RawUnit {
+                data,
+                contents,
+                offset_size,
+            },
+        ) = split_unit(ctx, input)?;
+        let (i, version) = ctx.u16()(contents)?;
+        if !(2..=5).contains(&version) {
+            return Err(DwarfError::UnsupportedVersion(version));
+        }
+        let mut enc = Encoding {
+            ctx,
+            version,
+            offset_size,
+            address_size: ctx.word_size() as u8,
+        };
+        let (i, abbrev_offset) = if version >= 5 {
+            let (i, (unit_type, address_size)) = (ctx.u8(), ctx.u8()).parse(i)?;
+            enc.address_size = check_address_size(address_size)?;
+            let (mut i, abbrev_offset) = enc.offset()(i)?;
+            // DW_UT_*: skip the unit ID or type signature
+            match unit_type {
+                2 | 6 => (i, _) = (take(8usize), enc.offset()).parse(i)?,
+                4 | 5 => (i, _) = take(8usize).parse(i)?,
+                _ => {}
+            }
+            (i, abbrev_offset)
+        } else {
+            let (i, (abbrev_offset, address_size)) = (enc.offset(), ctx.u8()).parse(i)?;
+            enc.address_size = check_address_size(address_size)?;
+            (i, abbrev_offset)
+        };
+        let abbrev = self
+            .sections
+            .abbrev
+            .get(abbrev_offset as usize..)
+            .unwrap_or_default();
+        let (_, abbrevs) = abbrevs(abbrev)?;
+
+        let mut unit = Unit {
+            sections: self.sections,
+            enc,
+            offset,
+            data,
+            entries: data.len() - i.len(),
+            abbrevs,
+            root: Die {
+                tag: 0,
+                has_children: false,
+                attrs: vec![],
+            },
+            str_offsets_base: 0,
+            addr_base: 0,
+            rnglists_base: 0,
+        };
+        if let (_, Some(root)) = unit.entry(unit.entries)? {
+            let base = |name| root.attr(name).and_then(|v| v.udata()).unwrap_or_default();
+            unit.str_offsets_base = base(AT_STR_OFFSETS_BASE);
+            unit.addr_base = base(AT_ADDR_BASE);
+            unit.rnglists_base = base(AT_RNGLISTS_BASE);
+            unit.root = root;
+        }
+        Ok(unit)
+    }
+
+    /// Iterates over the offsets of the units of `.debug_info`
+    fn unit_offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            let input = self.sections.info.get(offset..).filter(|i| !i.is_empty())?;
+            let (rest, _) = split_unit(self.ctx, input).ok()?;
+            let res = offset;
+            offset += input.len() - rest.len();
+            Some(res)
+        })
+    }
+
+    /// Decodes the line program of a unit, if it has one
+    fn unit_line_program(&self, unit: &Unit<'a>) -> Result<Option<LineProgram>, DwarfError> {
+        let Some(offset) = unit.root.attr(AT_STMT_LIST).and_then(|v| v.udata()) else {
+            return Ok(None);
+        };
+        let comp_dir = unit
+            .root
+            .attr(AT_COMP_DIR)
+            .and_then(|v| unit.string(v))
+            .unwrap_or_default();
+        self.line_program(offset, comp_dir).map(Some)
+    }
+
+    /// Decodes the line program at `offset` in `.debug_line`. Relative paths
+    /// are resolved against `comp_dir`.
+    pub fn line_program(&self, offset: u64, comp_dir: &[u8]) -> Result<LineProgram, DwarfError> {
+        let ctx = self.ctx;
+        let input = self
+            .sections
+            .line
+            .get(offset as usize..)
+            .unwrap_or_default();
+        let (_, unit) = split_unit(ctx, input)?;
+        let offset_size = unit.offset_size;
+        let (i, version) = ctx.u16()(unit.contents)?;
+        if !(2..=5).contains(&version) {
+            return Err(DwarfError::UnsupportedVersion(version));
+        }
+        let mut enc = Encoding {
+            ctx,
+            version,
+            offset_size,
+            address_size: ctx.word_size() as u8,
+        };
+        let mut i = i;
+        if version >= 5 {
+            let (rest, (address_size, _segment_selector_size)) = (ctx.u8(), ctx.u8()).parse(i)?;
+            (i, enc.address_size) = (rest, check_address_size(address_size)?);
+        }
+        let (i, header_length) = enc.offset()(i)?;
+        let (program, header) = take(header_length).parse(i)?;
+
+        let (i, min_inst_length) = ctx.u8()(header)?;
+        let (i, _max_ops) = if version >= 4 { ctx.u8()(i)? } else { (i, 1) };
+        let (i, (default_is_stmt, line_base, line_range, opcode_base)) =
+            (ctx.u8(), ctx.u8(), ctx.u8(), ctx.u8()).parse(i)?;
+        let (mut i, standard_opcode_lengths) = take(opcode_base.saturating_sub(1)).parse(i)?;
+        let line_base = line_base as i8;
+        let line_range = line_range.max(1);
+
+        // (path, directory index) entries
+        let mut dirs: Vec<&[u8]> = Vec::new();
+        let mut files: Vec<(&[u8], u64)> = Vec::new();
+        if version >= 5 {
+            let entries = |i: parse::Input<'a>| -> Result<_, DwarfError> {
+                let (mut i, format_count) = ctx.u8()(i)?;
+                let mut format = Vec::new();
+                for _ in 0..format_count {
+                    let (rest, pair) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    format.push(pair);
+                }
+                let (mut i, count) = uleb128(i)?;
+                let mut res = Vec::new();
+                for _ in 0..count {
+                    let (mut path, mut dir) = (&[][..], 0);
+                    for &(content, form) in &format {
+                        let (rest, val) = value(enc, form, 0)(i)?;
+                        i = rest;
+                        match content {
+                            LNCT_PATH => path = self.line_string(val).unwrap_or_default(),
+                            LNCT_DIRECTORY_INDEX => dir = val.udata().unwrap_or_default(),
+                            _ => {}
+                        }
+                    }
+                    res.push((path, dir));
+                }
+                Ok((i, res))
+            };
+            let (rest, entries_dirs) = entries(i)?;
+            let (_, entries_files) = entries(rest)?;
+            dirs = entries_dirs.into_iter().map(|(path, _)| path).collect();
+            files = entries_files;
+        } else {
+            // directory 0 is the compilation directory
+            dirs.push(comp_dir);
+            loop {
+                let (rest, dir) = cstr(i)?;
+                i = rest;
+                if dir.is_empty() {
+                    break;
+                }
+                dirs.push(dir);
+            }
+            loop {
+                let (rest, path) = cstr(i)?;
+                if path.is_empty() {
+                    break;
+                }
+                let (rest, (dir, _mtime, _len)) = (uleb128, uleb128, uleb128).parse(rest)?;
+                i = rest;
+                files.push((path, dir));
+            }
+        }
+
+        let path = |(path, dir): (&[u8], u64)| {
+            let dir = dirs.get(dir as usize).copied().unwrap_or_default();
+            let path = join(comp_dir, &join(dir, path));
+            String::from_utf8_lossy(&path).into_owned()
+        };
+        let mut res = LineProgram {
+            version,
+            files: files.into_iter().map(path).collect(),
+            rows: Vec::new(),
+        };
+
+        let initial = LineRow {
+            address: Addr(0),
+            file: 1,
+            line: 1,
+            column: 0,
+            is_stmt: default_is_stmt != 0,
+            end_sequence: false,
+        };
+        let mut row = initial;
+        let mut i = program;
+        let min_inst_length = u64::from(min_inst_length);
+        let advance = |address: Addr, delta: u64| {
+            let delta = delta
+                .checked_mul(min_inst_length)
+                .ok_or(DwarfError::AddressOverflow)?;
+            add_offset(address.0, delta).map(Addr)
+        };
+        while !i.is_empty() {
+            let (rest, opcode) = ctx.u8()(i)?;
+            i = rest;
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                row.address = advance(row.address, u64::from(adjusted / line_range))?;
+                row.line = row
+                    .line
+                    .wrapping_add_signed(i64::from(line_base) + i64::from(adjusted % line_range));
+                res.rows.push(row);
+                continue;
+            }
+            // DW_LNS_*, or DW_LNE_* after a 0
+            match opcode {
+                0 => {
+                    let (rest, len) = uleb128(i)?;
+                    let (rest, ext) = take(len).parse(rest)?;
+                    i = rest;
+                    match ext.split_first() {
+                        Some((1, _)) => {
+                            row.end_sequence = true;
+                            res.rows.push(row);
+                            row = initial;
+                        }
+                        Some((2, addr)) => {
+                            (_, row.address.0) = sized(ctx, addr.len() as u8)(addr)?;
+                        }
+                        Some((3, entry)) => {
+                            let (entry, file) = cstr(entry)?;
+                            let (_, dir) = uleb128(entry)?;
+                            res.files.push(path((file, dir)));
+                        }
+                        _ => {}
+                    }
+                }
+                1 => res.rows.push(row),
+                2 => {
+                    let (rest, delta) = uleb128(i)?;
+                    i = rest;
+                    row.address = advance(row.address, delta)?;
+                }
+                3 => {
+                    let (rest, delta) = sleb128(i)?;
+                    i = rest;
+                    row.line = row.line.wrapping_add_signed(delta);
+                }
+                4 => (i, row.file) = uleb128(i)?,
+                5 => (i, row.column) = uleb128(i)?,
+                6 => row.is_stmt = !row.is_stmt,
+                8 => {
+                    let adjusted = 255 - opcode_base;
+                    row.address = advance(row.address, u64::from(adjusted / line_range))?;
+                }
+                9 => {
+                    let (rest, delta) = ctx.u16()(i)?;
+                    i = rest;
+                    row.address.0 = add_offset(row.address.0, u64::from(delta))?;
+                }
+                // skip the operands of opcodes we don't track
+                _ => {
+                    let argc = standard_opcode_lengths[opcode as usize - 1];
+                    for _ in 0..argc {
+                        (i, _) = uleb128(i)?;
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn line_string(&self, val: Value<'a>) -> Option<&'a [u8]> {
+        let at = |section: &'a [u8], offset: u64| {
+            cstr(section.get(offset as usize..)?).ok().map(|(_, s)| s)
+        };
+        match val {
+            Value::Str(s) => Some(s),
+            Value::Strp(offset) => at(self.sections.str, offset),
+            Value::LineStrp(offset) => at(self.sections.line_str, offset),
+            _ => None,
+        }
+    }
+
+    /// Decodes the line programs of every unit
+    pub fn line_programs(&self) -> Result<Vec<LineProgram>, DwarfError> {
+        let mut res = Vec::new();
+        for offset in self.unit_offsets() {
+            let unit = self.unit(offset)?;
+            res.extend(self.unit_line_program(&unit)?);
+        }
+        Ok(res)
+    }
+
+    /// Returns the name of a function entry, following `DW_AT_abstract_origin`
+    /// and `DW_AT_specification` for inlined and out-of-line definitions
+    fn function_name(&self, unit: &Unit<'a>, die: &Die<'a>, depth: usize) -> Option<String> {
+        for name in [AT_NAME, AT_LINKAGE_NAME, AT_MIPS_LINKAGE_NAME] {
+            if let Some(name) = die.attr(name).and_then(|v| unit.string(v)) {
+                return Some(String::from_utf8_lossy(name).into_owned());
+            }
+        }
+        if depth > 8 {
+            return None;
+        }
+        for reference in [AT_ABSTRACT_ORIGIN, AT_SPECIFICATION] {
+            let (other, offset) = match die.attr(reference) {
+                Some(Value::UnitRef(offset)) => (None, offset as usize),
+                Some(Value::InfoRef(offset)) => {
+                    let offset = offset as usize;
+                    if (unit.offset..unit.offset + unit.data.len()).contains(&offset) {
+                        (None, offset - unit.offset)
+                    } else {
+                        let start = self
+                            .unit_offsets()
+                            .take_while(|&start| start <= offset)
+                            .last()?;
+                        (Some(self.unit(start).ok()?), offset - start)
+                    }
+                }
+                _ => continue,
+            };
+            let unit = other.as_ref().unwrap_or(unit);
+            if let Ok((_, Some(target))) = unit.entry(offset) {
+                return self.function_name(unit, &target, depth + 1);
+            }
+        }
+        None
+    }
+
+    /// Maps an address to its source location and to the functions containing
+    /// it, innermost first: every frame but the last one was inlined into the
+    /// next, whose location is the call site. Returns no frames for addresses
+    /// without debug info.
+    pub fn locate(&self, addr: Addr) -> Result<Vec<Frame>, DwarfError> {
+        for offset in self.unit_offsets() {
+            let unit = self.unit(offset)?;
+            let ranges = unit.ranges(&unit.root)?;
+            let line_program = if ranges.is_empty() {
+                // no ranges: only the line table tells whether the unit covers `addr`
+                match self.unit_line_program(&unit)? {
+                    Some(program) if program.find_row(addr).is_some() => Some(program),
+                    _ => continue,
+                }
+            } else if ranges.iter().any(|r| r.contains(&addr.0)) {
+                self.unit_line_program(&unit)?
+            } else {
+                continue;
+            };
+
+            // functions and inlined calls containing `addr`, outermost first
+            let mut chain: Vec<(usize, Die<'a>)> = Vec::new();
+            let (mut offset, mut depth) = (unit.entries, 0usize);
+            while offset < unit.data.len() {
+                let (next, die) = unit.entry(offset)?;
+                offset = next;
+                let Some(die) = die else {
+                    depth = depth.saturating_sub(1);
+                    continue;
+                };
+                if matches!(die.tag, TAG_SUBPROGRAM | TAG_INLINED_SUBROUTINE)
+                    && unit.ranges(&die)?.iter().any(|r| r.contains(&addr.0))
+                {
+                    chain.retain(|(d, _)| *d < depth);
+                    chain.push((depth, die.clone()));
+                }
+                if die.has_children {
+                    depth += 1;
+                }
+            }
+
+            let program = line_program.as_ref();
+            let mut location = program.and_then(|program| {
+                let row = program.find_row(addr)?;
+                Some(program.location(row.file, row.line, row.column))
+            });
+            if chain.is_empty() {
+                return Ok(vec![Frame {
+                    function: None,
+                    location,
+                }]);
+            }
+            let mut frames = Vec::new();
+            for (_, die) in chain.iter().rev() {
+                frames.push(Frame {
+                    function: self.function_name(&unit, die, 0),
+                    location: location.take(),
+                });
+                let attr = |name| die.attr(name).and_then(|v| v.udata());
+                if let (Some(program), Some(file), Some(line)) =
+                    (program, attr(AT_CALL_FILE), attr(AT_CALL_LINE))
+                {
+                    let column = attr(AT_CALL_COLUMN).unwrap_or_default();
+                    location = Some(program.location(file, line, column));
+                }
+            }
+            return Ok(frames);
+        }
+        Ok(vec![])
+    }
+}
+
+/// Joins two paths, keeping `path` as-is if it's absolute
+fn join(dir: &[u8], path: &[u8]) -> Vec<u8> {
+    if path.starts_with(b"/") || dir.is_empty() {
+        return path.to_vec();
+    }
+    let mut res = dir.to_vec();
+    if !res.ends_with(b"/") {
+        res.push(b'/');
+    }
+    res.extend(path);
+    res
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Returns the DWARF debug info of the file, if it has a `.debug_info`
+    /// section. Relocations of relocatable objects are not applied.
+    pub fn dwarf(&self) -> Result<Option<Dwarf<'_>>, DwarfError> {
+        if self.section_by_name(b".debug_info").is_none() {
+            return Ok(None);
+        }
+        let section = |name: &'static str| -> Result<&[u8], DwarfError> {
+            match self.section_by_name(name.as_bytes()) {
+                Some(sh) if sh.flags & SHF_COMPRESSED != 0 => {
+                    Err(DwarfError::CompressedSection(name))
+                }
+                Some(sh) => Ok(self.section_slice(sh)),
+                None => Ok(&[]),
+            }
+        };
+        let sections = Sections {
+            info: section(".debug_info")?,
+            abbrev: section(".debug_abbrev")?,
+            line: section(".debug_line")?,
+            str: section(".debug_str")?,
+            line_str: section(".debug_line_str")?,
+            ranges: section(".debug_ranges")?,
+            rnglists: section(".debug_rnglists")?,
+            addr: section(".debug_addr")?,
+            str_offsets: section(".debug_str_offsets")?,
+        };
+        Ok(Some(Dwarf {
+            ctx: self.ctx(),
+            sections,
+        }))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DwarfError {
+    #[error("Section {0} is compressed, which is not supported")]
+    CompressedSection(&'static str),
+    #[error("Unsupported DWARF version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Abbreviation {0} not found")]
+    AbbrevNotFound(u64),
+    #[error("Unsupported address size {0}")]
+    UnsupportedAddressSize(u8),
+    #[error("Address arithmetic overflowed")]
+    AddressOverflow,
+    #[error("Parsing error: {0}")]
+    ParsingError(String),
+}
+
+/// `base + offset`, as an error rather than a panic or a wrap-around
+fn add_offset(base: u64, offset: u64) -> Result<u64, DwarfError> {
+    base.checked_add(offset).ok_or(DwarfError::AddressOverflow)
+}
+
+/// Addresses are read with `sized`, and masks are derived from their size
+fn check_address_size(size: u8) -> Result<u8, DwarfError> {
+    match size {
+        1..=8 => Ok(size),
+        _ => Err(DwarfError::UnsupportedAddressSize(size)),
+    }
+}
+
+impl From<nom::Err<parse::Error<parse::Input<'_>>>> for DwarfError {
+    fn from(err: nom::Err<parse::Error<parse::Input<'_>>>) -> Self {
+        match err {
+            nom::Err::Error(err) | nom::Err::Failure(err) => Self::ParsingError(format!("{err:?}")),
+            nom::Err::Incomplete(_) => Self::ParsingError("incomplete input".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn leb128() {
+        assert_eq!(
+            uleb128(&[0xe5, 0x8e, 0x26, 0xff]).unwrap(),
+            (&[0xff][..], 624485)
+        );
+        assert_eq!(sleb128(&[0xc0, 0xbb, 0x78]).unwrap().1, -123456);
+        assert_eq!(sleb128(&[0x7f]).unwrap().1, -1);
+        assert_eq!(sleb128(&[0x3f]).unwrap().1, 63);
+        assert!(uleb128(&[0x80, 0x80]).is_err());
+    }
+
+    fn dwarf_of(sections: Sections<'_>) -> Dwarf<'_> {
+        let ctx = parse::Ctx {
+            class: crate::Class::Elf64,
+            endianness: crate::Endianness::Little,
+        };
+        Dwarf { ctx, sections }
+    }
+
+    #[test]
+    fn reject_bad_address_size() {
+        for address_size in [0, 9, 255] {
+            // DWARF 4: length, version, abbreviation offset, address size
+            let mut info = 8u32.to_le_bytes().to_vec();
+            info.extend_from_slice(&4u16.to_le_bytes());
+            info.extend_from_slice(&0u32.to_le_bytes());
+            info.extend_from_slice(&[address_size, 0]);
+            let dwarf = dwarf_of(Sections {
+                info: &info,
+                ..Default::default()
+            });
+            assert!(
+                matches!(
+                    dwarf.unit(0),
+                    Err(DwarfError::UnsupportedAddressSize(size)) if size == address_size
+                ),
+                "{address_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn reject_nested_indirect_form() {
+        let enc = Encoding {
+            ctx: dwarf_of(Sections::default()).ctx,
+            version: 4,
+            offset_size: 4,
+            address_size: 8,
+        };
+        let (_, val) = value(enc, FORM_INDIRECT, 0)(&[FORM_DATA1 as u8, 42]).unwrap();
+        assert_eq!(val.udata(), Some(42));
+        let nested = [FORM_INDIRECT as u8; 64];
+        assert!(value(enc, FORM_INDIRECT, 0)(&nested).is_err());
+    }
+
+    #[test]
+    fn line_program_address_overflow() {
+        // DWARF 4 header: no directories, no files
+        let mut header = vec![1, 1, 1, (-5i8) as u8, 14, 13];
+        header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend_from_slice(&[0, 0]);
+        // DW_LNE_set_address near the top, then DW_LNS_advance_pc past it
+        let mut program = vec![0, 9, 2];
+        program.extend_from_slice(&(u64::MAX - 0x10).to_le_bytes());
+        program.extend_from_slice(&[2, 0x20]);
+
+        let mut contents = 4u16.to_le_bytes().to_vec();
+        contents.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        contents.extend(header);
+        contents.extend(program);
+        let mut line = (contents.len() as u32).to_le_bytes().to_vec();
+        line.extend(contents);
+
+        let dwarf = dwarf_of(Sections {
+            line: &line,
+            ..Default::default()
+        });
+        assert!(matches!(
+            dwarf.line_program(0, b""),
+            Err(DwarfError::AddressOverflow)
+        ));
+    }
+
+    const MARKER_LINE: u32 = line!() + 2;
+    #[inline(never)]
+    fn located_marker() -> u32 {
+        std::hint::black_box(MARKER_LINE)
+    }
+
+    #[test]
+    fn locate_self() {
+        assert_eq!(located_marker(), MARKER_LINE);
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        // a test binary built without debug info has nothing to locate, but
+        // one that has it must parse
+        let Some(dwarf) = file.dwarf().unwrap() else {
+            return;
+        };
+        let syms = file.read_symtab_entries().unwrap();
+        let marker = syms
+            .iter()
+            .find(|sym| {
+                let name = file.strtab_entry(sym.name);
+                name.windows(14).any(|w| w == b"located_marker")
+                    && name.windows(5).any(|w| w == b"dwarf")
+            })
+            .expect("marker symbol");
+
+        let frames = dwarf.locate(marker.value).unwrap();
+        let frame = frames.last().expect("a frame");
+        assert_eq!(frame.function.as_deref(), Some("located_marker"));
+        let location = frames[0].location.as_ref().expect("a location");
+        assert!(location.file.ends_with("dwarf.rs"), "{location}");
+        assert_eq!(location.line, u64::from(MARKER_LINE));
+    }
+
+    #[test]
+    fn locate_inlined_c() {
+        let source = "\
+int value;
+static inline __attribute__((always_inline)) int add(int a) {
+    return a * value;
+}
+__attribute__((noinline)) int outer(int x) {
+    return add(x) + 1;
+}
+int main(int argc, char **argv) { return outer(argc); }
+";
+        let dir = test_util::temp_dir("dwarf");
+        std::fs::write(dir.join("inline.c"), source).unwrap();
+
+        for version in ["-gdwarf-4", "-gdwarf-5"] {
+            let out = format!("inline{version}");
+            test_util::gcc(&dir, &["-O1", version, "inline.c", "-o", &out]);
+
+            let file = File::parse_or_print_error(std::fs::read(dir.join(&out)).unwrap()).unwrap();
+            let dwarf = file.dwarf().unwrap().unwrap();
+            let program = dwarf
+                .line_programs()
+                .unwrap()
+                .into_iter()
+                .find(|p| p.files.iter().any(|f| f.ends_with("inline.c")))
+                .expect("line program for inline.c");
+            let row = program
+                .rows
+                .iter()
+                .find(|row| row.line == 3 && !row.end_sequence)
+                .expect("row for the inlined body");
+            let frames = dwarf.locate(row.address).unwrap();
+            assert_eq!(frames.len(), 2, "{version}: {frames:?}");
+            assert_eq!(frames[0].function.as_deref(), Some("add"));
+            let inner = frames[0].location.as_ref().unwrap();
+            assert_eq!(inner.line, 3);
+            assert!(inner.file.starts_with('/') && inner.file.ends_with("inline.c"));
+            assert_eq!(frames[1].function.as_deref(), Some("outer"));
+            assert_eq!(frames[1].location.as_ref().unwrap().line, 6);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
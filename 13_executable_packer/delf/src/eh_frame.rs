@@ -0,0 +1,1228 @@
+use std::ops::Range;
+
+use crate::{
+    Addr, File, SegmentType,
+    dwarf::{cstr, initial_length, sized, sleb128, uleb128},
+    parse,
+};
+use nom::{Parser as _, bytes::complete::take, combinator::map};
+
+// DW_EH_PE_*: low nibble is the value format, high nibble how it's applied
+const PE_ABSPTR: u8 = 0x00;
+const PE_ULEB128: u8 = 0x01;
+const PE_UDATA2: u8 = 0x02;
+const PE_UDATA4: u8 = 0x03;
+const PE_UDATA8: u8 = 0x04;
+const PE_SLEB128: u8 = 0x09;
+const PE_SDATA2: u8 = 0x0a;
+const PE_SDATA4: u8 = 0x0b;
+const PE_SDATA8: u8 = 0x0c;
+const PE_PCREL: u8 = 0x10;
+const PE_DATAREL: u8 = 0x30;
+const PE_OMIT: u8 = 0xff;
+
+/// A section or segment holding call frame information, along with the
+/// address it's loaded at, which pc-relative pointers are relative to
+#[derive(Debug, Clone, Copy)]
+struct Section<'a> {
+    ctx: parse::Ctx,
+    data: &'a [u8],
+    vaddr: Addr,
+}
+
+impl<'a> Section<'a> {
+    /// Offset of `i`, a subslice of `data`
+    fn offset_of(&self, i: &[u8]) -> usize {
+        i.as_ptr() as usize - self.data.as_ptr() as usize
+    }
+
+    /// Decodes a `DW_EH_PE_*` encoded pointer. Indirect pointers aren't
+    /// followed: the address the pointer is stored at is returned instead.
+    fn pointer(&self, encoding: u8, data_base: u64, i: parse::Input<'a>) -> parse::Result<'a, u64> {
+        let ctx = self.ctx;
+        let (rest, val) = match encoding & 0x0f {
+            PE_ABSPTR => ctx.word()(i)?,
+            PE_ULEB128 => uleb128(i)?,
+            PE_UDATA2 => map(ctx.u16(), u64::from).parse(i)?,
+            PE_UDATA4 => map(ctx.u32(), u64::from).parse(i)?,
+            PE_UDATA8 | PE_SDATA8 => ctx.u64()(i)?,
+            PE_SLEB128 => map(sleb128, |x| x as u64).parse(i)?,
+            PE_SDATA2 => map(ctx.u16(), |x| x as i16 as u64).parse(i)?,
+            PE_SDATA4 => map(ctx.u32(), |x| x as i32 as u64).parse(i)?,
+            _ => return Err(unsupported_encoding(i, encoding)),
+        };
+        let base = match encoding & 0x70 {
+            0 => 0,
+            PE_PCREL => self.vaddr.0 + self.offset_of(i) as u64,
+            PE_DATAREL => data_base,
+            _ => return Err(unsupported_encoding(i, encoding)),
+        };
+        Ok((rest, base.wrapping_add(val)))
+    }
+}
+
+fn unsupported_encoding(i: parse::Input, encoding: u8) -> nom::Err<parse::Error<parse::Input>> {
+    nom::Err::Failure(parse::Error::from_string(
+        i,
+        format!("Unsupported pointer encoding 0x{encoding:x}"),
+    ))
+}
+
+/// Size of the values of a pointer encoding, for those that have a fixed size
+fn encoded_size(encoding: u8) -> Option<usize> {
+    match encoding & 0x0f {
+        PE_UDATA2 | PE_SDATA2 => Some(2),
+        PE_UDATA4 | PE_SDATA4 => Some(4),
+        PE_UDATA8 | PE_SDATA8 => Some(8),
+        _ => None,
+    }
+}
+
+/// A Common Information Entry: the parts shared by several FDEs
+#[derive(Debug, Clone, Copy)]
+pub struct Cie<'a> {
+    /// Offset of the entry in `.eh_frame`
+    pub offset: usize,
+    pub version: u8,
+    /// e.g. "zR", "zPLR"
+    pub augmentation: &'a [u8],
+    pub code_alignment: u64,
+    pub data_alignment: i64,
+    pub return_address_register: u16,
+    /// Encoding of the pointers of FDEs using this CIE
+    pub fde_encoding: u8,
+    /// Encoding of the LSDA pointer of FDEs using this CIE, `0xff` if absent
+    pub lsda_encoding: u8,
+    /// The personality routine, used to run destructors and catch exceptions
+    pub personality: Option<Addr>,
+    /// Frames of this CIE are signal handlers: their return address is the
+    /// interrupted instruction rather than the one after a call
+    pub signal_frame: bool,
+    pub instructions: &'a [u8],
+}
+
+/// A Frame Description Entry: how to unwind a range of code
+#[derive(Debug, Clone, Copy)]
+pub struct Fde<'a> {
+    /// Offset of the entry in `.eh_frame`
+    pub offset: usize,
+    pub cie: Cie<'a>,
+    pub initial_location: Addr,
+    pub address_range: u64,
+    /// The Language Specific Data Area (e.g. C++ exception tables)
+    pub lsda: Option<Addr>,
+    pub instructions: &'a [u8],
+    section: Section<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CfiEntry<'a> {
+    Cie(Cie<'a>),
+    Fde(Fde<'a>),
+}
+
+/// How to compute the Canonical Frame Address, the value of the stack
+/// pointer in the caller before the call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfaRule<'a> {
+    RegisterOffset { register: u16, offset: i64 },
+    Expression(&'a [u8]),
+}
+
+/// How to recover the caller's value of a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterRule<'a> {
+    Undefined,
+    SameValue,
+    /// Saved at CFA + offset
+    Offset(i64),
+    /// Is CFA + offset
+    ValOffset(i64),
+    /// Saved in another register
+    Register(u16),
+    /// Saved at the address computed by the expression, the CFA being pushed first
+    Expression(&'a [u8]),
+    /// Is the value computed by the expression, the CFA being pushed first
+    ValExpression(&'a [u8]),
+}
+
+/// The unwinding rules for a range of addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwindRow<'a> {
+    pub range: Range<Addr>,
+    pub cfa: CfaRule<'a>,
+    /// Rules for the registers that don't keep their value
+    pub registers: Vec<(u16, RegisterRule<'a>)>,
+    pub return_address_register: u16,
+    ctx: parse::Ctx,
+}
+
+/// x86-64 register values, indexed by DWARF register number: `rax`, `rdx`,
+/// `rcx`, `rbx`, `rsi`, `rdi`, `rbp`, `rsp`, `r8` to `r15`, then the return
+/// address column, which holds `rip`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers(pub [Option<u64>; 17]);
+
+impl Registers {
+    pub const RBP: u16 = 6;
+    pub const RSP: u16 = 7;
+    pub const RIP: u16 = 16;
+
+    pub fn get(&self, reg: u16) -> Option<u64> {
+        self.0.get(reg as usize).copied().flatten()
+    }
+
+    /// Sets a register, ignoring those we don't track (e.g. vector registers)
+    pub fn set(&mut self, reg: u16, val: Option<u64>) {
+        if let Some(slot) = self.0.get_mut(reg as usize) {
+            *slot = val;
+        }
+    }
+
+    pub fn pc(&self) -> Option<Addr> {
+        self.get(Self::RIP).map(Addr)
+    }
+}
+
+/// Reads words from the memory of the program being unwound
+pub trait Memory {
+    fn read_word(&self, addr: Addr) -> Option<u64>;
+}
+
+impl<F> Memory for F
+where
+    F: Fn(Addr) -> Option<u64>,
+{
+    fn read_word(&self, addr: Addr) -> Option<u64> {
+        self(addr)
+    }
+}
+
+/// The contents of `.eh_frame`: a list of CIEs and FDEs
+#[derive(Debug, Clone, Copy)]
+pub struct EhFrame<'a> {
+    section: Section<'a>,
+}
+
+impl<'a> EhFrame<'a> {
+    pub fn new(ctx: parse::Ctx, data: &'a [u8], vaddr: Addr) -> Self {
+        Self {
+            section: Section { ctx, data, vaddr },
+        }
+    }
+
+    pub fn vaddr(&self) -> Addr {
+        self.section.vaddr
+    }
+
+    /// Splits the entry at `offset` into its CIE id (or CIE pointer), the
+    /// input after it, the offset of the id and that of the next entry.
+    /// Returns `None` at the zero terminator or the end of the data.
+    fn header(
+        &self,
+        offset: usize,
+    ) -> Result<Option<(u64, parse::Input<'a>, usize, usize)>, EhFrameError> {
+        let ctx = self.section.ctx;
+        let i = self.section.data.get(offset..).unwrap_or_default();
+        if i.is_empty() {
+            return Ok(None);
+        }
+        let (i, (len, offset_size)) = initial_length(ctx)(i)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        let (rest, body) = take(len).parse(i)?;
+        let next = self.section.offset_of(rest);
+        let (i, id) = sized(ctx, offset_size)(body)?;
+        Ok(Some((id, i, self.section.offset_of(body), next)))
+    }
+
+    /// Parses the entry at `offset`, returning it along with the offset of
+    /// the next one. Returns `None` at the zero terminator or the end of the data.
+    pub fn entry(&self, offset: usize) -> Result<Option<(CfiEntry<'a>, usize)>, EhFrameError> {
+        let Some((id, i, id_offset, next)) = self.header(offset)? else {
+            return Ok(None);
+        };
+        let entry = if id == 0 {
+            CfiEntry::Cie(self.parse_cie(offset, i)?)
+        } else {
+            // the CIE pointer is relative to its own position
+            let cie_offset = (id_offset as u64)
+                .checked_sub(id)
+                .ok_or(EhFrameError::NotACie(offset))?;
+            let cie = self.cie(cie_offset as usize)?;
+            CfiEntry::Fde(self.parse_fde(offset, cie, i)?)
+        };
+        Ok(Some((entry, next)))
+    }
+
+    /// Parses the CIE at `offset`. Never follows a CIE pointer, so FDEs
+    /// pointing at themselves or at each other are `NotACie`, not a loop
+    pub fn cie(&self, offset: usize) -> Result<Cie<'a>, EhFrameError> {
+        match self.header(offset)? {
+            Some((0, i, _, _)) => self.parse_cie(offset, i),
+            _ => Err(EhFrameError::NotACie(offset)),
+        }
+    }
+
+    pub fn fde(&self, offset: usize) -> Result<Fde<'a>, EhFrameError> {
+        match self.entry(offset)? {
+            Some((CfiEntry::Fde(fde), _)) => Ok(fde),
+            _ => Err(EhFrameError::NotAnFde(offset)),
+        }
+    }
+
+    /// Iterates over the entries, up to the terminator or the first malformed entry
+    pub fn entries(&self) -> impl Iterator<Item = Result<CfiEntry<'a>, EhFrameError>> + '_ {
+        let mut offset = Some(0);
+        std::iter::from_fn(move || match self.entry(offset?) {
+            Ok(Some((entry, next))) => {
+                offset = Some(next);
+                Some(Ok(entry))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                offset = None;
+                Some(Err(e))
+            }
+        })
+    }
+
+    /// Finds the FDE covering `pc` by scanning every entry
+    pub fn find_fde(&self, pc: Addr) -> Result<Option<Fde<'a>>, EhFrameError> {
+        for entry in self.entries() {
+            if let CfiEntry::Fde(fde) = entry?
+                && fde.pc_range()?.contains(&pc)
+            {
+                return Ok(Some(fde));
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse_cie(&self, offset: usize, i: parse::Input<'a>) -> Result<Cie<'a>, EhFrameError> {
+        let ctx = self.section.ctx;
+        let (i, version) = ctx.u8()(i)?;
+        if !matches!(version, 1 | 3 | 4) {
+            return Err(EhFrameError::UnsupportedVersion(version));
+        }
+        let (mut i, augmentation) = cstr(i)?;
+        if augmentation.starts_with(b"eh") {
+            (i, _) = take(ctx.word_size()).parse(i)?;
+        }
+        if version == 4 {
+            // address and segment selector sizes
+            (i, _) = take(2usize).parse(i)?;
+        }
+        let (i, (code_alignment, data_alignment)) = (uleb128, sleb128).parse(i)?;
+        let (mut i, return_address_register) = if version == 1 {
+            map(ctx.u8(), u16::from).parse(i)?
+        } else {
+            map(uleb128, |x| x as u16).parse(i)?
+        };
+
+        let mut cie = Cie {
+            offset,
+            version,
+            augmentation,
+            code_alignment,
+            data_alignment,
+            return_address_register,
+            fde_encoding: PE_ABSPTR,
+            lsda_encoding: PE_OMIT,
+            personality: None,
+            signal_frame: false,
+            instructions: &[],
+        };
+        if let Some((b'z', rest)) = augmentation.split_first() {
+            let (after, len) = uleb128(i)?;
+            let (after, mut data) = take(len).parse(after)?;
+            i = after;
+            for c in rest {
+                match c {
+                    b'L' => (data, cie.lsda_encoding) = ctx.u8()(data)?,
+                    b'R' => (data, cie.fde_encoding) = ctx.u8()(data)?,
+                    b'P' => {
+                        let (after, encoding) = ctx.u8()(data)?;
+                        let (after, personality) = self.section.pointer(encoding, 0, after)?;
+                        (data, cie.personality) = (after, Some(Addr(personality)));
+                    }
+                    b'S' => cie.signal_frame = true,
+                    // the rest of the augmentation data can't be interpreted
+                    _ => break,
+                }
+            }
+        }
+        cie.instructions = i;
+        Ok(cie)
+    }
+
+    fn parse_fde(
+        &self,
+        offset: usize,
+        cie: Cie<'a>,
+        i: parse::Input<'a>,
+    ) -> Result<Fde<'a>, EhFrameError> {
+        let (i, initial_location) = self.section.pointer(cie.fde_encoding, 0, i)?;
+        // the range is a length: only the value format applies
+        let (mut i, address_range) = self.section.pointer(cie.fde_encoding & 0x0f, 0, i)?;
+        let mut lsda = None;
+        if cie.augmentation.starts_with(b"z") {
+            let (after, len) = uleb128(i)?;
+            let (after, data) = take(len).parse(after)?;
+            i = after;
+            if cie.lsda_encoding != PE_OMIT {
+                let (_, addr) = self.section.pointer(cie.lsda_encoding, 0, data)?;
+                lsda = Some(Addr(addr));
+            }
+        }
+        Ok(Fde {
+            offset,
+            cie,
+            initial_location: Addr(initial_location),
+            address_range,
+            lsda,
+            instructions: i,
+            section: self.section,
+        })
+    }
+}
+
+/// The contents of `.eh_frame_hdr` (`PT_GNU_EH_FRAME`): where `.eh_frame`
+/// is, and usually a sorted table to find FDEs by binary search
+#[derive(Debug, Clone, Copy)]
+pub struct EhFrameHdr<'a> {
+    /// Address of `.eh_frame`
+    pub eh_frame_ptr: Addr,
+    pub fde_count: u64,
+    table_encoding: u8,
+    table: &'a [u8],
+    section: Section<'a>,
+}
+
+impl<'a> EhFrameHdr<'a> {
+    pub fn parse(ctx: parse::Ctx, data: &'a [u8], vaddr: Addr) -> Result<Self, EhFrameError> {
+        let section = Section { ctx, data, vaddr };
+        let (i, (version, eh_frame_ptr_encoding, fde_count_encoding, table_encoding)) =
+            (ctx.u8(), ctx.u8(), ctx.u8(), ctx.u8()).parse(data)?;
+        if version != 1 {
+            return Err(EhFrameError::UnsupportedVersion(version));
+        }
+        let (i, eh_frame_ptr) = section.pointer(eh_frame_ptr_encoding, vaddr.0, i)?;
+        let (table, fde_count) = match fde_count_encoding {
+            PE_OMIT => (i, 0),
+            encoding => section.pointer(encoding, vaddr.0, i)?,
+        };
+        Ok(Self {
+            eh_frame_ptr: Addr(eh_frame_ptr),
+            fde_count,
+            table_encoding,
+            table,
+            section,
+        })
+    }
+
+    /// Whether the table can be binary searched
+    pub fn is_searchable(&self) -> bool {
+        self.fde_count > 0 && self.table_encoding != PE_OMIT && self.entry_size().is_some()
+    }
+
+    fn entry_size(&self) -> Option<usize> {
+        encoded_size(self.table_encoding).map(|size| size * 2)
+    }
+
+    /// Returns the initial location and FDE address of a table entry
+    pub fn entry(&self, index: u64) -> Result<(Addr, Addr), EhFrameError> {
+        let size = self.entry_size().ok_or(EhFrameError::NotSearchable)?;
+        let offset = usize::try_from(index)
+            .ok()
+            .and_then(|index| index.checked_mul(size))
+            .ok_or(EhFrameError::AddressOverflow)?;
+        let i = self.table.get(offset..).unwrap_or_default();
+        let base = self.section.vaddr.0;
+        let (i, location) = self.section.pointer(self.table_encoding, base, i)?;
+        let (_, fde) = self.section.pointer(self.table_encoding, base, i)?;
+        Ok((Addr(location), Addr(fde)))
+    }
+
+    /// Returns the address of the FDE whose initial location is the closest
+    /// before `pc`. The FDE may still not cover `pc`.
+    pub fn lookup(&self, pc: Addr) -> Result<Option<Addr>, EhFrameError> {
+        let (mut lo, mut hi) = (0, self.fde_count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.entry(mid)?.0 <= pc {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        match lo {
+            0 => Ok(None),
+            lo => Ok(Some(self.entry(lo - 1)?.1)),
+        }
+    }
+}
+
+impl<'a> Fde<'a> {
+    pub fn pc_range(&self) -> Result<Range<Addr>, EhFrameError> {
+        let end = self
+            .initial_location
+            .0
+            .checked_add(self.address_range)
+            .ok_or(EhFrameError::AddressOverflow)?;
+        Ok(self.initial_location..Addr(end))
+    }
+
+    /// Runs the call frame instructions up to `pc`, returning the rules that
+    /// apply there
+    pub fn unwind_row(&self, pc: Addr) -> Result<UnwindRow<'a>, EhFrameError> {
+        let row = UnwindRow {
+            range: self.pc_range()?,
+            cfa: CfaRule::RegisterOffset {
+                register: 0,
+                offset: 0,
+            },
+            registers: Vec::new(),
+            return_address_register: self.cie.return_address_register,
+            ctx: self.section.ctx,
+        };
+        let mut executor = Executor {
+            fde: self,
+            row,
+            initial: Vec::new(),
+            stack: Vec::new(),
+        };
+        executor.run(self.cie.instructions, self.initial_location)?;
+        executor.initial = executor.row.registers.clone();
+        executor.run(self.instructions, pc)?;
+        Ok(executor.row)
+    }
+}
+
+/// Executes call frame instructions
+struct Executor<'a, 'f> {
+    fde: &'f Fde<'a>,
+    row: UnwindRow<'a>,
+    /// Register rules set by the CIE, for `DW_CFA_restore`
+    initial: Vec<(u16, RegisterRule<'a>)>,
+    /// Saved by `DW_CFA_remember_state`
+    stack: Vec<(CfaRule<'a>, Vec<(u16, RegisterRule<'a>)>)>,
+}
+
+impl<'a> Executor<'a, '_> {
+    fn set(&mut self, reg: u16, rule: RegisterRule<'a>) {
+        match self.row.registers.iter_mut().find(|(r, _)| *r == reg) {
+            Some((_, existing)) => *existing = rule,
+            None => self.row.registers.push((reg, rule)),
+        }
+    }
+
+    fn restore(&mut self, reg: u16) {
+        match self.initial.iter().find(|(r, _)| *r == reg) {
+            Some(&(_, rule)) => self.set(reg, rule),
+            None => self.row.registers.retain(|(r, _)| *r != reg),
+        }
+    }
+
+    /// Moves the row to `loc`. Returns true if `loc` is past `pc`, in which
+    /// case the current row is the one that applies.
+    fn move_to(&mut self, loc: Addr, pc: Addr) -> bool {
+        if loc > pc {
+            self.row.range.end = loc;
+            true
+        } else {
+            self.row.range.start = loc;
+            false
+        }
+    }
+
+    /// The location `delta` code alignment units past the current row
+    fn advance(&self, delta: u64) -> Result<Addr, EhFrameError> {
+        delta
+            .checked_mul(self.fde.cie.code_alignment)
+            .and_then(|delta| self.row.range.start.0.checked_add(delta))
+            .map(Addr)
+            .ok_or(EhFrameError::AddressOverflow)
+    }
+
+    fn cfa_offset(&mut self, new_offset: i64) {
+        if let CfaRule::RegisterOffset { offset, .. } = &mut self.row.cfa {
+            *offset = new_offset;
+        }
+    }
+
+    fn run(&mut self, mut i: parse::Input<'a>, pc: Addr) -> Result<(), EhFrameError> {
+        let ctx = self.row.ctx;
+        let cie = self.fde.cie;
+        let factored = |x: u64| (x as i64).wrapping_mul(cie.data_alignment);
+        let reg = |x: u64| x as u16;
+        let block = |i: parse::Input<'a>| -> parse::Result<'a, &'a [u8]> {
+            let (i, len) = uleb128(i)?;
+            take(len).parse(i)
+        };
+
+        while !i.is_empty() {
+            let (rest, op) = ctx.u8()(i)?;
+            i = rest;
+            // DW_CFA_*
+            match (op >> 6, op & 0x3f) {
+                (1, delta) => {
+                    let loc = self.advance(delta.into())?;
+                    if self.move_to(loc, pc) {
+                        return Ok(());
+                    }
+                }
+                (2, r) => {
+                    let (rest, offset) = uleb128(i)?;
+                    i = rest;
+                    self.set(r.into(), RegisterRule::Offset(factored(offset)));
+                }
+                (3, r) => self.restore(r.into()),
+                (_, 0x00) => {}
+                (_, 0x01) => {
+                    let (rest, loc) = self.fde.section.pointer(cie.fde_encoding, 0, i)?;
+                    i = rest;
+                    if self.move_to(Addr(loc), pc) {
+                        return Ok(());
+                    }
+                }
+                (_, n @ 0x02..=0x04) => {
+                    let size = 1 << (n - 2);
+                    let (rest, delta) = sized(ctx, size)(i)?;
+                    i = rest;
+                    let loc = self.advance(delta)?;
+                    if self.move_to(loc, pc) {
+                        return Ok(());
+                    }
+                }
+                (_, 0x05) => {
+                    let (rest, (r, offset)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::Offset(factored(offset)));
+                }
+                (_, 0x06) => {
+                    let (rest, r) = uleb128(i)?;
+                    i = rest;
+                    self.restore(reg(r));
+                }
+                (_, 0x07) => {
+                    let (rest, r) = uleb128(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::Undefined);
+                }
+                (_, 0x08) => {
+                    let (rest, r) = uleb128(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::SameValue);
+                }
+                (_, 0x09) => {
+                    let (rest, (r, other)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::Register(reg(other)));
+                }
+                (_, 0x0a) => {
+                    let state = (self.row.cfa, self.row.registers.clone());
+                    self.stack.push(state);
+                }
+                (_, 0x0b) => {
+                    // the CFA rule isn't part of the saved state in DWARF 2,
+                    // but every producer restores it too
+                    let (cfa, registers) = self.stack.pop().ok_or(EhFrameError::EmptyStack)?;
+                    (self.row.cfa, self.row.registers) = (cfa, registers);
+                }
+                (_, 0x0c) => {
+                    let (rest, (r, offset)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    self.row.cfa = CfaRule::RegisterOffset {
+                        register: reg(r),
+                        offset: offset as i64,
+                    };
+                }
+                (_, 0x0d) => {
+                    let (rest, r) = uleb128(i)?;
+                    i = rest;
+                    self.row.cfa = match self.row.cfa {
+                        CfaRule::RegisterOffset { offset, .. } => CfaRule::RegisterOffset {
+                            register: reg(r),
+                            offset,
+                        },
+                        CfaRule::Expression(_) => CfaRule::RegisterOffset {
+                            register: reg(r),
+                            offset: 0,
+                        },
+                    };
+                }
+                (_, 0x0e) => {
+                    let (rest, offset) = uleb128(i)?;
+                    i = rest;
+                    self.cfa_offset(offset as i64);
+                }
+                (_, 0x0f) => {
+                    let (rest, expr) = block(i)?;
+                    i = rest;
+                    self.row.cfa = CfaRule::Expression(expr);
+                }
+                (_, 0x10) => {
+                    let (rest, r) = uleb128(i)?;
+                    let (rest, expr) = block(rest)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::Expression(expr));
+                }
+                (_, 0x11) => {
+                    let (rest, (r, offset)) = (uleb128, sleb128).parse(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::Offset(offset * cie.data_alignment));
+                }
+                (_, 0x12) => {
+                    let (rest, (r, offset)) = (uleb128, sleb128).parse(i)?;
+                    i = rest;
+                    self.row.cfa = CfaRule::RegisterOffset {
+                        register: reg(r),
+                        offset: offset * cie.data_alignment,
+                    };
+                }
+                (_, 0x13) => {
+                    let (rest, offset) = sleb128(i)?;
+                    i = rest;
+                    self.cfa_offset(offset * cie.data_alignment);
+                }
+                (_, 0x14) => {
+                    let (rest, (r, offset)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::ValOffset(factored(offset)));
+                }
+                (_, 0x15) => {
+                    let (rest, (r, offset)) = (uleb128, sleb128).parse(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::ValOffset(offset * cie.data_alignment));
+                }
+                (_, 0x16) => {
+                    let (rest, r) = uleb128(i)?;
+                    let (rest, expr) = block(rest)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::ValExpression(expr));
+                }
+                // DW_CFA_GNU_args_size
+                (_, 0x2e) => (i, _) = uleb128(i)?,
+                // DW_CFA_GNU_negative_offset_extended
+                (_, 0x2f) => {
+                    let (rest, (r, offset)) = (uleb128, uleb128).parse(i)?;
+                    i = rest;
+                    self.set(reg(r), RegisterRule::Offset(-factored(offset)));
+                }
+                _ => return Err(EhFrameError::UnknownInstruction(op)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UnwindRow<'_> {
+    /// Returns the rule for a register. Registers without a rule keep their value.
+    pub fn register(&self, reg: u16) -> RegisterRule<'_> {
+        self.registers
+            .iter()
+            .find(|(r, _)| *r == reg)
+            .map_or(RegisterRule::SameValue, |&(_, rule)| rule)
+    }
+
+    pub fn cfa(&self, regs: &Registers, mem: &impl Memory) -> Result<u64, EhFrameError> {
+        match self.cfa {
+            CfaRule::RegisterOffset { register, offset } => regs
+                .get(register)
+                .map(|val| val.wrapping_add_signed(offset))
+                .ok_or(EhFrameError::MissingRegister(register)),
+            CfaRule::Expression(expr) => evaluate(self.ctx, expr, regs, mem, None),
+        }
+    }
+
+    /// Computes the caller's registers from those of the current frame. The
+    /// caller's stack pointer is the CFA, and its `rip` the return address.
+    /// Returns `None` if the return address is undefined, which marks the
+    /// outermost frame.
+    pub fn unwind(
+        &self,
+        regs: &Registers,
+        mem: &impl Memory,
+    ) -> Result<Option<Registers>, EhFrameError> {
+        let cfa = self.cfa(regs, mem)?;
+        let read = |addr: u64| {
+            mem.read_word(Addr(addr))
+                .ok_or(EhFrameError::UnreadableMemory(Addr(addr)))
+        };
+
+        let mut caller = *regs;
+        for &(reg, rule) in &self.registers {
+            let val = match rule {
+                RegisterRule::Undefined => None,
+                RegisterRule::SameValue => regs.get(reg),
+                RegisterRule::Offset(offset) => Some(read(cfa.wrapping_add_signed(offset))?),
+                RegisterRule::ValOffset(offset) => Some(cfa.wrapping_add_signed(offset)),
+                RegisterRule::Register(other) => regs.get(other),
+                RegisterRule::Expression(expr) => {
+                    Some(read(evaluate(self.ctx, expr, regs, mem, Some(cfa))?)?)
+                }
+                RegisterRule::ValExpression(expr) => {
+                    Some(evaluate(self.ctx, expr, regs, mem, Some(cfa))?)
+                }
+            };
+            caller.set(reg, val);
+        }
+        let Some(ra) = caller.get(self.return_address_register) else {
+            return Ok(None);
+        };
+        caller.set(Registers::RSP, Some(cfa));
+        caller.set(Registers::RIP, Some(ra));
+        Ok(Some(caller))
+    }
+}
+
+/// Evaluates a DWARF expression, as found in CFA and register rules
+fn evaluate(
+    ctx: parse::Ctx,
+    expr: &[u8],
+    regs: &Registers,
+    mem: &impl Memory,
+    initial: Option<u64>,
+) -> Result<u64, EhFrameError> {
+    let mut stack: Vec<u64> = initial.into_iter().collect();
+    let mut i = expr;
+    let register = |r: u16| regs.get(r).ok_or(EhFrameError::MissingRegister(r));
+    while !i.is_empty() {
+        let (rest, op) = ctx.u8()(i)?;
+        i = rest;
+        let mut pop = || stack.pop().ok_or(EhFrameError::EmptyStack);
+        // DW_OP_*
+        let val = match op {
+            // deref
+            0x06 => {
+                let addr = pop()?;
+                mem.read_word(Addr(addr))
+                    .ok_or(EhFrameError::UnreadableMemory(Addr(addr)))?
+            }
+            // const1u to const8s
+            0x08..=0x0f => {
+                let size = 1 << ((op - 0x08) / 2);
+                let (rest, val) = sized(ctx, size)(i)?;
+                i = rest;
+                let signed = op % 2 == 1;
+                match (signed, size) {
+                    (true, 1) => val as i8 as u64,
+                    (true, 2) => val as i16 as u64,
+                    (true, 4) => val as i32 as u64,
+                    _ => val,
+                }
+            }
+            0x10 => {
+                let (rest, val) = uleb128(i)?;
+                i = rest;
+                val
+            }
+            0x11 => {
+                let (rest, val) = sleb128(i)?;
+                i = rest;
+                val as u64
+            }
+            // dup
+            0x12 => {
+                let val = pop()?;
+                stack.push(val);
+                val
+            }
+            // drop
+            0x13 => {
+                pop()?;
+                continue;
+            }
+            // over
+            0x14 => *stack
+                .len()
+                .checked_sub(2)
+                .and_then(|n| stack.get(n))
+                .ok_or(EhFrameError::EmptyStack)?,
+            // swap
+            0x16 => {
+                let (a, b) = (pop()?, pop()?);
+                stack.push(a);
+                b
+            }
+            // binary operators
+            0x1a..=0x1e | 0x21 | 0x22 | 0x24..=0x27 | 0x29..=0x2e => {
+                let (b, a) = (pop()?, pop()?);
+                match op {
+                    0x1a => a & b,
+                    0x1b => (a as i64).checked_div(b as i64).unwrap_or_default() as u64,
+                    0x1c => a.wrapping_sub(b),
+                    0x1d => a.checked_rem(b).unwrap_or_default(),
+                    0x1e => a.wrapping_mul(b),
+                    0x21 => a | b,
+                    0x22 => a.wrapping_add(b),
+                    0x24 => a.wrapping_shl(b as u32),
+                    0x25 => a.wrapping_shr(b as u32),
+                    0x26 => (a as i64).wrapping_shr(b as u32) as u64,
+                    0x27 => a ^ b,
+                    0x29 => (a == b).into(),
+                    0x2a => (a as i64 >= b as i64).into(),
+                    0x2b => (a as i64 > b as i64).into(),
+                    0x2c => ((a as i64) <= b as i64).into(),
+                    0x2d => ((a as i64) < b as i64).into(),
+                    _ => (a != b).into(),
+                }
+            }
+            // abs, neg, not
+            0x19 => (pop()? as i64).wrapping_abs() as u64,
+            0x1f => (pop()? as i64).wrapping_neg() as u64,
+            0x20 => !pop()?,
+            // plus_uconst
+            0x23 => {
+                let (rest, val) = uleb128(i)?;
+                i = rest;
+                pop()?.wrapping_add(val)
+            }
+            // lit0 to lit31
+            0x30..=0x4f => u64::from(op - 0x30),
+            // reg0 to reg31
+            0x50..=0x6f => register(u16::from(op - 0x50))?,
+            // breg0 to breg31
+            0x70..=0x8f => {
+                let (rest, offset) = sleb128(i)?;
+                i = rest;
+                register(u16::from(op - 0x70))?.wrapping_add_signed(offset)
+            }
+            // bregx
+            0x92 => {
+                let (rest, (r, offset)) = (uleb128, sleb128).parse(i)?;
+                i = rest;
+                register(r as u16)?.wrapping_add_signed(offset)
+            }
+            // nop
+            0x96 => continue,
+            _ => return Err(EhFrameError::UnsupportedOperation(op)),
+        };
+        stack.push(val);
+    }
+    stack.pop().ok_or(EhFrameError::EmptyStack)
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Parses the `PT_GNU_EH_FRAME` segment, if any
+    pub fn eh_frame_hdr(&self) -> Result<Option<EhFrameHdr<'_>>, EhFrameError> {
+        let Some(ph) = self.segment_of_type(SegmentType::GnuEhFrame) else {
+            return Ok(None);
+        };
+        EhFrameHdr::parse(self.ctx(), self.segment_slice(ph), ph.vaddr).map(Some)
+    }
+
+    /// Returns the `.eh_frame` section, found through `.eh_frame_hdr` for
+    /// files without section headers
+    pub fn eh_frame(&self) -> Result<Option<EhFrame<'_>>, EhFrameError> {
+        if let Some(sh) = self.section_by_name(b".eh_frame") {
+            return Ok(Some(EhFrame::new(
+                self.ctx(),
+                self.section_slice(sh),
+                sh.addr,
+            )));
+        }
+        let Some(hdr) = self.eh_frame_hdr()? else {
+            return Ok(None);
+        };
+        Ok(self
+            .mem_slice_from(hdr.eh_frame_ptr)
+            .map(|data| EhFrame::new(self.ctx(), data, hdr.eh_frame_ptr)))
+    }
+
+    /// Finds the FDE covering `pc`, a link-time address, with a binary search
+    /// in `.eh_frame_hdr` when possible
+    pub fn find_fde(&self, pc: Addr) -> Result<Option<Fde<'_>>, EhFrameError> {
+        let Some(eh_frame) = self.eh_frame()? else {
+            return Ok(None);
+        };
+        match self.eh_frame_hdr()? {
+            Some(hdr) if hdr.is_searchable() => {
+                let Some(addr) = hdr.lookup(pc)? else {
+                    return Ok(None);
+                };
+                let offset = addr
+                    .0
+                    .checked_sub(eh_frame.vaddr().0)
+                    .ok_or(EhFrameError::AddressOverflow)?;
+                let fde = eh_frame.fde(offset as usize)?;
+                Ok(fde.pc_range()?.contains(&pc).then_some(fde))
+            }
+            _ => eh_frame.find_fde(pc),
+        }
+    }
+
+    /// Unwinds one frame of code from this file, loaded `bias` bytes after its
+    /// link-time addresses. `regs` are those of the frame; for every frame but
+    /// the innermost, `regs.pc()` is a return address and the rules of the
+    /// call instruction, just before it, are used instead.
+    /// Returns `None` when no FDE covers the frame, or at the outermost frame.
+    pub fn unwind_frame(
+        &self,
+        bias: u64,
+        regs: &Registers,
+        mem: &impl Memory,
+        innermost: bool,
+    ) -> Result<Option<Registers>, EhFrameError> {
+        let Some(pc) = regs.pc() else {
+            return Ok(None);
+        };
+        let mut pc = Addr(pc.0.wrapping_sub(bias));
+        if !innermost {
+            pc.0 = pc.0.checked_sub(1).ok_or(EhFrameError::AddressOverflow)?;
+        }
+        let Some(fde) = self.find_fde(pc)? else {
+            return Ok(None);
+        };
+        if fde.cie.signal_frame && !innermost {
+            pc.0 += 1;
+        }
+        fde.unwind_row(pc)?.unwind(regs, mem)
+    }
+
+    /// Walks the stack for as long as frames run code from this file, returning
+    /// the program counter of each frame, innermost first
+    pub fn backtrace(
+        &self,
+        bias: u64,
+        mut regs: Registers,
+        mem: &impl Memory,
+    ) -> Result<Vec<Addr>, EhFrameError> {
+        let mut res = Vec::new();
+        while let Some(pc) = regs.pc() {
+            let innermost = res.is_empty();
+            res.push(pc);
+            match self.unwind_frame(bias, &regs, mem, innermost)? {
+                // guard against rules that don't make progress
+                Some(caller) if res.len() < 1024 && caller != regs => regs = caller,
+                _ => break,
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EhFrameError {
+    #[error("Unsupported call frame information version {0}")]
+    UnsupportedVersion(u8),
+    #[error("No CIE at offset 0x{0:x}")]
+    NotACie(usize),
+    #[error("No FDE at offset 0x{0:x}")]
+    NotAnFde(usize),
+    #[error(".eh_frame_hdr table cannot be searched")]
+    NotSearchable,
+    #[error("Unknown call frame instruction 0x{0:x}")]
+    UnknownInstruction(u8),
+    #[error("Unsupported DWARF expression operation 0x{0:x}")]
+    UnsupportedOperation(u8),
+    #[error("DWARF stack underflow")]
+    EmptyStack,
+    #[error("Value of register {0} is unknown")]
+    MissingRegister(u16),
+    #[error("Could not read memory at {0:?}")]
+    UnreadableMemory(Addr),
+    #[error("Address arithmetic overflowed")]
+    AddressOverflow,
+    #[error("Parsing error: {0}")]
+    ParsingError(String),
+}
+
+impl From<nom::Err<parse::Error<parse::Input<'_>>>> for EhFrameError {
+    fn from(err: nom::Err<parse::Error<parse::Input<'_>>>) -> Self {
+        match err {
+            nom::Err::Error(err) | nom::Err::Failure(err) => Self::ParsingError(format!("{err:?}")),
+            nom::Err::Incomplete(_) => Self::ParsingError("incomplete input".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hdr_agrees_with_scan() {
+        let input = std::fs::read("/usr/lib/x86_64-linux-gnu/libc.so.6").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        let hdr = file.eh_frame_hdr().unwrap().expect(".eh_frame_hdr");
+        assert!(hdr.is_searchable());
+        let eh_frame = file.eh_frame().unwrap().expect(".eh_frame");
+        assert_eq!(hdr.eh_frame_ptr, eh_frame.vaddr());
+
+        let mut count = 0;
+        for entry in eh_frame.entries() {
+            let CfiEntry::Fde(fde) = entry.unwrap() else {
+                continue;
+            };
+            count += 1;
+            let found = file.find_fde(fde.initial_location).unwrap().unwrap();
+            assert_eq!(found.offset, fde.offset);
+            // every row can be computed
+            fde.unwind_row(fde.pc_range().unwrap().end - Addr(1))
+                .unwrap();
+        }
+        assert_eq!(count, hdr.fde_count);
+    }
+
+    #[test]
+    fn overflows_are_errors() {
+        let ctx = parse::Ctx {
+            class: crate::Class::Elf64,
+            endianness: crate::Endianness::Little,
+        };
+        // a "zR" CIE with 8-byte absolute pointers...
+        let mut data = vec![0; 4];
+        data.extend_from_slice(&[0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x04]);
+        let len = data.len() - 4;
+        data[..4].copy_from_slice(&(len as u32).to_le_bytes());
+        // ...and an FDE whose range wraps around
+        let fde_start = data.len();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(fde_start as u32 + 4).to_le_bytes());
+        data.extend_from_slice(&(u64::MAX - 0x10).to_le_bytes());
+        data.extend_from_slice(&0x100u64.to_le_bytes());
+        data.push(0);
+        let len = data.len() - fde_start - 4;
+        data[fde_start..][..4].copy_from_slice(&(len as u32).to_le_bytes());
+
+        let eh_frame = EhFrame::new(ctx, &data, Addr(0x1000));
+        let fde = eh_frame.fde(fde_start).unwrap();
+        assert!(matches!(fde.pc_range(), Err(EhFrameError::AddressOverflow)));
+        assert!(matches!(
+            eh_frame.find_fde(Addr(u64::MAX - 8)),
+            Err(EhFrameError::AddressOverflow)
+        ));
+
+        // an FDE whose CIE pointer leads back to itself, then another
+        // pointing at the first
+        let mut looped = data.clone();
+        looped[fde_start + 4..][..4].copy_from_slice(&4u32.to_le_bytes());
+        let second = looped.len();
+        looped.extend_from_slice(&data[fde_start..]);
+        let pointer = (second + 4 - fde_start) as u32;
+        looped[second + 4..][..4].copy_from_slice(&pointer.to_le_bytes());
+        let eh_frame = EhFrame::new(ctx, &looped, Addr(0x1000));
+        for offset in [fde_start, second] {
+            assert!(matches!(
+                eh_frame.entry(offset),
+                Err(EhFrameError::NotACie(o)) if o == fde_start
+            ));
+        }
+        let entries: Vec<_> = eh_frame.entries().collect();
+        assert!(matches!(entries[..], [Ok(CfiEntry::Cie(_)), Err(_)]));
+        assert!(eh_frame.find_fde(Addr(0x1000)).is_err());
+
+        // version, udata4 pointers, one table entry
+        let mut hdr = vec![1, 0x03, 0x03, 0x03];
+        for word in [0x1000u32, 1, 0x1000, 0x1000 + fde_start as u32] {
+            hdr.extend_from_slice(&word.to_le_bytes());
+        }
+        let hdr = EhFrameHdr::parse(ctx, &hdr, Addr(0x2000)).unwrap();
+        assert!(hdr.is_searchable());
+        assert!(hdr.entry(0).is_ok());
+        assert!(matches!(
+            hdr.entry(u64::MAX),
+            Err(EhFrameError::AddressOverflow)
+        ));
+
+        // a caller can't have returned to address 0
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        let mut regs = Registers::default();
+        regs.set(Registers::RIP, Some(0));
+        let mem = |_| None;
+        assert!(matches!(
+            file.unwind_frame(0, &regs, &mem, false),
+            Err(EhFrameError::AddressOverflow)
+        ));
+    }
+
+    #[test]
+    fn evaluate_plt_expression() {
+        let ctx = parse::Ctx {
+            class: crate::Class::Elf64,
+            endianness: crate::Endianness::Little,
+        };
+        // the CFA of lazy PLT entries: rsp + 8 + ((rip & 15) >= 11 ? 8 : 0)
+        let expr = [0x77, 8, 0x80, 0, 0x3f, 0x1a, 0x3b, 0x2a, 0x33, 0x24, 0x22];
+        let mem = |_| None;
+        let mut regs = Registers::default();
+        regs.set(Registers::RSP, Some(0x1000));
+        regs.set(Registers::RIP, Some(0x2005));
+        assert_eq!(evaluate(ctx, &expr, &regs, &mem, None).unwrap(), 0x1008);
+        regs.set(Registers::RIP, Some(0x200b));
+        assert_eq!(evaluate(ctx, &expr, &regs, &mem, None).unwrap(), 0x1010);
+        assert!(matches!(
+            evaluate(ctx, &[0x22], &regs, &mem, None),
+            Err(EhFrameError::EmptyStack)
+        ));
+    }
+
+    /// Unwinds from inside this function, while the frames it walks are live
+    #[cfg(target_arch = "x86_64")]
+    #[inline(never)]
+    fn backtrace_here(file: &File<Vec<u8>>, bias: u64) -> Vec<Addr> {
+        use std::os::unix::fs::FileExt;
+
+        let (rip, rsp, rbp): (u64, u64, u64);
+        unsafe {
+            std::arch::asm!(
+                "lea {rip}, [rip]",
+                "mov {rsp}, rsp",
+                "mov {rbp}, rbp",
+                rip = out(reg) rip,
+                rsp = out(reg) rsp,
+                rbp = out(reg) rbp,
+            );
+        }
+        let mut regs = Registers::default();
+        regs.set(Registers::RIP, Some(rip));
+        regs.set(Registers::RSP, Some(rsp));
+        regs.set(Registers::RBP, Some(rbp));
+
+        let mem_file = std::fs::File::open("/proc/self/mem").unwrap();
+        let mem = |addr: Addr| {
+            let mut buf = [0; 8];
+            mem_file.read_exact_at(&mut buf, addr.0).ok()?;
+            Some(u64::from_le_bytes(buf))
+        };
+        file.backtrace(bias, regs, &mem).unwrap()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline(never)]
+    fn unwound_caller(file: &File<Vec<u8>>, bias: u64) -> Vec<Addr> {
+        std::hint::black_box(backtrace_here(file, bias))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn backtrace_self() {
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        let syms = file.read_symtab_entries().unwrap();
+        let sym = syms
+            .iter()
+            .find(|sym| {
+                let name = file.strtab_entry(sym.name);
+                name.windows(14).any(|w| w == b"unwound_caller")
+                    && name.windows(8).any(|w| w == b"eh_frame")
+            })
+            .expect("caller symbol");
+        let caller = unwound_caller as *const () as u64;
+        let bias = caller - sym.value.0;
+
+        let frames = unwound_caller(&file, bias);
+        assert!(frames.len() >= 3, "{frames:?}");
+        let range = caller..caller + sym.size;
+        assert!(range.contains(&frames[1].0), "{frames:?} vs {range:x?}");
+        let test = backtrace_self as *const () as u64;
+        assert!(frames.iter().any(|pc| pc.0 > test && pc.0 < test + 0x1000));
+    }
+}
@@ -1,7 +1,23 @@
-use num_enum::TryFromPrimitive;
+use num_enum::{FromPrimitive, TryFromPrimitive};
 
 use crate::{impl_parse_for_bitenum, impl_parse_for_enum, impl_parse_for_enumflags, parse};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Class {
+    Elf32 = 0x1,
+    Elf64 = 0x2,
+}
+impl_parse_for_enum!(Class, u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Endianness {
+    Little = 0x1,
+    Big = 0x2,
+}
+impl_parse_for_enum!(Endianness, u8);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u16)]
 pub enum Type {
@@ -11,15 +27,23 @@ pub enum Type {
     Dyn = 0x3,
     Core = 0x4,
 }
-impl_parse_for_enum!(Type, le_u16);
+impl_parse_for_enum!(Type, u16);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u16)]
 pub enum Machine {
+    Sparc = 0x02,
     X86 = 0x03,
+    Mips = 0x08,
+    PowerPC = 0x14,
+    PowerPC64 = 0x15,
+    S390 = 0x16,
+    Arm = 0x28,
     X86_64 = 0x3e,
+    AArch64 = 0xb7,
+    RiscV = 0xf3,
 }
-impl_parse_for_enum!(Machine, le_u16);
+impl_parse_for_enum!(Machine, u16);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u32)]
@@ -40,8 +64,9 @@ pub enum SegmentType {
     GnuStack = 0x6474_E551,
     GnuRelRo = 0x6474_E552,
     GnuProperty = 0x6474_E553,
+    ArmExidx = 0x7000_0001,
 }
-impl_parse_for_enum!(SegmentType, le_u32);
+impl_parse_for_enum!(SegmentType, u32);
 
 #[enumflags2::bitflags]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -52,7 +77,31 @@ pub enum SegmentFlag {
     Read = 0x4,
 }
 
-impl_parse_for_enumflags!(SegmentFlag, le_u32);
+impl_parse_for_enumflags!(SegmentFlag, u32);
+
+/// Note types, for notes owned by "GNU"
+#[derive(Debug, FromPrimitive, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum GnuNoteType {
+    AbiTag = 1,
+    Hwcap = 2,
+    BuildId = 3,
+    GoldVersion = 4,
+    PropertyType0 = 5,
+    #[num_enum(catch_all)]
+    Other(u32),
+}
+
+/// Bits of the `GNU_PROPERTY_X86_FEATURE_1_AND` property
+#[enumflags2::bitflags]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum X86Feature {
+    /// Indirect Branch Tracking
+    Ibt = 0x1,
+    /// Shadow Stack
+    Shstk = 0x2,
+}
 
 #[derive(Debug, TryFromPrimitive, PartialEq, Eq, Clone, Copy)]
 #[repr(u64)]
@@ -101,6 +150,7 @@ pub enum DynamicTag {
     GnuHash = 0x6ffffef5,
     Flags1 = 0x6ffffffb,
     RelACount = 0x6ffffff9,
+    RelCount = 0x6ffffffa,
     VerSym = 0x6ffffff0,
     VerDef = 0x6ffffffc,
     VerDefNum = 0x6ffffffd,
@@ -109,9 +159,46 @@ pub enum DynamicTag {
     X8664Reserved1 = 0x70000003,
 }
 
-impl_parse_for_enum!(DynamicTag, le_u64);
+impl_parse_for_enum!(DynamicTag, word);
 
-#[derive(Debug, TryFromPrimitive, Clone, Copy, PartialEq, Eq)]
+impl DynamicTag {
+    /// `DT_VERNEEDNUM` shares its value with `DT_HIOS`
+    pub const VER_NEED_NUM: Self = Self::HiOs;
+}
+
+/// Bits of the `DT_FLAGS` dynamic entry
+#[enumflags2::bitflags]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum DynamicFlag {
+    /// Uses `$ORIGIN` in its paths
+    Origin = 0x1,
+    /// Resolves its own symbols first
+    Symbolic = 0x2,
+    TextRel = 0x4,
+    /// Must be bound before running, as with `DT_BIND_NOW`
+    BindNow = 0x8,
+    StaticTls = 0x10,
+}
+
+/// Bits of the `DT_FLAGS_1` dynamic entry we care about
+#[enumflags2::bitflags]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum DynamicFlag1 {
+    /// Must be bound before running, as with `DT_BIND_NOW`
+    Now = 0x1,
+    Global = 0x2,
+    Group = 0x4,
+    NoDelete = 0x8,
+    NoOpen = 0x40,
+    Origin = 0x80,
+    Pie = 0x0800_0000,
+}
+
+/// x86-64 relocation types. Other machines reuse the same numbers with different meanings,
+/// anything not listed here is kept as `Other`.
+#[derive(Debug, FromPrimitive, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum RelType {
     None = 0,
@@ -127,11 +214,11 @@ pub enum RelType {
     DTPOFF64 = 17,
     TPOff64 = 18,
     IRelative = 37,
+    #[num_enum(catch_all)]
+    Other(u32),
 }
 
-impl_parse_for_enum!(RelType, le_u32);
-
-#[derive(Debug, TryFromPrimitive, Clone, Copy)]
+#[derive(Debug, TryFromPrimitive, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum SymBind {
     Local = 0,
@@ -141,7 +228,7 @@ pub enum SymBind {
 
 impl_parse_for_bitenum!(SymBind, 4_usize);
 
-#[derive(Debug, TryFromPrimitive, Clone, Copy)]
+#[derive(Debug, TryFromPrimitive, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum SymType {
     None = 0,
@@ -155,6 +242,20 @@ pub enum SymType {
 
 impl_parse_for_bitenum!(SymType, 4_usize);
 
+/// The low bits of `st_other`
+#[derive(Debug, TryFromPrimitive, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SymVisibility {
+    Default = 0,
+    Internal = 1,
+    Hidden = 2,
+    /// Visible to other objects, but references from the defining object
+    /// can't be interposed
+    Protected = 3,
+}
+
+impl_parse_for_bitenum!(SymVisibility, 2_usize);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u32)]
 pub enum SectionType {
@@ -183,7 +284,9 @@ pub enum SectionType {
     GnuVerdef = 0x6ffffffd,
     GnuVerneed = 0x6ffffffe,
     GnuVersym = 0x6fffffff,
+    /// Also `SHT_ARM_EXIDX` on 32-bit ARM
     X8664Unwind = 0x70000001,
+    ArmAttributes = 0x70000003,
 }
 
-impl_parse_for_enum!(SectionType, le_u32);
+impl_parse_for_enum!(SectionType, u32);
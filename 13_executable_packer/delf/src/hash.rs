@@ -0,0 +1,398 @@
+use crate::{DynamicTag, File, ReadSymsError, Sym, parse};
+use nom::{Parser as _, multi};
+
+/// The SysV hash function used by `DT_HASH`
+pub fn elf_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0u32, |h, &c| {
+        let h = (h << 4).wrapping_add(c as u32);
+        let g = h & 0xf000_0000;
+        (h ^ (g >> 24)) & !g
+    })
+}
+
+/// The DJB-style hash function used by `DT_GNU_HASH`
+pub fn gnu_hash(name: &[u8]) -> u32 {
+    name.iter()
+        .fold(5381u32, |h, &c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
+
+/// A `DT_HASH` table: `buckets[hash % nbucket]` is the first symbol index of a
+/// chain, `chains[index]` is the next one, 0 ends the chain.
+#[derive(Debug, Clone)]
+pub struct SysvHashTable {
+    pub buckets: Vec<u32>,
+    pub chains: Vec<u32>,
+}
+
+impl SysvHashTable {
+    pub fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, (nbucket, nchain)) = (ctx.u32(), ctx.u32()).parse(i)?;
+            let (i, (buckets, chains)) = (
+                multi::count(ctx.u32(), nbucket as usize),
+                multi::count(ctx.u32(), nchain as usize),
+            )
+                .parse(i)?;
+            Ok((i, Self { buckets, chains }))
+        }
+    }
+
+    /// Symbol indices whose name may hash to `hash`, in chain order
+    fn candidates(&self, hash: u32) -> Vec<usize> {
+        let mut res = Vec::new();
+        if self.buckets.is_empty() {
+            return res;
+        }
+        let mut index = self.buckets[hash as usize % self.buckets.len()] as usize;
+        // bounded, so that a corrupt chain can't loop forever
+        while index != 0 && res.len() < self.chains.len() {
+            res.push(index);
+            index = self.chains.get(index).copied().unwrap_or_default() as usize;
+        }
+        res
+    }
+}
+
+/// A `DT_GNU_HASH` table. Only symbols from `symoffset` onwards are hashed, they're
+/// sorted by bucket, and `chain` holds their hashes with the lowest bit marking
+/// the end of a bucket.
+#[derive(Debug, Clone)]
+pub struct GnuHashTable {
+    pub symoffset: u32,
+    pub bloom_shift: u32,
+    /// Words of the bloom filter, widened to `u64` (they're 32-bit in ELF32)
+    pub bloom: Vec<u64>,
+    pub buckets: Vec<u32>,
+    pub chain: Vec<u32>,
+}
+
+impl GnuHashTable {
+    /// Parses the table. The length of the chain isn't stored anywhere: it is
+    /// walked from the highest bucket until its last entry.
+    pub fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, (nbuckets, symoffset, bloom_size, bloom_shift)) =
+                (ctx.u32(), ctx.u32(), ctx.u32(), ctx.u32()).parse(i)?;
+            // `may_contain` shifts hashes by `bloom_shift` and picks a word
+            // modulo the bloom size
+            if bloom_shift >= 32 || bloom_size == 0 {
+                return Err(nom::Err::Failure(parse::Error::from_string(
+                    i,
+                    format!("Invalid bloom filter (size {bloom_size}, shift {bloom_shift})"),
+                )));
+            }
+            let (mut i, (bloom, buckets)) = (
+                multi::count(ctx.word(), bloom_size as usize),
+                multi::count(ctx.u32(), nbuckets as usize),
+            )
+                .parse(i)?;
+
+            let mut chain = Vec::new();
+            if let Some(&last) = buckets.iter().max()
+                && last >= symoffset
+            {
+                let (rest, skipped) =
+                    multi::count(ctx.u32(), (last - symoffset) as usize).parse(i)?;
+                chain = skipped;
+                i = rest;
+                loop {
+                    let (rest, hash) = ctx.u32()(i)?;
+                    chain.push(hash);
+                    i = rest;
+                    if hash & 1 != 0 {
+                        break;
+                    }
+                }
+            }
+
+            let res = Self {
+                symoffset,
+                bloom_shift,
+                bloom,
+                buckets,
+                chain,
+            };
+            Ok((i, res))
+        }
+    }
+
+    /// Checks the bloom filter: `false` means the symbol is definitely absent
+    fn may_contain(&self, hash: u32, word_bits: u32) -> bool {
+        if self.bloom.is_empty() {
+            return true;
+        }
+        let word = self.bloom[(hash / word_bits) as usize % self.bloom.len()];
+        let mask =
+            (1u64 << (hash % word_bits)) | (1u64 << ((hash >> self.bloom_shift) % word_bits));
+        word & mask == mask
+    }
+
+    /// Symbol indices whose hash matches `hash`, in chain order
+    fn candidates(&self, hash: u32, word_bits: u32) -> Vec<usize> {
+        let mut res = Vec::new();
+        if self.buckets.is_empty() || !self.may_contain(hash, word_bits) {
+            return res;
+        }
+        let mut index = self.buckets[hash as usize % self.buckets.len()] as usize;
+        if index < self.symoffset as usize {
+            return res;
+        }
+        while let Some(&entry) = self.chain.get(index - self.symoffset as usize) {
+            if (entry | 1) == (hash | 1) {
+                res.push(index);
+            }
+            if entry & 1 != 0 {
+                break;
+            }
+            index += 1;
+        }
+        res
+    }
+}
+
+/// The hash table used to look up dynamic symbols
+#[derive(Debug, Clone)]
+pub enum HashTable {
+    Gnu(GnuHashTable),
+    Sysv(SysvHashTable),
+}
+
+/// Looks up defined dynamic symbols by name, using `DT_GNU_HASH` if available,
+/// then `DT_HASH`, and falling back to a linear scan for objects that have neither.
+pub struct SymbolLookup<'a, I>
+where
+    I: AsRef<[u8]>,
+{
+    file: &'a File<I>,
+    syms: Vec<Sym>,
+    table: Option<HashTable>,
+    /// Contents of `.dynstr`, kept around to avoid looking the section up each time
+    strtab: &'a [u8],
+}
+
+impl<'a, I> SymbolLookup<'a, I>
+where
+    I: AsRef<[u8]>,
+{
+    /// The dynamic symbols, as returned by `read_dynsym_entries`
+    pub fn syms(&self) -> &[Sym] {
+        &self.syms
+    }
+
+    /// The hash table in use, if any
+    pub fn table(&self) -> Option<&HashTable> {
+        self.table.as_ref()
+    }
+
+    /// Returns the first defined symbol named `name`, along with its index
+    pub fn lookup(&self, name: &[u8]) -> Option<(usize, &Sym)> {
+        self.lookup_all(name).next()
+    }
+
+    /// Returns every defined symbol named `name`, e.g. all versions of it
+    pub fn lookup_all<'s>(&'s self, name: &[u8]) -> impl Iterator<Item = (usize, &'s Sym)> {
+        let candidates: Vec<usize> = match &self.table {
+            Some(HashTable::Gnu(table)) => {
+                let word_bits = self.file.ctx().word_size() as u32 * 8;
+                table.candidates(gnu_hash(name), word_bits)
+            }
+            Some(HashTable::Sysv(table)) => table.candidates(elf_hash(name)),
+            None => (0..self.syms.len()).collect(),
+        };
+        candidates.into_iter().filter_map(move |index| {
+            let sym = self.syms.get(index)?;
+            let sym_name = self
+                .strtab
+                .get(sym.name.into()..)?
+                .split(|&c| c == 0)
+                .next()?;
+            (!sym.shndx.is_undef() && sym_name == name).then_some((index, sym))
+        })
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Read the table pointed to by `DT_HASH`, if any
+    pub fn read_hash_table(&self) -> Result<Option<SysvHashTable>, ReadHashError> {
+        self.read_hash(DynamicTag::Hash, SysvHashTable::parse)
+    }
+
+    /// Read the table pointed to by `DT_GNU_HASH`, if any
+    pub fn read_gnu_hash_table(&self) -> Result<Option<GnuHashTable>, ReadHashError> {
+        self.read_hash(DynamicTag::GnuHash, GnuHashTable::parse)
+    }
+
+    fn read_hash<'a, T, P>(
+        &'a self,
+        tag: DynamicTag,
+        parser: fn(parse::Ctx) -> P,
+    ) -> Result<Option<T>, ReadHashError>
+    where
+        P: Fn(parse::Input<'a>) -> parse::Result<'a, T>,
+    {
+        let Some(addr) = self.dynamic_entry(tag) else {
+            return Ok(None);
+        };
+        let i = self
+            .mem_slice_from(addr)
+            .ok_or(ReadHashError::SegmentNotFound(tag))?;
+        match parser(self.ctx())(i) {
+            Ok((_, table)) => Ok(Some(table)),
+            Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                Err(ReadHashError::ParsingError(format!("{err:?}")))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Prepare O(1) lookups of dynamic symbols by name
+    pub fn symbol_lookup(&self) -> Result<SymbolLookup<'_, I>, ReadHashError> {
+        let table = match self.read_gnu_hash_table()? {
+            Some(table) => Some(HashTable::Gnu(table)),
+            None => self.read_hash_table()?.map(HashTable::Sysv),
+        };
+        let strtab = self
+            .section_by_name(b".dynstr")
+            .map(|section| self.section_slice(section))
+            .unwrap_or_default();
+        Ok(SymbolLookup {
+            file: self,
+            syms: self.read_dynsym_entries()?,
+            table,
+            strtab,
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadHashError {
+    #[error("{0}")]
+    ReadSyms(#[from] ReadSymsError),
+    #[error("{0:?} segment not found")]
+    SegmentNotFound(DynamicTag),
+    #[error("Parsing error: {0}")]
+    ParsingError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn hash_functions() {
+        assert_eq!(elf_hash(b""), 0);
+        assert_eq!(elf_hash(b"printf"), 0x077905a6);
+        assert_eq!(gnu_hash(b""), 0x00001505);
+        assert_eq!(gnu_hash(b"printf"), 0x156b2bb8);
+    }
+
+    #[test]
+    fn lookup_agrees_with_linear_scan() {
+        let input = std::fs::read(crate::test_util::libc_path()).unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        let gnu = file.symbol_lookup().unwrap();
+        assert!(matches!(gnu.table(), Some(HashTable::Gnu(_))));
+
+        let mut lookups = vec![gnu];
+        if let Some(table) = file.read_hash_table().unwrap() {
+            let mut sysv = file.symbol_lookup().unwrap();
+            sysv.table = Some(HashTable::Sysv(table));
+            lookups.push(sysv);
+        }
+
+        let syms = file.read_dynsym_entries().unwrap();
+        let mut linear: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        for (index, sym) in syms.iter().enumerate() {
+            if !sym.shndx.is_undef() {
+                linear
+                    .entry(file.dynstr_entry(sym.name))
+                    .or_default()
+                    .push(index);
+            }
+        }
+        let mut checked = 0;
+        for sym in &syms {
+            let name = file.dynstr_entry(sym.name);
+            if name.is_empty() {
+                continue;
+            }
+            let expected = linear.get(name).cloned().unwrap_or_default();
+            for lookup in &lookups {
+                // chains aren't necessarily in index order, so several versions
+                // of a symbol may come out in any order
+                let mut found: Vec<_> = lookup.lookup_all(name).map(|(i, _)| i).collect();
+                found.sort();
+                assert_eq!(
+                    found,
+                    expected,
+                    "{:?} with {:?}",
+                    String::from_utf8_lossy(name),
+                    lookup.table().map(std::mem::discriminant),
+                );
+                assert_eq!(lookup.lookup(name).is_some(), !expected.is_empty());
+            }
+            checked += 1;
+        }
+        assert!(checked > 1000);
+
+        for lookup in &lookups {
+            assert!(lookup.lookup(b"definitely_not_in_libc").is_none());
+            assert_eq!(lookup.lookup_all(b"memcpy").count(), 2);
+        }
+    }
+
+    #[test]
+    fn reject_bad_bloom_filter() {
+        let ctx = parse::Ctx {
+            class: crate::Class::Elf64,
+            endianness: crate::Endianness::Little,
+        };
+        // one bucket, symoffset 1, then the bloom size and shift, one bloom
+        // word, the bucket and a one-entry chain
+        let table = |bloom_size: u32, bloom_shift: u32| {
+            let mut input = Vec::new();
+            for word in [1, 1, bloom_size, bloom_shift] {
+                input.extend_from_slice(&u32::to_le_bytes(word));
+            }
+            input.extend_from_slice(&u64::MAX.to_le_bytes());
+            input.extend_from_slice(&1u32.to_le_bytes());
+            input.extend_from_slice(&(gnu_hash(b"f") | 1).to_le_bytes());
+            input
+        };
+
+        let input = table(1, 6);
+        let (_, parsed) = GnuHashTable::parse(ctx)(&input).unwrap();
+        assert_eq!(parsed.candidates(gnu_hash(b"f"), 64), vec![1]);
+
+        for (size, shift) in [(1, 32), (1, u32::MAX), (0, 6)] {
+            let input = table(size, shift);
+            assert!(
+                GnuHashTable::parse(ctx)(&input).is_err(),
+                "size {size}, shift {shift}"
+            );
+        }
+    }
+
+    #[test]
+    fn lookup_without_hash_table() {
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        let mut lookup = file.symbol_lookup().unwrap();
+        lookup.table = None;
+
+        for (index, sym) in lookup.syms().iter().enumerate() {
+            let name = file.dynstr_entry(sym.name);
+            if sym.shndx.is_undef() || name.is_empty() {
+                continue;
+            }
+            assert_eq!(lookup.lookup(name).map(|(i, _)| i), Some(index));
+        }
+        // undefined symbols aren't returned
+        assert!(lookup.lookup(b"malloc").is_none());
+    }
+}
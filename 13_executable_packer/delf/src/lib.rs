@@ -1,16 +1,28 @@
 mod addr;
+mod coredump;
+mod dwarf;
+mod eh_frame;
 mod enums;
+mod hash;
+mod note;
 mod parse;
 mod program_header;
+mod relocatable;
 mod sym;
+#[cfg(test)]
+mod test_util;
+mod validate;
+mod version;
+mod write;
 
 use std::ops::Range;
 
-pub use crate::{addr::*, enums::*, program_header::*, sym::*};
-use nom::{
-    Parser as _, branch, combinator, multi,
-    number::complete::{le_u16, le_u32, le_u64},
+pub use crate::{
+    addr::*, coredump::*, dwarf::*, eh_frame::*, enums::*, hash::*, note::*, parse::Ctx,
+    program_header::*, relocatable::*, sym::*, version::*, write::*,
 };
+use enumflags2::BitFlags;
+use nom::{Parser as _, branch, combinator, multi};
 
 #[derive(Debug)]
 pub struct File<I>
@@ -35,15 +47,14 @@ impl<I> File<I>
 where
     I: AsRef<[u8]>,
 {
-    /// Decode a DT_RELR / Elf64_Relr table (raw `u64` words) into relocation offsets (virtual
-    /// addresses).
-    fn decode_relr_vaddrs(relr: &[u64]) -> Vec<Addr> {
+    /// Decode a DT_RELR / Elf{32,64}_Relr table (raw words, widened to `u64`) into relocation
+    /// offsets (virtual addresses).
+    fn decode_relr_vaddrs(relr: &[u64], word_size: u64) -> Vec<Addr> {
         // Format per DT_RELR / Elf64_Relr:
         // - Even entries: an address A, relocate A, then advance by word size.
         // - Odd entries: a bitmap for the next (word_bits-1) addresses starting at `where`.
-        const WORD_SIZE: u64 = 8;
-        const BITS_PER_WORD: u64 = 64;
-        const BITMAP_BITS: u64 = BITS_PER_WORD - 1;
+        let bits_per_word = word_size * 8;
+        let bitmap_bits = bits_per_word - 1;
 
         let mut out = Vec::new();
         let mut where_addr: u64 = 0;
@@ -52,17 +63,17 @@ where
             if (entry & 1) == 0 {
                 where_addr = entry;
                 out.push(Addr(where_addr));
-                where_addr = where_addr.wrapping_add(WORD_SIZE);
+                where_addr = where_addr.wrapping_add(word_size);
             } else {
-                // Bits 1..63 correspond to relocations at where_addr + i*WORD_SIZE (i=0..62)
+                // Bits 1..63 correspond to relocations at where_addr + i*word_size (i=0..62)
                 let bitmap = entry >> 1;
-                for i in 0..BITMAP_BITS {
+                for i in 0..bitmap_bits {
                     if (bitmap & (1u64 << i)) != 0 {
-                        let a = where_addr.wrapping_add(i.wrapping_mul(WORD_SIZE));
+                        let a = where_addr.wrapping_add(i.wrapping_mul(word_size));
                         out.push(Addr(a));
                     }
                 }
-                where_addr = where_addr.wrapping_add(BITMAP_BITS.wrapping_mul(WORD_SIZE));
+                where_addr = where_addr.wrapping_add(bitmap_bits.wrapping_mul(word_size));
             }
         }
 
@@ -86,9 +97,13 @@ where
         }
     }
 
-    /// Returns a slice of the input, indexed by file offsets
-    pub fn file_slice(&self, addr: Addr, len: usize) -> &[u8] {
-        &self.input.as_ref()[addr.into()..len]
+    /// Returns a slice of the input, indexed by file offsets (from `addr` to `end`).
+    /// Out-of-bounds ranges yield an empty slice, `validate` reports them.
+    pub fn file_slice(&self, addr: Addr, end: usize) -> &[u8] {
+        self.input
+            .as_ref()
+            .get(addr.into()..end)
+            .unwrap_or_default()
     }
 
     /// Returns a slice of the input corresponding to the given section
@@ -105,9 +120,18 @@ where
 
     /// Returns a slice of the input, indexed by virtual addresses
     pub fn mem_slice(&self, addr: Addr, len: usize) -> Option<&[u8]> {
-        self.segment_containing(addr).map(|segment| {
+        self.segment_containing(addr).and_then(|segment| {
             let start: usize = (addr - segment.mem_range().start).into();
-            &self.segment_slice(segment)[start..start + len]
+            self.segment_slice(segment)
+                .get(start..start.checked_add(len)?)
+        })
+    }
+
+    /// Returns the rest of the file-backed part of a segment, starting at a virtual address
+    pub fn mem_slice_from(&self, addr: Addr) -> Option<&[u8]> {
+        self.segment_containing(addr).and_then(|segment| {
+            let start: usize = (addr - segment.mem_range().start).into();
+            self.segment_slice(segment).get(start..)
         })
     }
 
@@ -123,9 +147,34 @@ where
         self.read_relocations(DynamicTag::Rela, DynamicTag::RelaSz)
     }
 
-    /// Read relocation entries from the table pointed to by `DynamicTag::JmpRel`
+    /// Read relocation entries from the table pointed to by `DynamicTag::Rel`
+    pub fn read_rel_entries(&self) -> Result<Vec<Rel>, ReadRelaError> {
+        self.read_table(
+            DynamicTag::Rel,
+            DynamicTag::RelSz,
+            Rel::size(self.class),
+            Rel::parse,
+        )
+    }
+
+    /// Read relocation entries from the table pointed to by `DynamicTag::JmpRel`.
+    ///
+    /// When `DynamicTag::PltRel` says the table is REL-style (i386, 32-bit ARM), entries are
+    /// widened to `Rela` with a zero addend: the real addend lives at the relocated location.
     pub fn read_jmp_rel_entries(&self) -> Result<Vec<Rela>, ReadRelaError> {
-        self.read_relocations(DynamicTag::JmpRel, DynamicTag::PltRelSz)
+        match self.dynamic_entry(DynamicTag::PltRel) {
+            Some(Addr(kind)) if kind == DynamicTag::Rel as u64 => Ok(self
+                .read_table(
+                    DynamicTag::JmpRel,
+                    DynamicTag::PltRelSz,
+                    Rel::size(self.class),
+                    Rel::parse,
+                )?
+                .into_iter()
+                .map(Rela::from)
+                .collect()),
+            _ => self.read_relocations(DynamicTag::JmpRel, DynamicTag::PltRelSz),
+        }
     }
 
     /// Read a dynamic table referenced by `(addr_tag, size_tag)` and return its bytes.
@@ -146,10 +195,10 @@ where
         Ok(Some(bytes))
     }
 
-    /// Read compressed relative relocations (DT_RELR / Elf64_Relr).
+    /// Read compressed relative relocations (DT_RELR / Elf{32,64}_Relr).
     ///
-    /// Returns the raw `u64` entries of the RELR table. The caller is responsible for
-    /// interpreting the bitmap encoding.
+    /// Returns the raw entries of the RELR table, widened to `u64`. The caller is responsible
+    /// for interpreting the bitmap encoding.
     pub fn read_relr_entries(&self) -> Result<Vec<u64>, ReadRelaError> {
         use ReadRelaError as E;
 
@@ -160,16 +209,18 @@ where
             return Ok(Vec::new());
         };
 
-        if bytes.len() % 8 != 0 {
+        let ctx = self.ctx();
+        let word_size = ctx.word_size();
+        if bytes.len() % word_size != 0 {
             return Err(E::ParsingError(format!(
-                "DT_RELR size is not a multiple of 8 (got {})",
+                "DT_RELR size is not a multiple of {word_size} (got {})",
                 bytes.len()
             )));
         }
 
         Ok(bytes
-            .chunks_exact(8)
-            .map(|c| u64::from_le_bytes(c.try_into().expect("chunk size is 8")))
+            .chunks_exact(word_size)
+            .map(|c| ctx.read_word(c))
             .collect())
     }
 
@@ -181,18 +232,25 @@ where
     /// offsets.
     pub fn read_relr_vaddrs(&self) -> Result<Vec<Addr>, ReadRelaError> {
         let raw = self.read_relr_entries()?;
-        Ok(Self::decode_relr_vaddrs(&raw))
+        Ok(Self::decode_relr_vaddrs(
+            &raw,
+            self.ctx().word_size() as u64,
+        ))
     }
 
-    /// Read symbols from the given section (internal)
+    /// Read symbols from the first section of the given type (internal)
     fn read_symbol_table(&self, section_type: SectionType) -> Result<Vec<Sym>, ReadSymsError> {
-        let Some(section) = self.section_of_type(section_type) else {
-            return Ok(Vec::new());
-        };
+        match self.section_of_type(section_type) {
+            Some(section) => self.read_symbols(section),
+            None => Ok(Vec::new()),
+        }
+    }
 
+    /// Read symbols from a given `SHT_SYMTAB` or `SHT_DYNSYM` section
+    pub fn read_symbols(&self, section: &SectionHeader) -> Result<Vec<Sym>, ReadSymsError> {
         let i = self.section_slice(section);
-        let n = i.len() / section.entsize.0 as usize;
-        match multi::many_m_n(n, n, Sym::parse).parse(i) {
+        let n = i.len() / (section.entsize.0 as usize).max(1);
+        match multi::many_m_n(n, n, Sym::parse(self.ctx())).parse(i) {
             Ok((_, syms)) => Ok(syms),
             Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
                 Err(ReadSymsError::ParsingError(format!("{err:?}")))
@@ -213,9 +271,27 @@ where
 
     // Returns a null-terminated "string" from the ".shstrtab" section as an u8 slice
     pub fn shstrtab_entry(&self, offset: Addr) -> &[u8] {
-        let section = &self.contents.section_headers[self.contents.shstrndx];
-        let slice = &self.section_slice(section)[offset.into()..];
-        slice.split(|&c| c == 0).next().unwrap_or_default()
+        self.contents
+            .section_headers
+            .get(self.contents.shstrndx)
+            .and_then(|section| self.section_slice(section).get(offset.into()..))
+            .and_then(|slice| slice.split(|&c| c == 0).next())
+            .unwrap_or_default()
+    }
+
+    /// Returns the name of a section
+    pub fn section_name(&self, section: &SectionHeader) -> &[u8] {
+        self.shstrtab_entry(section.name)
+    }
+
+    /// Returns the name of a symbol from `symtab`, looked up in the string
+    /// table linked to it (`sh_link`)
+    pub fn symbol_name(&self, symtab: &SectionHeader, sym: &Sym) -> &[u8] {
+        self.section_headers
+            .get(symtab.link as usize)
+            .and_then(|strtab| self.section_slice(strtab).get(sym.name.into()..))
+            .and_then(|slice| slice.split(|&c| c == 0).next())
+            .unwrap_or_default()
     }
 
     /// Get a section by name
@@ -228,10 +304,8 @@ where
     /// Returns an entry from a string table contained in the section with a given name
     fn string_table_entry(&self, name: &[u8], offset: Addr) -> &[u8] {
         self.section_by_name(name)
-            .map(|section| {
-                let slice = &self.section_slice(section)[offset.into()..];
-                slice.split(|&c| c == 0).next().unwrap_or_default()
-            })
+            .and_then(|section| self.section_slice(section).get(offset.into()..))
+            .and_then(|slice| slice.split(|&c| c == 0).next())
             .unwrap_or_default()
     }
 
@@ -250,16 +324,30 @@ where
         addr_tag: DynamicTag,
         size_tag: DynamicTag,
     ) -> Result<Vec<Rela>, ReadRelaError> {
+        self.read_table(addr_tag, size_tag, Rela::size(self.class), Rela::parse)
+    }
+
+    /// Parse every entry of a dynamic table referenced by `(addr_tag, size_tag)`
+    fn read_table<'a, T, P>(
+        &'a self,
+        addr_tag: DynamicTag,
+        size_tag: DynamicTag,
+        entry_size: usize,
+        parser: fn(Ctx) -> P,
+    ) -> Result<Vec<T>, ReadRelaError>
+    where
+        P: Fn(parse::Input<'a>) -> parse::Result<'a, T>,
+    {
         use ReadRelaError as E;
 
         let Some(i) = self.dynamic_table_slice(addr_tag, size_tag, || E::RelaSegmentNotFound)?
         else {
             return Ok(Vec::new());
         };
-        let n = i.len() / Rela::SIZE;
+        let n = i.len() / entry_size;
 
-        match multi::many_m_n(n, n, Rela::parse).parse(i) {
-            Ok((_, rela_entires)) => Ok(rela_entires),
+        match multi::many_m_n(n, n, parser(self.ctx())).parse(i) {
+            Ok((_, entries)) => Ok(entries),
             Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
                 Err(E::ParsingError(format!("{err:?}")))
             }
@@ -274,9 +362,16 @@ where
 
 #[derive(Debug)]
 pub struct FileContents {
+    pub class: Class,
+    pub endianness: Endianness,
+    pub os_abi: u8,
+    pub abi_version: u8,
     pub r#type: Type,
     pub machine: Machine,
     pub entry_point: Addr,
+    pub flags: u32,
+    pub ph_offset: Addr,
+    pub sh_offset: Addr,
     pub program_headers: Vec<ProgramHeader>,
     pub section_headers: Vec<SectionHeader>,
     pub shstrndx: usize,
@@ -292,55 +387,78 @@ impl FileContents {
         };
         let full_input = i;
 
-        let (i, _) = (
+        let (i, (_, class, endianness)) = (
             context("Magic", tag(Self::MAGIC)),
-            context("Class", tag([0x2].as_slice())),
-            context("Endianness", tag([0x1].as_slice())),
+            context("Class", Class::parse(Ctx::IDENT)),
+            context("Endianness", Endianness::parse(Ctx::IDENT)),
+        )
+            .parse(i)?;
+        let ctx = Ctx { class, endianness };
+
+        let (i, (_, os_abi, abi_version, _)) = (
             context("Version", tag([0x1].as_slice())),
             context(
                 "OS ABI",
                 branch::alt((tag([0x0].as_slice()), tag([0x3].as_slice()))),
             ),
-            context("ABI Version", take(1usize)),
+            context("ABI Version", ctx.u8()),
             context("Padding", take(7_usize)),
         )
             .parse(i)?;
 
-        let (i, (r#type, machine)) = (Type::parse, Machine::parse).parse(i)?;
-        let (i, _) = context("Version (bis)", combinator::verify(le_u32, |&x| x == 1)).parse(i)?;
-        let (i, entry_point) = Addr::parse(i)?;
+        let (i, (r#type, machine)) = (Type::parse(ctx), Machine::parse(ctx)).parse(i)?;
+        let (i, _) =
+            context("Version (bis)", combinator::verify(ctx.u32(), |&x| x == 1)).parse(i)?;
+        let (i, entry_point) = Addr::parse(ctx)(i)?;
 
-        let u16_usize = || combinator::map(le_u16, |x| x as usize);
+        let u16_usize = || combinator::map(ctx.u16(), |x| x as usize);
 
         // ph = program header, sh = section header
-        let (i, (ph_offset, sh_offset)) = (Addr::parse, Addr::parse).parse(i)?;
-        let (i, (_flags, _hdr_size)) = (le_u32, le_u16).parse(i)?;
+        let (i, (ph_offset, sh_offset)) = (Addr::parse(ctx), Addr::parse(ctx)).parse(i)?;
+        let (i, (flags, _hdr_size)) = (ctx.u32(), ctx.u16()).parse(i)?;
         let (i, (ph_entsize, ph_count)) = (u16_usize(), u16_usize()).parse(i)?;
         let (i, (sh_entsize, sh_count, sh_nidx)) =
             (u16_usize(), u16_usize(), u16_usize()).parse(i)?;
 
-        let ph_slices = (full_input[ph_offset.into()..]).chunks(ph_entsize);
+        let table = |offset: Addr, count: usize, what: &'static str| match count {
+            0 => Ok(&full_input[..0]),
+            _ => full_input.get(offset.into()..).ok_or_else(|| {
+                nom::Err::Failure(parse::Error::from_string(
+                    full_input,
+                    format!("{what} table starts past the end of the file ({offset:?})"),
+                ))
+            }),
+        };
+
+        let ph_slices = table(ph_offset, ph_count, "Program header")?.chunks(ph_entsize.max(1));
         let program_headers = ph_slices
             .take(ph_count)
             .map(|ph_slice| {
-                let (_, ph) = ProgramHeader::parse(full_input, ph_slice)?;
+                let (_, ph) = ProgramHeader::parse(full_input, ctx)(ph_slice)?;
                 Ok(ph)
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        let sh_slices = (full_input[sh_offset.into()..]).chunks(sh_entsize);
+        let sh_slices = table(sh_offset, sh_count, "Section header")?.chunks(sh_entsize.max(1));
         let section_headers = sh_slices
             .take(sh_count)
             .map(|sh_slice| {
-                let (_, sh) = SectionHeader::parse(sh_slice)?;
+                let (_, sh) = SectionHeader::parse(ctx)(sh_slice)?;
                 Ok(sh)
             })
             .collect::<Result<Vec<_>, _>>()?;
 
         let res = Self {
+            class,
+            endianness,
+            os_abi: os_abi[0],
+            abi_version,
             machine,
             r#type,
             entry_point,
+            flags,
+            ph_offset,
+            sh_offset,
             program_headers,
             section_headers,
             shstrndx: sh_nidx as _,
@@ -348,6 +466,22 @@ impl FileContents {
         Ok((i, res))
     }
 
+    /// Size of the ELF file header
+    pub fn header_size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 52,
+            Class::Elf64 => 64,
+        }
+    }
+
+    /// Returns the parsing context (word size and byte order) detected from the header
+    pub fn ctx(&self) -> Ctx {
+        Ctx {
+            class: self.class,
+            endianness: self.endianness,
+        }
+    }
+
     /// Returns the first segment of a given type
     pub fn segment_of_type(&self, r#type: SegmentType) -> Option<&ProgramHeader> {
         self.program_headers.iter().find(|ph| ph.r#type == r#type)
@@ -365,6 +499,25 @@ impl FileContents {
             .find(|ph| ph.r#type == SegmentType::Load && ph.mem_range().contains(&addr))
     }
 
+    /// Translates a virtual address into a file offset, if it is backed by a Load segment
+    pub fn file_offset(&self, addr: Addr) -> Option<Addr> {
+        self.segment_containing(addr)
+            .filter(|ph| addr < ph.vaddr + ph.filesz)
+            .map(|ph| addr - ph.vaddr + ph.offset)
+    }
+
+    /// Attempts to find the Dynamic segment and return its entries as a mutable vector,
+    /// e.g. to patch them before writing the file back
+    pub fn dynamic_table_mut(&mut self) -> Option<&mut Vec<DynamicEntry>> {
+        self.program_headers
+            .iter_mut()
+            .find(|ph| ph.r#type == SegmentType::Dynamic)
+            .and_then(|ph| match &mut ph.contents {
+                SegmentContents::Dynamic(entries) => Some(entries),
+                SegmentContents::Unknown => None,
+            })
+    }
+
     /// Attempts to find the Dynamic segment and return its entries as a slice
     pub fn dynamic_table(&self) -> Option<&[DynamicEntry]> {
         match self.segment_of_type(SegmentType::Dynamic) {
@@ -391,6 +544,35 @@ impl FileContents {
         self.dynamic_entries(tag).next()
     }
 
+    /// Returns the known bits of `DT_FLAGS`
+    pub fn dynamic_flags(&self) -> BitFlags<DynamicFlag> {
+        self.dynamic_entry(DynamicTag::Flags)
+            .map(|Addr(bits)| BitFlags::from_bits_truncate(bits))
+            .unwrap_or_default()
+    }
+
+    /// Returns the known bits of `DT_FLAGS_1`
+    pub fn dynamic_flags_1(&self) -> BitFlags<DynamicFlag1> {
+        self.dynamic_entry(DynamicTag::Flags1)
+            .map(|Addr(bits)| BitFlags::from_bits_truncate(bits))
+            .unwrap_or_default()
+    }
+
+    /// Whether the object asks for all its symbols to be bound at load time,
+    /// through `DT_BIND_NOW`, `DF_BIND_NOW` or `DF_1_NOW`
+    pub fn binds_now(&self) -> bool {
+        self.dynamic_entry(DynamicTag::BindNow).is_some()
+            || self.dynamic_flags().contains(DynamicFlag::BindNow)
+            || self.dynamic_flags_1().contains(DynamicFlag1::Now)
+    }
+
+    /// Whether the object resolves its own references first (`DT_SYMBOLIC` or
+    /// `DF_SYMBOLIC`), as linking with `-Bsymbolic` asks for
+    pub fn is_symbolic(&self) -> bool {
+        self.dynamic_entry(DynamicTag::Symbolic).is_some()
+            || self.dynamic_flags().contains(DynamicFlag::Symbolic)
+    }
+
     /// Returns the value of the first dynamic entry with the given tag, or an error
     pub fn get_dynamic_entry(&self, tag: DynamicTag) -> Result<Addr, GetDynamicEntryError> {
         self.dynamic_entry(tag)
@@ -398,16 +580,26 @@ impl FileContents {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DynamicEntry {
     pub tag: DynamicTag,
     pub addr: Addr,
 }
 
 impl DynamicEntry {
-    fn parse(i: parse::Input) -> parse::Result<Self> {
-        let (i, (tag, addr)) = (DynamicTag::parse, Addr::parse).parse(i)?;
-        Ok((i, Self { tag, addr }))
+    /// Size of an `Elf32_Dyn` / `Elf64_Dyn` entry
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 8,
+            Class::Elf64 => 16,
+        }
+    }
+
+    fn parse<'a>(ctx: Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, (tag, addr)) = (DynamicTag::parse(ctx), Addr::parse(ctx)).parse(i)?;
+            Ok((i, Self { tag, addr }))
+        }
     }
 }
 
@@ -426,18 +618,81 @@ pub struct Rela {
 }
 
 impl Rela {
-    pub const SIZE: usize = 24;
-    pub fn parse(i: parse::Input) -> parse::Result<Self> {
-        combinator::map(
-            (Addr::parse, RelType::parse, le_u32, Addr::parse),
-            |(offset, r#type, sym, addend)| Rela {
-                offset,
-                r#type,
-                sym,
-                addend,
-            },
-        )
-        .parse(i)
+    pub fn parse<'a>(ctx: Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            combinator::map(
+                (Addr::parse(ctx), ctx.word(), ctx.sword()),
+                |(offset, info, addend)| {
+                    let (sym, r#type) = split_rel_info(ctx.class, info);
+                    Rela {
+                        offset,
+                        r#type,
+                        sym,
+                        addend: Addr(addend as u64),
+                    }
+                },
+            )
+            .parse(i)
+        }
+    }
+
+    /// Size of an `Elf32_Rela` / `Elf64_Rela` entry
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 12,
+            Class::Elf64 => 24,
+        }
+    }
+}
+
+impl From<Rel> for Rela {
+    fn from(rel: Rel) -> Self {
+        Self {
+            offset: rel.offset,
+            r#type: rel.r#type,
+            sym: rel.sym,
+            addend: Addr(0),
+        }
+    }
+}
+
+/// A relocation without an explicit addend: the addend is stored at the relocated location
+#[derive(Debug)]
+pub struct Rel {
+    pub offset: Addr,
+    pub r#type: RelType,
+    pub sym: u32,
+}
+
+impl Rel {
+    pub fn parse<'a>(ctx: Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            combinator::map((Addr::parse(ctx), ctx.word()), |(offset, info)| {
+                let (sym, r#type) = split_rel_info(ctx.class, info);
+                Rel {
+                    offset,
+                    r#type,
+                    sym,
+                }
+            })
+            .parse(i)
+        }
+    }
+
+    /// Size of an `Elf32_Rel` / `Elf64_Rel` entry
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 8,
+            Class::Elf64 => 16,
+        }
+    }
+}
+
+/// Splits `r_info` into (symbol index, relocation type) per `ELF32_R_*` / `ELF64_R_*`
+fn split_rel_info(class: Class, info: u64) -> (u32, RelType) {
+    match class {
+        Class::Elf32 => ((info >> 8) as u32, RelType::from((info & 0xff) as u32)),
+        Class::Elf64 => ((info >> 32) as u32, RelType::from(info as u32)),
     }
 }
 
@@ -463,7 +718,7 @@ pub enum GetSringErorr {
     StringNotFound,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SectionHeader {
     pub name: Addr,
     pub r#type: SectionType,
@@ -478,41 +733,51 @@ pub struct SectionHeader {
 }
 
 impl SectionHeader {
-    pub fn parse(i: parse::Input) -> parse::Result<Self> {
-        let (i, (name, r#type, flags, addr, offset, size, link, info, addralign, entsize)) = (
-            combinator::map(le_u32, |x| Addr(x as u64)),
-            SectionType::parse,
-            le_u64,
-            Addr::parse,
-            Addr::parse,
-            Addr::parse,
-            le_u32,
-            le_u32,
-            Addr::parse,
-            Addr::parse,
-        )
-            .parse(i)?;
-        let res = Self {
-            name,
-            r#type,
-            flags,
-            addr,
-            offset,
-            size,
-            link,
-            info,
-            addralign,
-            entsize,
-        };
-        Ok((i, res))
+    pub fn parse<'a>(ctx: Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, (name, r#type, flags, addr, offset, size, link, info, addralign, entsize)) = (
+                combinator::map(ctx.u32(), |x| Addr(x as u64)),
+                SectionType::parse(ctx),
+                ctx.word(),
+                Addr::parse(ctx),
+                Addr::parse(ctx),
+                Addr::parse(ctx),
+                ctx.u32(),
+                ctx.u32(),
+                Addr::parse(ctx),
+                Addr::parse(ctx),
+            )
+                .parse(i)?;
+            let res = Self {
+                name,
+                r#type,
+                flags,
+                addr,
+                offset,
+                size,
+                link,
+                info,
+                addralign,
+                entsize,
+            };
+            Ok((i, res))
+        }
+    }
+
+    /// Size of an `Elf32_Shdr` / `Elf64_Shdr` entry
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 40,
+            Class::Elf64 => 64,
+        }
     }
 
     pub fn file_range(&self) -> Range<Addr> {
-        self.offset..self.offset + self.size
+        self.offset..Addr(self.offset.0.saturating_add(self.size.0))
     }
 
     pub fn mem_range(&self) -> Range<Addr> {
-        self.addr..self.addr + self.size
+        self.addr..Addr(self.addr.0.saturating_add(self.size.0))
     }
 }
 
@@ -532,6 +797,7 @@ mod tests {
     use enumflags2::BitFlags;
 
     use super::*;
+    use crate::test_util;
 
     #[test]
     fn type_to_u16() {
@@ -559,4 +825,185 @@ mod tests {
         assert_eq!(flags.bits(), flags_integer);
         assert!(BitFlags::<SegmentFlag>::from_bits(1992).is_err());
     }
+
+    /// Builds a tiny ELF image: header, one PT_LOAD, one PT_DYNAMIC with a `DT_NEEDED` entry,
+    /// and a null + `.shstrtab` section header.
+    pub(crate) fn tiny_elf(ctx: Ctx) -> Vec<u8> {
+        let mut out = Vec::new();
+        let half = |out: &mut Vec<u8>, x: u16| match ctx.endianness {
+            Endianness::Little => out.extend(x.to_le_bytes()),
+            Endianness::Big => out.extend(x.to_be_bytes()),
+        };
+        let word32 = |out: &mut Vec<u8>, x: u32| match ctx.endianness {
+            Endianness::Little => out.extend(x.to_le_bytes()),
+            Endianness::Big => out.extend(x.to_be_bytes()),
+        };
+        let word = |out: &mut Vec<u8>, x: u64| match (ctx.class, ctx.endianness) {
+            (Class::Elf32, Endianness::Little) => out.extend((x as u32).to_le_bytes()),
+            (Class::Elf32, Endianness::Big) => out.extend((x as u32).to_be_bytes()),
+            (Class::Elf64, Endianness::Little) => out.extend(x.to_le_bytes()),
+            (Class::Elf64, Endianness::Big) => out.extend(x.to_be_bytes()),
+        };
+        let ws = ctx.word_size() as u64;
+        let (eh_size, ph_size, sh_size) = match ctx.class {
+            Class::Elf32 => (52, 32, 40),
+            Class::Elf64 => (64, 56, 64),
+        };
+        let ph_offset = eh_size;
+        let dyn_offset = ph_offset + 2 * ph_size;
+        let dyn_size = 2 * 2 * ws;
+        let shstrtab_offset = dyn_offset + dyn_size;
+        let shstrtab = b"\0.shstrtab\0";
+        let sh_offset = shstrtab_offset + shstrtab.len() as u64;
+
+        out.extend(FileContents::MAGIC);
+        out.extend([ctx.class as u8, ctx.endianness as u8, 1, 0]);
+        out.extend([0; 8]);
+        half(&mut out, Type::Dyn as u16);
+        half(&mut out, Machine::PowerPC as u16);
+        word32(&mut out, 1);
+        word(&mut out, 0x1234); // entry
+        word(&mut out, ph_offset);
+        word(&mut out, sh_offset);
+        word32(&mut out, 0); // flags
+        half(&mut out, eh_size as u16);
+        half(&mut out, ph_size as u16);
+        half(&mut out, 2);
+        half(&mut out, sh_size as u16);
+        half(&mut out, 2);
+        half(&mut out, 1);
+
+        let phdr = |out: &mut Vec<u8>, r#type: SegmentType, offset: u64, size: u64| {
+            word32(out, r#type as u32);
+            if ctx.class == Class::Elf64 {
+                word32(out, 0x4 | 0x1);
+            }
+            for x in [offset, offset, offset, size, size] {
+                word(out, x);
+            }
+            if ctx.class == Class::Elf32 {
+                word32(out, 0x4 | 0x1);
+            }
+            word(out, 0x1000);
+        };
+        phdr(&mut out, SegmentType::Load, 0, sh_offset);
+        phdr(&mut out, SegmentType::Dynamic, dyn_offset, dyn_size);
+
+        word(&mut out, DynamicTag::Needed as u64);
+        word(&mut out, 1);
+        word(&mut out, DynamicTag::Null as u64);
+        word(&mut out, 0);
+        out.extend(shstrtab);
+
+        out.extend(std::iter::repeat_n(0, sh_size as usize));
+        word32(&mut out, 1); // name
+        word32(&mut out, SectionType::StrTab as u32);
+        for x in [0, 0, shstrtab_offset, shstrtab.len() as u64] {
+            word(&mut out, x);
+        }
+        word32(&mut out, 0);
+        word32(&mut out, 0);
+        word(&mut out, 1);
+        word(&mut out, 0);
+        out
+    }
+
+    #[test]
+    fn parse_all_classes_and_endiannesses() {
+        for class in [Class::Elf32, Class::Elf64] {
+            for endianness in [Endianness::Little, Endianness::Big] {
+                let ctx = Ctx { class, endianness };
+                let input = tiny_elf(ctx);
+                let file = File::parse_or_print_error(&input[..]).expect("tiny ELF should parse");
+
+                assert_eq!(file.ctx(), ctx);
+                assert_eq!(file.machine, Machine::PowerPC);
+                assert_eq!(file.entry_point, Addr(0x1234));
+                assert_eq!(file.program_headers.len(), 2);
+                assert_eq!(file.program_headers[0].r#type, SegmentType::Load);
+                assert_eq!(
+                    file.program_headers[0].flags,
+                    SegmentFlag::Read | SegmentFlag::Execute
+                );
+                assert_eq!(file.dynamic_entry(DynamicTag::Needed), Some(Addr(1)));
+                assert_eq!(
+                    file.section_by_name(b".shstrtab").map(|sh| sh.r#type),
+                    Some(SectionType::StrTab)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn split_rel_info_per_class() {
+        assert_eq!(
+            split_rel_info(Class::Elf32, 0x0000_0407),
+            (4, RelType::JumpSlot)
+        );
+        assert_eq!(
+            split_rel_info(Class::Elf64, 0x0000_0004_0000_0007),
+            (4, RelType::JumpSlot)
+        );
+        assert_eq!(
+            split_rel_info(Class::Elf32, 0x0000_012a),
+            (1, RelType::Other(42))
+        );
+    }
+
+    #[test]
+    fn binds_now() {
+        let dir = test_util::temp_dir("bind");
+        std::fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+
+        for (flag, now) in [("-Wl,-z,lazy", false), ("-Wl,-z,now", true)] {
+            let out = format!("main{flag}");
+            test_util::gcc(&dir, &[flag, "main.c", "-o", &out]);
+
+            let file = File::parse_or_print_error(std::fs::read(dir.join(&out)).unwrap()).unwrap();
+            assert_eq!(file.binds_now(), now, "{flag}");
+            if now {
+                assert!(file.dynamic_flags_1().contains(DynamicFlag1::Now));
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn symbolic() {
+        let dir = test_util::temp_dir("symbolic");
+        std::fs::write(dir.join("lib.c"), "int f(void) { return 1; }\n").unwrap();
+
+        for (flags, symbolic) in [(&[][..], false), (&["-Wl,-Bsymbolic"][..], true)] {
+            let mut args = vec!["-shared", "-fPIC", "lib.c", "-o", "lib.so"];
+            args.extend(flags);
+            test_util::gcc(&dir, &args);
+            let file =
+                File::parse_or_print_error(std::fs::read(dir.join("lib.so")).unwrap()).unwrap();
+            assert_eq!(file.is_symbolic(), symbolic, "{flags:?}");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn symbol_visibility() {
+        let dir = test_util::temp_dir("vis");
+        let source = "__attribute__((visibility(\"protected\"))) int mine(void) { return 1; }\n\
+                      int yours(void) { return mine(); }\n";
+        std::fs::write(dir.join("lib.c"), source).unwrap();
+        test_util::gcc(&dir, &["-shared", "-fPIC", "lib.c", "-o", "lib.so"]);
+
+        let file = File::parse_or_print_error(std::fs::read(dir.join("lib.so")).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        let syms = file.read_dynsym_entries().unwrap();
+        let protected: Vec<_> = syms
+            .iter()
+            .filter(|sym| sym.visibility == SymVisibility::Protected)
+            .collect();
+        assert_eq!(protected.len(), 1);
+        assert_eq!(protected[0].r#type, SymType::Func);
+        assert!(
+            syms.iter()
+                .any(|sym| sym.visibility == SymVisibility::Default && sym.r#type == SymType::Func)
+        );
+    }
 }
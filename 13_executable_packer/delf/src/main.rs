@@ -0,0 +1,763 @@
+use std::error::Error;
+
+use clap::{Parser, Subcommand};
+use delf::{DynamicTag, File, GnuNote, GnuNoteType, GnuProperty, SegmentFlag};
+use serde_json::{Map, Value};
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(long, global = true)]
+    /// print machine-readable JSON instead of tables
+    json: bool,
+    #[command(subcommand)]
+    nested: SubCommand,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Dump everything: header, segments, sections, dynamic table, symbols, relocations, notes and groups
+    All(FileArgs),
+    /// Show the ELF file header
+    Header(FileArgs),
+    /// Show the program headers
+    #[command(alias = "program-headers")]
+    Segments(FileArgs),
+    /// Show the section headers
+    #[command(alias = "section-headers")]
+    Sections(FileArgs),
+    /// Show the dynamic table
+    Dynamic(FileArgs),
+    /// Show the symbol table
+    Symbols(SymbolsArgs),
+    /// Show relocations (REL, RELA, JMPREL and decoded RELR)
+    Relocations(FileArgs),
+    /// Show notes (build-id, ABI tag, GNU properties)
+    Notes(FileArgs),
+    /// Show section groups (COMDAT) of relocatable objects
+    Groups(FileArgs),
+    /// Show the frame description entries of ".eh_frame"
+    EhFrame(FileArgs),
+    /// Show the threads, mapped files and auxiliary vector of a core dump
+    Core(FileArgs),
+    /// Check the file structure and report every problem found
+    Lint(FileArgs),
+}
+
+#[derive(clap::Args)]
+struct FileArgs {
+    /// the path of an ELF file to inspect
+    path: String,
+}
+
+#[derive(clap::Args)]
+struct SymbolsArgs {
+    /// the path of an ELF file to inspect
+    path: String,
+    #[arg(long)]
+    /// read ".dynsym" (loader view) instead of ".symtab" (linker view)
+    dynsym: bool,
+}
+
+impl SubCommand {
+    fn path(&self) -> &str {
+        match self {
+            SubCommand::All(a)
+            | SubCommand::Header(a)
+            | SubCommand::Segments(a)
+            | SubCommand::Sections(a)
+            | SubCommand::Dynamic(a)
+            | SubCommand::Relocations(a)
+            | SubCommand::Notes(a)
+            | SubCommand::Groups(a)
+            | SubCommand::EhFrame(a)
+            | SubCommand::Core(a)
+            | SubCommand::Lint(a) => &a.path,
+            SubCommand::Symbols(a) => &a.path,
+        }
+    }
+}
+
+type AnyError = Box<dyn Error>;
+
+#[derive(thiserror::Error, Debug)]
+enum CliError {
+    #[error("could not parse ELF file {0:?}")]
+    Parse(String),
+}
+
+fn main() {
+    if let Err(e) = do_main() {
+        eprintln!("Fatal error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn do_main() -> Result<(), AnyError> {
+    let args = Args::parse();
+    let path = args.nested.path();
+    let contents = std::fs::read(path)?;
+    let file = File::parse_or_print_error(contents).ok_or_else(|| CliError::Parse(path.into()))?;
+    let reports = reports_for(&file, &args.nested)?;
+
+    if args.json {
+        let json = match reports.as_slice() {
+            [report] => report.to_json(),
+            reports => Value::Object(
+                reports
+                    .iter()
+                    .map(|r| (r.key.to_string(), r.to_json()))
+                    .collect(),
+            ),
+        };
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    } else {
+        for report in &reports {
+            report.print();
+        }
+    }
+    Ok(())
+}
+
+fn reports_for(file: &File<Vec<u8>>, cmd: &SubCommand) -> Result<Vec<Report>, AnyError> {
+    let res = match cmd {
+        SubCommand::All(_) => vec![
+            header(file),
+            segments(file),
+            sections(file),
+            dynamic(file),
+            symbols(file, false)?,
+            symbols(file, true)?,
+            relocations(file)?,
+            notes(file),
+            groups(file)?,
+            eh_frame(file)?,
+        ],
+        SubCommand::Header(_) => vec![header(file)],
+        SubCommand::Segments(_) => vec![segments(file)],
+        SubCommand::Sections(_) => vec![sections(file)],
+        SubCommand::Dynamic(_) => vec![dynamic(file)],
+        SubCommand::Symbols(a) => vec![symbols(file, a.dynsym)?],
+        SubCommand::Relocations(_) => vec![relocations(file)?],
+        SubCommand::Notes(_) => vec![notes(file)],
+        SubCommand::Groups(_) => vec![groups(file)?],
+        SubCommand::EhFrame(_) => vec![eh_frame(file)?],
+        SubCommand::Core(_) => core(file)?,
+        SubCommand::Lint(_) => vec![lint(file)],
+    };
+    Ok(res)
+}
+
+fn header(file: &File<Vec<u8>>) -> Report {
+    Report {
+        key: "header",
+        title: "ELF header".into(),
+        body: Body::Fields(vec![
+            ("class", format!("{:?}", file.class).into()),
+            ("endianness", format!("{:?}", file.endianness).into()),
+            ("type", format!("{:?}", file.r#type).into()),
+            ("machine", format!("{:?}", file.machine).into()),
+            ("entry_point", file.entry_point.into()),
+            (
+                "program_headers",
+                (file.program_headers.len() as u64).into(),
+            ),
+            (
+                "section_headers",
+                (file.section_headers.len() as u64).into(),
+            ),
+            ("shstrndx", (file.shstrndx as u64).into()),
+        ]),
+    }
+}
+
+fn segments(file: &File<Vec<u8>>) -> Report {
+    let rows = file
+        .program_headers
+        .iter()
+        .map(|ph| {
+            let flags = [
+                (SegmentFlag::Read, "R"),
+                (SegmentFlag::Write, "W"),
+                (SegmentFlag::Execute, "X"),
+            ]
+            .iter()
+            .map(|&(flag, letter)| if ph.flags.contains(flag) { letter } else { "." })
+            .collect::<String>();
+            vec![
+                format!("{:?}", ph.r#type).into(),
+                flags.into(),
+                ph.offset.into(),
+                ph.vaddr.into(),
+                ph.paddr.into(),
+                ph.filesz.into(),
+                ph.memsz.into(),
+                ph.align.into(),
+            ]
+        })
+        .collect();
+    Report {
+        key: "program_headers",
+        title: format!("Program headers ({})", file.program_headers.len()),
+        body: Body::Table {
+            columns: vec![
+                "type", "flags", "offset", "vaddr", "paddr", "filesz", "memsz", "align",
+            ],
+            rows,
+        },
+    }
+}
+
+fn section_flags(flags: u64) -> String {
+    const LETTERS: &[(u64, char)] = &[
+        (0x1, 'W'),
+        (0x2, 'A'),
+        (0x4, 'X'),
+        (0x10, 'M'),
+        (0x20, 'S'),
+        (0x40, 'I'),
+        (0x80, 'L'),
+        (0x100, 'O'),
+        (0x200, 'G'),
+        (0x400, 'T'),
+    ];
+    LETTERS
+        .iter()
+        .filter(|&&(bit, _)| flags & bit != 0)
+        .map(|&(_, letter)| letter)
+        .collect()
+}
+
+fn sections(file: &File<Vec<u8>>) -> Report {
+    let rows = file
+        .section_headers
+        .iter()
+        .enumerate()
+        .map(|(index, sh)| {
+            vec![
+                (index as u64).into(),
+                String::from_utf8_lossy(file.shstrtab_entry(sh.name)).into(),
+                format!("{:?}", sh.r#type).into(),
+                section_flags(sh.flags).into(),
+                sh.addr.into(),
+                sh.offset.into(),
+                sh.size.into(),
+                (sh.link as u64).into(),
+                (sh.info as u64).into(),
+                sh.addralign.into(),
+                sh.entsize.into(),
+            ]
+        })
+        .collect();
+    Report {
+        key: "section_headers",
+        title: format!("Section headers ({})", file.section_headers.len()),
+        body: Body::Table {
+            columns: vec![
+                "index", "name", "type", "flags", "addr", "offset", "size", "link", "info",
+                "align", "entsize",
+            ],
+            rows,
+        },
+    }
+}
+
+fn dynamic(file: &File<Vec<u8>>) -> Report {
+    let entries = file.dynamic_table().unwrap_or_default();
+    let rows = entries
+        .iter()
+        .map(|e| {
+            let string = match e.tag {
+                DynamicTag::Needed
+                | DynamicTag::SoName
+                | DynamicTag::RPath
+                | DynamicTag::RunPath => String::from_utf8_lossy(file.dynstr_entry(e.addr)).into(),
+                _ => String::new(),
+            };
+            vec![format!("{:?}", e.tag).into(), e.addr.into(), string.into()]
+        })
+        .collect();
+    Report {
+        key: "dynamic",
+        title: format!("Dynamic table ({} entries)", entries.len()),
+        body: Body::Table {
+            columns: vec!["tag", "value", "string"],
+            rows,
+        },
+    }
+}
+
+fn notes(file: &File<Vec<u8>>) -> Report {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    let rows: Vec<_> = file
+        .notes()
+        .map(|note| {
+            let (r#type, desc) = match note.gnu() {
+                Some(GnuNote::BuildId(id)) => ("BuildId".into(), hex(id)),
+                Some(GnuNote::AbiTag(tag)) => ("AbiTag".into(), tag.to_string()),
+                Some(GnuNote::Properties(properties)) => (
+                    "PropertyType0".into(),
+                    properties
+                        .iter()
+                        .map(|property| match property {
+                            GnuProperty::X86Features(features) => {
+                                format!("x86 features: {features:?}")
+                            }
+                            GnuProperty::Other { r#type, data } => {
+                                format!("0x{type:x}: {}", hex(data))
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                Some(GnuNote::Other(r#type, desc)) => match r#type {
+                    GnuNoteType::Other(n) => (format!("0x{n:x}"), hex(desc)),
+                    r#type => (format!("{type:?}"), hex(desc)),
+                },
+                None => (format!("0x{:x}", note.r#type), hex(note.desc)),
+            };
+            vec![
+                String::from_utf8_lossy(note.name).into(),
+                r#type.into(),
+                (note.desc.len() as u64).into(),
+                desc.into(),
+            ]
+        })
+        .collect();
+    Report {
+        key: "notes",
+        title: format!("Notes ({} entries)", rows.len()),
+        body: Body::Table {
+            columns: vec!["owner", "type", "size", "description"],
+            rows,
+        },
+    }
+}
+
+fn symbols(file: &File<Vec<u8>>, dynsym: bool) -> Result<Report, AnyError> {
+    let (key, section, syms) = if dynsym {
+        ("dynsym", ".dynsym", file.read_dynsym_entries()?)
+    } else {
+        ("symtab", ".symtab", file.read_symtab_entries()?)
+    };
+    // Only the loader view carries GNU symbol versions
+    let versions = if dynsym {
+        Some(file.read_dynsym_versions()?)
+    } else {
+        None
+    };
+    let rows = syms
+        .iter()
+        .enumerate()
+        .map(|(index, sym)| {
+            let name = if dynsym {
+                file.dynstr_entry(sym.name)
+            } else {
+                file.strtab_entry(sym.name)
+            };
+            let mut row = vec![
+                (index as u64).into(),
+                sym.value.into(),
+                sym.size.into(),
+                format!("{:?}", sym.r#type).into(),
+                format!("{:?}", sym.bind).into(),
+                format!("{:?}", sym.shndx).into(),
+                String::from_utf8_lossy(name).into(),
+            ];
+            if let Some(versions) = &versions {
+                let version = versions.get(index).copied().flatten();
+                row.push(version.map(|v| v.to_string()).unwrap_or_default().into());
+            }
+            row
+        })
+        .collect();
+    let mut columns = vec!["index", "value", "size", "type", "bind", "shndx", "name"];
+    if versions.is_some() {
+        columns.push("version");
+    }
+    Ok(Report {
+        key,
+        title: format!("Symbol table {section:?} ({} entries)", syms.len()),
+        body: Body::Table { columns, rows },
+    })
+}
+
+fn relocations(file: &File<Vec<u8>>) -> Result<Report, AnyError> {
+    if file.r#type == delf::Type::Rel {
+        return section_relocations(file);
+    }
+
+    let syms = file.read_dynsym_entries()?;
+    let sym_name = |index: u32| -> String {
+        syms.get(index as usize)
+            .filter(|_| index != 0)
+            .map(|sym| String::from_utf8_lossy(file.dynstr_entry(sym.name)).into())
+            .unwrap_or_default()
+    };
+
+    let mut rows = Vec::new();
+    for (table, rels) in [
+        (
+            "rel",
+            file.read_rel_entries()?
+                .into_iter()
+                .map(delf::Rela::from)
+                .collect(),
+        ),
+        ("rela", file.read_rela_entries()?),
+        ("jmprel", file.read_jmp_rel_entries()?),
+    ] {
+        rows.extend(rels.iter().map(|rel| {
+            vec![
+                table.into(),
+                rel.offset.into(),
+                format!("{:?}", rel.r#type).into(),
+                (rel.sym as u64).into(),
+                sym_name(rel.sym).into(),
+                rel.addend.into(),
+            ]
+        }));
+    }
+    rows.extend(file.read_relr_vaddrs()?.into_iter().map(|offset| {
+        vec![
+            "relr".into(),
+            offset.into(),
+            "Relative".into(),
+            0.into(),
+            String::new().into(),
+            0.into(),
+        ]
+    }));
+
+    Ok(Report {
+        key: "relocations",
+        title: format!("Relocations ({} entries)", rows.len()),
+        body: Body::Table {
+            columns: vec!["table", "offset", "type", "sym", "name", "addend"],
+            rows,
+        },
+    })
+}
+
+/// Relocatable objects have no dynamic table: relocations live in `.rela.*` /
+/// `.rel.*` sections, with offsets relative to the section they apply to
+fn section_relocations(file: &File<Vec<u8>>) -> Result<Report, AnyError> {
+    let mut rows = Vec::new();
+    for rels in file.read_section_relocations()? {
+        let table = String::from_utf8_lossy(file.section_name(rels.section));
+        let symtab = file.section_headers.get(rels.symtab);
+        let syms = match symtab {
+            Some(symtab) => file.read_symbols(symtab)?,
+            None => Vec::new(),
+        };
+        let sym_name = |index: u32| -> String {
+            let Some((symtab, sym)) = symtab.zip(syms.get(index as usize)) else {
+                return String::new();
+            };
+            // section symbols are nameless, show the section instead
+            match file.symbol_section_index(symtab, index as usize, sym) {
+                Some(shndx) if sym.r#type == delf::SymType::Section => file
+                    .section_headers
+                    .get(shndx)
+                    .map(|sh| String::from_utf8_lossy(file.section_name(sh)).into())
+                    .unwrap_or_default(),
+                _ => String::from_utf8_lossy(file.symbol_name(symtab, sym)).into(),
+            }
+        };
+        rows.extend(rels.relocations.iter().map(|rel| {
+            vec![
+                table.clone().into(),
+                rel.offset.into(),
+                format!("{:?}", rel.r#type).into(),
+                (rel.sym as u64).into(),
+                sym_name(rel.sym).into(),
+                rel.addend.into(),
+            ]
+        }));
+    }
+
+    Ok(Report {
+        key: "relocations",
+        title: format!("Relocations ({} entries)", rows.len()),
+        body: Body::Table {
+            columns: vec!["table", "offset", "type", "sym", "name", "addend"],
+            rows,
+        },
+    })
+}
+
+fn lint(file: &File<Vec<u8>>) -> Report {
+    let rows: Vec<_> = file
+        .validate()
+        .iter()
+        .map(|d| vec![format!("{:?}", d.severity()).into(), d.to_string().into()])
+        .collect();
+    Report {
+        key: "diagnostics",
+        title: format!("Diagnostics ({} entries)", rows.len()),
+        body: Body::Table {
+            columns: vec!["severity", "message"],
+            rows,
+        },
+    }
+}
+
+fn groups(file: &File<Vec<u8>>) -> Result<Report, AnyError> {
+    let mut rows = Vec::new();
+    for group in file.read_section_groups()? {
+        let members = group
+            .members
+            .iter()
+            .map(|&index| {
+                file.section_headers
+                    .get(index as usize)
+                    .map(|sh| String::from_utf8_lossy(file.section_name(sh)).into_owned())
+                    .unwrap_or_else(|| format!("#{index}"))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        rows.push(vec![
+            String::from_utf8_lossy(file.section_name(group.section)).into(),
+            String::from_utf8_lossy(file.group_signature(&group)?).into(),
+            (if group.is_comdat() { "COMDAT" } else { "" }).into(),
+            members.into(),
+        ]);
+    }
+
+    Ok(Report {
+        key: "groups",
+        title: format!("Section groups ({} entries)", rows.len()),
+        body: Body::Table {
+            columns: vec!["section", "signature", "flags", "members"],
+            rows,
+        },
+    })
+}
+
+fn eh_frame(file: &File<Vec<u8>>) -> Result<Report, AnyError> {
+    let mut rows = Vec::new();
+    if let Some(eh_frame) = file.eh_frame()? {
+        for entry in eh_frame.entries() {
+            let delf::CfiEntry::Fde(fde) = entry? else {
+                continue;
+            };
+            let range = fde.pc_range()?;
+            rows.push(vec![
+                (fde.offset as u64).into(),
+                range.start.into(),
+                range.end.into(),
+                (fde.cie.offset as u64).into(),
+                String::from_utf8_lossy(fde.cie.augmentation).into(),
+                fde.lsda.map_or_else(|| "".into(), Cell::from),
+            ]);
+        }
+    }
+
+    Ok(Report {
+        key: "eh_frame",
+        title: format!("Frame description entries ({} entries)", rows.len()),
+        body: Body::Table {
+            columns: vec!["offset", "start", "end", "cie", "augmentation", "lsda"],
+            rows,
+        },
+    })
+}
+
+fn core(file: &File<Vec<u8>>) -> Result<Vec<Report>, AnyError> {
+    let core = file.read_core()?;
+    let threads = core
+        .threads
+        .iter()
+        .map(|thread| {
+            vec![
+                (thread.pid as u64).into(),
+                u64::from(thread.signal).into(),
+                delf::Addr(thread.registers.rip).into(),
+                delf::Addr(thread.registers.rsp).into(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let files = core
+        .files
+        .iter()
+        .map(|mapping| {
+            vec![
+                mapping.range.start.into(),
+                mapping.range.end.into(),
+                mapping.offset.into(),
+                String::from_utf8_lossy(mapping.path).into(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let auxv = core
+        .auxv
+        .iter()
+        .map(|entry| vec![entry.r#type.into(), delf::Addr(entry.value).into()])
+        .collect::<Vec<_>>();
+
+    Ok(vec![
+        Report {
+            key: "threads",
+            title: format!("Threads ({} entries)", threads.len()),
+            body: Body::Table {
+                columns: vec!["pid", "signal", "rip", "rsp"],
+                rows: threads,
+            },
+        },
+        Report {
+            key: "files",
+            title: format!("Mapped files ({} entries)", files.len()),
+            body: Body::Table {
+                columns: vec!["start", "end", "offset", "path"],
+                rows: files,
+            },
+        },
+        Report {
+            key: "auxv",
+            title: format!("Auxiliary vector ({} entries)", auxv.len()),
+            body: Body::Table {
+                columns: vec!["type", "value"],
+                rows: auxv,
+            },
+        },
+    ])
+}
+
+/// A single table cell, rendered as hex in tables and as a plain number in JSON
+enum Cell {
+    Addr(delf::Addr),
+    Num(u64),
+    Text(String),
+}
+
+impl From<delf::Addr> for Cell {
+    fn from(value: delf::Addr) -> Self {
+        Self::Addr(value)
+    }
+}
+
+impl From<u64> for Cell {
+    fn from(value: u64) -> Self {
+        Self::Num(value)
+    }
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Self::Text(value.into())
+    }
+}
+
+impl From<std::borrow::Cow<'_, str>> for Cell {
+    fn from(value: std::borrow::Cow<'_, str>) -> Self {
+        Self::Text(value.into_owned())
+    }
+}
+
+impl Cell {
+    fn to_json(&self) -> Value {
+        match self {
+            Cell::Addr(addr) => addr.0.into(),
+            Cell::Num(x) => (*x).into(),
+            Cell::Text(s) => s.as_str().into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cell::Addr(addr) => write!(f, "{addr}"),
+            Cell::Num(x) => write!(f, "{x}"),
+            Cell::Text(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+enum Body {
+    Fields(Vec<(&'static str, Cell)>),
+    Table {
+        columns: Vec<&'static str>,
+        rows: Vec<Vec<Cell>>,
+    },
+}
+
+struct Report {
+    key: &'static str,
+    title: String,
+    body: Body,
+}
+
+impl Report {
+    fn to_json(&self) -> Value {
+        match &self.body {
+            Body::Fields(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_json()))
+                    .collect(),
+            ),
+            Body::Table { columns, rows } => Value::Array(
+                rows.iter()
+                    .map(|row| {
+                        Value::Object(
+                            columns
+                                .iter()
+                                .zip(row)
+                                .map(|(k, v)| (k.to_string(), v.to_json()))
+                                .collect::<Map<_, _>>(),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn print(&self) {
+        println!("{}:", self.title);
+        match &self.body {
+            Body::Fields(fields) => {
+                let width = fields.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+                for (k, v) in fields {
+                    println!("  {k:<width$}  {v}");
+                }
+            }
+            Body::Table { columns, rows } => {
+                let rows: Vec<Vec<String>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|c| c.to_string()).collect())
+                    .collect();
+                let widths: Vec<usize> = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        rows.iter()
+                            .map(|row| row[i].len())
+                            .chain(std::iter::once(c.len()))
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                let line = |cells: &mut dyn Iterator<Item = &str>| {
+                    let line = cells
+                        .zip(&widths)
+                        .map(|(c, &w)| format!("{c:<w$}"))
+                        .collect::<Vec<_>>()
+                        .join("  ");
+                    println!("  {}", line.trim_end());
+                };
+                line(&mut columns.iter().copied());
+                for row in &rows {
+                    line(&mut row.iter().map(String::as_str));
+                }
+            }
+        }
+        println!();
+    }
+}
@@ -0,0 +1,308 @@
+use std::fmt;
+
+use crate::{File, GnuNoteType, SectionType, SegmentType, X86Feature, parse};
+use enumflags2::BitFlags;
+use nom::{Parser as _, bytes::complete::take};
+
+/// An entry of a `PT_NOTE` segment or `SHT_NOTE` section
+#[derive(Debug, Clone, Copy)]
+pub struct Note<'a> {
+    /// The owner of the note, without its null terminator (e.g. "GNU")
+    pub name: &'a [u8],
+    /// The note type, whose meaning depends on the owner
+    pub r#type: u32,
+    pub desc: &'a [u8],
+    ctx: parse::Ctx,
+}
+
+impl<'a> Note<'a> {
+    /// Parses a note, including the padding after its name and descriptor,
+    /// which are aligned to `align` (4, or 8 for some 64-bit notes)
+    pub fn parse(
+        ctx: parse::Ctx,
+        align: usize,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let (i, (namesz, descsz, r#type)) = (ctx.u32(), ctx.u32(), ctx.u32()).parse(i)?;
+            let (i, name) = take(namesz).parse(i)?;
+            let (i, _) = take(padding(12 + namesz as usize, align)).parse(i)?;
+            let (i, desc) = take(descsz).parse(i)?;
+            // the last note of a segment isn't always padded
+            let i = &i[padding(descsz as usize, align).min(i.len())..];
+
+            let name = match name.split_last() {
+                Some((0, name)) => name,
+                _ => name,
+            };
+            let res = Self {
+                name,
+                r#type,
+                desc,
+                ctx,
+            };
+            Ok((i, res))
+        }
+    }
+
+    /// Decodes notes owned by "GNU"
+    pub fn gnu(&self) -> Option<GnuNote<'a>> {
+        if self.name != b"GNU" {
+            return None;
+        }
+        let r#type = GnuNoteType::from(self.r#type);
+        let ctx = self.ctx;
+        let res = match r#type {
+            GnuNoteType::BuildId => GnuNote::BuildId(self.desc),
+            GnuNoteType::AbiTag => {
+                match (ctx.u32(), ctx.u32(), ctx.u32(), ctx.u32()).parse(self.desc) {
+                    Ok((_, (os, major, minor, patch))) => GnuNote::AbiTag(AbiTag {
+                        os,
+                        major,
+                        minor,
+                        patch,
+                    }),
+                    Err(_) => GnuNote::Other(r#type, self.desc),
+                }
+            }
+            GnuNoteType::PropertyType0 => {
+                GnuNote::Properties(GnuProperties::new(self.desc, ctx).collect())
+            }
+            _ => GnuNote::Other(r#type, self.desc),
+        };
+        Some(res)
+    }
+}
+
+fn padding(len: usize, align: usize) -> usize {
+    (align - len % align) % align
+}
+
+/// Iterates over the notes of a segment or section. Stops at the first entry
+/// that doesn't fit, instead of failing.
+#[derive(Clone)]
+pub struct Notes<'a> {
+    input: &'a [u8],
+    align: usize,
+    ctx: parse::Ctx,
+}
+
+impl<'a> Notes<'a> {
+    pub fn new(input: &'a [u8], align: u64, ctx: parse::Ctx) -> Self {
+        // Notes are 4-byte aligned, except for those in 8-byte aligned
+        // segments (e.g. `.note.gnu.property` on 64-bit)
+        let align = if align == 8 { 8 } else { 4 };
+        Self { input, align, ctx }
+    }
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = Note<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.input.is_empty() {
+            return None;
+        }
+        match Note::parse(self.ctx, self.align)(self.input) {
+            Ok((rest, note)) => {
+                self.input = rest;
+                Some(note)
+            }
+            Err(_) => {
+                self.input = &[];
+                None
+            }
+        }
+    }
+}
+
+/// A typed view of a note owned by "GNU"
+#[derive(Debug, Clone)]
+pub enum GnuNote<'a> {
+    /// `NT_GNU_BUILD_ID`: a unique identifier, usually a SHA-1, used to find
+    /// separate debug info
+    BuildId(&'a [u8]),
+    /// `NT_GNU_ABI_TAG`: the minimum kernel version required
+    AbiTag(AbiTag),
+    /// `NT_GNU_PROPERTY_TYPE_0`
+    Properties(Vec<GnuProperty<'a>>),
+    Other(GnuNoteType, &'a [u8]),
+}
+
+/// The descriptor of a `NT_GNU_ABI_TAG` note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiTag {
+    /// 0 for Linux, 1 for GNU, 2 for Solaris, 3 for FreeBSD
+    pub os: u32,
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl fmt::Display for AbiTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let os = match self.os {
+            0 => "Linux",
+            1 => "GNU",
+            2 => "Solaris",
+            3 => "FreeBSD",
+            _ => "Unknown OS",
+        };
+        write!(f, "{os} {}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// An entry of a `NT_GNU_PROPERTY_TYPE_0` note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GnuProperty<'a> {
+    /// `GNU_PROPERTY_X86_FEATURE_1_AND`: CET features every input object supports
+    X86Features(BitFlags<X86Feature>),
+    Other {
+        r#type: u32,
+        data: &'a [u8],
+    },
+}
+
+impl GnuProperty<'_> {
+    pub const X86_FEATURE_1_AND: u32 = 0xc000_0002;
+}
+
+/// Iterates over the properties of a `NT_GNU_PROPERTY_TYPE_0` descriptor,
+/// whose entries are aligned to the word size
+struct GnuProperties<'a> {
+    input: &'a [u8],
+    ctx: parse::Ctx,
+}
+
+impl<'a> GnuProperties<'a> {
+    fn new(input: &'a [u8], ctx: parse::Ctx) -> Self {
+        Self { input, ctx }
+    }
+}
+
+impl<'a> Iterator for GnuProperties<'a> {
+    type Item = GnuProperty<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ctx = self.ctx;
+        let (i, (r#type, datasz)) = (ctx.u32(), ctx.u32()).parse(self.input).ok()?;
+        let Ok((i, data)) = take::<_, _, parse::Error<_>>(datasz).parse(i) else {
+            self.input = &[];
+            return None;
+        };
+        self.input = &i[padding(datasz as usize, ctx.word_size()).min(i.len())..];
+
+        let property = match (r#type, ctx.u32()(data)) {
+            (GnuProperty::X86_FEATURE_1_AND, Ok((_, bits))) => {
+                GnuProperty::X86Features(BitFlags::from_bits_truncate(bits))
+            }
+            _ => GnuProperty::Other { r#type, data },
+        };
+        Some(property)
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Iterates over the notes of every `PT_NOTE` segment, or of every
+    /// `SHT_NOTE` section for files without program headers (e.g. relocatable objects)
+    pub fn notes(&self) -> impl Iterator<Item = Note<'_>> {
+        let ctx = self.ctx();
+        let segments = self
+            .program_headers
+            .iter()
+            .filter(|ph| ph.r#type == SegmentType::Note)
+            .map(move |ph| Notes::new(self.segment_slice(ph), ph.align.0, ctx));
+        let sections = self
+            .section_headers
+            .iter()
+            .filter(|sh| self.program_headers.is_empty() && sh.r#type == SectionType::Note)
+            .map(move |sh| Notes::new(self.section_slice(sh), sh.addralign.0, ctx));
+        segments.chain(sections).flatten()
+    }
+
+    /// Returns the `NT_GNU_BUILD_ID` of the file, if any
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.notes().find_map(|note| match note.gnu()? {
+            GnuNote::BuildId(id) => Some(id),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Class, Endianness};
+
+    fn note(name: &[u8], r#type: u32, desc: &[u8], align: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend((name.len() as u32 + 1).to_le_bytes());
+        out.extend((desc.len() as u32).to_le_bytes());
+        out.extend(r#type.to_le_bytes());
+        out.extend(name);
+        out.push(0);
+        out.resize(out.len() + padding(out.len(), align), 0);
+        out.extend(desc);
+        out.resize(out.len() + padding(out.len(), align), 0);
+        out
+    }
+
+    #[test]
+    fn decode_gnu_notes() {
+        let ctx = parse::Ctx {
+            class: Class::Elf64,
+            endianness: Endianness::Little,
+        };
+        let mut abi = Vec::new();
+        for x in [0u32, 3, 2, 0] {
+            abi.extend(x.to_le_bytes());
+        }
+        let mut input = note(b"GNU", 1, &abi, 4);
+        input.extend(note(b"GNU", 3, &[0xde, 0xad, 0xbe, 0xef, 0x42], 4));
+        input.extend(note(b"Xen", 3, &[1, 2, 3], 4));
+
+        let notes: Vec<_> = Notes::new(&input, 4, ctx).collect();
+        assert_eq!(notes.len(), 3);
+        assert!(
+            matches!(notes[0].gnu(), Some(GnuNote::AbiTag(tag)) if tag.to_string() == "Linux 3.2.0")
+        );
+        assert!(
+            matches!(notes[1].gnu(), Some(GnuNote::BuildId(id)) if id == [0xde, 0xad, 0xbe, 0xef, 0x42])
+        );
+        assert_eq!(notes[2].name, b"Xen");
+        assert!(notes[2].gnu().is_none());
+
+        let mut property = Vec::new();
+        property.extend(GnuProperty::X86_FEATURE_1_AND.to_le_bytes());
+        property.extend(4u32.to_le_bytes());
+        property.extend(3u32.to_le_bytes());
+        property.extend([0; 4]);
+        let input = note(b"GNU", 5, &property, 8);
+        let notes: Vec<_> = Notes::new(&input, 8, ctx).collect();
+        let Some(GnuNote::Properties(properties)) = notes[0].gnu() else {
+            panic!("expected GNU properties, got {notes:?}");
+        };
+        assert_eq!(
+            properties,
+            [GnuProperty::X86Features(
+                X86Feature::Ibt | X86Feature::Shstk
+            )]
+        );
+
+        // truncated notes end the iteration instead of panicking
+        assert_eq!(Notes::new(&input[..20], 8, ctx).count(), 0);
+    }
+
+    #[test]
+    fn self_has_build_id() {
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        if file.section_by_name(b".note.gnu.build-id").is_none() {
+            return;
+        }
+        let id = file.build_id().expect("build-id note");
+        assert!(!id.is_empty());
+    }
+}
@@ -1,16 +1,23 @@
+use nom::Parser as _;
+
+use crate::{Class, Endianness};
+
 #[macro_export]
 macro_rules! impl_parse_for_enum {
     ($type: ident, $number_parser: ident) => {
         impl $type {
-            pub fn parse(full_input: parse::Input) -> parse::Result<Self> {
-                use nom::number::complete::$number_parser;
-                let (i, val) = $number_parser(full_input)?;
-                match Self::try_from(val) {
-                    Ok(val) => Ok((i, val)),
-                    Err(_) => Err(nom::Err::Failure(parse::Error::from_string(
-                        full_input,
-                        format!("Unknown {} {} (0x{:x})", stringify!($type), val, val),
-                    ))),
+            pub fn parse<'a>(
+                ctx: parse::Ctx,
+            ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+                move |full_input| {
+                    let (i, val) = ctx.$number_parser()(full_input)?;
+                    match Self::try_from(val) {
+                        Ok(val) => Ok((i, val)),
+                        Err(_) => Err(nom::Err::Failure(parse::Error::from_string(
+                            full_input,
+                            format!("Unknown {} {} (0x{:x})", stringify!($type), val, val),
+                        ))),
+                    }
                 }
             }
         }
@@ -40,17 +47,20 @@ macro_rules! impl_parse_for_bitenum {
 macro_rules! impl_parse_for_enumflags {
     ($type: ident, $number_parser: ident) => {
         impl $type {
-            pub fn parse(i: parse::Input) -> parse::Result<enumflags2::BitFlags<Self>> {
+            pub fn parse<'a>(
+                ctx: parse::Ctx,
+            ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, enumflags2::BitFlags<Self>> {
                 use nom::{
                     Parser,
                     combinator::map_res,
                     error::{ErrorKind, context},
-                    number::complete::$number_parser,
                 };
-                let parser = map_res($number_parser, |x| {
-                    enumflags2::BitFlags::<Self>::from_bits(x).map_err(|_| ErrorKind::Alt)
-                });
-                context(stringify!($type), parser).parse(i)
+                move |i| {
+                    let parser = map_res(ctx.$number_parser(), |x| {
+                        enumflags2::BitFlags::<Self>::from_bits(x).map_err(|_| ErrorKind::Alt)
+                    });
+                    context(stringify!($type), parser).parse(i)
+                }
             }
         }
     };
@@ -79,6 +89,90 @@ impl<I> nom::error::ParseError<I> for Error<I> {
     }
 }
 
+/// Word size and byte order of the file being parsed, as announced by `e_ident`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ctx {
+    pub class: Class,
+    pub endianness: Endianness,
+}
+
+impl Ctx {
+    /// Placeholder for parsing `e_ident`, which is made of single bytes only
+    pub(crate) const IDENT: Self = Self {
+        class: Class::Elf64,
+        endianness: Endianness::Little,
+    };
+
+    fn nom_endianness(&self) -> nom::number::Endianness {
+        match self.endianness {
+            Endianness::Little => nom::number::Endianness::Little,
+            Endianness::Big => nom::number::Endianness::Big,
+        }
+    }
+
+    pub fn u8<'a>(&self) -> impl Fn(Input<'a>) -> Result<'a, u8> + use<'a> {
+        nom::number::complete::u8
+    }
+
+    pub fn u16<'a>(&self) -> impl Fn(Input<'a>) -> Result<'a, u16> + use<'a> {
+        nom::number::complete::u16(self.nom_endianness())
+    }
+
+    pub fn u32<'a>(&self) -> impl Fn(Input<'a>) -> Result<'a, u32> + use<'a> {
+        nom::number::complete::u32(self.nom_endianness())
+    }
+
+    pub fn u64<'a>(&self) -> impl Fn(Input<'a>) -> Result<'a, u64> + use<'a> {
+        nom::number::complete::u64(self.nom_endianness())
+    }
+
+    /// Parses a class-sized word (`Elf32_Word`/`Elf32_Addr` or `Elf64_Xword`/`Elf64_Addr`),
+    /// widened to `u64`
+    pub fn word<'a>(&self) -> impl Fn(Input<'a>) -> Result<'a, u64> + use<'a> {
+        let ctx = *self;
+        move |i| match ctx.class {
+            Class::Elf32 => nom::combinator::map(ctx.u32(), u64::from).parse(i),
+            Class::Elf64 => ctx.u64()(i),
+        }
+    }
+
+    /// Parses a class-sized signed word (`Elf32_Sword` or `Elf64_Sxword`), sign-extended
+    /// to `i64`
+    pub fn sword<'a>(&self) -> impl Fn(Input<'a>) -> Result<'a, i64> + use<'a> {
+        let ctx = *self;
+        move |i| match ctx.class {
+            Class::Elf32 => nom::combinator::map(ctx.u32(), |x| x as i32 as i64).parse(i),
+            Class::Elf64 => nom::combinator::map(ctx.u64(), |x| x as i64).parse(i),
+        }
+    }
+
+    /// Size in bytes of a class-sized word
+    pub fn word_size(&self) -> usize {
+        match self.class {
+            Class::Elf32 => 4,
+            Class::Elf64 => 8,
+        }
+    }
+
+    /// Decodes a class-sized word from a slice of exactly `word_size()` bytes
+    pub fn read_word(&self, bytes: &[u8]) -> u64 {
+        match (self.class, self.endianness) {
+            (Class::Elf32, Endianness::Little) => {
+                u32::from_le_bytes(bytes.try_into().expect("4-byte word")) as u64
+            }
+            (Class::Elf32, Endianness::Big) => {
+                u32::from_be_bytes(bytes.try_into().expect("4-byte word")) as u64
+            }
+            (Class::Elf64, Endianness::Little) => {
+                u64::from_le_bytes(bytes.try_into().expect("8-byte word"))
+            }
+            (Class::Elf64, Endianness::Big) => {
+                u64::from_be_bytes(bytes.try_into().expect("8-byte word"))
+            }
+        }
+    }
+}
+
 pub type Input<'a> = &'a [u8];
 pub type Result<'a, O> = nom::IResult<Input<'a>, O, Error<Input<'a>>>; //nom::error::
 
@@ -1,6 +1,6 @@
 use std::{fmt, ops::Range};
 
-use crate::{Addr, DynamicEntry, DynamicTag, SegmentFlag, SegmentType, parse};
+use crate::{Addr, Class, DynamicEntry, DynamicTag, SegmentFlag, SegmentType, parse};
 use enumflags2::BitFlags;
 use nom::{
     Parser as _,
@@ -8,6 +8,7 @@ use nom::{
     multi,
 };
 
+#[derive(Clone)]
 pub struct ProgramHeader {
     pub r#type: SegmentType,
     pub flags: BitFlags<SegmentFlag>,
@@ -21,47 +22,89 @@ pub struct ProgramHeader {
 }
 
 impl ProgramHeader {
+    /// Size of an `Elf32_Phdr` / `Elf64_Phdr` entry
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 32,
+            Class::Elf64 => 56,
+        }
+    }
+
     pub fn file_range(&self) -> Range<Addr> {
-        self.offset..self.offset + self.filesz
+        self.offset..Addr(self.offset.0.saturating_add(self.filesz.0))
     }
 
     pub fn mem_range(&self) -> Range<Addr> {
-        self.vaddr..self.vaddr + self.memsz
+        self.vaddr..Addr(self.vaddr.0.saturating_add(self.memsz.0))
     }
 
-    pub(crate) fn parse<'a>(full_input: &'a [u8], i: parse::Input<'a>) -> parse::Result<'a, Self> {
-        //fn parse<'a>(full_input: parse::Input<'_>, i: parse::Input<'a>) -> parse::Result<'a, Self> {
-        let (i, (r#type, flags)) = (SegmentType::parse, SegmentFlag::parse).parse(i)?;
-
-        let ap = Addr::parse;
-        let (i, (offset, vaddr, paddr, filesz, memsz, align)) =
-            (ap, ap, ap, ap, ap, ap).parse(i)?;
+    pub(crate) fn parse<'a>(
+        full_input: &'a [u8],
+        ctx: parse::Ctx,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let ap = Addr::parse(ctx);
+            let (i, (r#type, flags, offset, vaddr, paddr, filesz, memsz, align)) = match ctx.class {
+                // Elf32_Phdr keeps `p_flags` after `p_memsz`
+                Class::Elf32 => {
+                    let (i, (r#type, offset, vaddr, paddr, filesz, memsz, flags, align)) = (
+                        SegmentType::parse(ctx),
+                        &ap,
+                        &ap,
+                        &ap,
+                        &ap,
+                        &ap,
+                        SegmentFlag::parse(ctx),
+                        &ap,
+                    )
+                        .parse(i)?;
+                    (
+                        i,
+                        (r#type, flags, offset, vaddr, paddr, filesz, memsz, align),
+                    )
+                }
+                Class::Elf64 => (
+                    SegmentType::parse(ctx),
+                    SegmentFlag::parse(ctx),
+                    &ap,
+                    &ap,
+                    &ap,
+                    &ap,
+                    &ap,
+                    &ap,
+                )
+                    .parse(i)?,
+            };
 
-        let slice = &full_input[offset.into()..][..filesz.into()];
-        let (_, contents) = match r#type {
-            SegmentType::Dynamic => combinator::map(
-                multi::many_till(
-                    DynamicEntry::parse,
-                    verify(DynamicEntry::parse, |e| e.tag == DynamicTag::Null),
-                ),
-                |(entries, _last)| SegmentContents::Dynamic(entries),
-            )
-            .parse(slice)?,
-            _ => (slice, SegmentContents::Unknown),
-        };
+            // segments reaching past the end of the file are left opaque, `validate` reports them
+            let slice = full_input
+                .get(offset.into()..)
+                .and_then(|slice| slice.get(..filesz.into()));
+            let (_, contents) = match (r#type, slice) {
+                (SegmentType::Dynamic, Some(slice)) => combinator::map(
+                    multi::many_till(
+                        DynamicEntry::parse(ctx),
+                        verify(DynamicEntry::parse(ctx), |e| e.tag == DynamicTag::Null),
+                    ),
+                    |(entries, _last)| SegmentContents::Dynamic(entries),
+                )
+                .parse(slice)?,
+                _ => (i, SegmentContents::Unknown),
+            };
 
-        let res = Self {
-            r#type,
-            flags,
-            offset,
-            vaddr,
-            paddr,
-            filesz,
-            memsz,
-            align,
-            contents,
-        };
-        Ok((i, res))
+            let res = Self {
+                r#type,
+                flags,
+                offset,
+                vaddr,
+                paddr,
+                filesz,
+                memsz,
+                align,
+                contents,
+            };
+            Ok((i, res))
+        }
     }
 }
 
@@ -93,7 +136,7 @@ impl fmt::Debug for ProgramHeader {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SegmentContents {
     Dynamic(Vec<DynamicEntry>),
     Unknown,
@@ -0,0 +1,291 @@
+use crate::{
+    File, ReadRelaError, ReadSymsError, Rel, Rela, SectionHeader, SectionIndex, SectionType, Sym,
+    parse,
+};
+use nom::{Parser as _, multi};
+
+/// The relocations of a `SHT_RELA` or `SHT_REL` section, as found in relocatable
+/// objects (`.rela.text`, `.rel.data`...)
+#[derive(Debug)]
+pub struct SectionRelocations<'a> {
+    /// The relocation section itself
+    pub section: &'a SectionHeader,
+    /// Index of the section the relocations apply to (`sh_info`)
+    pub target: usize,
+    /// Index of the symbol table the relocations refer to (`sh_link`)
+    pub symtab: usize,
+    /// Relocations, with a zero addend for `SHT_REL` sections (the addend is
+    /// then stored at the relocated location)
+    pub relocations: Vec<Rela>,
+}
+
+/// A `SHT_GROUP` section: sections that must be kept or discarded together
+#[derive(Debug)]
+pub struct SectionGroup<'a> {
+    /// The group section itself
+    pub section: &'a SectionHeader,
+    /// `GRP_*` flags
+    pub flags: u32,
+    /// Index of the symbol table holding the signature (`sh_link`)
+    pub symtab: usize,
+    /// Index of the symbol whose name is the group's signature (`sh_info`)
+    pub signature: usize,
+    /// Indices of the member sections
+    pub members: Vec<u32>,
+}
+
+impl SectionGroup<'_> {
+    /// `GRP_COMDAT`: only one group with a given signature is kept by the linker
+    pub const COMDAT: u32 = 0x1;
+
+    pub fn is_comdat(&self) -> bool {
+        self.flags & Self::COMDAT != 0
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Read every `SHT_RELA` and `SHT_REL` section
+    pub fn read_section_relocations(&self) -> Result<Vec<SectionRelocations<'_>>, ReadRelaError> {
+        self.section_headers
+            .iter()
+            .filter(|sh| matches!(sh.r#type, SectionType::Rela | SectionType::Rel))
+            .map(|section| {
+                let relocations = if section.r#type == SectionType::Rela {
+                    self.read_section_entries(section, Rela::size(self.class), Rela::parse)?
+                } else {
+                    self.read_section_entries(section, Rel::size(self.class), Rel::parse)?
+                        .into_iter()
+                        .map(Rela::from)
+                        .collect()
+                };
+                Ok(SectionRelocations {
+                    section,
+                    target: section.info as usize,
+                    symtab: section.link as usize,
+                    relocations,
+                })
+            })
+            .collect()
+    }
+
+    /// Read every `SHT_GROUP` section
+    pub fn read_section_groups(&self) -> Result<Vec<SectionGroup<'_>>, ReadRelaError> {
+        self.section_headers
+            .iter()
+            .filter(|sh| sh.r#type == SectionType::Group)
+            .map(|section| {
+                let words = self.read_section_entries(section, 4, |ctx| ctx.u32())?;
+                let (flags, members) = words.split_first().unwrap_or((&0, &[]));
+                Ok(SectionGroup {
+                    section,
+                    flags: *flags,
+                    symtab: section.link as usize,
+                    signature: section.info as usize,
+                    members: members.to_vec(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the signature of a group, i.e. the name of its `sh_info` symbol
+    pub fn group_signature(&self, group: &SectionGroup<'_>) -> Result<&[u8], ReadSymsError> {
+        let Some(symtab) = self.section_headers.get(group.symtab) else {
+            return Ok(&[]);
+        };
+        let syms = self.read_symbols(symtab)?;
+        Ok(syms
+            .get(group.signature)
+            .map(|sym| self.symbol_name(symtab, sym))
+            .unwrap_or_default())
+    }
+
+    /// Returns the index of the section a symbol from `symtab` is defined in,
+    /// following `SHN_XINDEX` through the matching `SHT_SYMTAB_SHNDX` section.
+    /// `index` is the position of `sym` in the symbol table.
+    pub fn symbol_section_index(
+        &self,
+        symtab: &SectionHeader,
+        index: usize,
+        sym: &Sym,
+    ) -> Option<usize> {
+        if sym.shndx.0 != SectionIndex::XINDEX {
+            return sym.shndx.get();
+        }
+        let symtab_index = self
+            .section_headers
+            .iter()
+            .position(|sh| std::ptr::eq(sh, symtab))?;
+        let shndx = self
+            .section_headers
+            .iter()
+            .find(|sh| sh.r#type == SectionType::SymTabShndx && sh.link as usize == symtab_index)?;
+        let bytes = self.section_slice(shndx).get(index * 4..)?;
+        let (_, index) = self.ctx().u32()(bytes).ok()?;
+        Some(index as usize)
+    }
+
+    fn read_section_entries<'a, T, P>(
+        &'a self,
+        section: &SectionHeader,
+        entry_size: usize,
+        parser: fn(parse::Ctx) -> P,
+    ) -> Result<Vec<T>, ReadRelaError>
+    where
+        P: Fn(parse::Input<'a>) -> parse::Result<'a, T>,
+    {
+        let i = self.section_slice(section);
+        let n = i.len() / entry_size;
+        match multi::many_m_n(n, n, parser(self.ctx())).parse(i) {
+            Ok((_, entries)) => Ok(entries),
+            Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                Err(ReadRelaError::ParsingError(format!("{err:?}")))
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RelType, Type, test_util};
+
+    /// Builds `samples/what.c` into a relocatable object: the samples
+    /// themselves aren't built on a fresh checkout
+    fn what_o() -> File<Vec<u8>> {
+        let samples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../samples");
+        let dir = test_util::temp_dir("what");
+        let out = dir.join("what.o");
+        test_util::gcc(&samples, &["-c", "what.c", "-o", out.to_str().unwrap()]);
+        let input = std::fs::read(&out).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        File::parse_or_print_error(input).unwrap()
+    }
+
+    #[test]
+    fn relocatable_object() {
+        let file = what_o();
+        assert_eq!(file.r#type, Type::Rel);
+
+        let rels = file.read_section_relocations().unwrap();
+        let text = rels
+            .iter()
+            .find(|r| file.section_name(r.section) == b".rela.text")
+            .expect(".rela.text");
+        assert_eq!(
+            file.section_name(&file.section_headers[text.target]),
+            b".text"
+        );
+
+        let symtab = &file.section_headers[text.symtab];
+        assert_eq!(symtab.r#type, SectionType::SymTab);
+        let syms = file.read_symbols(symtab).unwrap();
+        let puts = text
+            .relocations
+            .iter()
+            .find(|rel| file.symbol_name(symtab, &syms[rel.sym as usize]) == b"puts")
+            .expect("call to puts");
+        assert_eq!(puts.r#type, RelType::Plt32);
+
+        let (index, main) = syms
+            .iter()
+            .enumerate()
+            .find(|(_, sym)| file.symbol_name(symtab, sym) == b"main")
+            .unwrap();
+        let section = file.symbol_section_index(symtab, index, main).unwrap();
+        assert_eq!(file.section_name(&file.section_headers[section]), b".text");
+    }
+
+    #[test]
+    fn comdat_group() {
+        // a 32-bit object with a COMDAT group and REL relocations
+        let mut out = Vec::new();
+        let strtab = b"\0f\0";
+        let shstrtab = b"\0.group\0.text.f\0.rel.text.f\0.symtab\0.strtab\0.shstrtab\0";
+        let name = |s: &[u8]| shstrtab.windows(s.len()).position(|w| w == s).unwrap() as u32;
+
+        // header, patched at the end
+        out.resize(52, 0);
+        let group_off = out.len() as u32;
+        for word in [SectionGroup::COMDAT, 2, 3] {
+            out.extend(word.to_le_bytes());
+        }
+        let text_off = out.len() as u32;
+        out.extend([0xe8, 0xfc, 0xff, 0xff, 0xff, 0xc3]);
+        let rel_off = out.len() as u32;
+        out.extend(1u32.to_le_bytes());
+        out.extend(((1u32 << 8) | 2).to_le_bytes()); // R_386_PC32 against symbol 1
+        let symtab_off = out.len() as u32;
+        out.extend([0; 16]);
+        out.extend(1u32.to_le_bytes()); // name
+        out.extend(0u32.to_le_bytes()); // value
+        out.extend(6u32.to_le_bytes()); // size
+        out.extend([0x12, 0]); // global func
+        out.extend(2u16.to_le_bytes()); // shndx
+        let strtab_off = out.len() as u32;
+        out.extend(strtab);
+        let shstrtab_off = out.len() as u32;
+        out.extend(shstrtab);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+
+        let sh_off = out.len() as u32;
+        // name, type, flags, addr, offset, size, link, info, addralign, entsize
+        let sections: [[u32; 10]; 7] = [
+            [0; 10],
+            [name(b".group"), 17, 0, 0, group_off, 12, 4, 1, 4, 4],
+            [name(b".text.f"), 1, 0x206, 0, text_off, 6, 0, 0, 1, 0],
+            [name(b".rel.text.f"), 9, 0x240, 0, rel_off, 8, 4, 2, 4, 8],
+            [name(b".symtab"), 2, 0, 0, symtab_off, 32, 5, 1, 4, 16],
+            [name(b".strtab"), 3, 0, 0, strtab_off, 3, 0, 0, 1, 0],
+            [
+                name(b".shstrtab"),
+                3,
+                0,
+                0,
+                shstrtab_off,
+                shstrtab.len() as u32,
+                0,
+                0,
+                1,
+                0,
+            ],
+        ];
+        for section in sections {
+            for word in section {
+                out.extend(word.to_le_bytes());
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend(b"\x7fELF\x01\x01\x01\x00");
+        header.extend([0; 8]);
+        header.extend(1u16.to_le_bytes()); // ET_REL
+        header.extend(3u16.to_le_bytes()); // EM_386
+        header.extend(1u32.to_le_bytes());
+        header.extend([0; 8]); // entry, phoff
+        header.extend(sh_off.to_le_bytes());
+        header.extend(0u32.to_le_bytes()); // flags
+        for half in [52u16, 0, 0, 40, 7, 6] {
+            header.extend(half.to_le_bytes());
+        }
+        out[..52].copy_from_slice(&header);
+
+        let file = File::parse_or_print_error(&out[..]).unwrap();
+        let groups = file.read_section_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].is_comdat());
+        assert_eq!(groups[0].members, [2, 3]);
+        assert_eq!(file.group_signature(&groups[0]).unwrap(), b"f");
+
+        let rels = file.read_section_relocations().unwrap();
+        assert_eq!(rels.len(), 1);
+        assert_eq!(rels[0].target, 2);
+        assert_eq!(rels[0].relocations[0].sym, 1);
+        assert_eq!(rels[0].relocations[0].offset.0, 1);
+    }
+}
@@ -1,41 +1,66 @@
 use std::fmt;
 
 use crate::{Addr, GetDynamicEntryError, enums::*, parse};
-use nom::{
-    Parser as _, combinator,
-    number::complete::{le_u8, le_u16, le_u32, le_u64},
-};
+use nom::{Parser as _, combinator};
 
 #[derive(Debug, Clone)]
 pub struct Sym {
     pub name: Addr,
     pub bind: SymBind,
     pub r#type: SymType,
+    pub visibility: SymVisibility,
     pub shndx: SectionIndex,
     pub value: Addr,
     pub size: u64,
 }
 
 impl Sym {
-    pub fn parse(i: parse::Input) -> parse::Result<Self> {
-        let (i, (name, (bind, r#type), _reserved, shndx, value, size)) = (
-            combinator::map(le_u32, |x| Addr(x as u64)),
-            nom::bits::bits((SymBind::parse, SymType::parse)),
-            le_u8,
-            combinator::map(le_u16, SectionIndex),
-            Addr::parse,
-            le_u64,
-        )
-            .parse(i)?;
-        let res = Self {
-            name,
-            bind,
-            r#type,
-            shndx,
-            value,
-            size,
-        };
-        Ok((i, res))
+    pub fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| {
+            let name = combinator::map(ctx.u32(), |x| Addr(x as u64));
+            let info = nom::bits::bits((SymBind::parse, SymType::parse));
+            // the upper bits of `st_other` are unused
+            let other = nom::bits::bits(combinator::map(
+                (
+                    nom::bits::complete::take::<_, u8, _, _>(6_usize),
+                    SymVisibility::parse,
+                ),
+                |(_, visibility)| visibility,
+            ));
+            let shndx = combinator::map(ctx.u16(), SectionIndex);
+            let (i, (name, bind, r#type, visibility, shndx, value, size)) = match ctx.class {
+                // Elf32_Sym: name, value, size, info, other, shndx
+                Class::Elf32 => {
+                    let (i, (name, value, size, (bind, r#type), visibility, shndx)) =
+                        (name, Addr::parse(ctx), ctx.word(), info, other, shndx).parse(i)?;
+                    (i, (name, bind, r#type, visibility, shndx, value, size))
+                }
+                // Elf64_Sym: name, info, other, shndx, value, size
+                Class::Elf64 => {
+                    let (i, (name, (bind, r#type), visibility, shndx, value, size)) =
+                        (name, info, other, shndx, Addr::parse(ctx), ctx.word()).parse(i)?;
+                    (i, (name, bind, r#type, visibility, shndx, value, size))
+                }
+            };
+            let res = Self {
+                name,
+                bind,
+                r#type,
+                visibility,
+                shndx,
+                value,
+                size,
+            };
+            Ok((i, res))
+        }
+    }
+
+    /// Size of an `Elf32_Sym` / `Elf64_Sym` entry
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 16,
+            Class::Elf64 => 24,
+        }
     }
 }
 
@@ -43,6 +68,13 @@ impl Sym {
 pub struct SectionIndex(pub u16);
 
 impl SectionIndex {
+    /// `SHN_ABS`: the symbol has an absolute value, unaffected by relocation
+    pub const ABS: u16 = 0xfff1;
+    /// `SHN_COMMON`: a common block not allocated yet, `value` holds its alignment
+    pub const COMMON: u16 = 0xfff2;
+    /// `SHN_XINDEX`: the real index is in the `SHT_SYMTAB_SHNDX` section
+    pub const XINDEX: u16 = 0xffff;
+
     pub fn is_undef(&self) -> bool {
         self.0 == 0
     }
@@ -63,7 +95,12 @@ impl SectionIndex {
 impl fmt::Debug for SectionIndex {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.is_special() {
-            write!(f, "Special({:04x})", self.0)
+            match self.0 {
+                Self::ABS => write!(f, "Abs"),
+                Self::COMMON => write!(f, "Common"),
+                Self::XINDEX => write!(f, "XIndex"),
+                _ => write!(f, "Special({:04x})", self.0),
+            }
         } else if self.is_undef() {
             write!(f, "Undef")
         } else {
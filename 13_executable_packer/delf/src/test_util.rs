@@ -0,0 +1,36 @@
+//! Helpers shared by the unit tests. The fixtures are built from C on the
+//! spot, so a missing gcc fails a test rather than skipping it.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Runs gcc in `dir`
+pub fn gcc(dir: &Path, args: &[&str]) {
+    let status = Command::new("gcc")
+        .current_dir(dir)
+        .args(args)
+        .status()
+        .expect("gcc is needed to build the test fixtures");
+    assert!(status.success(), "gcc {args:?} failed");
+}
+
+/// A fresh `delf-{name}-{pid}` directory in the system's temporary directory
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("delf-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The `libc.so.6` gcc links against: a large, versioned shared library,
+/// wherever the system keeps it
+pub fn libc_path() -> PathBuf {
+    let output = Command::new("gcc")
+        .arg("-print-file-name=libc.so.6")
+        .output()
+        .expect("gcc is needed to find libc.so.6");
+    let path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    assert!(path.is_absolute(), "gcc doesn't know where libc.so.6 is");
+    path.canonicalize().unwrap()
+}
@@ -0,0 +1,464 @@
+use std::ops::Range;
+
+use crate::{
+    Class, DynamicEntry, File, ProgramHeader, Rel, Rela, SectionHeader, SectionType, SegmentType,
+    Sym,
+};
+
+/// A structural problem found by `File::validate`
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    #[error("program header table {range:x?} is outside the file ({file_len} bytes)")]
+    ProgramHeaderTableOutsideFile { range: Range<u64>, file_len: u64 },
+    #[error("section header table {range:x?} is outside the file ({file_len} bytes)")]
+    SectionHeaderTableOutsideFile { range: Range<u64>, file_len: u64 },
+    #[error("{table} header entry size is {entsize}, expected {expected}")]
+    HeaderEntsize {
+        table: &'static str,
+        entsize: u16,
+        expected: u16,
+    },
+    #[error(
+        "segment {index} ({kind:?}) file range {range:x?} is outside the file ({file_len} bytes)"
+    )]
+    SegmentOutsideFile {
+        index: usize,
+        kind: SegmentType,
+        range: Range<u64>,
+        file_len: u64,
+    },
+    #[error(
+        "segment {index} ({kind:?}) has a file size ({filesz:#x}) larger than its memory size ({memsz:#x})"
+    )]
+    SegmentFileSizeTooLarge {
+        index: usize,
+        kind: SegmentType,
+        filesz: u64,
+        memsz: u64,
+    },
+    #[error("load segment {index} has an alignment of {align:#x}, which is not a power of two")]
+    BadSegmentAlignment { index: usize, align: u64 },
+    #[error(
+        "load segment {index} is misaligned: vaddr {vaddr:#x} and offset {offset:#x} differ modulo {align:#x}"
+    )]
+    MisalignedLoadSegment {
+        index: usize,
+        vaddr: u64,
+        offset: u64,
+        align: u64,
+    },
+    #[error("load segments {first} and {second} overlap in memory")]
+    OverlappingSegments { first: usize, second: usize },
+    #[error(
+        "section {index} ({kind:?}) file range {range:x?} is outside the file ({file_len} bytes)"
+    )]
+    SectionOutsideFile {
+        index: usize,
+        kind: SectionType,
+        range: Range<u64>,
+        file_len: u64,
+    },
+    #[error("section name table index {shstrndx} is out of range ({count} sections)")]
+    BadShstrndx { shstrndx: usize, count: usize },
+    #[error("section name table (section {shstrndx}) is {kind:?}, not StrTab")]
+    ShstrtabNotStrTab { shstrndx: usize, kind: SectionType },
+    #[error("section {index} ({kind:?}) has an entry size of {entsize}, expected {expected}")]
+    SectionEntsize {
+        index: usize,
+        kind: SectionType,
+        entsize: u64,
+        expected: u64,
+    },
+    #[error(
+        "section {index} ({kind:?}) size {size:#x} is not a multiple of its entry size {entsize}"
+    )]
+    SectionSizeNotMultipleOfEntsize {
+        index: usize,
+        kind: SectionType,
+        size: u64,
+        entsize: u64,
+    },
+    #[error("section {index} ({kind:?}) links to section {link}, which doesn't exist")]
+    SectionLinkOutOfRange {
+        index: usize,
+        kind: SectionType,
+        link: u32,
+    },
+}
+
+/// How bad a `Diagnostic` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Tools may get confused, but the file can still be inspected or loaded
+    Warning,
+    /// The file can't be loaded or inspected reliably
+    Error,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::HeaderEntsize { .. }
+            | Self::SectionEntsize { .. }
+            | Self::SectionSizeNotMultipleOfEntsize { .. }
+            | Self::ShstrtabNotStrTab { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Checks the structure of the file, reporting every problem found instead
+    /// of stopping at the first one. Never panics, whatever `File::parse` accepted.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        self.validate_header(&mut res);
+        self.validate_segments(&mut res);
+        self.validate_sections(&mut res);
+        res
+    }
+
+    fn validate_header(&self, res: &mut Vec<Diagnostic>) {
+        let file_len = self.input.as_ref().len() as u64;
+        let ctx = self.ctx();
+        // `e_phentsize` and `e_shentsize` aren't kept by the parser
+        let (ph_entsize_at, sh_entsize_at) = match self.class {
+            Class::Elf32 => (42, 46),
+            Class::Elf64 => (54, 58),
+        };
+        let half = |at: usize| {
+            self.input
+                .as_ref()
+                .get(at..)
+                .and_then(|i| ctx.u16()(i).ok())
+                .map(|(_, x)| x)
+        };
+
+        let tables = [
+            (
+                "program",
+                self.ph_offset,
+                self.program_headers.len(),
+                half(ph_entsize_at),
+                ProgramHeader::size(self.class),
+            ),
+            (
+                "section",
+                self.sh_offset,
+                self.section_headers.len(),
+                half(sh_entsize_at),
+                SectionHeader::size(self.class),
+            ),
+        ];
+        for (table, offset, count, entsize, expected) in tables {
+            if count == 0 {
+                continue;
+            }
+            let entsize = entsize.unwrap_or_default();
+            if entsize as usize != expected {
+                res.push(Diagnostic::HeaderEntsize {
+                    table,
+                    entsize,
+                    expected: expected as u16,
+                });
+            }
+            let end = offset
+                .0
+                .saturating_add((count as u64).saturating_mul(entsize as u64));
+            if end > file_len {
+                let range = offset.0..end;
+                res.push(match table {
+                    "program" => Diagnostic::ProgramHeaderTableOutsideFile { range, file_len },
+                    _ => Diagnostic::SectionHeaderTableOutsideFile { range, file_len },
+                });
+            }
+        }
+    }
+
+    fn validate_segments(&self, res: &mut Vec<Diagnostic>) {
+        let file_len = self.input.as_ref().len() as u64;
+
+        for (index, ph) in self.program_headers.iter().enumerate() {
+            let range = raw_range(ph.offset.0, ph.filesz.0);
+            if range.end > file_len || range.end < range.start {
+                res.push(Diagnostic::SegmentOutsideFile {
+                    index,
+                    kind: ph.r#type,
+                    range,
+                    file_len,
+                });
+            }
+            if ph.r#type != SegmentType::Load {
+                continue;
+            }
+            if ph.filesz.0 > ph.memsz.0 {
+                res.push(Diagnostic::SegmentFileSizeTooLarge {
+                    index,
+                    kind: ph.r#type,
+                    filesz: ph.filesz.0,
+                    memsz: ph.memsz.0,
+                });
+            }
+            let align = ph.align.0;
+            if align > 1 {
+                if !align.is_power_of_two() {
+                    res.push(Diagnostic::BadSegmentAlignment { index, align });
+                } else if ph.vaddr.0 % align != ph.offset.0 % align {
+                    res.push(Diagnostic::MisalignedLoadSegment {
+                        index,
+                        vaddr: ph.vaddr.0,
+                        offset: ph.offset.0,
+                        align,
+                    });
+                }
+            }
+        }
+
+        let loads: Vec<_> = self
+            .program_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, ph)| ph.r#type == SegmentType::Load && ph.memsz.0 > 0)
+            .collect();
+        for (i, &(first, a)) in loads.iter().enumerate() {
+            for &(second, b) in &loads[i + 1..] {
+                let (a, b) = (a.mem_range(), b.mem_range());
+                if a.start < b.end && b.start < a.end {
+                    res.push(Diagnostic::OverlappingSegments { first, second });
+                }
+            }
+        }
+    }
+
+    fn validate_sections(&self, res: &mut Vec<Diagnostic>) {
+        let file_len = self.input.as_ref().len() as u64;
+        let count = self.section_headers.len();
+
+        if count > 0 {
+            match self.section_headers.get(self.shstrndx) {
+                None => res.push(Diagnostic::BadShstrndx {
+                    shstrndx: self.shstrndx,
+                    count,
+                }),
+                Some(sh) if sh.r#type != SectionType::StrTab => {
+                    res.push(Diagnostic::ShstrtabNotStrTab {
+                        shstrndx: self.shstrndx,
+                        kind: sh.r#type,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (index, sh) in self.section_headers.iter().enumerate() {
+            let r#type = sh.r#type;
+            if !matches!(r#type, SectionType::Null | SectionType::NoBits) {
+                let range = raw_range(sh.offset.0, sh.size.0);
+                if range.end > file_len || range.end < range.start {
+                    res.push(Diagnostic::SectionOutsideFile {
+                        index,
+                        kind: r#type,
+                        range,
+                        file_len,
+                    });
+                }
+            }
+
+            let links_to_section = matches!(
+                r#type,
+                SectionType::SymTab
+                    | SectionType::DynSym
+                    | SectionType::Rela
+                    | SectionType::Rel
+                    | SectionType::Dynamic
+                    | SectionType::Hash
+                    | SectionType::GnuHash
+                    | SectionType::Group
+                    | SectionType::SymTabShndx
+                    | SectionType::GnuVersym
+                    | SectionType::GnuVerdef
+                    | SectionType::GnuVerneed
+            );
+            if links_to_section && sh.link as usize >= count {
+                res.push(Diagnostic::SectionLinkOutOfRange {
+                    index,
+                    kind: r#type,
+                    link: sh.link,
+                });
+            }
+
+            let Some(expected) = expected_entsize(self.class, r#type) else {
+                continue;
+            };
+            if sh.entsize.0 != expected {
+                res.push(Diagnostic::SectionEntsize {
+                    index,
+                    kind: r#type,
+                    entsize: sh.entsize.0,
+                    expected,
+                });
+            } else if sh.size.0 % expected != 0 {
+                res.push(Diagnostic::SectionSizeNotMultipleOfEntsize {
+                    index,
+                    kind: r#type,
+                    size: sh.size.0,
+                    entsize: expected,
+                });
+            }
+        }
+    }
+}
+
+/// `offset..offset + size`, where an overflow gives an empty-looking, backwards range
+fn raw_range(offset: u64, size: u64) -> Range<u64> {
+    offset..offset.wrapping_add(size)
+}
+
+/// The entry size sections of a given type must have, for sections that are tables
+fn expected_entsize(class: Class, r#type: SectionType) -> Option<u64> {
+    let size = match r#type {
+        SectionType::SymTab | SectionType::DynSym => Sym::size(class),
+        SectionType::Rela => Rela::size(class),
+        SectionType::Rel => Rel::size(class),
+        SectionType::Dynamic => DynamicEntry::size(class),
+        SectionType::Hash | SectionType::Group | SectionType::SymTabShndx => 4,
+        SectionType::GnuVersym => 2,
+        _ => return None,
+    };
+    Some(size as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Ctx, Endianness, FileContents, tests::tiny_elf};
+
+    const CTXS: [Ctx; 4] = [
+        Ctx {
+            class: Class::Elf32,
+            endianness: Endianness::Little,
+        },
+        Ctx {
+            class: Class::Elf32,
+            endianness: Endianness::Big,
+        },
+        Ctx {
+            class: Class::Elf64,
+            endianness: Endianness::Little,
+        },
+        Ctx {
+            class: Class::Elf64,
+            endianness: Endianness::Big,
+        },
+    ];
+
+    #[test]
+    fn well_formed_files_are_clean() {
+        for ctx in CTXS {
+            let input = tiny_elf(ctx);
+            let file = File::parse_or_print_error(&input[..]).unwrap();
+            assert_eq!(file.validate(), [], "{ctx:?}");
+        }
+        for path in ["/proc/self/exe", "/usr/lib/x86_64-linux-gnu/libc.so.6"] {
+            let Ok(input) = std::fs::read(path) else {
+                continue;
+            };
+            let file = File::parse_or_print_error(input).unwrap();
+            let errors: Vec<_> = file
+                .validate()
+                .into_iter()
+                .filter(|d| d.severity() == Severity::Error)
+                .collect();
+            assert_eq!(errors, [], "{path}");
+        }
+    }
+
+    #[test]
+    fn reports_structural_problems() {
+        let ctx = CTXS[2];
+        let mut input = tiny_elf(ctx);
+        let file = File::parse_or_print_error(&input[..]).unwrap();
+        let ph_offset = file.ph_offset.0 as usize;
+        let sh_offset = file.sh_offset.0 as usize;
+
+        // second section: make it reach past the end of the file
+        let sh = sh_offset + 64;
+        input[sh + 32..sh + 40].copy_from_slice(&0x10_0000u64.to_le_bytes());
+        // shstrndx
+        input[62..64].copy_from_slice(&7u16.to_le_bytes());
+        // first segment: misaligned
+        input[ph_offset + 16..ph_offset + 24].copy_from_slice(&0x10u64.to_le_bytes());
+
+        let file = File::parse_or_print_error(&input[..]).unwrap();
+        let diagnostics = file.validate();
+        assert!(diagnostics.contains(&Diagnostic::BadShstrndx {
+            shstrndx: 7,
+            count: 2
+        }));
+        assert!(diagnostics.iter().any(|d| matches!(
+            d,
+            Diagnostic::SectionOutsideFile {
+                index: 1,
+                kind: SectionType::StrTab,
+                ..
+            }
+        )));
+        assert!(
+            diagnostics
+                .iter()
+                .any(|d| matches!(d, Diagnostic::MisalignedLoadSegment { index: 0, .. }))
+        );
+        // accessors don't panic on the broken file either
+        assert_eq!(file.shstrtab_entry(crate::Addr(1)), b"");
+        assert!(file.section_slice(&file.section_headers[1]).is_empty());
+    }
+
+    /// Deterministic version of the `parse` fuzz target: parsing and validating
+    /// mangled files must never panic.
+    #[test]
+    fn mangled_files_never_panic() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for ctx in CTXS {
+            let input = tiny_elf(ctx);
+            for len in 0..=input.len() {
+                check(&input[..len]);
+            }
+            for _ in 0..2000 {
+                let mut input = input.clone();
+                for _ in 0..(next() % 8 + 1) {
+                    let at = next() as usize % input.len();
+                    input[at] = match next() % 4 {
+                        0 => 0,
+                        1 => 0xff,
+                        _ => next() as u8,
+                    };
+                }
+                check(&input);
+            }
+        }
+    }
+
+    fn check(input: &[u8]) {
+        if let Ok((_, contents)) = FileContents::parse(input) {
+            let file = File { input, contents };
+            let _ = file.validate();
+            for sh in &file.section_headers {
+                let _ = file.section_name(sh);
+                let _ = file.section_slice(sh);
+            }
+            for ph in &file.program_headers {
+                let _ = file.segment_slice(ph);
+            }
+        }
+    }
+}
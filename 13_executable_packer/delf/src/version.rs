@@ -0,0 +1,345 @@
+use std::fmt;
+
+use crate::{Addr, DynamicTag, File, GetDynamicEntryError, ReadSymsError, parse};
+use nom::Parser as _;
+
+/// An entry of the `DT_VERSYM` table: one per `.dynsym` entry, the low 15 bits
+/// are an index into the version definitions/needs, the top bit marks the symbol
+/// as hidden (not a default version)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Versym(pub u16);
+
+impl Versym {
+    /// `VER_NDX_LOCAL`: the symbol is local and not available outside the object
+    pub const LOCAL: u16 = 0;
+    /// `VER_NDX_GLOBAL`: the symbol is global and unversioned
+    pub const GLOBAL: u16 = 1;
+
+    pub fn parse<'a>(ctx: parse::Ctx) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |i| nom::combinator::map(ctx.u16(), Self).parse(i)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.0 & 0x7fff
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.0 & 0x8000 != 0
+    }
+}
+
+/// An `Elfxx_Verdef` entry along with the names from its `Elfxx_Verdaux` chain.
+/// The first name is the version itself, the following ones are its predecessors.
+#[derive(Debug, Clone)]
+pub struct VersionDefinition {
+    pub flags: u16,
+    pub index: u16,
+    pub hash: u32,
+    pub names: Vec<Addr>,
+}
+
+impl VersionDefinition {
+    /// `VER_FLG_BASE`: this definition is the file itself, not a real version
+    pub const FLAG_BASE: u16 = 0x1;
+
+    pub fn is_base(&self) -> bool {
+        self.flags & Self::FLAG_BASE != 0
+    }
+}
+
+/// An `Elfxx_Verneed` entry: the versions required from a single dependency
+#[derive(Debug, Clone)]
+pub struct VersionNeed {
+    pub file: Addr,
+    pub versions: Vec<VersionNeedAux>,
+}
+
+/// An `Elfxx_Vernaux` entry
+#[derive(Debug, Clone)]
+pub struct VersionNeedAux {
+    pub hash: u32,
+    pub flags: u16,
+    /// Index referenced from `DT_VERSYM` (`vna_other`)
+    pub index: u16,
+    pub name: Addr,
+}
+
+/// The resolved version of a `.dynsym` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolVersion<'a> {
+    pub name: &'a [u8],
+    /// For needed versions, the dependency they're required from
+    pub file: Option<&'a [u8]>,
+    pub hidden: bool,
+}
+
+impl SymbolVersion<'_> {
+    /// Whether this version is defined by the object itself (as opposed to needed)
+    pub fn is_defined(&self) -> bool {
+        self.file.is_none()
+    }
+}
+
+impl fmt::Display for SymbolVersion<'_> {
+    /// Formats the suffix binutils appends to a symbol name: `@@VER` for the
+    /// default definition, `@VER` for anything else
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = if self.is_defined() && !self.hidden {
+            "@@"
+        } else {
+            "@"
+        };
+        write!(f, "{at}{}", String::from_utf8_lossy(self.name))
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Read the `DT_VERSYM` table, which has as many entries as `.dynsym`
+    pub fn read_versym_entries(&self) -> Result<Vec<Versym>, ReadVersionError> {
+        let Some(addr) = self.dynamic_entry(DynamicTag::VerSym) else {
+            return Ok(Vec::new());
+        };
+        let n = self.read_dynsym_entries()?.len();
+        let i = self
+            .mem_slice_from(addr)
+            .filter(|i| i.len() >= n * 2)
+            .ok_or(ReadVersionError::SegmentNotFound(DynamicTag::VerSym))?;
+        parse_all(nom::multi::many_m_n(n, n, Versym::parse(self.ctx())), i)
+    }
+
+    /// Read the `DT_VERDEF` chain
+    pub fn read_verdef_entries(&self) -> Result<Vec<VersionDefinition>, ReadVersionError> {
+        let Some(addr) = self.dynamic_entry(DynamicTag::VerDef) else {
+            return Ok(Vec::new());
+        };
+        let count = self.get_dynamic_entry(DynamicTag::VerDefNum)?.0;
+        let table = self
+            .mem_slice_from(addr)
+            .ok_or(ReadVersionError::SegmentNotFound(DynamicTag::VerDef))?;
+        let ctx = self.ctx();
+
+        let mut res = Vec::new();
+        let mut offset = 0usize;
+        for _ in 0..count {
+            let i = table.get(offset..).ok_or(ReadVersionError::OutOfBounds)?;
+            // Elfxx_Verdef: version, flags, ndx, cnt, hash, aux, next
+            let (version, flags, index, aux_count, hash, aux, next) = parse_all(
+                (
+                    ctx.u16(),
+                    ctx.u16(),
+                    ctx.u16(),
+                    ctx.u16(),
+                    ctx.u32(),
+                    ctx.u32(),
+                    ctx.u32(),
+                ),
+                i,
+            )?;
+            if version != 1 {
+                return Err(ReadVersionError::UnsupportedVersion(version));
+            }
+
+            let mut names = Vec::new();
+            let mut aux_offset = offset + aux as usize;
+            for _ in 0..aux_count {
+                let i = table
+                    .get(aux_offset..)
+                    .ok_or(ReadVersionError::OutOfBounds)?;
+                // Elfxx_Verdaux: name, next
+                let (name, next) = parse_all((ctx.u32(), ctx.u32()), i)?;
+                names.push(Addr(name as u64));
+                if next == 0 {
+                    break;
+                }
+                aux_offset += next as usize;
+            }
+
+            res.push(VersionDefinition {
+                flags,
+                index,
+                hash,
+                names,
+            });
+            if next == 0 {
+                break;
+            }
+            offset += next as usize;
+        }
+        Ok(res)
+    }
+
+    /// Read the `DT_VERNEED` chain
+    pub fn read_verneed_entries(&self) -> Result<Vec<VersionNeed>, ReadVersionError> {
+        let Some(addr) = self.dynamic_entry(DynamicTag::VerNeed) else {
+            return Ok(Vec::new());
+        };
+        let count = self.get_dynamic_entry(DynamicTag::VER_NEED_NUM)?.0;
+        let table = self
+            .mem_slice_from(addr)
+            .ok_or(ReadVersionError::SegmentNotFound(DynamicTag::VerNeed))?;
+        let ctx = self.ctx();
+
+        let mut res = Vec::new();
+        let mut offset = 0usize;
+        for _ in 0..count {
+            let i = table.get(offset..).ok_or(ReadVersionError::OutOfBounds)?;
+            // Elfxx_Verneed: version, cnt, file, aux, next
+            let (version, aux_count, file, aux, next) =
+                parse_all((ctx.u16(), ctx.u16(), ctx.u32(), ctx.u32(), ctx.u32()), i)?;
+            if version != 1 {
+                return Err(ReadVersionError::UnsupportedVersion(version));
+            }
+
+            let mut versions = Vec::new();
+            let mut aux_offset = offset + aux as usize;
+            for _ in 0..aux_count {
+                let i = table
+                    .get(aux_offset..)
+                    .ok_or(ReadVersionError::OutOfBounds)?;
+                // Elfxx_Vernaux: hash, flags, other, name, next
+                let (hash, flags, index, name, next) =
+                    parse_all((ctx.u32(), ctx.u16(), ctx.u16(), ctx.u32(), ctx.u32()), i)?;
+                versions.push(VersionNeedAux {
+                    hash,
+                    flags,
+                    index,
+                    name: Addr(name as u64),
+                });
+                if next == 0 {
+                    break;
+                }
+                aux_offset += next as usize;
+            }
+
+            res.push(VersionNeed {
+                file: Addr(file as u64),
+                versions,
+            });
+            if next == 0 {
+                break;
+            }
+            offset += next as usize;
+        }
+        Ok(res)
+    }
+
+    /// Resolve the version of every `.dynsym` entry: the result is indexed like
+    /// `read_dynsym_entries`, and is `None` for local and unversioned symbols
+    /// (or for every symbol, if the object has no version information at all).
+    pub fn read_dynsym_versions(&self) -> Result<Vec<Option<SymbolVersion<'_>>>, ReadVersionError> {
+        let versyms = self.read_versym_entries()?;
+        let verdefs = self.read_verdef_entries()?;
+        let verneeds = self.read_verneed_entries()?;
+
+        let lookup = |versym: Versym| -> Option<SymbolVersion<'_>> {
+            let index = versym.index();
+            if index == Versym::LOCAL || index == Versym::GLOBAL {
+                return None;
+            }
+            let hidden = versym.is_hidden();
+            if let Some(def) = verdefs.iter().find(|def| def.index == index) {
+                return def.names.first().map(|&name| SymbolVersion {
+                    name: self.dynstr_entry(name),
+                    file: None,
+                    hidden,
+                });
+            }
+            verneeds.iter().find_map(|need| {
+                need.versions
+                    .iter()
+                    .find(|aux| aux.index == index)
+                    .map(|aux| SymbolVersion {
+                        name: self.dynstr_entry(aux.name),
+                        file: Some(self.dynstr_entry(need.file)),
+                        hidden,
+                    })
+            })
+        };
+
+        Ok(versyms.into_iter().map(lookup).collect())
+    }
+}
+
+fn parse_all<'a, T, P>(mut parser: P, i: parse::Input<'a>) -> Result<T, ReadVersionError>
+where
+    P: nom::Parser<parse::Input<'a>, Output = T, Error = parse::Error<parse::Input<'a>>>,
+{
+    match parser.parse(i) {
+        Ok((_, res)) => Ok(res),
+        Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+            Err(ReadVersionError::ParsingError(format!("{err:?}")))
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadVersionError {
+    #[error("{0}")]
+    DynamicEntryNotFound(#[from] GetDynamicEntryError),
+    #[error("{0}")]
+    ReadSyms(#[from] ReadSymsError),
+    #[error("{0:?} segment not found")]
+    SegmentNotFound(DynamicTag),
+    #[error("Version table entry out of bounds")]
+    OutOfBounds,
+    #[error("Unsupported version structure revision {0}")]
+    UnsupportedVersion(u16),
+    #[error("Parsing error: {0}")]
+    ParsingError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versioned_names(file: &File<Vec<u8>>) -> Vec<String> {
+        let syms = file.read_dynsym_entries().unwrap();
+        let versions = file.read_dynsym_versions().unwrap();
+        assert_eq!(syms.len(), versions.len());
+        syms.iter()
+            .zip(versions)
+            .filter_map(|(sym, version)| {
+                let version = version?;
+                let name = String::from_utf8_lossy(file.dynstr_entry(sym.name));
+                Some(format!("{name}{version}"))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn libc_defines_memcpy_versions() {
+        let input = std::fs::read(crate::test_util::libc_path()).unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+        let names = versioned_names(&file);
+        assert!(names.iter().any(|n| n == "memcpy@@GLIBC_2.14"));
+        assert!(names.iter().any(|n| n == "memcpy@GLIBC_2.2.5"));
+
+        let defs = file.read_verdef_entries().unwrap();
+        assert!(defs.first().is_some_and(|def| def.is_base()));
+    }
+
+    #[test]
+    fn self_needs_libc_versions() {
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        let file = File::parse_or_print_error(input).unwrap();
+
+        let needs = file.read_verneed_entries().unwrap();
+        let libc = needs
+            .iter()
+            .find(|need| file.dynstr_entry(need.file) == b"libc.so.6")
+            .expect("test binary should need versions from libc");
+        assert!(
+            libc.versions
+                .iter()
+                .all(|aux| file.dynstr_entry(aux.name).starts_with(b"GLIBC_"))
+        );
+
+        for version in file.read_dynsym_versions().unwrap().into_iter().flatten() {
+            assert!(!version.is_defined());
+        }
+    }
+}
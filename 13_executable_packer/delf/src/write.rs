@@ -0,0 +1,567 @@
+use std::ops::Range;
+
+use crate::{
+    Addr, Class, Ctx, DynamicEntry, DynamicTag, Endianness, File, FileContents,
+    GetDynamicEntryError, ProgramHeader, SectionHeader, SectionType, SegmentContents, SegmentFlag,
+    SegmentType,
+};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+impl Ctx {
+    pub fn write_u16(&self, out: &mut Vec<u8>, x: u16) {
+        match self.endianness {
+            Endianness::Little => out.extend(x.to_le_bytes()),
+            Endianness::Big => out.extend(x.to_be_bytes()),
+        }
+    }
+
+    pub fn write_u32(&self, out: &mut Vec<u8>, x: u32) {
+        match self.endianness {
+            Endianness::Little => out.extend(x.to_le_bytes()),
+            Endianness::Big => out.extend(x.to_be_bytes()),
+        }
+    }
+
+    pub fn write_u64(&self, out: &mut Vec<u8>, x: u64) {
+        match self.endianness {
+            Endianness::Little => out.extend(x.to_le_bytes()),
+            Endianness::Big => out.extend(x.to_be_bytes()),
+        }
+    }
+
+    /// Writes a class-sized word, truncating it to 32 bits for ELF32
+    pub fn write_word(&self, out: &mut Vec<u8>, x: u64) {
+        match self.class {
+            Class::Elf32 => self.write_u32(out, x as u32),
+            Class::Elf64 => self.write_u64(out, x),
+        }
+    }
+}
+
+impl FileContents {
+    /// Serializes the ELF file header, with the given program header table placement
+    fn write_header(&self, ph_offset: Addr, ph_count: usize, sh_offset: Addr, out: &mut Vec<u8>) {
+        let ctx = self.ctx();
+        let entsize = |count: usize, size: usize| if count == 0 { 0 } else { size as u16 };
+
+        out.extend(Self::MAGIC);
+        out.extend([self.class as u8, self.endianness as u8, 1, self.os_abi]);
+        out.push(self.abi_version);
+        out.extend([0; 7]);
+        ctx.write_u16(out, self.r#type as u16);
+        ctx.write_u16(out, self.machine as u16);
+        ctx.write_u32(out, 1);
+        ctx.write_word(out, self.entry_point.0);
+        ctx.write_word(out, ph_offset.0);
+        ctx.write_word(out, sh_offset.0);
+        ctx.write_u32(out, self.flags);
+        ctx.write_u16(out, Self::header_size(self.class) as u16);
+        ctx.write_u16(out, entsize(ph_count, ProgramHeader::size(self.class)));
+        ctx.write_u16(out, ph_count as u16);
+        ctx.write_u16(
+            out,
+            entsize(self.section_headers.len(), SectionHeader::size(self.class)),
+        );
+        ctx.write_u16(out, self.section_headers.len() as u16);
+        ctx.write_u16(out, self.shstrndx as u16);
+    }
+
+    /// Returns false if `range` overlaps the file header or the contents of any section or
+    /// non-Load segment
+    fn is_free(&self, range: &Range<u64>) -> bool {
+        let overlaps = |r: Range<Addr>| r.start.0 < range.end && range.start < r.end.0;
+        let header = Addr(0)..Addr(Self::header_size(self.class) as u64);
+
+        !overlaps(header)
+            && !self
+                .section_headers
+                .iter()
+                .filter(|sh| !matches!(sh.r#type, SectionType::Null | SectionType::NoBits))
+                .any(|sh| overlaps(sh.file_range()))
+            && !self
+                .program_headers
+                .iter()
+                .filter(|ph| !matches!(ph.r#type, SegmentType::Load | SegmentType::PHdr))
+                .any(|ph| overlaps(ph.file_range()))
+    }
+
+    /// Decides where the program header table goes. Returns its file offset and the program
+    /// headers to write, which may include an extra Load segment mapping the table.
+    fn layout_program_headers(&self, file_len: u64) -> (Addr, Vec<ProgramHeader>) {
+        let ph_size = ProgramHeader::size(self.class) as u64;
+        let mut program_headers = self.program_headers.clone();
+        if program_headers.is_empty() {
+            return (self.ph_offset, program_headers);
+        }
+
+        let has_phdr = program_headers
+            .iter()
+            .any(|ph| ph.r#type == SegmentType::PHdr);
+        let in_place = self.ph_offset.0..self.ph_offset.0 + ph_size * program_headers.len() as u64;
+        // With PT_PHDR the table must stay mapped, so it has to remain inside its Load segment.
+        let still_mapped = !has_phdr
+            || program_headers.iter().any(|ph| {
+                ph.r#type == SegmentType::Load
+                    && ph.file_range().contains(&self.ph_offset)
+                    && in_place.end <= (ph.offset + ph.filesz).0
+            });
+
+        let (offset, vaddr) = if self.is_free(&in_place) && still_mapped {
+            let vaddr = program_headers
+                .iter()
+                .find(|ph| ph.r#type == SegmentType::PHdr)
+                .map(|ph| ph.vaddr)
+                .unwrap_or_default();
+            (self.ph_offset, vaddr)
+        } else if has_phdr {
+            // Move the table past everything else and give it its own Load segment, placed
+            // after the last one so Load segments stay sorted by address.
+            let offset = Addr(align_up(file_len, PAGE_SIZE));
+            let vaddr = Addr(align_up(
+                program_headers
+                    .iter()
+                    .filter(|ph| ph.r#type == SegmentType::Load)
+                    .map(|ph| (ph.vaddr + ph.memsz).0)
+                    .max()
+                    .unwrap_or_default(),
+                PAGE_SIZE,
+            ));
+            let size = Addr(ph_size * (program_headers.len() as u64 + 1));
+            let load = ProgramHeader {
+                r#type: SegmentType::Load,
+                flags: SegmentFlag::Read.into(),
+                offset,
+                vaddr,
+                paddr: vaddr,
+                filesz: size,
+                memsz: size,
+                align: Addr(PAGE_SIZE),
+                contents: SegmentContents::Unknown,
+            };
+            let index = program_headers
+                .iter()
+                .rposition(|ph| ph.r#type == SegmentType::Load)
+                .map_or(program_headers.len(), |i| i + 1);
+            program_headers.insert(index, load);
+            (offset, vaddr)
+        } else {
+            (Addr(align_up(file_len, 8)), Addr(0))
+        };
+
+        let size = Addr(ph_size * program_headers.len() as u64);
+        for ph in program_headers
+            .iter_mut()
+            .filter(|ph| ph.r#type == SegmentType::PHdr)
+        {
+            ph.offset = offset;
+            ph.vaddr = vaddr;
+            ph.paddr = vaddr;
+            ph.filesz = size;
+            ph.memsz = size;
+        }
+        (offset, program_headers)
+    }
+}
+
+impl ProgramHeader {
+    pub fn write(&self, ctx: Ctx, out: &mut Vec<u8>) {
+        let flags = self.flags.bits();
+        ctx.write_u32(out, self.r#type as u32);
+        if ctx.class == Class::Elf64 {
+            ctx.write_u32(out, flags);
+        }
+        for x in [self.offset, self.vaddr, self.paddr, self.filesz, self.memsz] {
+            ctx.write_word(out, x.0);
+        }
+        if ctx.class == Class::Elf32 {
+            ctx.write_u32(out, flags);
+        }
+        ctx.write_word(out, self.align.0);
+    }
+}
+
+impl SectionHeader {
+    pub fn write(&self, ctx: Ctx, out: &mut Vec<u8>) {
+        ctx.write_u32(out, self.name.0 as u32);
+        ctx.write_u32(out, self.r#type as u32);
+        ctx.write_word(out, self.flags);
+        for x in [self.addr, self.offset, self.size] {
+            ctx.write_word(out, x.0);
+        }
+        ctx.write_u32(out, self.link);
+        ctx.write_u32(out, self.info);
+        ctx.write_word(out, self.addralign.0);
+        ctx.write_word(out, self.entsize.0);
+    }
+}
+
+impl DynamicEntry {
+    pub fn write(&self, ctx: Ctx, out: &mut Vec<u8>) {
+        ctx.write_word(out, self.tag as u64);
+        ctx.write_word(out, self.addr.0);
+    }
+}
+
+impl<I> File<I>
+where
+    I: AsRef<[u8]>,
+{
+    /// Serializes the (possibly modified) file header, program headers, section headers and
+    /// dynamic table on top of the original file contents.
+    ///
+    /// If the program header table grew and no longer fits where it was, it is moved to the
+    /// end of the file; when there is a `PT_PHDR`, an extra Load segment maps it there.
+    pub fn write(&self) -> Result<Vec<u8>, WriteError> {
+        let ctx = self.ctx();
+        let mut out = self.input.as_ref().to_vec();
+
+        let (ph_offset, program_headers) = self.layout_program_headers(out.len() as u64);
+        let ph_end = ph_offset.0 + (ProgramHeader::size(self.class) * program_headers.len()) as u64;
+
+        let sh_size = SectionHeader::size(self.class) as u64;
+        let mut sh_offset = self.sh_offset;
+        let sh_table = sh_offset.0..sh_offset.0 + sh_size * self.section_headers.len() as u64;
+        let clashes_with_ph = sh_table.start < ph_end && ph_offset.0 < sh_table.end;
+        if !self.section_headers.is_empty() && (!self.is_free(&sh_table) || clashes_with_ph) {
+            let end = (out.len() as u64).max(ph_end);
+            sh_offset = Addr(align_up(end, ctx.word_size() as u64));
+        }
+
+        let mut buf = Vec::new();
+        self.write_header(ph_offset, program_headers.len(), sh_offset, &mut buf);
+        put(&mut out, 0, &buf);
+
+        buf.clear();
+        for ph in &program_headers {
+            ph.write(ctx, &mut buf);
+        }
+        put(&mut out, ph_offset.0, &buf);
+
+        buf.clear();
+        for sh in &self.section_headers {
+            sh.write(ctx, &mut buf);
+        }
+        put(&mut out, sh_offset.0, &buf);
+
+        if let Some(ph) = program_headers
+            .iter()
+            .find(|ph| ph.r#type == SegmentType::Dynamic)
+            && let SegmentContents::Dynamic(entries) = &ph.contents
+        {
+            buf.clear();
+            let null = DynamicEntry {
+                tag: DynamicTag::Null,
+                addr: Addr(0),
+            };
+            for entry in entries.iter().chain(std::iter::once(&null)) {
+                entry.write(ctx, &mut buf);
+            }
+            let available: usize = ph.filesz.into();
+            if buf.len() > available {
+                return Err(WriteError::DynamicTableTooLarge {
+                    needed: buf.len(),
+                    available,
+                });
+            }
+            // Pad with DT_NULL entries, like linkers do.
+            buf.resize(available, 0);
+            put(&mut out, ph.offset.0, &buf);
+        }
+
+        Ok(out)
+    }
+}
+
+impl File<Vec<u8>> {
+    /// Appends `data` at the end of the file, aligned to `align`, and returns its file offset.
+    /// Useful to give a new segment (e.g. a `PT_NOTE`) some contents.
+    pub fn append(&mut self, data: &[u8], align: u64) -> Addr {
+        let offset = align_up(self.input.len() as u64, align);
+        self.input.resize(offset as usize, 0);
+        self.input.extend_from_slice(data);
+        Addr(offset)
+    }
+
+    /// Rewrites, in place, the string referenced by the first dynamic entry with the given tag
+    /// (e.g. `DynamicTag::RunPath`). The new value can't be longer than the old one.
+    pub fn set_dynamic_string(&mut self, tag: DynamicTag, value: &[u8]) -> Result<(), WriteError> {
+        let strtab = self.get_dynamic_entry(DynamicTag::StrTab)?;
+        let addr = strtab + self.get_dynamic_entry(tag)?;
+        let start: usize = self
+            .file_offset(addr)
+            .ok_or(WriteError::AddressNotMapped(addr))?
+            .into();
+
+        let available = self.input[start..]
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or(WriteError::AddressNotMapped(addr))?;
+        if value.len() > available {
+            return Err(WriteError::StringTooLong {
+                new: value.len(),
+                available,
+            });
+        }
+
+        let dst = &mut self.input[start..start + available];
+        dst[..value.len()].copy_from_slice(value);
+        dst[value.len()..].fill(0);
+        Ok(())
+    }
+}
+
+/// Copies `bytes` at `offset`, growing `out` with zeroes if needed
+fn put(out: &mut Vec<u8>, offset: u64, bytes: &[u8]) {
+    let offset = offset as usize;
+    if out.len() < offset + bytes.len() {
+        out.resize(offset + bytes.len(), 0);
+    }
+    out[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn align_up(x: u64, align: u64) -> u64 {
+    if align <= 1 {
+        x
+    } else {
+        x.div_ceil(align) * align
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WriteError {
+    #[error("{0}")]
+    DynamicEntryNotFound(#[from] GetDynamicEntryError),
+    #[error("dynamic table needs {needed} bytes, but the segment only has {available}")]
+    DynamicTableTooLarge { needed: usize, available: usize },
+    #[error("new string needs {new} bytes, but only {available} are available in place")]
+    StringTooLong { new: usize, available: usize },
+    #[error("address {0:?} is not backed by the file")]
+    AddressNotMapped(Addr),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::test_util;
+
+    /// Every ELF file we can find: built samples, a few objects built here,
+    /// the test binary itself and libc
+    fn elf_inputs() -> Vec<(String, Vec<u8>)> {
+        let samples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../samples");
+        let mut paths: Vec<_> = std::fs::read_dir(samples)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+        let dir = test_util::temp_dir("write");
+        paths.extend(build_inputs(&dir));
+        paths.push("/proc/self/exe".into());
+        paths.push(test_util::libc_path());
+
+        let inputs = paths
+            .into_iter()
+            .map(|path| (path.display().to_string(), std::fs::read(&path).unwrap()))
+            .filter(|(_, input)| input.starts_with(FileContents::MAGIC))
+            .collect();
+        std::fs::remove_dir_all(&dir).ok();
+        inputs
+    }
+
+    /// Builds a relocatable object, a PIE, a non-PIE and a shared library in
+    /// `dir`, since the samples aren't built on a fresh checkout
+    fn build_inputs(dir: &Path) -> Vec<PathBuf> {
+        std::fs::write(
+            dir.join("round.c"),
+            "int counter = 3;\nint bump(int x) { return counter += x; }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.c"),
+            "int bump(int);\nint main(int argc, char **argv) { return bump(argc); }\n",
+        )
+        .unwrap();
+
+        let builds: [(&str, &[&str]); 5] = [
+            ("round.o", &["-c", "round.c"]),
+            ("round-pie", &["-pie", "main.c", "round.c"]),
+            ("round-nopie", &["-no-pie", "main.c", "round.c"]),
+            ("round.so", &["-shared", "-fPIC", "round.c"]),
+            (
+                "round-runpath",
+                &[
+                    "main.c",
+                    "round.c",
+                    "-Wl,--enable-new-dtags,-rpath,/opt/delf/lib",
+                ],
+            ),
+        ];
+        builds
+            .into_iter()
+            .map(|(name, args)| {
+                let mut args = args.to_vec();
+                args.extend(["-o", name]);
+                test_util::gcc(dir, &args);
+                dir.join(name)
+            })
+            .collect()
+    }
+
+    fn self_exe() -> File<Vec<u8>> {
+        let input = std::fs::read("/proc/self/exe").unwrap();
+        File::parse_or_print_error(input).unwrap()
+    }
+
+    #[test]
+    fn round_trip_is_identical() {
+        let mut checked = Vec::new();
+        for (path, input) in elf_inputs() {
+            let file = File::parse_or_print_error(&input[..])
+                .unwrap_or_else(|| panic!("{path} doesn't parse"));
+            let output = file.write().unwrap();
+            assert!(output == input, "round trip changed {path}");
+            checked.push(path);
+        }
+        // the test binary, and everything built for the test
+        for name in ["/proc/self/exe", "round.o", "round.so", "round-runpath"] {
+            assert!(
+                checked.iter().any(|path| path.ends_with(name)),
+                "{name} wasn't checked: {checked:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn patch_entry_point() {
+        let mut file = self_exe();
+        file.contents.entry_point = Addr(0xdead_beef);
+
+        let output = file.write().unwrap();
+        let patched = File::parse_or_print_error(&output[..]).unwrap();
+        assert_eq!(patched.entry_point, Addr(0xdead_beef));
+        assert_eq!(output.len(), file.input.len());
+    }
+
+    #[test]
+    fn add_note_segment() {
+        let mut file = self_exe();
+        let note = b"\x04\0\0\0\x04\0\0\0\x2a\0\0\0elk\0\x01\x02\x03\x04";
+        let offset = file.append(note, 4);
+        let size = Addr(note.len() as u64);
+        file.contents.program_headers.push(ProgramHeader {
+            r#type: SegmentType::Note,
+            flags: SegmentFlag::Read.into(),
+            offset,
+            vaddr: Addr(0),
+            paddr: Addr(0),
+            filesz: size,
+            memsz: size,
+            align: Addr(4),
+            contents: SegmentContents::Unknown,
+        });
+
+        let output = file.write().unwrap();
+        let patched = File::parse_or_print_error(&output[..]).unwrap();
+
+        // the table didn't fit in place, so it moved and got its own Load segment
+        let count = file.program_headers.len();
+        assert_eq!(patched.program_headers.len(), count + 1);
+        let phdr = patched.segment_of_type(SegmentType::PHdr).unwrap();
+        assert_eq!(phdr.offset, patched.ph_offset);
+        let load = patched.segment_containing(phdr.vaddr).unwrap();
+        assert_eq!(load.offset, phdr.offset);
+
+        let notes: Vec<_> = patched
+            .program_headers
+            .iter()
+            .filter(|ph| ph.r#type == SegmentType::Note)
+            .collect();
+        assert_eq!(patched.segment_slice(notes.last().unwrap()), note);
+
+        // everything that was already there is still readable
+        assert_eq!(
+            patched.read_dynsym_entries().unwrap().len(),
+            file.read_dynsym_entries().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn rewrite_dynamic_string() {
+        let mut file = self_exe();
+        let needed = file
+            .dynamic_entry_strings(DynamicTag::Needed)
+            .next()
+            .unwrap();
+        let shorter = needed[..needed.len() - 1].to_vec();
+        let longer = [needed, b"-too-long"].concat();
+
+        assert!(matches!(
+            file.set_dynamic_string(DynamicTag::Needed, &longer),
+            Err(WriteError::StringTooLong { .. })
+        ));
+        file.set_dynamic_string(DynamicTag::Needed, &shorter)
+            .unwrap();
+
+        let output = file.write().unwrap();
+        let patched = File::parse_or_print_error(&output[..]).unwrap();
+        assert_eq!(
+            patched.dynamic_entry_strings(DynamicTag::Needed).next(),
+            Some(&shorter[..])
+        );
+    }
+
+    #[test]
+    fn rewrite_runpath() {
+        let dir = test_util::temp_dir("runpath");
+        let [.., runpath] = &build_inputs(&dir)[..] else {
+            unreachable!()
+        };
+        let mut file = File::parse_or_print_error(std::fs::read(runpath).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            file.dynamic_entry_strings(DynamicTag::RunPath).next(),
+            Some(&b"/opt/delf/lib"[..])
+        );
+
+        assert!(matches!(
+            file.set_dynamic_string(DynamicTag::RunPath, b"/opt/delf/lib:/usr/local/lib"),
+            Err(WriteError::StringTooLong { .. })
+        ));
+        file.set_dynamic_string(DynamicTag::RunPath, b"$ORIGIN")
+            .unwrap();
+
+        let output = file.write().unwrap();
+        let patched = File::parse_or_print_error(&output[..]).unwrap();
+        assert_eq!(
+            patched.dynamic_entry_strings(DynamicTag::RunPath).next(),
+            Some(&b"$ORIGIN"[..])
+        );
+        // the other strings didn't move
+        assert!(
+            patched
+                .dynamic_entry_strings(DynamicTag::Needed)
+                .eq(file.dynamic_entry_strings(DynamicTag::Needed))
+        );
+        assert_eq!(output.len(), file.input.len());
+    }
+
+    #[test]
+    fn dynamic_table_must_fit() {
+        let mut file = self_exe();
+        let entries = file.contents.dynamic_table_mut().unwrap();
+        let extra = entries.len();
+        entries.extend((0..extra).map(|_| DynamicEntry {
+            tag: DynamicTag::Debug,
+            addr: Addr(0),
+        }));
+        assert!(matches!(
+            file.write(),
+            Err(WriteError::DynamicTableTooLarge { .. })
+        ));
+    }
+}
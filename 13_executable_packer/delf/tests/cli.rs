@@ -0,0 +1,155 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde_json::Value;
+
+/// Builds a small PIE with a global function, a global variable and a libc
+/// import in a fresh `delf-cli-{name}-{pid}` directory
+fn fixture(name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("delf-cli-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = r#"
+#include <stdio.h>
+int counter = 3;
+int bump(int x) { return counter += x; }
+int main(int argc, char **argv) { printf("%d\n", bump(argc)); return 0; }
+"#;
+    std::fs::write(dir.join("fixture.c"), source).unwrap();
+    let status = Command::new("gcc")
+        .current_dir(&dir)
+        .args(["-pie", "-fPIE", "fixture.c", "-o", "fixture"])
+        .status()
+        .expect("gcc is needed to build the test fixture");
+    assert!(status.success(), "gcc failed");
+    let path = dir.join("fixture");
+    (dir, path)
+}
+
+/// Runs the `delf` binary, which must succeed, and returns its output
+fn delf(args: &[&str], path: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_delf"))
+        .args(args)
+        .arg(path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "delf {args:?}: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn json(args: &[&str], path: &Path) -> Value {
+    let mut args = args.to_vec();
+    args.push("--json");
+    let stdout = delf(&args, path);
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{e}: {stdout}"))
+}
+
+#[test]
+fn header_and_symbols() {
+    let (dir, path) = fixture("header");
+    let file = delf::File::parse_or_print_error(std::fs::read(&path).unwrap()).unwrap();
+
+    // text: a title, then one aligned field per line
+    let text = delf(&["header"], &path);
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("ELF header:"));
+    let fields: Vec<Vec<&str>> = lines
+        .map(|line| line.split_whitespace().collect())
+        .filter(|fields: &Vec<_>| !fields.is_empty())
+        .collect();
+    let entry_point = format!("{:016x}", file.entry_point.0);
+    for expected in [
+        ["class", "Elf64"],
+        ["type", "Dyn"],
+        ["machine", "X86_64"],
+        ["entry_point", &entry_point],
+    ] {
+        assert!(fields.contains(&expected.to_vec()), "{expected:?}: {text}");
+    }
+
+    // JSON: the same fields, addresses as plain numbers
+    let header = json(&["header"], &path);
+    assert_eq!(header["type"], "Dyn");
+    assert_eq!(header["entry_point"], file.entry_point.0);
+    assert_eq!(header["section_headers"], file.section_headers.len());
+
+    let text = delf(&["symbols"], &path);
+    assert!(text.starts_with("Symbol table \".symtab\""), "{text}");
+    assert!(
+        text.lines().any(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            // index, value, size, type, bind, shndx, name
+            matches!(fields[..], [_, _, _, "Func", "Global", _, "bump"])
+        }),
+        "{text}"
+    );
+
+    let symbols = json(&["symbols", "--dynsym"], &path);
+    let printf = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|sym| sym["name"] == "printf")
+        .expect("printf is imported");
+    assert_eq!(printf["shndx"], "Undef");
+    assert_eq!(printf["type"], "Func");
+    assert!(
+        printf["version"].as_str().unwrap().contains("GLIBC_"),
+        "{printf}"
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn all_as_json() {
+    let (dir, path) = fixture("all");
+    let all = json(&["all"], &path);
+    std::fs::remove_dir_all(&dir).ok();
+
+    // one key per report
+    for key in [
+        "header",
+        "program_headers",
+        "section_headers",
+        "dynamic",
+        "symtab",
+        "dynsym",
+        "relocations",
+        "notes",
+        "groups",
+        "eh_frame",
+    ] {
+        assert!(all.get(key).is_some(), "{key}: {all}");
+    }
+    let dynamic = all["dynamic"].as_array().unwrap();
+    assert!(
+        dynamic
+            .iter()
+            .any(|entry| entry["tag"] == "Needed" && entry["string"] == "libc.so.6"),
+        "{dynamic:?}"
+    );
+    let notes = all["notes"].as_array().unwrap();
+    assert!(!notes.is_empty());
+}
+
+#[test]
+fn not_an_elf_file() {
+    let dir = std::env::temp_dir().join(format!("delf-cli-bad-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("not-elf");
+    std::fs::write(&path, "#!/bin/sh\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_delf"))
+        .arg("header")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Fatal error: could not parse ELF file"),
+        "{stderr}"
+    );
+}
@@ -2,7 +2,8 @@ import subprocess
 
 
 class AutoSym(gdb.Command):
-    """Load symbols for all executable files mapped in memory, through elk"""
+    """Load symbols for all executable files mapped in memory, through elk.
+    Not needed with `elk run --gdbserver`, which tells gdb about them"""
 
     def __init__(self):
         super(AutoSym, self).__init__("autosym", gdb.COMMAND_USER)
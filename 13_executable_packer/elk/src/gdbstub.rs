@@ -0,0 +1,922 @@
+//! A GDB remote protocol server, living in the debugged process itself.
+//!
+//! elk runs the program on its own thread, so there's no ptrace here: the
+//! program's traps and faults land in elk's signal handlers, which talk to gdb
+//! until it resumes, and return to the program with whatever registers gdb
+//! left in the signal frame. Breakpoints are `int3` instructions, single steps
+//! set the trap flag, and gdb interrupting the program raises `SIGIO` on the
+//! socket.
+//!
+//! gdb learns about the objects elk loaded from `qXfer:libraries-svr4:read`,
+//! and is told to read that list again whenever `dlopen` or `dlclose` change
+//! it. Only the thread that stopped is stopped: others keep running.
+
+use std::{
+    collections::HashMap,
+    ffi::c_int,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering},
+};
+
+use crate::process::{
+    AuxType, Auxv, Loader, Object, running_loader, thread_pointer, with_host_tls,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum GdbError {
+    #[error("invalid address {0:?}, expected host:port or :port")]
+    InvalidAddress(String),
+    #[error("could not accept a gdb connection on {0}: {1}")]
+    IO(String, std::io::Error),
+    #[error("could not install signal handlers: {0}")]
+    Signal(std::io::Error),
+}
+
+/// The connection to gdb, once it attached
+static SESSION: AtomicPtr<Session> = AtomicPtr::new(std::ptr::null_mut());
+/// Whether gdb is attached, even while a thread holds `SESSION`
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+/// Why elk itself trapped, for the `SIGTRAP` handler
+static PENDING: AtomicU8 = AtomicU8::new(Event::None as u8);
+
+/// Faults gdb gets to look at before the program dies of them
+const FATAL_SIGNALS: [c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+/// The trap flag, in `eflags`
+const TRAP_FLAG: i64 = 0x100;
+
+/// What `g` sends: gdb's amd64 registers, up to `mxcsr`
+const G_REGISTERS: usize = 57;
+/// Past those: `orig_rax`, `fs_base` and `gs_base`
+const REGISTERS: usize = 60;
+
+/// The registers gdb numbers 0 to 16, in `mcontext_t`
+const GREGS: [c_int; 17] = [
+    libc::REG_RAX,
+    libc::REG_RBX,
+    libc::REG_RCX,
+    libc::REG_RDX,
+    libc::REG_RSI,
+    libc::REG_RDI,
+    libc::REG_RBP,
+    libc::REG_RSP,
+    libc::REG_R8,
+    libc::REG_R9,
+    libc::REG_R10,
+    libc::REG_R11,
+    libc::REG_R12,
+    libc::REG_R13,
+    libc::REG_R14,
+    libc::REG_R15,
+    libc::REG_RIP,
+];
+
+/// Why elk stops the program on its own
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Event {
+    None,
+    /// Everything is loaded, and none of the program's code ran yet
+    Start,
+    /// `dlopen` or `dlclose` changed the list of objects
+    Libraries,
+}
+
+/// Waits for gdb to connect on `addr`, then installs the signal handlers
+/// that serve it. The program stops for gdb once `stop(Event::Start)` is called
+pub fn listen(addr: &str) -> Result<(), GdbError> {
+    let bind = match addr.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{port}"),
+        None => addr.to_string(),
+    };
+    if !bind.contains(':') {
+        return Err(GdbError::InvalidAddress(addr.into()));
+    }
+    let listener = TcpListener::bind(&bind).map_err(|e| GdbError::IO(bind.clone(), e))?;
+    let local = listener
+        .local_addr()
+        .map_err(|e| GdbError::IO(bind.clone(), e))?;
+    println!("Waiting for gdb on {local}");
+    let (stream, peer) = listener
+        .accept()
+        .map_err(|e| GdbError::IO(bind.clone(), e))?;
+    println!("gdb connected from {peer}");
+    stream
+        .set_nodelay(true)
+        .map_err(|e| GdbError::IO(bind.clone(), e))?;
+
+    // gdb interrupting the program shows up as SIGIO
+    let fd = stream.as_raw_fd();
+    unsafe {
+        if libc::fcntl(fd, libc::F_SETOWN, libc::getpid()) < 0
+            || libc::fcntl(
+                fd,
+                libc::F_SETFL,
+                libc::fcntl(fd, libc::F_GETFL) | libc::O_ASYNC,
+            ) < 0
+        {
+            return Err(GdbError::Signal(std::io::Error::last_os_error()));
+        }
+    }
+
+    let session = Box::new(Session {
+        stream,
+        no_ack: false,
+        running: false,
+        breakpoints: HashMap::new(),
+        step_over: None,
+        stepping: false,
+        last_stop: String::new(),
+    });
+    SESSION.store(Box::into_raw(session), Ordering::Release);
+    ATTACHED.store(true, Ordering::Release);
+
+    for sig in [libc::SIGTRAP, libc::SIGIO]
+        .into_iter()
+        .chain(FATAL_SIGNALS)
+    {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO;
+            libc::sigfillset(&mut action.sa_mask);
+            if libc::sigaction(sig, &action, std::ptr::null_mut()) < 0 {
+                return Err(GdbError::Signal(std::io::Error::last_os_error()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stops the program for gdb, if it's attached
+#[inline(never)]
+pub fn stop(event: Event) {
+    if !ATTACHED.load(Ordering::Acquire) {
+        return;
+    }
+    PENDING.store(event as u8, Ordering::Release);
+    unsafe { core::arch::asm!("int3") };
+}
+
+/// Stands in for libc's `_exit` while gdb is attached, to tell it the
+/// program exited
+pub unsafe extern "C" fn exit(status: c_int) -> ! {
+    unsafe {
+        with_host_tls(|| {
+            if let Some(session) = take_session(true) {
+                session.send(format!("W{:02x}", status & 0xff));
+            }
+            libc::_exit(status)
+        })
+    }
+}
+
+/// Takes `SESSION` for the current thread, waiting for any other thread
+/// to be done with it if `wait` is set
+fn take_session(wait: bool) -> Option<&'static mut Session> {
+    while ATTACHED.load(Ordering::Acquire) {
+        let session = SESSION.swap(std::ptr::null_mut(), Ordering::AcqRel);
+        if let Some(session) = unsafe { session.as_mut() } {
+            return Some(session);
+        }
+        if !wait {
+            break;
+        }
+        std::hint::spin_loop();
+    }
+    None
+}
+
+extern "C" fn on_signal(sig: c_int, _info: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+    // the program's thread pointer, before swapping it out
+    let fs_base = thread_pointer();
+    let ctx = unsafe { &mut *(ctx as *mut libc::ucontext_t) };
+    unsafe {
+        with_host_tls(|| {
+            // whoever holds the session reads what gdb sent, and may be
+            // what this thread interrupted
+            let Some(session) = take_session(sig != libc::SIGIO) else {
+                return;
+            };
+            let detached = session.on_signal(sig, &mut Registers { ctx, fs_base });
+            if detached {
+                ATTACHED.store(false, Ordering::Release);
+                drop(Box::from_raw(session));
+            } else {
+                SESSION.store(session, Ordering::Release);
+            }
+        })
+    }
+}
+
+/// The registers of the stopped thread, as the signal frame has them
+struct Registers<'a> {
+    ctx: &'a mut libc::ucontext_t,
+    fs_base: u64,
+}
+
+impl Registers<'_> {
+    fn rip(&self) -> u64 {
+        self.ctx.uc_mcontext.gregs[libc::REG_RIP as usize] as u64
+    }
+
+    fn set_rip(&mut self, rip: u64) {
+        self.ctx.uc_mcontext.gregs[libc::REG_RIP as usize] = rip as i64;
+    }
+
+    fn set_trap_flag(&mut self, set: bool) {
+        let eflags = &mut self.ctx.uc_mcontext.gregs[libc::REG_EFL as usize];
+        if set {
+            *eflags |= TRAP_FLAG;
+        } else {
+            *eflags &= !TRAP_FLAG;
+        }
+    }
+
+    fn fpregs(&mut self) -> Option<&mut libc::_libc_fpstate> {
+        unsafe { self.ctx.uc_mcontext.fpregs.as_mut() }
+    }
+
+    /// Register `n` in gdb's numbering, little-endian
+    fn get(&mut self, n: usize) -> Option<Vec<u8>> {
+        let gregs = &self.ctx.uc_mcontext.gregs;
+        // cs, gs, fs and ss, 16 bits each
+        let segments = gregs[libc::REG_CSGSFS as usize] as u64;
+        let segment = |shift: u32| ((segments >> shift) & 0xffff) as u32;
+        let bytes = match n {
+            0..=16 => (gregs[GREGS[n] as usize] as u64).to_le_bytes().to_vec(),
+            17 => (gregs[libc::REG_EFL as usize] as u32)
+                .to_le_bytes()
+                .to_vec(),
+            18..=23 => {
+                let value = match n {
+                    18 => segment(0),
+                    19 => segment(48),
+                    22 => segment(32),
+                    23 => segment(16),
+                    // ds and es are always 0 in 64-bit mode
+                    _ => 0,
+                };
+                value.to_le_bytes().to_vec()
+            }
+            24..=56 => {
+                let fp = self.fpregs()?;
+                match n {
+                    24..=31 => {
+                        let st = &fp._st[n - 24];
+                        let mut bytes: Vec<u8> = st
+                            .significand
+                            .iter()
+                            .flat_map(|w| w.to_le_bytes())
+                            .collect();
+                        bytes.extend(st.exponent.to_le_bytes());
+                        bytes
+                    }
+                    32 => u32::from(fp.cwd).to_le_bytes().to_vec(),
+                    33 => u32::from(fp.swd).to_le_bytes().to_vec(),
+                    34 => full_tag_word(fp.ftw).to_le_bytes().to_vec(),
+                    36 => (fp.rip as u32).to_le_bytes().to_vec(),
+                    38 => (fp.rdp as u32).to_le_bytes().to_vec(),
+                    39 => u32::from(fp.fop).to_le_bytes().to_vec(),
+                    35 | 37 => 0_u32.to_le_bytes().to_vec(),
+                    40..=55 => fp._xmm[n - 40]
+                        .element
+                        .iter()
+                        .flat_map(|e| e.to_le_bytes())
+                        .collect(),
+                    _ => fp.mxcsr.to_le_bytes().to_vec(),
+                }
+            }
+            // orig_rax: not in a system call
+            57 => (-1_i64).to_le_bytes().to_vec(),
+            58 => self.fs_base.to_le_bytes().to_vec(),
+            59 => 0_u64.to_le_bytes().to_vec(),
+            _ => return None,
+        };
+        Some(bytes)
+    }
+
+    /// Sets register `n` from the bytes `get` would return. Segment registers
+    /// and `fs_base` can't be changed from a signal frame, and are left alone
+    fn set(&mut self, n: usize, bytes: &[u8]) -> bool {
+        let u64_at = |bytes: &[u8]| bytes.try_into().ok().map(u64::from_le_bytes);
+        let u32_at = |bytes: &[u8]| bytes.try_into().ok().map(u32::from_le_bytes);
+        match n {
+            0..=16 => match u64_at(bytes) {
+                Some(value) => self.ctx.uc_mcontext.gregs[GREGS[n] as usize] = value as i64,
+                None => return false,
+            },
+            17 => match u32_at(bytes) {
+                Some(value) => self.ctx.uc_mcontext.gregs[libc::REG_EFL as usize] = value.into(),
+                None => return false,
+            },
+            24..=31 if bytes.len() == 10 => {
+                let Some(fp) = self.fpregs() else {
+                    return false;
+                };
+                let st = &mut fp._st[n - 24];
+                for (i, word) in st.significand.iter_mut().enumerate() {
+                    *word = u16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]);
+                }
+                st.exponent = u16::from_le_bytes([bytes[8], bytes[9]]);
+            }
+            40..=55 if bytes.len() == 16 => {
+                let Some(fp) = self.fpregs() else {
+                    return false;
+                };
+                for (i, element) in fp._xmm[n - 40].element.iter_mut().enumerate() {
+                    *element = u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+                }
+            }
+            56 => {
+                let (Some(value), Some(fp)) = (u32_at(bytes), self.fpregs()) else {
+                    return false;
+                };
+                fp.mxcsr = value;
+            }
+            _ => return n < REGISTERS,
+        }
+        true
+    }
+}
+
+/// `fxsave` only keeps one bit per x87 register, where gdb wants two: call
+/// them all valid or empty
+fn full_tag_word(abridged: u16) -> u32 {
+    (0..8)
+        .map(|i| if abridged & (1 << i) != 0 { 0b00 } else { 0b11 } << (2 * i))
+        .fold(0, |word, tag| word | tag)
+}
+
+/// What gdb asked for once done with a stopped program
+enum Resume {
+    Continue { step: bool, signal: Option<c_int> },
+    Detach,
+}
+
+struct Session {
+    stream: TcpStream,
+    /// Set by `QStartNoAckMode`
+    no_ack: bool,
+    /// Whether gdb waits for a stop reply
+    running: bool,
+    /// Addresses of inserted breakpoints, and the bytes `int3` replaced
+    breakpoints: HashMap<u64, u8>,
+    /// A breakpoint taken out to step over it, to put back after that step
+    step_over: Option<u64>,
+    /// Whether gdb asked for a single step
+    stepping: bool,
+    /// The reply to `?`
+    last_stop: String,
+}
+
+impl Session {
+    /// Handles a signal of the program. Returns true once gdb detached
+    fn on_signal(&mut self, sig: c_int, regs: &mut Registers) -> bool {
+        let stop = match sig {
+            libc::SIGTRAP => {
+                let event = PENDING.swap(Event::None as u8, Ordering::AcqRel);
+                let rip = regs.rip();
+                if let Some(addr) = self.step_over.take() {
+                    self.insert_breakpoint(addr);
+                    if !self.stepping {
+                        regs.set_trap_flag(false);
+                        return false;
+                    }
+                    "T05thread:1;".to_string()
+                } else if event == Event::Libraries as u8 {
+                    "T05thread:1;library:;".to_string()
+                } else if event == Event::Start as u8 || self.stepping {
+                    "T05thread:1;".to_string()
+                } else if self.breakpoints.contains_key(&(rip - 1)) {
+                    regs.set_rip(rip - 1);
+                    "T05thread:1;swbreak:;".to_string()
+                } else {
+                    // an int3 of the program's own
+                    "T05thread:1;".to_string()
+                }
+            }
+            libc::SIGIO => {
+                if !self.interrupted() {
+                    return false;
+                }
+                format!("T{:02x}thread:1;", libc::SIGINT)
+            }
+            sig => format!("T{sig:02x}thread:1;"),
+        };
+        // not before: `SIGIO` from gdb's last packet may come in right
+        // before the step it asked for
+        regs.set_trap_flag(false);
+
+        match self.serve(regs, stop) {
+            Resume::Detach => {
+                for (addr, byte) in std::mem::take(&mut self.breakpoints) {
+                    write_memory(addr, &[byte]);
+                }
+                true
+            }
+            Resume::Continue { step, signal } => {
+                if FATAL_SIGNALS.contains(&sig) && signal == Some(sig) {
+                    // the program dies of it, as it would without gdb
+                    self.send(format!("X{sig:02x}"));
+                    unsafe {
+                        libc::signal(sig, libc::SIG_DFL);
+                        libc::raise(sig);
+                    }
+                    return true;
+                }
+                let rip = regs.rip();
+                if self.breakpoints.contains_key(&rip) {
+                    self.remove_breakpoint(rip);
+                    self.step_over = Some(rip);
+                    regs.set_trap_flag(true);
+                }
+                if step {
+                    regs.set_trap_flag(true);
+                }
+                self.stepping = step;
+                self.running = true;
+                false
+            }
+        }
+    }
+
+    /// Whether gdb sent the interrupt byte, which `SIGIO` announced
+    fn interrupted(&mut self) -> bool {
+        let mut byte = 0_u8;
+        let n = unsafe {
+            libc::recv(
+                self.stream.as_raw_fd(),
+                (&mut byte as *mut u8).cast(),
+                1,
+                libc::MSG_DONTWAIT | libc::MSG_PEEK,
+            )
+        };
+        if n == 1 && byte == 0x03 {
+            self.stream.read_exact(&mut [0]).ok();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Answers gdb's requests, until it resumes the program
+    fn serve(&mut self, regs: &mut Registers, stop: String) -> Resume {
+        // gdb asks with `?` when it attaches, and waits for a reply otherwise
+        if self.running {
+            self.send(&stop);
+            self.running = false;
+        }
+        self.last_stop = stop;
+
+        loop {
+            let Some(packet) = self.receive() else {
+                println!("gdb disconnected");
+                return Resume::Detach;
+            };
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            let reply = match packet.as_str() {
+                "?" => self.last_stop.clone(),
+                "g" => (0..G_REGISTERS)
+                    .map(|n| hex(&regs.get(n).unwrap_or_default()))
+                    .collect(),
+                "D" => {
+                    self.send("OK");
+                    return Resume::Detach;
+                }
+                "k" => kill(),
+                "qAttached" => "1".into(),
+                "qC" => "QC1".into(),
+                "qfThreadInfo" => "m1".into(),
+                "qsThreadInfo" => "l".into(),
+                "vCont?" => "vCont;c;C;s;S".into(),
+                "QStartNoAckMode" => {
+                    self.send("OK");
+                    self.no_ack = true;
+                    continue;
+                }
+                p if p.starts_with("qSupported") => "PacketSize=4000;QStartNoAckMode+;\
+                     qXfer:libraries-svr4:read+;qXfer:auxv:read+;qXfer:exec-file:read+;swbreak+"
+                    .into(),
+                p if p.starts_with('D') => {
+                    self.send("OK");
+                    return Resume::Detach;
+                }
+                p if p.starts_with("vKill") => kill(),
+                p if p.starts_with('H') || p.starts_with('T') => "OK".into(),
+                p if p.starts_with('G') => {
+                    let bytes = unhex(&p[1..]).unwrap_or_default();
+                    let mut offset = 0;
+                    for n in 0..G_REGISTERS {
+                        let size = regs.get(n).map_or(0, |r| r.len());
+                        if let Some(value) = bytes.get(offset..offset + size) {
+                            regs.set(n, value);
+                        }
+                        offset += size;
+                    }
+                    "OK".into()
+                }
+                p if p.starts_with('p') => match usize::from_str_radix(&p[1..], 16)
+                    .ok()
+                    .and_then(|n| regs.get(n))
+                {
+                    Some(value) => hex(&value),
+                    None => "E01".into(),
+                },
+                p if p.starts_with('P') => {
+                    let set = p[1..].split_once('=').and_then(|(n, value)| {
+                        let n = usize::from_str_radix(n, 16).ok()?;
+                        regs.set(n, &unhex(value)?).then_some(())
+                    });
+                    ok_or_error(set)
+                }
+                p if p.starts_with('m') => {
+                    let read = parse_range(&p[1..]).and_then(|(addr, len)| {
+                        Some(self.unpatched(&read_memory(addr, len)?, addr))
+                    });
+                    match read {
+                        Some(bytes) => hex(&bytes),
+                        None => "E01".into(),
+                    }
+                }
+                p if p.starts_with('M') => {
+                    let written = p[1..].split_once(':').and_then(|(range, data)| {
+                        let (addr, _) = parse_range(range)?;
+                        let data = unhex(data)?;
+                        self.write_around_breakpoints(addr, &data).then_some(())
+                    });
+                    ok_or_error(written)
+                }
+                p if p.starts_with("Z0,") => {
+                    let inserted = parse_range(&p[3..]).and_then(|(addr, _)| {
+                        self.breakpoints
+                            .contains_key(&addr)
+                            .then_some(())
+                            .or_else(|| self.insert_breakpoint(addr).then_some(()))
+                    });
+                    ok_or_error(inserted)
+                }
+                p if p.starts_with("z0,") => {
+                    let removed =
+                        parse_range(&p[3..]).map(|(addr, _)| self.remove_breakpoint(addr));
+                    ok_or_error(removed)
+                }
+                p if p.starts_with('c') || p.starts_with('s') => {
+                    if let Ok(addr) = u64::from_str_radix(&p[1..], 16) {
+                        regs.set_rip(addr);
+                    }
+                    let step = p.starts_with('s');
+                    return Resume::Continue { step, signal: None };
+                }
+                p if p.starts_with('C') || p.starts_with('S') => {
+                    let mut args = p[1..].split(';');
+                    let signal = args.next().and_then(|s| c_int::from_str_radix(s, 16).ok());
+                    if let Some(Ok(addr)) = args.next().map(|a| u64::from_str_radix(a, 16)) {
+                        regs.set_rip(addr);
+                    }
+                    let step = p.starts_with('S');
+                    return Resume::Continue { step, signal };
+                }
+                p if p.starts_with("vCont;") => {
+                    // there's only one thread: the first action is for it
+                    let action = p[6..].split(';').next().unwrap_or_default();
+                    let action = action.split(':').next().unwrap_or_default();
+                    let signal =
+                        c_int::from_str_radix(action.get(1..).unwrap_or_default(), 16).ok();
+                    match action.chars().next() {
+                        Some('c') => {
+                            return Resume::Continue {
+                                step: false,
+                                signal: None,
+                            };
+                        }
+                        Some('s') => {
+                            return Resume::Continue {
+                                step: true,
+                                signal: None,
+                            };
+                        }
+                        Some('C') => {
+                            return Resume::Continue {
+                                step: false,
+                                signal,
+                            };
+                        }
+                        Some('S') => return Resume::Continue { step: true, signal },
+                        _ => "E01".into(),
+                    }
+                }
+                p if p.starts_with("qXfer:") => {
+                    // binary data, unlike the other replies
+                    let reply = self.transfer(&p[6..]);
+                    self.send(reply);
+                    continue;
+                }
+                _ => String::new(),
+            };
+            self.send(&reply);
+        }
+    }
+
+    /// `qXfer:object:read:annex:offset,length`
+    fn transfer(&self, request: &str) -> Vec<u8> {
+        let mut parts = request.splitn(4, ':');
+        let (Some(object), Some("read"), Some(_annex), Some(range)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Vec::new();
+        };
+        let Some((offset, length)) = parse_range(range) else {
+            return "E01".into();
+        };
+        let Some(loader) = running_loader() else {
+            return "E01".into();
+        };
+        let data = match object {
+            "libraries-svr4" => libraries(loader).into_bytes(),
+            "auxv" => auxv(&loader.objects[0]),
+            "exec-file" => loader.objects[0].path.as_os_str().as_bytes().to_vec(),
+            _ => return Vec::new(),
+        };
+        let start = (offset as usize).min(data.len());
+        let end = start.saturating_add(length).min(data.len());
+        let more = if end < data.len() { b'm' } else { b'l' };
+        let mut reply = vec![more];
+        reply.extend(escape(&data[start..end]));
+        reply
+    }
+
+    /// Hides the `int3` instructions elk inserted from memory gdb reads
+    fn unpatched(&self, bytes: &[u8], addr: u64) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        for (&bp, &original) in &self.breakpoints {
+            if let Some(byte) = bp
+                .checked_sub(addr)
+                .and_then(|offset| bytes.get_mut(offset as usize))
+            {
+                *byte = original;
+            }
+        }
+        bytes
+    }
+
+    /// Writes memory for gdb, keeping breakpoints in place
+    fn write_around_breakpoints(&mut self, addr: u64, data: &[u8]) -> bool {
+        let mut patched = data.to_vec();
+        for (&bp, original) in self.breakpoints.iter_mut() {
+            if let Some(offset) = bp.checked_sub(addr).filter(|&o| o < data.len() as u64) {
+                *original = data[offset as usize];
+                patched[offset as usize] = 0xcc;
+            }
+        }
+        write_memory(addr, &patched)
+    }
+
+    fn insert_breakpoint(&mut self, addr: u64) -> bool {
+        let Some(original) = read_memory(addr, 1).and_then(|bytes| bytes.first().copied()) else {
+            return false;
+        };
+        if !write_memory(addr, &[0xcc]) {
+            return false;
+        }
+        self.breakpoints.insert(addr, original);
+        true
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64) {
+        if let Some(original) = self.breakpoints.remove(&addr) {
+            write_memory(addr, &[original]);
+        }
+    }
+
+    /// Reads a packet's data, acknowledging it. None once gdb hung up
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let mut byte = [0_u8];
+        loop {
+            // acknowledgements, and interrupts of a program that's already stopped
+            loop {
+                self.stream.read_exact(&mut byte).ok()?;
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte).ok()?;
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut checksum = [0_u8; 2];
+            self.stream.read_exact(&mut checksum).ok()?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                == Some(sum(&data));
+
+            if !self.no_ack {
+                self.stream
+                    .write_all(if valid { b"+" } else { b"-" })
+                    .ok()?;
+            }
+            if valid || self.no_ack {
+                return Some(unescape(&data));
+            }
+        }
+    }
+
+    /// Sends a packet, until gdb acknowledges it
+    fn send(&mut self, data: impl AsRef<[u8]>) {
+        let data = data.as_ref();
+        let mut packet = vec![b'$'];
+        packet.extend(data);
+        packet.extend(format!("#{:02x}", sum(data)).as_bytes());
+        loop {
+            if self.stream.write_all(&packet).is_err() || self.no_ack {
+                return;
+            }
+            let mut ack = [0_u8];
+            match self.stream.read_exact(&mut ack) {
+                Ok(()) if ack[0] == b'-' => continue,
+                _ => return,
+            }
+        }
+    }
+}
+
+fn kill() -> ! {
+    unsafe {
+        libc::kill(libc::getpid(), libc::SIGKILL);
+    }
+    unreachable!()
+}
+
+fn ok_or_error(done: Option<()>) -> String {
+    match done {
+        Some(()) => "OK".into(),
+        None => "E01".into(),
+    }
+}
+
+/// `addr,length`, in hex
+fn parse_range(range: &str) -> Option<(u64, usize)> {
+    let (addr, len) = range.split_once(',')?;
+    Some((
+        u64::from_str_radix(addr, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0_u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Binary data in a reply: `#`, `$`, `}` and `*` are escaped with `}`
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &b in data {
+        if matches!(b, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend([b'}', b ^ 0x20]);
+        } else {
+            escaped.push(b);
+        }
+    }
+    escaped
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(&b) = iter.next() {
+        match b {
+            b'}' => bytes.extend(iter.next().map(|b| b ^ 0x20)),
+            b => bytes.push(b),
+        }
+    }
+    bytes
+}
+
+/// Reads the program's memory, stopping at the first unmapped page
+fn read_memory(addr: u64, len: usize) -> Option<Vec<u8>> {
+    let mut bytes = vec![0_u8; len];
+    let local = libc::iovec {
+        iov_base: bytes.as_mut_ptr().cast(),
+        iov_len: len,
+    };
+    let remote = libc::iovec {
+        iov_base: addr as *mut libc::c_void,
+        iov_len: len,
+    };
+    // unlike dereferencing, this fails on unmapped memory instead of faulting
+    let n = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    if n <= 0 {
+        return None;
+    }
+    bytes.truncate(n as usize);
+    Some(bytes)
+}
+
+/// Writes to the program's memory, making read-only pages writable for
+/// the time being
+fn write_memory(addr: u64, data: &[u8]) -> bool {
+    let ptr = addr as *const u8;
+    let Ok(regions) = region::query_range(ptr, data.len()) else {
+        return false;
+    };
+    let Ok(regions) = regions.collect::<Result<Vec<_>, _>>() else {
+        return false;
+    };
+    // every byte must be mapped
+    let mut end = addr;
+    for region in &regions {
+        let start = region.as_range().start as u64;
+        if start > end {
+            return false;
+        }
+        end = end.max(region.as_range().end as u64);
+    }
+    if end < addr + data.len() as u64 {
+        return false;
+    }
+
+    for region in &regions {
+        let protection = region.protection();
+        if !protection.contains(region::Protection::WRITE) {
+            let writable = protection | region::Protection::WRITE;
+            if unsafe { region::protect(region.as_ptr::<u8>(), region.len(), writable) }.is_err() {
+                return false;
+            }
+        }
+    }
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), addr as *mut u8, data.len()) };
+    for region in &regions {
+        unsafe { region::protect(region.as_ptr::<u8>(), region.len(), region.protection()) }.ok();
+    }
+    true
+}
+
+/// The objects gdb should load symbols for, as `<link.h>`'s `r_debug` would
+/// have them. The program is `main-lm`, and not listed
+fn libraries(loader: &Loader) -> String {
+    let mut xml = format!(
+        "<library-list-svr4 version=\"1.0\" main-lm=\"{:#x}\">",
+        loader.rtld.handle(0) as u64
+    );
+    for (index, obj) in loader.objects.iter().enumerate().skip(1) {
+        if !obj.is_loaded() {
+            continue;
+        }
+        let dynamic = obj
+            .file
+            .segment_of_type(delf::SegmentType::Dynamic)
+            .map_or(0, |ph| (obj.base + ph.vaddr).0);
+        xml.push_str(&format!(
+            "<library name=\"{}\" lm=\"{:#x}\" l_addr=\"{:#x}\" l_ld=\"{:#x}\"/>",
+            xml_escape(&obj.path.to_string_lossy()),
+            loader.rtld.handle(index) as u64,
+            obj.base.0,
+            dynamic,
+        ));
+    }
+    xml.push_str("</library-list-svr4>");
+    xml
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The program's auxiliary vector: gdb relocates a position-independent
+/// program from its entry point
+fn auxv(program: &Object) -> Vec<u8> {
+    Auxv::for_program(program)
+        .iter()
+        .map(|auxv| [auxv.typ as u64, auxv.value])
+        .chain([[AuxType::Null as u64, 0]])
+        .flatten()
+        .flat_map(u64::to_le_bytes)
+        .collect()
+}
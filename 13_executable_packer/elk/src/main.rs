@@ -1,11 +1,14 @@
 use std::error::Error;
 
-use crate::procfs::Mapping;
+use crate::{process::ProcessState, procfs::Mapping};
 use clap::{Parser, Subcommand};
 
+mod gdbstub;
 mod name;
 mod process;
 mod procfs;
+mod rtld;
+mod search;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -19,6 +22,8 @@ enum SubCommand {
     Autosym(AutosymArgs),
     Run(RunArgs),
     Dig(DigArgs),
+    Ldd(LddArgs),
+    Maps(MapsArgs),
 }
 
 #[derive(clap::Args)]
@@ -34,21 +39,64 @@ struct AutosymArgs {
 struct RunArgs {
     /// the absolute path of an executable file to load and run
     exec_path: String,
+    /// bind PLT entries on first call rather than at load time
+    #[arg(long)]
+    lazy: bool,
+    /// directories to search before the defaults, instead of LD_LIBRARY_PATH
+    #[arg(long)]
+    library_path: Option<String>,
+    /// objects to load right after the executable, after those in LD_PRELOAD
+    #[arg(long)]
+    preload: Vec<String>,
+    /// wait for gdb to connect on [host]:port, and serve it from within the process
+    #[arg(long, value_name = "ADDR")]
+    gdbserver: Option<String>,
     /// arguments for the executable file
     args: Vec<String>,
 }
 
+#[derive(clap::Args)]
+/// Loads an executable and its dependencies without running it, and shows
+/// what it would be linked against
+struct LddArgs {
+    /// the path of the executable file to examine
+    exec_path: String,
+    /// directories to search before the defaults, instead of LD_LIBRARY_PATH
+    #[arg(long)]
+    library_path: Option<String>,
+    /// objects to load right after the executable, after those in LD_PRELOAD
+    #[arg(long)]
+    preload: Vec<String>,
+    /// show which object satisfies each symbol reference
+    #[arg(long)]
+    bindings: bool,
+}
+
 #[derive(clap::Args)]
 /// Shows information about an address in a memory's address space
 struct DigArgs {
-    #[arg(long)]
+    #[arg(long, required_unless_present = "core", conflicts_with = "core")]
     /// the PID of the process whose memory space to examine
-    pid: u32,
+    pid: Option<u32>,
+    #[arg(long)]
+    /// a core dump to examine instead of a live process
+    core: Option<String>,
     #[arg(long)]
     /// the address to look for
     addr: u64,
 }
 
+#[derive(clap::Args)]
+/// Lists a process's mappings by object, with how much of each is resident
+/// and dirty, and the ELF sections found in the executable ones
+struct MapsArgs {
+    /// the PID of the process to examine
+    pid: u32,
+    #[arg(long)]
+    /// print machine-readable JSON instead of a listing
+    json: bool,
+}
+
 type AnyError = Box<dyn Error>;
 
 fn main() {
@@ -63,6 +111,8 @@ fn do_main() -> Result<(), AnyError> {
         SubCommand::Autosym(args) => cmd_autosym(args),
         SubCommand::Run(args) => cmd_run(args),
         SubCommand::Dig(args) => cmd_dig(args),
+        SubCommand::Ldd(args) => cmd_ldd(args),
+        SubCommand::Maps(args) => cmd_maps(args),
     }
 }
 
@@ -83,6 +133,51 @@ where
     }
 }
 
+/// Builds the memory map of a dumped process from its `PT_LOAD` segments
+/// and `NT_FILE` note, then calls `f` with it
+fn with_core_mappings<F, T>(path: &str, f: F) -> Result<T, AnyError>
+where
+    F: Fn(&Vec<procfs::Mapping<'_>>) -> Result<T, AnyError>,
+{
+    let contents = std::fs::read(path)?;
+    let file = delf::File::parse_or_print_error(&contents[..])
+        .ok_or_else(|| WithMappingsError::Parse(format!("could not parse {path:?}")))?;
+    let core = file.read_core()?;
+
+    let mappings = file
+        .program_headers
+        .iter()
+        .filter(|ph| ph.r#type == delf::SegmentType::Load)
+        .map(|ph| {
+            let addr_range = ph.mem_range();
+            let file = core.file_containing(addr_range.start);
+            let (source, offset) =
+                match file.and_then(|f| Some((std::str::from_utf8(f.path).ok()?, f))) {
+                    Some((path, file)) => (
+                        procfs::Source::File(path),
+                        file.offset + addr_range.start - file.range.start,
+                    ),
+                    None => (procfs::Source::Anonymous, delf::Addr(0)),
+                };
+            procfs::Mapping {
+                addr_range,
+                perms: procfs::Perms {
+                    r: ph.flags.contains(delf::SegmentFlag::Read),
+                    w: ph.flags.contains(delf::SegmentFlag::Write),
+                    x: ph.flags.contains(delf::SegmentFlag::Execute),
+                    p: true,
+                },
+                offset,
+                dev: procfs::Dev { major: 0, minor: 0 },
+                len: 0,
+                source,
+                deleted: false,
+            }
+        })
+        .collect();
+    f(&mappings)
+}
+
 fn cmd_autosym(args: AutosymArgs) -> Result<(), AnyError> {
     with_mappings(args.pid, |mappings| {
         mappings
@@ -95,7 +190,7 @@ fn cmd_autosym(args: AutosymArgs) -> Result<(), AnyError> {
 fn cmd_dig(args: DigArgs) -> Result<(), AnyError> {
     let addr = delf::Addr(args.addr);
 
-    with_mappings(args.pid, |mappings| {
+    let dig_in = |mappings: &Vec<Mapping<'_>>| {
         if let Some(mapping) = mappings
             .iter()
             .find(|mapping| mapping.addr_range.contains(&addr))
@@ -103,7 +198,12 @@ fn cmd_dig(args: DigArgs) -> Result<(), AnyError> {
             dig(mapping, addr)?;
         }
         Ok(())
-    })
+    };
+    match (args.pid, args.core) {
+        (Some(pid), _) => with_mappings(pid, dig_in),
+        (None, Some(path)) => with_core_mappings(&path, dig_in),
+        (None, None) => unreachable!("clap requires --pid or --core"),
+    }
 }
 
 fn dig(mapping: &Mapping<'_>, addr: delf::Addr) -> Result<(), AnyError> {
@@ -166,15 +266,164 @@ fn dig(mapping: &Mapping<'_>, addr: delf::Addr) -> Result<(), AnyError> {
             }
         }
     }
+
+    match file.dwarf().and_then(|dwarf| match dwarf {
+        Some(dwarf) => dwarf.locate(vaddr),
+        None => Ok(vec![]),
+    }) {
+        Err(e) => println!("Could not read debug info: {e}"),
+        Ok(frames) => {
+            for (i, frame) in frames.iter().enumerate() {
+                let function = frame.function.as_deref().unwrap_or("??");
+                let location = frame
+                    .location
+                    .as_ref()
+                    .map_or_else(|| "??".to_owned(), ToString::to_string);
+                if i == 0 {
+                    println!("At source {location} in {function:?}");
+                } else {
+                    println!("  inlined into {function:?} at {location}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Directories from `--library-path`, or else `LD_LIBRARY_PATH`
+fn library_path(flag: Option<String>, exec_path: &str) -> Vec<std::path::PathBuf> {
+    flag.or_else(|| std::env::var("LD_LIBRARY_PATH").ok())
+        .filter(|list| !list.is_empty())
+        .map(|list| {
+            // `$ORIGIN` is the program's directory here
+            let origin = std::path::Path::new(exec_path)
+                .canonicalize()
+                .ok()
+                .and_then(|path| path.parent().map(|dir| dir.to_path_buf()))
+                .unwrap_or_default();
+            search::expand(list.as_bytes(), &origin)
+        })
+        .unwrap_or_default()
+}
+
+/// Objects from `LD_PRELOAD`, then from `--preload`, as ld.so takes them
+fn preload(flag: Vec<String>) -> Vec<String> {
+    std::env::var("LD_PRELOAD")
+        .unwrap_or_default()
+        .split([' ', ':'])
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .chain(flag)
+        .collect()
+}
+
+fn cmd_ldd(args: LddArgs) -> Result<(), AnyError> {
+    let mut proc = process::Process::new(library_path(args.library_path, &args.exec_path));
+    let (exec_index, missing) =
+        proc.load_object_and_available_dependencies(&args.exec_path, &preload(args.preload))?;
+    let loader = &proc.state.loader;
+
+    println!();
+    println!("{}", loader.objects[exec_index].path.display());
+    let mut listed = vec![exec_index];
+    // preloaded objects come first in lookup order, even though nothing needs them
+    let preloaded = (0..loader.objects.len())
+        .filter(|&index| index != exec_index && loader.objects[index].loaded_by.is_none());
+    for index in preloaded {
+        println!("    {} (preloaded)", loader.objects[index].path.display());
+        listed.push(index);
+        print_dependencies(loader, &missing, index, 2, &mut listed);
+    }
+    print_dependencies(loader, &missing, exec_index, 1, &mut listed);
+
+    let bindings = loader.symbol_bindings();
+    if args.bindings {
+        println!();
+        println!("bindings:");
+        for binding in &bindings {
+            let to = match binding.to {
+                Some(to) => loader.objects[to].path.display().to_string(),
+                None if binding.is_unresolved() => "unresolved".to_string(),
+                None => "unresolved (weak)".to_string(),
+            };
+            println!(
+                "    {}: {} => {to}",
+                loader.objects[binding.from].path.display(),
+                binding.sym
+            );
+        }
+    }
+
+    println!();
+    let mut unresolved = bindings.iter().filter(|b| b.is_unresolved()).peekable();
+    if unresolved.peek().is_none() {
+        println!("no unresolved symbols");
+    } else {
+        println!("unresolved symbols:");
+        for binding in unresolved {
+            println!(
+                "    {}: {}",
+                loader.objects[binding.from].path.display(),
+                binding.sym
+            );
+        }
+    }
     Ok(())
 }
 
+/// Prints what `index` needs, and what those need in turn the first time
+/// they show up
+fn print_dependencies(
+    loader: &process::Loader,
+    missing: &[process::MissingDependency],
+    index: usize,
+    depth: usize,
+    listed: &mut Vec<usize>,
+) {
+    let obj = &loader.objects[index];
+    let indent = "    ".repeat(depth);
+    let mut found = obj.needed.iter();
+    for name in obj.file.dynamic_entry_strings(delf::DynamicTag::Needed) {
+        let name = String::from_utf8_lossy(name);
+        if missing
+            .iter()
+            .any(|m| m.needed_by == index && m.name == name)
+        {
+            println!("{indent}{name} => not found");
+            continue;
+        }
+        let Some(&dep) = found.next() else {
+            break;
+        };
+        let path = loader.objects[dep].path.display();
+        if listed.contains(&dep) {
+            println!("{indent}{name} => {path} (see above)");
+        } else {
+            println!("{indent}{name} => {path}");
+            listed.push(dep);
+            print_dependencies(loader, missing, dep, depth + 1, listed);
+        }
+    }
+}
+
 fn cmd_run(args: RunArgs) -> Result<(), AnyError> {
-    let mut proc = process::Process::new();
-    let exec_index = proc.load_object_and_dependencies(&args.exec_path)?;
-    proc.pathc_libc();
+    let mut proc = process::Process::new(library_path(args.library_path.clone(), &args.exec_path));
+    let exec_index =
+        proc.load_object_and_dependencies(&args.exec_path, &preload(args.preload.clone()))?;
     let proc = proc.allocate_tls();
-    let proc = proc.apply_relocations()?;
+    let binding = if args.lazy {
+        process::Binding::Lazy
+    } else {
+        process::Binding::Eager
+    };
+    let proc = proc.apply_relocations(binding)?;
+    if let Some(addr) = &args.gdbserver {
+        // gdb is told when the program exits
+        proc.state
+            .loader()
+            .redirect("_exit", gdbstub::exit as *const () as u64);
+        gdbstub::listen(addr)?;
+    }
     let proc = proc.initialize_tls();
     let proc = proc.adjust_protections()?;
 
@@ -195,11 +444,193 @@ fn cmd_run(args: RunArgs) -> Result<(), AnyError> {
         exec_index,
         args,
         env,
-        auxv: process::Auxv::get_known(),
+        auxv: process::Auxv::for_program(&proc.state.loader().objects[exec_index]),
     };
     proc.start(&opts)
 }
 
+/// `SHF_ALLOC`: the section occupies memory when the file is loaded
+const SHF_ALLOC: u64 = 0x2;
+
+/// The mappings that share a backing object, in address order
+struct MapGroup<'a> {
+    source: &'a procfs::Source<'a>,
+    deleted: bool,
+    usages: Vec<&'a procfs::Usage<'a>>,
+}
+
+impl MapGroup<'_> {
+    fn name(&self) -> String {
+        let name = match self.source {
+            procfs::Source::Anonymous => "[anonymous]".to_owned(),
+            procfs::Source::Special(name) => format!("[{name}]"),
+            procfs::Source::File(path) => path.to_string(),
+        };
+        if self.deleted {
+            format!("{name} (deleted)")
+        } else {
+            name
+        }
+    }
+
+    fn total(&self, name: &str) -> u64 {
+        self.usages.iter().map(|usage| usage.field(name)).sum()
+    }
+}
+
+/// An allocated section of the file, where it ended up in memory
+struct MappedSection {
+    name: String,
+    addr: delf::Addr,
+    size: u64,
+}
+
+fn cmd_maps(args: MapsArgs) -> Result<(), AnyError> {
+    let smaps = std::fs::read_to_string(format!("/proc/{}/smaps", args.pid))?;
+    let usages = match procfs::usages(&smaps) {
+        Ok((_, usages)) => usages,
+        Err(e) => return Err(Box::new(WithMappingsError::Parse(format!("{e:?}")))),
+    };
+
+    // in order of first appearance
+    let mut groups: Vec<MapGroup<'_>> = Vec::new();
+    for usage in &usages {
+        let mapping = &usage.mapping;
+        let same = |group: &&mut MapGroup<'_>| {
+            group.deleted == mapping.deleted
+                && match (group.source, &mapping.source) {
+                    (procfs::Source::Anonymous, procfs::Source::Anonymous) => true,
+                    (procfs::Source::Special(a), procfs::Source::Special(b))
+                    | (procfs::Source::File(a), procfs::Source::File(b)) => a == b,
+                    _ => false,
+                }
+        };
+        match groups.iter_mut().find(same) {
+            Some(group) => group.usages.push(usage),
+            None => groups.push(MapGroup {
+                source: &mapping.source,
+                deleted: mapping.deleted,
+                usages: vec![usage],
+            }),
+        }
+    }
+
+    let mut files = std::collections::HashMap::new();
+    let mut sections = |mapping: &Mapping<'_>| -> Vec<MappedSection> {
+        let procfs::Source::File(path) = mapping.source else {
+            return vec![];
+        };
+        if !mapping.perms.x || mapping.deleted {
+            return vec![];
+        }
+        let file = files.entry(path.to_owned()).or_insert_with(|| {
+            std::fs::read(path)
+                .ok()
+                .and_then(delf::File::parse_or_print_error)
+        });
+        let Some(file) = file else {
+            return vec![];
+        };
+        let len = mapping.addr_range.end - mapping.addr_range.start;
+        let file_range = mapping.offset..mapping.offset + len;
+        file.section_headers
+            .iter()
+            .filter(|sh| sh.flags & SHF_ALLOC != 0 && sh.r#type != delf::SectionType::NoBits)
+            .filter(|sh| {
+                let range = sh.file_range();
+                range.start < file_range.end && file_range.start < range.end
+            })
+            .map(|sh| MappedSection {
+                name: String::from_utf8_lossy(file.section_name(sh)).into_owned(),
+                addr: mapping.addr_range.start + sh.offset - mapping.offset,
+                size: sh.size.0,
+            })
+            .collect()
+    };
+
+    if args.json {
+        let json: Vec<serde_json::Value> = groups
+            .iter()
+            .map(|group| {
+                let mappings: Vec<_> = group
+                    .usages
+                    .iter()
+                    .map(|usage| {
+                        let m = &usage.mapping;
+                        let sections: Vec<_> = sections(m)
+                            .into_iter()
+                            .map(|s| {
+                                serde_json::json!({
+                                    "name": s.name,
+                                    "addr": s.addr.0,
+                                    "size": s.size,
+                                })
+                            })
+                            .collect();
+                        serde_json::json!({
+                            "start": m.addr_range.start.0,
+                            "end": m.addr_range.end.0,
+                            "perms": format!("{:?}", m.perms),
+                            "offset": m.offset.0,
+                            "rss": usage.field("Rss"),
+                            "pss": usage.field("Pss"),
+                            "private_dirty": usage.field("Private_Dirty"),
+                            "shared_dirty": usage.field("Shared_Dirty"),
+                            "swap": usage.field("Swap"),
+                            "sections": sections,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "object": group.name(),
+                    "rss": group.total("Rss"),
+                    "pss": group.total("Pss"),
+                    "private_dirty": group.total("Private_Dirty"),
+                    "shared_dirty": group.total("Shared_Dirty"),
+                    "swap": group.total("Swap"),
+                    "mappings": mappings,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    let kib = |bytes: u64| Size(delf::Addr(bytes));
+    for group in &groups {
+        let dirty: u64 = group.usages.iter().map(|usage| usage.dirty()).sum();
+        println!(
+            "{} (RSS {:?}, PSS {:?}, dirty {:?})",
+            group.name(),
+            kib(group.total("Rss")),
+            kib(group.total("Pss")),
+            kib(dirty)
+        );
+        for usage in &group.usages {
+            let m = &usage.mapping;
+            println!(
+                "    {:?} {:?} {:?}  size {:?}, RSS {:?}, PSS {:?}, dirty {:?}",
+                m.addr_range,
+                m.perms,
+                m.offset,
+                kib((m.addr_range.end - m.addr_range.start).0),
+                kib(usage.field("Rss")),
+                kib(usage.field("Pss")),
+                kib(usage.dirty())
+            );
+            for section in sections(m) {
+                println!(
+                    "        {:?} {:<20} {:?}",
+                    section.addr,
+                    section.name,
+                    kib(section.size)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 fn analyze(mapping: &procfs::Mapping) -> Result<(), AnyError> {
     if mapping.deleted {
         return Ok(());
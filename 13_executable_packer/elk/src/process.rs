@@ -6,10 +6,18 @@ use std::{
     ops::Range,
     os::fd::AsRawFd,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicPtr, AtomicU64, Ordering},
+    },
 };
 
-use crate::name::Name;
+use crate::{
+    gdbstub,
+    name::Name,
+    rtld::Rtld,
+    search::{self, LdCache},
+};
 use derive_more::Debug;
 use enumflags2::BitFlags;
 use mmap::{MapOption, MemoryMap};
@@ -26,8 +34,15 @@ pub trait ProcessState {
 pub struct Loader {
     pub objects: Vec<Object>,
     pub objects_by_path: HashMap<PathBuf, usize>,
-    pub search_path: Vec<PathBuf>,
-    fake_ctype: Box<FakeCtype>,
+    /// `LD_LIBRARY_PATH` or `--library-path`, searched for every object
+    pub library_path: Vec<PathBuf>,
+    pub ld_cache: LdCache,
+    pub rtld: Rtld,
+    /// How `apply_relocations` bound the program, which `dlopen` honors
+    binding: Binding,
+    /// Objects whose initializers ran, in that order: finalizers run the
+    /// other way around
+    initialized: Vec<usize>,
 }
 
 pub struct Loading {
@@ -41,21 +56,326 @@ impl ProcessState for Loading {
 }
 
 impl Process<Loading> {
-    pub fn new() -> Self {
+    pub fn new(library_path: Vec<PathBuf>) -> Self {
         Self {
             state: Loading {
                 loader: Loader {
                     objects: Vec::new(),
                     objects_by_path: HashMap::new(),
-                    search_path: vec!["/usr/lib".into(), "/usr/lib/x86_64-linux-gnu/".into()],
-                    fake_ctype: FakeCtype::new(),
+                    library_path,
+                    ld_cache: LdCache::load().unwrap_or_default(),
+                    rtld: Rtld::default(),
+                    binding: Binding::Eager,
+                    initialized: Vec::new(),
                 },
             },
         }
     }
 
-    pub fn load_object<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, LoadError> {
+    /// Loads the program, the `preload` objects, then their dependencies
+    /// breadth-first: the global scope, in lookup order
+    pub fn load_object_and_dependencies<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        preload: &[String],
+    ) -> Result<usize, LoadError> {
+        self.state.loader.load_closure(path, preload, None)
+    }
+
+    /// Like `load_object_and_dependencies`, but carries on past libraries
+    /// that can't be found
+    pub fn load_object_and_available_dependencies<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        preload: &[String],
+    ) -> Result<(usize, Vec<MissingDependency>), LoadError> {
+        let mut missing = Vec::new();
+        let index = self
+            .state
+            .loader
+            .load_closure(path, preload, Some(&mut missing))?;
+        Ok((index, missing))
+    }
+    pub fn allocate_tls(mut self) -> Process<TlsAllocated> {
+        let mut offsets = HashMap::new();
+        let mut storage_space = 0;
+        // the thread pointer must suit the most demanding block, and glibc
+        // aligns its TCB on 64 bytes anyway
+        let mut align = 64;
+        let mut modules = 0;
+        for obj in &mut self.state.loader.objects {
+            let Some(ph) = obj
+                .file
+                .segment_of_type(delf::SegmentType::TLS)
+                .filter(|ph| ph.memsz.0 > 0)
+            else {
+                continue;
+            };
+
+            modules += 1;
+            obj.tls_module = Some(modules);
+
+            // blocks sit below the thread pointer (x86-64 uses TLS variant II),
+            // at offsets that keep each of them aligned
+            let block_align = ph.align.0.max(1);
+            storage_space = (storage_space + ph.memsz.0).next_multiple_of(block_align);
+            align = align.max(block_align);
+            offsets.insert(obj.base, delf::Addr(storage_space));
+        }
+
+        let tcbhead_size = 704;
+        let total_size = (align + storage_space) as usize + tcbhead_size;
+
+        let block = vec![0_u8; total_size];
+        let tcb_addr = delf::Addr((block.as_ptr() as u64 + storage_space).next_multiple_of(align));
+
+        // dtv[-1] holds the number of modules, dtv[0] the generation, then
+        // dtv[module] points to that module's block
+        let mut dtv = vec![DtvEntry::default(); modules as usize + 2];
+        dtv[0].val = modules;
+        dtv[1].val = 1;
+        for obj in &self.state.loader.objects {
+            if let Some(module) = obj.tls_module {
+                dtv[module as usize + 1].val = (tcb_addr - offsets[&obj.base]).0;
+            }
+        }
+
+        // the start of glibc's `tcbhead_t`
+        unsafe {
+            let word = delf::Addr(8);
+            tcb_addr.set(tcb_addr.0); // tcb
+            (tcb_addr + word).set(dtv[1..].as_ptr() as u64); // dtv
+            (tcb_addr + word + word).set(tcb_addr.0); // self
+            (tcb_addr + delf::Addr(0x28)).set(0xDEADBEEF_u64); // stack_guard
+            (tcb_addr + delf::Addr(0x30)).set(0xFEEDFACE_u64); // pointer_guard
+        }
+
+        // General-dynamic code asks ld.so for the address of its variables,
+        // but ld.so never set up its own bookkeeping: answer from our DTV
+        self.state
+            .loader
+            .redirect("__tls_get_addr", tls_get_addr as *const () as u64);
+
+        let tls = Tls {
+            offsets,
+            block,
+            dynamic_blocks: Vec::new(),
+            dtv,
+            tcb_addr,
+            static_size: storage_space + tcbhead_size as u64,
+            static_align: align,
+        };
+
+        Process {
+            state: TlsAllocated {
+                loader: self.state.loader,
+                tls,
+            },
+        }
+    }
+}
+
+pub struct TlsAllocated {
+    loader: Loader,
+    pub tls: Tls,
+}
+
+impl ProcessState for TlsAllocated {
+    fn loader(&self) -> &Loader {
+        &self.loader
+    }
+}
+
+pub struct Relocated {
+    loader: Loader,
+    tls: Tls,
+}
+
+impl ProcessState for Relocated {
+    fn loader(&self) -> &Loader {
+        &self.loader
+    }
+}
+
+impl Process<TlsAllocated> {
+    pub fn apply_relocations(
+        mut self,
+        binding: Binding,
+    ) -> Result<Process<Relocated>, RelocationError> {
+        let loader = &mut self.state.loader;
+        loader.binding = binding;
+        loader.relocate(&self.state.tls, 0..loader.objects.len())?;
+
+        // ld-linux's own startup never runs: stand in for it
+        for (name, target) in Rtld::redirects() {
+            loader.redirect(name, target);
+        }
+        loader.rtld.install(&loader.objects, &self.state.tls);
+
+        let res = Process {
+            state: Relocated {
+                loader: self.state.loader,
+                tls: self.state.tls,
+            },
+        };
+        Ok(res)
+    }
+}
+
+pub struct TlsInitialized {
+    loader: Loader,
+    tls: Tls,
+}
+
+impl ProcessState for TlsInitialized {
+    fn loader(&self) -> &Loader {
+        &self.loader
+    }
+}
+
+impl Process<Relocated> {
+    pub fn initialize_tls(self) -> Process<TlsInitialized> {
+        for obj in &self.state.loader.objects {
+            self.state.tls.initialize(obj);
+        }
+
+        Process {
+            state: TlsInitialized {
+                loader: self.state.loader,
+                tls: self.state.tls,
+            },
+        }
+    }
+}
+
+pub struct Protected {
+    loader: Loader,
+    tls: Tls,
+    /// Set by `start`, for the initializers of objects loaded later
+    init_args: InitArgs,
+}
+
+impl ProcessState for Protected {
+    fn loader(&self) -> &Loader {
+        &self.loader
+    }
+}
+
+impl Process<TlsInitialized> {
+    pub fn adjust_protections(self) -> Result<Process<Protected>, region::Error> {
+        for obj in &self.state.loader.objects {
+            obj.protect()?;
+        }
+        let res = Process {
+            state: Protected {
+                loader: self.state.loader,
+                tls: self.state.tls,
+                init_args: InitArgs::default(),
+            },
+        };
+        Ok(res)
+    }
+}
+
+impl Process<Protected> {
+    fn build_stack(opts: &StartOptions) -> Vec<u64> {
+        let mut stack = Vec::new();
+        let null = 0_u64;
+
+        macro_rules! push {
+            ($x:expr) => {
+                stack.push($x as u64)
+            };
+        }
+
+        let argc = opts.args.len();
+        push!(argc);
+
+        for argv in &opts.args {
+            push!(argv.as_ptr());
+        }
+        push!(null);
+
+        for envp in &opts.env {
+            push!(envp.as_ptr());
+        }
+        push!(null);
+
+        for auxv in &opts.auxv {
+            push!(auxv.typ);
+            push!(auxv.value);
+        }
+        push!(AuxType::Null);
+        push!(null);
+
+        if stack.len() % 2 == 1 {
+            push!(null);
+        }
+
+        stack
+    }
+
+    pub fn start(mut self, opts: &StartOptions) -> ! {
         let loader = &mut self.state.loader;
+        let exec = &loader.objects[opts.exec_index];
+        let entry_point = exec.file.entry_point + exec.base;
+        let stack = Self::build_stack(opts);
+        // the program's `DT_PREINIT_ARRAY` runs before any initializer.
+        // Without an interpreter, the program initializes itself: static
+        // glibc's `__libc_start_main` does
+        let mut initializers = Vec::new();
+        if exec
+            .file
+            .segment_of_type(delf::SegmentType::Interp)
+            .is_some()
+        {
+            initializers.extend(exec.preinitializers.iter().copied());
+            initializers.extend(loader.initialize(0..loader.objects.len()));
+        }
+        let early_init = self
+            .state
+            .loader
+            .rtld
+            .early_init(&self.state.loader.objects);
+
+        let argc = opts.args.len() as i32;
+        let mut argv: Vec<_> = opts.args.iter().map(|x| x.as_ptr()).collect();
+        argv.push(std::ptr::null());
+        let mut envp: Vec<_> = opts.env.iter().map(|x| x.as_ptr()).collect();
+        envp.push(std::ptr::null());
+        let init_args = InitArgs {
+            argc,
+            argv: argv.as_ptr(),
+            envp: envp.as_ptr(),
+        };
+        self.state.init_args = init_args;
+
+        unsafe {
+            HOST_FS.store(thread_pointer(), Ordering::Release);
+            set_fs(self.state.tls.tcb_addr.0);
+            // `start` never returns, so the process outlives every lazy PLT call
+            // and every `dlopen`
+            PROCESS.store(&mut self.state, Ordering::Release);
+            gdbstub::stop(gdbstub::Event::Start);
+            if let Some(early_init) = early_init {
+                early_init(true);
+            }
+            #[allow(clippy::needless_range_loop)]
+            for i in 0..initializers.len() {
+                init_args.call(initializers[i]);
+            }
+            jmp(
+                entry_point.as_ptr(),
+                stack.as_ptr(),
+                stack.len(),
+                run_finalizers,
+            )
+        }
+    }
+}
+
+impl Loader {
+    pub fn load_object<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, LoadError> {
         let path = path
             .as_ref()
             .canonicalize()
@@ -73,16 +393,15 @@ impl Process<Loading> {
 
         let origin = path
             .parent()
-            .ok_or_else(|| LoadError::InvalidPath(path.clone()))?
-            .to_str()
             .ok_or_else(|| LoadError::InvalidPath(path.clone()))?;
-        loader.search_path.extend(
-            file.dynamic_entry_strings(delf::DynamicTag::RunPath)
-                .map(|path| String::from_utf8_lossy(path))
-                .map(|path| path.replace("$ORIGIN", origin))
-                .inspect(|path| println!("Found RunPath entry {path:?}"))
-                .map(PathBuf::from),
-        );
+        let search_path = |tag| -> Vec<PathBuf> {
+            file.dynamic_entry_strings(tag)
+                .flat_map(|list| search::expand(list, origin))
+                .inspect(|path| println!("Found {tag:?} entry {path:?}"))
+                .collect()
+        };
+        let rpath = search_path(delf::DynamicTag::RPath);
+        let runpath = search_path(delf::DynamicTag::RunPath);
 
         let load_segments = || {
             file.program_headers
@@ -99,12 +418,18 @@ impl Process<Loading> {
             })
             .ok_or(LoadError::NoLoadSegments)?;
 
-        let mem_size: usize = (mem_range.end - mem_range.start).into();
-        let mem_mmap = std::mem::ManuallyDrop::new(MemoryMap::new(
-            mem_size,
-            &[MapOption::MapReadable, MapOption::MapWritable],
-        )?);
-        let base = delf::Addr(mem_mmap.data() as _) - mem_range.start;
+        let base = if file.r#type == delf::Type::Exec {
+            // not position-independent: it goes where it was linked for
+            reserve_fixed(&path, mem_range.clone())?;
+            delf::Addr(0)
+        } else {
+            let mem_size: usize = (mem_range.end - mem_range.start).into();
+            let mem_mmap = std::mem::ManuallyDrop::new(MemoryMap::new(
+                mem_size,
+                &[MapOption::MapReadable, MapOption::MapWritable],
+            )?);
+            delf::Addr(mem_mmap.data() as _) - mem_range.start
+        };
 
         let segments = load_segments()
             .map(|ph| -> Result<_, LoadError> {
@@ -160,26 +485,46 @@ impl Process<Loading> {
                 .find(|seg| seg.vaddr_range.contains(&dynstr))
                 .unwrap_or_else(|| panic!("Segment not found for string table in {path:#?}"));
 
+            let versions = file.read_dynsym_versions()?;
+
             syms.into_iter()
-                .map(|sym| {
+                .enumerate()
+                .map(|(index, sym)| {
                     let name = Name::mapped(
                         segment.map.clone(),
                         (dynstr + sym.name - segment.vaddr_range.start).into(),
                     );
-                    NamedSym { sym, name }
+                    let version = versions.get(index).copied().flatten();
+                    NamedSym {
+                        sym,
+                        name,
+                        version: version.map(|v| Name::owned(v.name)),
+                        hidden: version.is_some_and(|v| v.hidden),
+                    }
                 })
                 .collect::<Vec<_>>()
         };
 
         let sym_map = MultiMap::from_iter(syms.iter().cloned().map(|sym| (sym.name.clone(), sym)));
 
-        let mut rels = Vec::new();
-        rels.extend(file.read_rela_entries()?);
-        rels.extend(file.read_jmp_rel_entries()?);
+        let rels = file.read_rela_entries()?;
+        // Kept apart: PLT stubs refer to these by index when binding lazily
+        let plt_rels = file.read_jmp_rel_entries()?;
 
         // DT_RELR: "compressed relative relocations" used by modern glibc/ld-linux.
         let relr = file.read_relr_vaddrs()?;
 
+        let mut preinitializers = Vec::new();
+        if let Some(preinit_array) = file.dynamic_entry(delf::DynamicTag::PreInitArray)
+            && let Some(preinit_array_sz) = file.dynamic_entry(delf::DynamicTag::PreInitArraySZ)
+        {
+            let preinit_array = base + preinit_array;
+            let n = preinit_array_sz.0 as usize / std::mem::size_of::<delf::Addr>();
+
+            let preinits: &[delf::Addr] = unsafe { preinit_array.as_slice(n) };
+            preinitializers.extend(preinits.iter().map(|&preinit| preinit + base));
+        }
+
         let mut initializers = Vec::new();
         if let Some(init) = file.dynamic_entry(delf::DynamicTag::Init) {
             let init = init + base;
@@ -196,6 +541,22 @@ impl Process<Loading> {
             initializers.extend(inits.iter().map(|&init| init + base));
         }
 
+        let mut finalizers = Vec::new();
+        if let Some(fini_array) = file.dynamic_entry(delf::DynamicTag::FiniArray)
+            && let Some(fini_array_sz) = file.dynamic_entry(delf::DynamicTag::FiniArraySz)
+        {
+            let fini_array = base + fini_array;
+            let n = fini_array_sz.0 as usize / std::mem::size_of::<delf::Addr>();
+
+            let finis: &[delf::Addr] = unsafe { fini_array.as_slice(n) };
+            finalizers.extend(finis.iter().rev().map(|&fini| fini + base));
+        }
+
+        if let Some(fini) = file.dynamic_entry(delf::DynamicTag::Fini) {
+            finalizers.push(fini + base);
+        }
+
+        let symbolic = file.is_symbolic();
         let object = Object {
             path: path.clone(),
             base,
@@ -205,31 +566,77 @@ impl Process<Loading> {
             syms,
             sym_map,
             rels,
+            plt_rels,
             relr,
+            preinitializers,
             initializers,
+            finalizers,
+            tls_module: None,
+            symbolic,
+            needed: Vec::new(),
+            loaded_by: None,
+            rpath,
+            runpath,
+            global: true,
+            local_scope: Vec::new(),
+            refcount: None,
         };
 
-        let index = loader.objects.len();
-        loader.objects.push(object);
-        loader.objects_by_path.insert(path, index);
+        let index = self.objects.len();
+        self.objects.push(object);
+        self.objects_by_path.insert(path, index);
         Ok(index)
     }
 
-    pub fn object_path(&self, name: &str) -> Result<PathBuf, LoadError> {
-        let loader = &self.state.loader;
-        loader
-            .search_path
-            .iter()
-            .filter_map(|prefix| prefix.join(name).canonicalize().ok())
-            .find(|path| path.exists())
+    /// Finds the library `requester` knows as `name`, in the order glibc uses:
+    /// `DT_RPATH` of the requester and of the objects that loaded it (unless
+    /// they have a `DT_RUNPATH`), `LD_LIBRARY_PATH`, the requester's own
+    /// `DT_RUNPATH`, `/etc/ld.so.cache`, and the default directories
+    pub fn object_path(&self, name: &str, requester: Option<usize>) -> Result<PathBuf, LoadError> {
+        // names with a slash are paths, relative to the working directory
+        if name.contains('/') {
+            return Path::new(name)
+                .canonicalize()
+                .map_err(|_| LoadError::NotFound(name.into()));
+        }
+
+        let requester = requester.map(|index| &self.objects[index]);
+        let mut rpath = Vec::new();
+        if requester.is_none_or(|obj| obj.runpath.is_empty()) {
+            let loaders = std::iter::successors(requester, |obj| {
+                obj.loaded_by.map(|index| &self.objects[index])
+            });
+            // the program's own RPATH applies to objects it didn't load, too
+            let program = self.objects.first();
+            for obj in loaders.chain(program) {
+                if obj.runpath.is_empty() {
+                    rpath.extend(obj.rpath.iter());
+                }
+            }
+        }
+        let runpath = requester.into_iter().flat_map(|obj| obj.runpath.iter());
+        let cached = self
+            .ld_cache
+            .get(name)
+            .map(|path| path.parent().unwrap_or(path));
+        let defaults = search::DEFAULT_DIRS.iter().map(Path::new);
+
+        rpath
+            .into_iter()
+            .map(PathBuf::as_path)
+            .chain(self.library_path.iter().map(PathBuf::as_path))
+            .chain(runpath.map(PathBuf::as_path))
+            .chain(cached)
+            .chain(defaults)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+            .and_then(|path| path.canonicalize().ok())
             .ok_or_else(|| LoadError::NotFound(name.into()))
     }
 
-    pub fn get_object(&mut self, name: &str) -> Result<GetResult, LoadError> {
-        let path = self.object_path(name)?;
-        self.state
-            .loader
-            .objects_by_path
+    pub fn get_object(&mut self, name: &str, requester: usize) -> Result<GetResult, LoadError> {
+        let path = self.object_path(name, Some(requester))?;
+        self.objects_by_path
             .get(&path)
             .map(|&index| Ok(GetResult::Cached(index)))
             .unwrap_or_else(|| self.load_object(path).map(GetResult::Fresh))
@@ -239,279 +646,58 @@ impl Process<Loading> {
         &mut self,
         path: P,
     ) -> Result<usize, LoadError> {
-        let index = self.load_object(path)?;
-
-        let mut current = vec![index];
-        while !current.is_empty() {
-            current = current
-                .into_iter()
-                .map(|index| &self.state.loader.objects[index].file)
-                .flat_map(|file| file.dynamic_entry_strings(delf::DynamicTag::Needed))
-                .map(|s| String::from_utf8_lossy(s).to_string())
-                .collect::<Vec<_>>()
-                .into_iter()
-                .map(|dep| self.get_object(&dep))
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .filter_map(GetResult::fresh)
-                .collect();
-        }
-        Ok(index)
+        self.load_closure(path, &[], None)
     }
 
-    pub fn allocate_tls(mut self) -> Process<TlsAllocated> {
-        let mut offsets = HashMap::new();
-        let mut storage_space = 0;
-        for obj in &mut self.state.loader.objects {
-            let needed = obj
-                .file
-                .segment_of_type(delf::SegmentType::TLS)
-                .map(|ph| ph.memsz.0)
-                .unwrap_or_default();
+    /// Loads `path`, the `preload` objects, then their dependencies,
+    /// breadth-first. Libraries that can't be found are an error, unless
+    /// there's a `missing` list to add them to
+    fn load_closure<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        preload: &[String],
+        mut missing: Option<&mut Vec<MissingDependency>>,
+    ) -> Result<usize, LoadError> {
+        let index = self.load_object(path)?;
 
-            if needed == 0 {
-                continue;
+        let mut current = vec![index];
+        for name in preload {
+            // like ld.so, a preload that can't be found is only worth a warning
+            match self.get_object(name, index) {
+                Ok(GetResult::Fresh(preloaded)) => current.push(preloaded),
+                Ok(GetResult::Cached(_)) => {}
+                Err(e) => println!("Warning: {name:?} cannot be preloaded, ignoring it: {e}"),
             }
-            storage_space += needed;
-            let offset = delf::Addr(storage_space);
-            offsets.insert(obj.base, offset);
-        }
-
-        let storage_space = storage_space as usize;
-        let tcbhead_size = 704;
-        let total_size = storage_space + tcbhead_size;
-
-        let mut block = Vec::with_capacity(total_size);
-        let tcb_addr = delf::Addr(block.as_ptr() as u64 + storage_space as u64);
-        block.resize(storage_space, 0_u8);
-
-        #[allow(clippy::let_unit_value)]
-        {
-            // TODO. Maybe need to improve tls, dtv...
-            let _tcb = block.extend(&tcb_addr.0.to_le_bytes());
-            let _dtv = block.extend(&0_u64.to_le_bytes());
-            let _thread_pointer = block.extend(&tcb_addr.0.to_le_bytes());
-            let _multiple_threads = block.extend(&0_u32.to_le_bytes());
-            let _gscope_flag = block.extend(&0_u32.to_le_bytes());
-            let _sysinfo = block.extend(&0_u64.to_le_bytes());
-            let _stack_guard = block.extend(&0xDEADBEEF_u64.to_le_bytes());
-            let _pointer_guard = block.extend(&0xFEEDFACE_u64.to_le_bytes());
-        }
-
-        while block.len() < block.capacity() {
-            block.push(0_u8);
-        }
-
-        let tls = Tls {
-            offsets,
-            block,
-            tcb_addr,
-        };
-
-        Process {
-            state: TlsAllocated {
-                loader: self.state.loader,
-                tls,
-            },
         }
-    }
-
-    pub fn pathc_libc(&self) {
-        let mut stub_map = HashMap::<&str, Vec<u8>>::new();
-
-        // No such internal symbol in newer glibc
-        stub_map.insert(
-            "_dl_addr",
-            vec![
-                0x48, 0x31, 0xc0, // xor rax, rax
-                0xc3, // ret
-            ],
-        );
-
-        // Newer glibc exports dladdr/dladdr1 from libc (ptmalloc_init uses dl_addr internally).
-        stub_map.insert(
-            "dladdr",
-            vec![
-                0x48, 0x31, 0xc0, // xor rax, rax
-                0xc3, // ret
-            ],
-        );
-        stub_map.insert(
-            "dladdr1",
-            vec![
-                0x48, 0x31, 0xc0, // xor rax, rax
-                0xc3, // ret
-            ],
-        );
-
-        // Minimal hack: bypass locale initialization (otherwise glibc hits TLS/TSD assumptions
-        // we don't fully implement yet, and crashes in read_alias_file).
-        stub_map.insert(
-            "setlocale",
-            vec![
-                0x48, 0x31, 0xc0, // xor rax, rax
-                0xc3, // ret
-            ],
-        );
-
-        stub_map.insert(
-            "exit",
-            vec![
-                0x48, 0x31, 0xff, // xor rdi, rdi
-                0xb8, 0x3c, 0x00, 0x00, 0x00, // mov eax, 60
-                0x0f, 0x05, // syscall
-            ],
-        );
-        let pattern = "/libc.so.6";
-        let libc = match self
-            .state
-            .loader
-            .objects
-            .iter()
-            .find(|&obj| obj.path.to_string_lossy().contains(pattern))
-        {
-            Some(x) => x,
-            None => {
-                println!("Warning: could not find libc to patch!");
-                return;
-            }
-        };
-
-        for (name, instructions) in stub_map {
-            let name = Name::owned(name);
-            let sym = match libc.sym_map.get(&name) {
-                Some(sym) => ObjectSym { obj: libc, sym },
-                None => {
-                    println!("expected to find symbol {:?} in {:?}", name, libc.path);
-                    continue;
+        while !current.is_empty() {
+            let mut fresh = Vec::new();
+            for index in current {
+                let deps: Vec<_> = self.objects[index]
+                    .file
+                    .dynamic_entry_strings(delf::DynamicTag::Needed)
+                    .map(|s| String::from_utf8_lossy(s).to_string())
+                    .collect();
+                for dep in deps {
+                    let dep = match (self.get_object(&dep, index), &mut missing) {
+                        (Err(LoadError::NotFound(name)), Some(missing)) => {
+                            missing.push(MissingDependency {
+                                needed_by: index,
+                                name,
+                            });
+                            continue;
+                        }
+                        (res, _) => res?,
+                    };
+                    self.objects[index].needed.push(dep.index());
+                    if let GetResult::Fresh(dep) = dep {
+                        self.objects[dep].loaded_by = Some(index);
+                        fresh.push(dep);
+                    }
                 }
-            };
-            println!("Patching libc funtion {:?} ({:?})", sym.value(), name);
-            unsafe {
-                sym.value().write(&instructions);
-            }
-        }
-
-        // `__ctype_*_loc()` must return T **. Redirect them to our own static tables
-        // to keep ctype users working with locale support stubbed out.
-        let ctype_table = &self.state.loader.fake_ctype;
-        let ctype_fixups = [
-            ("__ctype_b_loc", &ctype_table.b_ptr as *const _ as *const ()),
-            (
-                "__ctype_tolower_loc",
-                &ctype_table.tolower_ptr as *const _ as *const (),
-            ),
-            (
-                "__ctype_toupper_loc",
-                &ctype_table.toupper_ptr as *const _ as *const (),
-            ),
-        ];
-
-        fn make_ret_ptr_stub(ptr: *const ()) -> Vec<u8> {
-            let addr = ptr as u64;
-            let mut code = Vec::with_capacity(11);
-            code.extend([0x48, 0xB8]); // movabs rax, imm64
-            code.extend(addr.to_le_bytes());
-            code.push(0xC3); // ret
-            code
-        }
-
-        for (name, ret_ptr) in ctype_fixups {
-            let name = Name::owned(name);
-            let sym = match libc.sym_map.get(&name) {
-                Some(sym) => ObjectSym { obj: libc, sym },
-                None => continue,
-            };
-            let code = make_ret_ptr_stub(ret_ptr);
-            println!(
-                "Patching libc function {:?} ({:?}) -> return fake ctype ptr {:?}",
-                sym.value(),
-                name,
-                delf::Addr(ret_ptr as u64),
-            );
-            unsafe {
-                sym.value().write(&code);
-            }
-        }
-    }
-}
-
-pub struct TlsAllocated {
-    loader: Loader,
-    pub tls: Tls,
-}
-
-impl ProcessState for TlsAllocated {
-    fn loader(&self) -> &Loader {
-        &self.loader
-    }
-}
-
-pub struct Relocated {
-    loader: Loader,
-    tls: Tls,
-}
-
-impl ProcessState for Relocated {
-    fn loader(&self) -> &Loader {
-        &self.loader
-    }
-}
-
-impl Process<TlsAllocated> {
-    fn bootstrap_rtld_global(&self) {
-        // On modern glibc, `ld-linux` defines `_rtld_global`, which is normally
-        // initialized very early during rtld startup.
-        //
-        // In `elk`, ld-linux is loaded as a regular shared object and its entrypoint
-        // is never executed, so `_rtld_global` remains zero-initialized and libc
-        // crashes extremely early (e.g. in `__libc_start_main_impl`).
-        //
-        // Minimal startup hack: bring `_rtld_global` into a minimally valid state
-        // so libc can proceed past early initialization.
-        let name_global = Name::owned("_rtld_global");
-
-        fn find_defined<'a>(obj: &'a Object, name: &Name) -> Option<&'a NamedSym> {
-            obj.sym_map
-                .get_vec(name)?
-                .iter()
-                .find(|sym| !sym.sym.shndx.is_undef())
-        }
-
-        // Many objects reference `_rtld_global` as an undefined symbol (e.g. libc),
-        // but only ld-linux actually defines it. We must locate the defining object.
-        let Some((obj_base, global_sym)) = self.state.loader.objects.iter().find_map(|obj| {
-            let global = find_defined(obj, &name_global)?;
-            Some((obj.base, global))
-        }) else {
-            return;
-        };
-
-        if global_sym.sym.size == 0 {
-            return;
-        }
-        let global_addr = obj_base + global_sym.sym.value;
-
-        // On this glibc, libc treats `_rtld_global` as a handle to some deeper structure:
-        // it loads `_rtld_global` from the GOT, then does `mov (%r15), %r14` and uses `r14`
-        // as a base pointer. If the first qword is null, libc crashes immediately.
-        //
-        // Minimal hack: Point it back to itself to ensure a non-NULL base pointer.
-        unsafe {
-            let first: u64 = std::ptr::read_unaligned(global_addr.as_ptr());
-            if first == 0 {
-                global_addr.set(global_addr.0);
             }
+            current = fresh;
         }
-
-        // More minimal hardening for modern glibc startup:
-        // `__libc_start_main_impl` may call function pointers derived from
-        // `_rtld_global + 0xa0` / `_rtld_global + 0x108`.
-        // Setting them to null makes libc take the "skip" paths.
-        unsafe {
-            (global_addr + delf::Addr(0xa0)).set::<u64>(0);
-            (global_addr + delf::Addr(0x108)).set::<u64>(0);
-        }
+        Ok(index)
     }
 
     unsafe fn apply_relr_at(obj: &Object, offset: delf::Addr) {
@@ -523,6 +709,7 @@ impl Process<TlsAllocated> {
 
     fn apply_relocation<'a>(
         &self,
+        tls: &Tls,
         objrel: ObjectRel<'a>,
         group: RelocGroup,
     ) -> Result<Option<ObjectRel<'a>>, RelocationError> {
@@ -550,8 +737,10 @@ impl Process<TlsAllocated> {
             }
         };
 
+        // resolvers run once everything else is relocated, since they may
+        // read relocated pointers
         if let RelocGroup::Direct = group
-            && (reltype == RT::Relative || found.is_indirect())
+            && (reltype == RT::IRelative || found.is_indirect())
         {
             return Ok(Some(objrel));
         }
@@ -576,27 +765,30 @@ impl Process<TlsAllocated> {
             },
             RT::TPOff64 => unsafe {
                 if let ResolvedSym::Defined(sym) = found {
-                    let obj_offset =
-                        self.state
-                            .tls
-                            .offsets
-                            .get(&sym.obj.base)
-                            .unwrap_or_else(|| {
-                                panic!(
-                                    "No thread-local storage allocated for object {:?}",
-                                    sym.obj.file
-                                )
-                            });
+                    // initial-exec code can only reach blocks allocated with the thread
+                    let obj_offset = tls.offsets.get(&sym.obj.base).ok_or_else(|| {
+                        RelocationError::NoStaticTls(obj.path.clone(), sym.obj.path.clone())
+                    })?;
                     let obj_offset = -(obj_offset.0 as i64);
                     let offset =
                         obj_offset + sym.sym.sym.value.0 as i64 + objrel.rel.addend.0 as i64;
                     objrel.addr().set(offset);
                 }
             },
-            RT::DTPMOD64 | RT::DTPOFF64 => {
-                // TODO. Implement rela
-                println!("skip for now relocation {reltype:?}")
-            }
+            RT::DTPMOD64 => unsafe {
+                // symbol 0 stands for the object's own block (local-dynamic model)
+                if let ResolvedSym::Defined(sym) = found {
+                    let module = sym
+                        .obj
+                        .tls_module
+                        .ok_or_else(|| RelocationError::NoTlsModule(sym.obj.path.clone()))?;
+                    objrel.addr().set(module);
+                }
+            },
+            RT::DTPOFF64 => unsafe {
+                // offset within the module's block, which mirrors its PT_TLS segment
+                objrel.addr().set(found.tls_offset() + addend);
+            },
             _ => {
                 return Err(RelocationError::UnimplementedRelocation(
                     obj.path.clone(),
@@ -604,219 +796,784 @@ impl Process<TlsAllocated> {
                 ));
             }
         }
-        Ok(None)
+        Ok(None)
+    }
+
+    /// Points the GOT entries of `obj`'s PLT back at the PLT stubs, which push the
+    /// relocation index and jump to PLT0, and PLT0 at our resolver through GOT[1]/GOT[2].
+    /// Returns false if the object has no lazy PLT to speak of.
+    fn prepare_lazy_binding(index: usize, obj: &Object) -> bool {
+        let Some(got) = obj.file.dynamic_entry(delf::DynamicTag::PltGot) else {
+            return false;
+        };
+        let slots: Vec<_> = obj
+            .plt_rels
+            .iter()
+            .filter(|rel| rel.r#type == delf::RelType::JumpSlot)
+            .map(|rel| ObjectRel { obj, rel })
+            .collect();
+        if slots.is_empty() || obj.file.binds_now() {
+            return false;
+        }
+
+        let got = obj.base + got;
+        let word = delf::Addr(std::mem::size_of::<u64>() as u64);
+        unsafe {
+            (got + word).set(index as u64);
+            (got + word + word).set(lazy_trampoline as *const () as u64);
+        }
+
+        for objrel in &slots {
+            // the link editor filled the slot with the address of the PLT stub's `push`
+            unsafe {
+                let stub: u64 = std::ptr::read_unaligned(objrel.addr().as_ptr());
+                objrel.addr().set(stub + obj.base.0);
+            }
+        }
+        println!(
+            "Binding {} PLT entries lazily for {:?}",
+            slots.len(),
+            obj.path
+        );
+        true
+    }
+
+    /// Relocates `objects[range]`, which were just loaded: either the whole
+    /// program or what `dlopen` brought in
+    fn relocate(&self, tls: &Tls, range: Range<usize>) -> Result<(), RelocationError> {
+        // Apply DT_RELR for all objects first: it initializes lots of pointers in modern glibc/ld-linux.
+        let objects = &self.objects[range.clone()];
+        for obj in objects {
+            for &offset in &obj.relr {
+                unsafe { Self::apply_relr_at(obj, offset) };
+            }
+        }
+
+        let lazy: Vec<_> = range
+            .zip(objects)
+            .map(|(index, obj)| {
+                matches!(self.binding, Binding::Lazy) && Self::prepare_lazy_binding(index, obj)
+            })
+            .collect();
+
+        let mut rels: Vec<_> = objects
+            .iter()
+            .zip(lazy)
+            .rev()
+            .flat_map(|(obj, lazy)| {
+                let plt_rels = obj
+                    .plt_rels
+                    .iter()
+                    .filter(move |rel| !(lazy && rel.r#type == delf::RelType::JumpSlot));
+                obj.rels
+                    .iter()
+                    .chain(plt_rels)
+                    .map(move |rel| ObjectRel { obj, rel })
+            })
+            .collect();
+
+        for &group in &[RelocGroup::Direct, RelocGroup::Indirect] {
+            println!("Applying {:?} relocations ({} left)", group, rels.len());
+            rels = rels
+                .into_iter()
+                .map(|objrel| self.apply_relocation(tls, objrel, group))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .collect();
+        }
+
+        Ok(())
+    }
+
+    /// Overwrites every definition of `name` with a jump to `target`
+    pub fn redirect(&self, name: &str, target: u64) {
+        let name = Name::owned(name);
+        for obj in &self.objects {
+            let Some(sym) = obj.defined(&name) else {
+                continue;
+            };
+            let sym = ObjectSym { obj, sym };
+            println!(
+                "Redirecting {:?} ({:?}) to {:?}",
+                sym.value(),
+                name,
+                delf::Addr(target)
+            );
+            let mut code = Vec::with_capacity(12);
+            code.extend([0x48, 0xB8]); // movabs rax, imm64
+            code.extend(target.to_le_bytes());
+            code.extend([0xFF, 0xE0]); // jmp rax
+            unsafe {
+                sym.value().write(&code);
+            }
+        }
+    }
+
+    /// Initializers of `objects[range]`, in `init_order`. The objects count
+    /// as initialized from then on
+    fn initialize(&mut self, range: Range<usize>) -> Vec<delf::Addr> {
+        let order = self.init_order(range);
+        let initializers = order
+            .iter()
+            .flat_map(|&index| self.objects[index].initializers.iter().copied())
+            .collect();
+        self.initialized.extend(order);
+        initializers
+    }
+
+    /// `objects[range]` in the order they're initialized: each after the
+    /// objects it needs, and otherwise in reverse load order, as glibc's
+    /// `_dl_sort_maps` does. Objects outside `range` are already initialized
+    fn init_order(&self, range: Range<usize>) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut path = Vec::new();
+        for index in range.clone().rev() {
+            self.visit_dependencies(index, &range, &mut path, &mut order);
+        }
+        order
+    }
+
+    /// Depth-first walk for `init_order`. `path` leads from the object the
+    /// walk started from to `index`
+    fn visit_dependencies(
+        &self,
+        index: usize,
+        range: &Range<usize>,
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) {
+        if order.contains(&index) {
+            return;
+        }
+        if let Some(start) = path.iter().position(|&i| i == index) {
+            // there's no right order: the last object of the cycle goes first
+            let name = |i: usize| self.objects[i].path.display().to_string();
+            let cycle: Vec<_> = path[start..]
+                .iter()
+                .chain([&index])
+                .map(|&i| name(i))
+                .collect();
+            println!(
+                "Warning: dependency cycle {}: {} is initialized before {}, which it needs",
+                cycle.join(" -> "),
+                name(path[path.len() - 1]),
+                name(index),
+            );
+            return;
+        }
+
+        path.push(index);
+        for &dep in &self.objects[index].needed {
+            if range.contains(&dep) {
+                self.visit_dependencies(dep, range, path, order);
+            }
+        }
+        path.pop();
+        order.push(index);
+    }
+
+    /// The objects `obj` binds against, in lookup order: the global scope,
+    /// then the ones it was opened with
+    fn scope<'a>(&'a self, obj: &'a Object) -> impl Iterator<Item = &'a Object> {
+        let global = self.objects.iter().filter(|obj| obj.global);
+        let local = obj
+            .local_scope
+            .iter()
+            .map(|&index| &self.objects[index])
+            .filter(|obj| !obj.global);
+        global.chain(local)
+    }
+
+    /// `index` and everything it depends on, breadth-first
+    fn closure(&self, index: usize) -> Vec<usize> {
+        let mut closure = vec![index];
+        let mut i = 0;
+        while let Some(&current) = closure.get(i) {
+            for &dep in &self.objects[current].needed {
+                if !closure.contains(&dep) {
+                    closure.push(dep);
+                }
+            }
+            i += 1;
+        }
+        closure
+    }
+
+    /// Looks `name` up the way `dlsym` does: in the global scope, after
+    /// `caller` in its own scope (`RTLD_NEXT`), or among the dependencies of
+    /// a `dlopen` handle
+    pub fn find_symbol(
+        &self,
+        scope: SymbolScope,
+        name: &[u8],
+        version: Option<&[u8]>,
+    ) -> Option<SymbolAddress> {
+        let objects: Vec<&Object> = match scope {
+            // the program's handle, from `dlopen(NULL)`, also stands for the global scope
+            SymbolScope::Default | SymbolScope::Object(0) => {
+                self.objects.iter().filter(|obj| obj.global).collect()
+            }
+            SymbolScope::Next(caller) => {
+                let caller = &self.objects[self.object_at(caller)?];
+                self.scope(caller)
+                    .skip_while(|&obj| !std::ptr::eq(obj, caller))
+                    .skip(1)
+                    .collect()
+            }
+            SymbolScope::Object(index) => self
+                .closure(index)
+                .into_iter()
+                .map(|index| &self.objects[index])
+                .collect(),
+        };
+
+        let name = Name::owned(name);
+        let (obj, sym) = objects.into_iter().find_map(|obj| {
+            let sym = obj.sym_map.get_vec(&name)?.iter().find(|sym| {
+                !sym.sym.shndx.is_undef()
+                    && match version {
+                        Some(version) => sym
+                            .version
+                            .as_ref()
+                            .is_some_and(|v| v.as_slice() == version),
+                        None => !sym.hidden,
+                    }
+            })?;
+            Some((obj, sym))
+        })?;
+
+        Some(match (sym.sym.r#type, obj.tls_module) {
+            (delf::SymType::TLS, Some(module)) => SymbolAddress::Tls {
+                module,
+                offset: sym.sym.value,
+            },
+            _ => SymbolAddress::Addr(ObjectSym { obj, sym }.value()),
+        })
+    }
+
+    /// The loaded object whose memory holds `addr`
+    fn object_at(&self, addr: delf::Addr) -> Option<usize> {
+        self.objects.iter().position(|obj| {
+            obj.is_loaded()
+                && (obj.base + obj.mem_range.start..obj.base + obj.mem_range.end).contains(&addr)
+        })
+    }
+
+    /// Loads `name` and its dependencies, as `dlopen` does when called from
+    /// code at `caller`, whose `DT_RPATH` and `DT_RUNPATH` are searched.
+    /// Returns the object's index, and the initializers to run before handing
+    /// it out
+    fn open(
+        &mut self,
+        tls: &mut Tls,
+        name: &str,
+        caller: delf::Addr,
+        opts: OpenOptions,
+    ) -> Result<(usize, Vec<delf::Addr>), DlError> {
+        // code elk doesn't know about (say, a JIT) searches the program's RPATH
+        let path = self.object_path(name, self.object_at(caller))?;
+        if let Some(&index) = self.objects_by_path.get(&path) {
+            let obj = &mut self.objects[index];
+            if opts.no_delete {
+                obj.refcount = None;
+            } else if let Some(refcount) = &mut obj.refcount {
+                *refcount += 1;
+            }
+            if opts.global {
+                self.promote(index);
+            }
+            return Ok((index, Vec::new()));
+        }
+        if opts.no_load {
+            return Err(DlError::NotLoaded(name.into()));
+        }
+
+        let first = self.objects.len();
+        let loaded = self
+            .load_object_and_dependencies(&path)
+            .map_err(DlError::from)
+            .and_then(|index| {
+                self.link(tls, index, first..self.objects.len(), opts)?;
+                Ok(index)
+            });
+        let index = match loaded {
+            Ok(index) => index,
+            Err(e) => {
+                // whatever was mapped stays mapped, but out of sight
+                for index in first..self.objects.len() {
+                    let obj = &mut self.objects[index];
+                    obj.global = false;
+                    obj.refcount = Some(0);
+                    self.objects_by_path.remove(&obj.path);
+                }
+                return Err(e);
+            }
+        };
+
+        self.rtld.add(&self.objects);
+        Ok((index, self.initialize(first..self.objects.len())))
+    }
+
+    /// Relocates and protects the objects `open` just loaded, with `index`
+    /// at the root
+    fn link(
+        &mut self,
+        tls: &mut Tls,
+        index: usize,
+        range: Range<usize>,
+        opts: OpenOptions,
+    ) -> Result<(), DlError> {
+        let scope = self.closure(index);
+        for i in range.clone() {
+            let obj = &mut self.objects[i];
+            obj.global = false;
+            obj.local_scope = scope.clone();
+            obj.refcount = if obj
+                .file
+                .dynamic_flags_1()
+                .contains(delf::DynamicFlag1::NoDelete)
+            {
+                None
+            } else {
+                Some(0)
+            };
+            tls.allocate_dynamic(obj);
+        }
+        // each new object is held by the objects that need it, the root by its handle
+        for i in range.clone() {
+            for dep in self.objects[i].needed.clone() {
+                if let Some(refcount) = &mut self.objects[dep].refcount {
+                    *refcount += 1;
+                }
+            }
+        }
+        let root = &mut self.objects[index];
+        if opts.no_delete {
+            root.refcount = None;
+        } else if let Some(refcount) = &mut root.refcount {
+            *refcount += 1;
+        }
+        // remember which objects were local, in case they must be again
+        let mut promoted = Vec::new();
+        if opts.global {
+            promoted = self
+                .closure(index)
+                .into_iter()
+                .filter(|&i| i < range.start && !self.objects[i].global)
+                .collect();
+            self.promote(index);
+        }
+
+        let binding = self.binding;
+        if let Binding::Eager = opts.binding {
+            self.binding = Binding::Eager;
+        }
+        let relocated = self.relocate(tls, range.clone());
+        self.binding = binding;
+        let linked = relocated.map_err(DlError::from).and_then(|()| {
+            for obj in &self.objects[range.clone()] {
+                tls.initialize(obj);
+                obj.protect()?;
+            }
+            Ok(())
+        });
+
+        if linked.is_err() {
+            // `open` hides the new objects: their TLS modules are free again...
+            for i in range.clone().rev() {
+                tls.release_dynamic(&mut self.objects[i]);
+            }
+            // ...and the others must not be held by them
+            for i in range.clone() {
+                for dep in self.objects[i].needed.clone() {
+                    if dep >= range.start {
+                        continue;
+                    }
+                    if let Some(refcount) = &mut self.objects[dep].refcount {
+                        *refcount -= 1;
+                    }
+                }
+            }
+            for i in promoted {
+                self.objects[i].global = false;
+            }
+        }
+        linked
+    }
+
+    /// Adds `index` and its dependencies to the global scope
+    fn promote(&mut self, index: usize) {
+        for i in self.closure(index) {
+            self.objects[i].global = true;
+        }
+    }
+
+    /// Drops a reference to `index`, unloading it if it was the last one.
+    /// Returns the finalizers of the objects it unloaded, dependents first
+    fn close(&mut self, index: usize) -> Result<Vec<delf::Addr>, DlError> {
+        let mut finalizers = Vec::new();
+        self.release(index, &mut finalizers)?;
+        Ok(finalizers)
+    }
+
+    fn release(&mut self, index: usize, finalizers: &mut Vec<delf::Addr>) -> Result<(), DlError> {
+        let obj = &mut self.objects[index];
+        match &mut obj.refcount {
+            None => return Ok(()),
+            Some(0) => return Err(DlError::InvalidHandle),
+            Some(refcount) => *refcount -= 1,
+        }
+        if obj.is_loaded() {
+            return Ok(());
+        }
+
+        // its code may still be on some stack: keep it mapped, but unreachable
+        println!("unloading {:?}", obj.path);
+        obj.global = false;
+        self.objects_by_path.remove(&obj.path);
+        // objects whose initializers never ran don't get finalized either
+        if let Some(position) = self.initialized.iter().position(|&i| i == index) {
+            self.initialized.remove(position);
+            finalizers.extend(&self.objects[index].finalizers);
+        }
+        self.rtld.relink(&self.objects);
+        for dep in self.objects[index].needed.clone() {
+            self.release(dep, finalizers)?;
+        }
+        Ok(())
+    }
+
+    /// What each symbol reference of each object binds to, as relocating
+    /// them would. Lists every symbol once per object, unless it binds to
+    /// the object itself
+    pub fn symbol_bindings(&self) -> Vec<SymbolBinding> {
+        let mut bindings = Vec::new();
+        for (index, obj) in self.objects.iter().enumerate() {
+            let mut seen = std::collections::HashSet::new();
+            for rel in obj.rels.iter().chain(&obj.plt_rels) {
+                if rel.sym == 0 || !seen.insert(rel.sym) {
+                    continue;
+                }
+                let wanted = ObjectSym {
+                    obj,
+                    sym: &obj.syms[rel.sym as usize],
+                };
+                let ignore_self = matches!(rel.r#type, delf::RelType::Copy);
+                let to = match self.lookup_symbol(&wanted, ignore_self) {
+                    ResolvedSym::Defined(found) => self
+                        .objects
+                        .iter()
+                        .position(|obj| std::ptr::eq(obj, found.obj)),
+                    ResolvedSym::Undefined => None,
+                };
+                // an object binding to its own definitions isn't worth reporting
+                if to == Some(index) {
+                    continue;
+                }
+                bindings.push(SymbolBinding {
+                    from: index,
+                    sym: wanted.sym.clone(),
+                    to,
+                });
+            }
+        }
+        bindings
     }
 
-    pub fn apply_relocations(self) -> Result<Process<Relocated>, RelocationError> {
-        // Apply DT_RELR for all objects first: it initializes lots of pointers in modern glibc/ld-linux.
-        for obj in &self.state.loader.objects {
-            for &offset in &obj.relr {
-                unsafe { Self::apply_relr_at(obj, offset) };
-            }
+    fn lookup_symbol<'a>(&'a self, wanted: &ObjectSym<'a>, ignore_self: bool) -> ResolvedSym<'a> {
+        if !ignore_self
+            && !wanted.sym.sym.shndx.is_undef()
+            && wanted.sym.sym.visibility == delf::SymVisibility::Protected
+        {
+            // other objects may use it, but can't interpose it
+            return ResolvedSym::Defined(wanted.clone());
         }
 
-        let mut rels: Vec<_> = self
-            .state
-            .loader
-            .objects
-            .iter()
-            .rev()
-            .flat_map(|obj| obj.rels.iter().map(move |rel| ObjectRel { obj, rel }))
-            .collect();
+        let symbolic = (!ignore_self && wanted.obj.symbolic).then_some(wanted.obj);
+        let candidates = symbolic.into_iter().chain(
+            self.scope(wanted.obj)
+                .filter(|&obj| !(ignore_self && std::ptr::eq(wanted.obj, obj))),
+        );
 
-        for &group in &[RelocGroup::Direct, RelocGroup::Indirect] {
-            println!("Applying {:?} relocations ({} left)", group, rels.len());
-            rels = rels
-                .into_iter()
-                .map(|objrel| self.apply_relocation(objrel, group))
-                .collect::<Result<Vec<_>, _>>()?
+        for obj in candidates {
+            if let Some(sym) = obj
+                .sym_map
+                .get_vec(&wanted.sym.name)
                 .into_iter()
                 .flatten()
-                .collect();
+                .find(|sym| !sym.sym.shndx.is_undef() && sym.satisfies(wanted.sym))
+            {
+                return ResolvedSym::Defined(ObjectSym { obj, sym });
+            }
         }
+        ResolvedSym::Undefined
+    }
+}
+
+/// Whether `apply_relocations` resolves `JUMP_SLOT` relocations upfront, or leaves
+/// them to the first call through the PLT
+#[derive(Clone, Copy, Debug)]
+pub enum Binding {
+    Eager,
+    /// Unless the object asks for `BIND_NOW`
+    Lazy,
+}
 
-        // After relocations, initialize ld-linux internal global state enough for libc startup.
-        self.bootstrap_rtld_global();
+/// The process lazy PLT entries are bound against and `dlopen` loads into,
+/// set once it starts
+static PROCESS: AtomicPtr<Protected> = AtomicPtr::new(std::ptr::null_mut());
 
-        let res = Process {
-            state: Relocated {
-                loader: self.state.loader,
-                tls: self.state.tls,
-            },
-        };
-        Ok(res)
-    }
+/// elk's own thread pointer, from before `start` switched to the program's
+static HOST_FS: AtomicU64 = AtomicU64::new(0);
+
+/// The loader of the running program, once `start` was called
+pub fn running_loader() -> Option<&'static Loader> {
+    unsafe { PROCESS.load(Ordering::Acquire).as_ref() }.map(|process| &process.loader)
 }
 
-pub struct TlsInitialized {
-    loader: Loader,
-    tls: Tls,
+/// The running program, for `dlopen` and `dlclose`. Must not be held across
+/// calls into the program
+pub unsafe fn running_process() -> Option<&'static mut Protected> {
+    unsafe { PROCESS.load(Ordering::Acquire).as_mut() }
 }
 
-impl ProcessState for TlsInitialized {
-    fn loader(&self) -> &Loader {
-        &self.loader
+/// Runs `f` on elk's own thread pointer. Once the program started, `fs`
+/// points to its TCB, where the host libc would look for its malloc arenas
+/// and Rust for its thread-locals: anything that allocates must go through here
+pub unsafe fn with_host_tls<T>(f: impl FnOnce() -> T) -> T {
+    let host = HOST_FS.load(Ordering::Acquire);
+    if host == 0 {
+        return f();
+    }
+    unsafe {
+        let guest = thread_pointer();
+        set_fs(host);
+        let res = f();
+        set_fs(guest);
+        res
     }
 }
 
-impl Process<Relocated> {
-    pub fn initialize_tls(self) -> Process<TlsInitialized> {
-        let tls = &self.state.tls;
+impl Protected {
+    /// `dlopen` from code at `caller`: the object's index, and initializers
+    /// the caller must run on the program's thread pointer
+    pub fn open(
+        &mut self,
+        name: &str,
+        caller: delf::Addr,
+        opts: OpenOptions,
+    ) -> Result<(usize, Vec<delf::Addr>), DlError> {
+        self.loader.open(&mut self.tls, name, caller, opts)
+    }
 
-        for obj in &self.state.loader.objects {
-            let Some(ph) = obj.file.segment_of_type(delf::SegmentType::TLS) else {
-                continue;
-            };
-            if let Some(offset) = tls.offsets.get(&obj.base).cloned() {
-                unsafe {
-                    (tls.tcb_addr - offset).write((ph.vaddr + obj.base).as_slice(ph.filesz.into()));
-                }
-            }
-        }
+    /// `dlclose`: the finalizers the caller must run on the program's
+    /// thread pointer
+    pub fn close(&mut self, index: usize) -> Result<Vec<delf::Addr>, DlError> {
+        self.loader.close(index)
+    }
 
-        Process {
-            state: TlsInitialized {
-                loader: self.state.loader,
-                tls: self.state.tls,
-            },
-        }
+    pub fn init_args(&self) -> InitArgs {
+        self.init_args
     }
 }
 
-pub struct Protected {
-    loader: Loader,
-    tls: Tls,
+/// A library named by `DT_NEEDED` that couldn't be found
+#[derive(Debug)]
+pub struct MissingDependency {
+    pub needed_by: usize,
+    pub name: String,
 }
 
-impl ProcessState for Protected {
-    fn loader(&self) -> &Loader {
-        &self.loader
-    }
+/// A symbol reference, and the object that satisfies it if any
+#[derive(Debug)]
+pub struct SymbolBinding {
+    pub from: usize,
+    pub sym: NamedSym,
+    pub to: Option<usize>,
 }
 
-impl Process<TlsInitialized> {
-    pub fn adjust_protections(self) -> Result<Process<Protected>, region::Error> {
-        for obj in &self.state.loader.objects {
-            for seg in &obj.segments {
-                let mut protection = region::Protection::NONE;
-                for flag in seg.flags.iter() {
-                    protection |= match flag {
-                        delf::SegmentFlag::Execute => region::Protection::EXECUTE,
-                        delf::SegmentFlag::Write => region::Protection::WRITE,
-                        delf::SegmentFlag::Read => region::Protection::READ,
-                    }
-                }
-                unsafe {
-                    region::protect(seg.map.data(), seg.map.len(), protection)?;
-                }
-            }
-        }
-        let res = Process {
-            state: Protected {
-                loader: self.state.loader,
-                tls: self.state.tls,
-            },
-        };
-        Ok(res)
+impl SymbolBinding {
+    /// Whether the program would fail to relocate because of it
+    pub fn is_unresolved(&self) -> bool {
+        self.to.is_none() && self.sym.sym.bind != delf::SymBind::Weak
     }
 }
 
-impl Process<Protected> {
-    fn build_stack(opts: &StartOptions) -> Vec<u64> {
-        let mut stack = Vec::new();
-        let null = 0_u64;
-
-        macro_rules! push {
-            ($x:expr) => {
-                stack.push($x as u64)
-            };
-        }
-
-        let argc = opts.args.len();
-        push!(argc);
-
-        for argv in &opts.args {
-            push!(argv.as_ptr());
-        }
-        push!(null);
+/// How `dlopen` was asked to load an object
+#[derive(Clone, Copy, Debug)]
+pub struct OpenOptions {
+    /// `RTLD_NOW` or `RTLD_LAZY`: lazy binding only applies if the program
+    /// was started with it, the way `LD_BIND_NOW` overrides `RTLD_LAZY`
+    pub binding: Binding,
+    /// `RTLD_GLOBAL`
+    pub global: bool,
+    /// `RTLD_NOLOAD`: only hand out objects already loaded
+    pub no_load: bool,
+    /// `RTLD_NODELETE`
+    pub no_delete: bool,
+}
 
-        for envp in &opts.env {
-            push!(envp.as_ptr());
-        }
-        push!(null);
+/// Where `dlsym` looks
+#[derive(Clone, Copy, Debug)]
+pub enum SymbolScope {
+    /// `RTLD_DEFAULT`
+    Default,
+    /// `RTLD_NEXT`, from code at that address
+    Next(delf::Addr),
+    /// A `dlopen` handle
+    Object(usize),
+}
 
-        for auxv in &opts.auxv {
-            push!(auxv.typ);
-            push!(auxv.value);
-        }
-        push!(AuxType::Null);
-        push!(null);
+/// What `find_symbol` found
+#[derive(Clone, Copy, Debug)]
+pub enum SymbolAddress {
+    Addr(delf::Addr),
+    /// Thread-local: the address depends on the thread asking
+    Tls {
+        module: u64,
+        offset: delf::Addr,
+    },
+}
 
-        if stack.len() % 2 == 1 {
-            push!(null);
+impl SymbolAddress {
+    /// Must be called on the program's thread pointer
+    pub unsafe fn resolve(self) -> u64 {
+        match self {
+            Self::Addr(addr) => addr.0,
+            Self::Tls { module, offset } => unsafe { tls_block(module) + offset.0 },
         }
-
-        stack
     }
+}
 
-    pub fn start(self, opts: &StartOptions) -> ! {
-        let exec = &self.state.loader.objects[opts.exec_index];
-        let entry_point = exec.file.entry_point + exec.base;
-        let stack = Self::build_stack(opts);
-        let initializers = self.initializers();
-
-        let argc = opts.args.len() as i32;
-        let mut argv: Vec<_> = opts.args.iter().map(|x| x.as_ptr()).collect();
-        argv.push(std::ptr::null());
-        let mut envp: Vec<_> = opts.env.iter().map(|x| x.as_ptr()).collect();
-        envp.push(std::ptr::null());
+/// What initializers are called with: the program's arguments and environment
+#[derive(Clone, Copy, Debug)]
+pub struct InitArgs {
+    argc: i32,
+    argv: *const *const i8,
+    envp: *const *const i8,
+}
 
-        unsafe {
-            set_fs(self.state.tls.tcb_addr.0);
-            #[allow(clippy::needless_range_loop)]
-            for i in 0..initializers.len() {
-                call_init(initializers[i].1, argc, argv.as_ptr(), envp.as_ptr());
-            }
-            jmp(entry_point.as_ptr(), stack.as_ptr(), stack.len())
+impl Default for InitArgs {
+    fn default() -> Self {
+        Self {
+            argc: 0,
+            argv: std::ptr::null(),
+            envp: std::ptr::null(),
         }
     }
 }
 
-impl<S: ProcessState> Process<S> {
-    fn lookup_symbol(&self, wanted: &ObjectSym, ignore_self: bool) -> ResolvedSym<'_> {
-        let loader = self.state.loader();
-        let candidates = loader
-            .objects
-            .iter()
-            .filter(|&obj| !(ignore_self && std::ptr::eq(wanted.obj, obj)));
+impl InitArgs {
+    pub unsafe fn call(&self, init: delf::Addr) {
+        unsafe { call_init(init, self.argc, self.argv, self.envp) }
+    }
+}
 
-        for obj in candidates {
-            if let Some(sym) = obj
-                .sym_map
-                .get_vec(&wanted.sym.name)
-                .into_iter()
-                .flatten()
-                .find(|sym| !sym.sym.shndx.is_undef())
-            {
-                return ResolvedSym::Defined(ObjectSym { obj, sym });
-            }
+/// The `rtld_fini` libc registers with `atexit`, so `exit` calls it after the
+/// handlers registered later, and before flushing stdio: runs the finalizers
+/// of every object still loaded, in reverse order of initialization
+unsafe extern "C" fn run_finalizers() {
+    // finalizers run as program code, and may call `dlclose`: look the
+    // process up again before each one
+    while let Some(index) = unsafe { running_process() }.and_then(|p| p.loader.initialized.pop()) {
+        for i in 0.. {
+            let Some(&fini) =
+                running_loader().and_then(|loader| loader.objects[index].finalizers.get(i))
+            else {
+                break;
+            };
+            unsafe { call_fini(fini) };
         }
-        ResolvedSym::Undefined
     }
+}
 
-    fn initializers(&self) -> Vec<(&Object, delf::Addr)> {
-        self.state
-            .loader()
-            .objects
-            .iter()
-            .rev()
-            .flat_map(|obj| obj.initializers.iter().map(move |&init| (obj, init)))
-            .collect()
+/// Called by `lazy_trampoline` on the first call through a PLT entry: binds its GOT
+/// slot and returns the address to jump to
+unsafe extern "C" fn bind_lazy(obj_index: usize, rel_index: usize) -> delf::Addr {
+    let loader = running_loader().expect("lazy binding before start");
+    let obj = &loader.objects[obj_index];
+    let objrel = ObjectRel {
+        obj,
+        rel: &obj.plt_rels[rel_index],
+    };
+    let wanted = ObjectSym {
+        obj,
+        sym: &obj.syms[objrel.rel.sym as usize],
+    };
+    let found = loader.lookup_symbol(&wanted, false);
+    if let ResolvedSym::Undefined = found
+        && wanted.sym.sym.bind != delf::SymBind::Weak
+    {
+        eprintln!(
+            "{:?}: {}",
+            obj.path,
+            RelocationError::UndefinedSymbol(wanted.sym.clone())
+        );
+        std::process::abort();
     }
+    let value = found.value() + objrel.rel.addend;
+    unsafe { objrel.addr().set(value) };
+    value
+}
+
+/// What GOT[2] points to: PLT0 jumps here with the object index (GOT[1]) and the
+/// relocation index on the stack, on top of the caller's return address.
+#[unsafe(naked)]
+unsafe extern "C" fn lazy_trampoline() {
+    core::arch::naked_asm!(
+        // the callee's arguments must survive the resolver
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "sub rsp, 128",
+        "movdqu [rsp+0x00], xmm0",
+        "movdqu [rsp+0x10], xmm1",
+        "movdqu [rsp+0x20], xmm2",
+        "movdqu [rsp+0x30], xmm3",
+        "movdqu [rsp+0x40], xmm4",
+        "movdqu [rsp+0x50], xmm5",
+        "movdqu [rsp+0x60], xmm6",
+        "movdqu [rsp+0x70], xmm7",
+        // 7 registers and 8 xmm registers above us: the stack is 16-byte aligned again
+        "mov rdi, [rsp+184]",
+        "mov rsi, [rsp+192]",
+        "call {bind}",
+        "mov r11, rax",
+        "movdqu xmm0, [rsp+0x00]",
+        "movdqu xmm1, [rsp+0x10]",
+        "movdqu xmm2, [rsp+0x20]",
+        "movdqu xmm3, [rsp+0x30]",
+        "movdqu xmm4, [rsp+0x40]",
+        "movdqu xmm5, [rsp+0x50]",
+        "movdqu xmm6, [rsp+0x60]",
+        "movdqu xmm7, [rsp+0x70]",
+        "add rsp, 128",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        // drop the object and relocation indices
+        "add rsp, 16",
+        "jmp r11",
+        bind = sym bind_lazy,
+    )
 }
 
 #[inline(never)]
-unsafe fn jmp(entry_point: *const u8, stack_contents: *const u64, qword_count: usize) -> ! {
+unsafe fn jmp(
+    entry_point: *const u8,
+    stack_contents: *const u64,
+    qword_count: usize,
+    rtld_fini: unsafe extern "C" fn(),
+) -> ! {
     unsafe {
         core::arch::asm!(
             // allocate (qword_count * 8) bytes
@@ -837,6 +1594,8 @@ unsafe fn jmp(entry_point: *const u8, stack_contents: *const u64, qword_count: u
             "jmp {entry_point}",
 
             entry_point = in(reg) entry_point,
+            // for libc to register with `atexit`
+            in("rdx") rtld_fini,
             stack_contents = in(reg) stack_contents,
             qword_count = in(reg) qword_count,
             tmp = out(reg) _,
@@ -868,6 +1627,12 @@ unsafe fn call_init(addr: delf::Addr, argc: i32, argv: *const *const i8, envp: *
     init(argc, argv, envp);
 }
 
+#[inline(never)]
+pub unsafe fn call_fini(addr: delf::Addr) {
+    let fini: extern "C" fn() = unsafe { std::mem::transmute(addr.0) };
+    fini();
+}
+
 #[derive(Debug)]
 pub struct Object {
     #[allow(unused)]
@@ -880,18 +1645,89 @@ pub struct Object {
     #[allow(unused)]
     pub mem_range: Range<delf::Addr>,
     #[debug(skip)]
-    syms: Vec<NamedSym>,
+    pub syms: Vec<NamedSym>,
     #[debug(skip)]
     sym_map: MultiMap<Name, NamedSym>,
     #[debug(skip)]
     pub rels: Vec<delf::Rela>,
+    /// `DT_JMPREL`, in table order
+    #[debug(skip)]
+    pub plt_rels: Vec<delf::Rela>,
     #[debug(skip)]
     pub relr: Vec<delf::Addr>,
+    /// `DT_PREINIT_ARRAY`, which only the program may have
+    #[debug(skip)]
+    pub preinitializers: Vec<delf::Addr>,
     #[debug(skip)]
     pub initializers: Vec<delf::Addr>,
+    /// `DT_FINI_ARRAY` backwards, then `DT_FINI`: the order they run in
+    #[debug(skip)]
+    pub finalizers: Vec<delf::Addr>,
+    /// Index of this object's TLS block in the DTV, starting at 1
+    pub tls_module: Option<u64>,
+    /// Whether its references look at its own definitions first
+    pub symbolic: bool,
+    /// Indices of the objects named by its `DT_NEEDED` entries
+    pub needed: Vec<usize>,
+    /// The object whose `DT_NEEDED` entry brought it in
+    pub loaded_by: Option<usize>,
+    /// `DT_RPATH`, expanded
+    pub rpath: Vec<PathBuf>,
+    /// `DT_RUNPATH`, expanded
+    pub runpath: Vec<PathBuf>,
+    /// Whether every lookup sees its definitions: true for the program and its
+    /// dependencies, and for objects opened with `RTLD_GLOBAL`
+    pub global: bool,
+    /// Searched after the global scope by objects opened with `RTLD_LOCAL`: the
+    /// object passed to `dlopen` and its dependencies
+    pub local_scope: Vec<usize>,
+    /// `dlopen` handles and loaded dependents holding on to the object, None
+    /// for objects that stay until exit
+    pub refcount: Option<usize>,
 }
 
 impl Object {
+    /// False once `dlclose` released the last reference to it
+    pub fn is_loaded(&self) -> bool {
+        self.refcount != Some(0)
+    }
+
+    /// Applies the protection each segment asks for
+    fn protect(&self) -> Result<(), region::Error> {
+        for seg in &self.segments {
+            let mut protection = region::Protection::NONE;
+            for flag in seg.flags.iter() {
+                protection |= match flag {
+                    delf::SegmentFlag::Execute => region::Protection::EXECUTE,
+                    delf::SegmentFlag::Write => region::Protection::WRITE,
+                    delf::SegmentFlag::Read => region::Protection::READ,
+                }
+            }
+            unsafe {
+                region::protect(seg.map.data(), seg.map.len(), protection)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// This object's definition of `name`, if any
+    pub fn defined(&self, name: &Name) -> Option<&NamedSym> {
+        self.sym_map
+            .get_vec(name)?
+            .iter()
+            .find(|sym| !sym.sym.shndx.is_undef())
+    }
+
+    /// Address of the program headers in memory
+    pub fn program_headers(&self) -> delf::Addr {
+        let vaddr = match self.file.segment_of_type(delf::SegmentType::PHdr) {
+            Some(ph) => ph.vaddr,
+            // otherwise they are mapped along with the start of the file
+            None => self.mem_range.start + self.file.ph_offset,
+        };
+        self.base + vaddr
+    }
+
     fn symzero(&self) -> ResolvedSym<'_> {
         ResolvedSym::Defined(ObjectSym {
             obj: self,
@@ -933,6 +1769,15 @@ impl ResolvedSym<'_> {
         }
     }
 
+    /// The raw symbol value, an offset in the defining module's TLS block
+    /// for `STT_TLS` symbols
+    fn tls_offset(&self) -> delf::Addr {
+        match self {
+            ResolvedSym::Defined(s) => s.sym.sym.value,
+            ResolvedSym::Undefined => delf::Addr(0x0),
+        }
+    }
+
     fn size(&self) -> usize {
         match self {
             ResolvedSym::Defined(s) => s.sym.sym.size as usize,
@@ -990,32 +1835,88 @@ pub enum LoadError {
     NoLoadSegments,
     #[error("ELF object could not be mapped in memory: {0}")]
     MapError(#[from] mmap::MapError),
+    #[error("{0:?} must be mapped at {1:?}, which overlaps {2}")]
+    AddressInUse(PathBuf, Range<delf::Addr>, String),
+    #[error("{0:?} could not be mapped at {1:?}: {2}")]
+    FixedMapping(PathBuf, Range<delf::Addr>, std::io::Error),
     #[error("Could not read symbols from ELF object: {0}")]
     ReadSymsError(#[from] delf::ReadSymsError),
     #[error("Could not read relocations from ELF object: {0}")]
     ReadRelaError(#[from] delf::ReadRelaError),
+    #[error("Could not read symbol versions from ELF object: {0}")]
+    ReadVersionError(#[from] delf::ReadVersionError),
 }
 
 pub enum GetResult {
-    #[allow(unused)]
     Cached(usize),
     Fresh(usize),
 }
 
 impl GetResult {
-    fn fresh(self) -> Option<usize> {
-        if let Self::Fresh(index) = self {
-            Some(index)
-        } else {
-            None
+    fn index(&self) -> usize {
+        match *self {
+            Self::Cached(index) | Self::Fresh(index) => index,
         }
     }
 }
 
+/// Reserves `range` for an object that can't be moved, without clobbering
+/// whatever is mapped there already
+fn reserve_fixed(path: &Path, range: Range<delf::Addr>) -> Result<(), LoadError> {
+    let start = delf::Addr(range.start.0 & !0xFFF);
+    let len = (range.end - start).0 as usize;
+    let addr = unsafe {
+        libc::mmap(
+            start.0 as *mut libc::c_void,
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+            -1,
+            0,
+        )
+    };
+    if addr == start.0 as *mut libc::c_void {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    if addr != libc::MAP_FAILED {
+        // kernels older than 4.17 take the address as a mere hint
+        unsafe { libc::munmap(addr, len) };
+    }
+
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    let occupant = crate::procfs::mappings(&maps)
+        .ok()
+        .and_then(|(_, mappings)| {
+            mappings
+                .into_iter()
+                .find(|m| m.addr_range.start < range.end && start < m.addr_range.end)
+                .map(|m| format!("{:?} {:?} from {:?}", m.addr_range, m.perms, m.source))
+        });
+    Err(match occupant {
+        Some(occupant) => LoadError::AddressInUse(path.to_path_buf(), range, occupant),
+        None => LoadError::FixedMapping(path.to_path_buf(), range, err),
+    })
+}
+
 fn convex_hull(a: Range<delf::Addr>, b: Range<delf::Addr>) -> Range<delf::Addr> {
     (min(a.start, b.start))..max(a.end, b.end)
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum DlError {
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Relocation(#[from] RelocationError),
+    #[error("could not protect segments: {0}")]
+    Protect(#[from] region::Error),
+    #[error("{0}: not loaded")]
+    NotLoaded(String),
+    #[error("invalid handle")]
+    InvalidHandle,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum RelocationError {
     #[error("{0:?}: unimplemented relocation: {1:?}")]
@@ -1025,12 +1926,43 @@ pub enum RelocationError {
     UnknownSymbolNumber(u32),
     #[error("undifined symbol: {0:?}")]
     UndefinedSymbol(NamedSym),
+    #[error("{0:?}: initial-exec TLS reference to {1:?}, which has no static TLS block")]
+    NoStaticTls(PathBuf, PathBuf),
+    #[error("{0:?}: thread-local symbol referenced, but no TLS block was allocated for it")]
+    NoTlsModule(PathBuf),
 }
 
 #[derive(Debug, Clone)]
 pub struct NamedSym {
-    sym: delf::Sym,
-    name: Name,
+    pub sym: delf::Sym,
+    pub name: Name,
+    /// GNU symbol version: the one defined for definitions, the one
+    /// required for references
+    version: Option<Name>,
+    /// Non-default definition (`sym@VER` rather than `sym@@VER`), only
+    /// reachable by references asking for that exact version
+    hidden: bool,
+}
+
+impl std::fmt::Display for NamedSym {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{:?}@{:?}", self.name, version),
+            None => write!(f, "{:?}", self.name),
+        }
+    }
+}
+
+impl NamedSym {
+    /// Whether this definition can be bound to a reference, per version rules
+    fn satisfies(&self, wanted: &NamedSym) -> bool {
+        match (&wanted.version, &self.version) {
+            (Some(wanted), Some(defined)) => wanted == defined,
+            // versioned references fall back on unversioned definitions
+            (Some(_), None) => true,
+            (None, _) => !self.hidden,
+        }
+    }
 }
 
 pub struct StartOptions {
@@ -1041,8 +1973,8 @@ pub struct StartOptions {
 }
 
 pub struct Auxv {
-    typ: AuxType,
-    value: u64,
+    pub typ: AuxType,
+    pub value: u64,
 }
 
 impl Auxv {
@@ -1097,6 +2029,24 @@ impl Auxv {
             .filter_map(Self::get)
             .collect()
     }
+
+    /// elk's auxiliary vector, describing `program` instead of elk, as if the
+    /// kernel had loaded it without an interpreter
+    pub fn for_program(program: &Object) -> Vec<Self> {
+        Self::get_known()
+            .into_iter()
+            .map(|Self { typ, value }| {
+                let value = match typ {
+                    AuxType::Entry => (program.base + program.file.entry_point).0,
+                    AuxType::PHdr => program.program_headers().0,
+                    AuxType::PhNum => program.file.program_headers.len() as u64,
+                    AuxType::Base => 0,
+                    _ => value,
+                };
+                Self { typ, value }
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1164,50 +2114,126 @@ pub struct Tls {
     offsets: HashMap<delf::Addr, delf::Addr>,
     #[allow(unused)]
     block: Vec<u8>,
+    /// Blocks of the modules `dlopen` brought in, which the static area has no room for
+    #[allow(unused)]
+    dynamic_blocks: Vec<Vec<u8>>,
+    dtv: Vec<DtvEntry>,
     tcb_addr: delf::Addr,
+    /// Size of the blocks and TCB around the thread pointer
+    static_size: u64,
+    static_align: u64,
 }
 
-#[repr(C)]
-struct FakeCtype {
-    b_table: [u16; 256],
-    b_ptr: *const u16,
+impl Tls {
+    pub fn static_size(&self) -> u64 {
+        self.static_size
+    }
 
-    tolower_table: [i32; 256],
-    tolower_ptr: *const i32,
+    pub fn static_align(&self) -> u64 {
+        self.static_align
+    }
 
-    toupper_table: [i32; 256],
-    toupper_ptr: *const i32,
-}
+    /// Gives `obj` a module ID and a block of its own, and makes room for it in
+    /// the DTV. Only the main thread's DTV knows about it
+    fn allocate_dynamic(&mut self, obj: &mut Object) {
+        let Some(ph) = obj
+            .file
+            .segment_of_type(delf::SegmentType::TLS)
+            .filter(|ph| ph.memsz.0 > 0)
+        else {
+            return;
+        };
+        let module = self.dtv.len() as u64 - 1;
+        obj.tls_module = Some(module);
+
+        let align = ph.align.0.max(1);
+        let block = vec![0_u8; (ph.memsz.0 + align) as usize];
+        let val = (block.as_ptr() as u64).next_multiple_of(align);
+        self.dynamic_blocks.push(block);
+
+        self.dtv.push(DtvEntry { val, to_free: 0 });
+        self.dtv[0].val = module;
+        self.dtv[1].val += 1;
+        // the DTV may have moved
+        unsafe {
+            (self.tcb_addr + delf::Addr(8)).set(self.dtv[1..].as_ptr() as u64);
+        }
+    }
 
-impl FakeCtype {
-    fn new() -> Box<Self> {
-        let mut c = Box::new(Self {
-            b_table: [0; 256],
-            b_ptr: std::ptr::null(),
+    /// Takes back the module ID `allocate_dynamic` just gave `obj`, and its
+    /// block, when it couldn't be linked after all. Objects must be released
+    /// in the reverse order they were allocated in
+    fn release_dynamic(&mut self, obj: &mut Object) {
+        let Some(module) = obj.tls_module.take() else {
+            return;
+        };
+        assert_eq!(
+            module,
+            self.dtv.len() as u64 - 2,
+            "only the last module can be released"
+        );
+        self.dtv.pop();
+        self.dynamic_blocks.pop();
+        self.dtv[0].val = module - 1;
+        self.dtv[1].val += 1;
+    }
 
-            tolower_table: [0; 256],
-            tolower_ptr: std::ptr::null(),
+    /// Copies `obj`'s TLS initialization image into its block, once relocated
+    fn initialize(&self, obj: &Object) {
+        let Some(module) = obj.tls_module else {
+            return;
+        };
+        let Some(ph) = obj.file.segment_of_type(delf::SegmentType::TLS) else {
+            return;
+        };
+        let block = delf::Addr(self.dtv[module as usize + 1].val);
+        unsafe {
+            block.write((ph.vaddr + obj.base).as_slice(ph.filesz.into()));
+        }
+    }
+}
 
-            toupper_table: [0; 256],
-            toupper_ptr: std::ptr::null(),
-        });
+/// glibc's `dtv_t`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct DtvEntry {
+    val: u64,
+    to_free: u64,
+}
 
-        // __ctype_b: всё printable ASCII
-        const _ISPRINT: u16 = 0x40;
-        for i in 0x20u8..=0x7eu8 {
-            c.b_table[i as usize] = _ISPRINT;
-        }
+/// glibc's `tls_index`, what general-dynamic code passes to `__tls_get_addr`
+#[repr(C)]
+struct TlsIndex {
+    module: u64,
+    offset: u64,
+}
 
-        // tolower / toupper — identity
-        for i in 0..256 {
-            c.tolower_table[i] = i as i32;
-            c.toupper_table[i] = i as i32;
-        }
+/// Stands in for ld.so's `__tls_get_addr`. Blocks are never allocated on first
+/// use: the program's are set up before it starts and `dlopen` allocates the
+/// others right away (in the main thread's DTV only), so this is a lookup in
+/// the current thread's DTV
+unsafe extern "C" fn tls_get_addr(index: &TlsIndex) -> u64 {
+    unsafe { tls_block(index.module) + index.offset }
+}
 
-        c.b_ptr = c.b_table.as_ptr();
-        c.tolower_ptr = c.tolower_table.as_ptr();
-        c.toupper_ptr = c.toupper_table.as_ptr();
+/// Address of a module's TLS block for the current thread
+pub unsafe fn tls_block(module: u64) -> u64 {
+    unsafe {
+        let tcb = thread_pointer() as *const u64;
+        let dtv = *tcb.add(1) as *const DtvEntry;
+        (*dtv.add(module as usize)).val
+    }
+}
 
-        c
+/// The current thread's TCB, which both glibc and elk's TLS setup point to itself
+pub fn thread_pointer() -> u64 {
+    let tcb: u64;
+    unsafe {
+        core::arch::asm!(
+            "mov {}, fs:0",
+            out(reg) tcb,
+            options(nostack, readonly, preserves_flags)
+        );
     }
+    tcb
 }
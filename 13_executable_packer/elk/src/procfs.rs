@@ -2,7 +2,7 @@ use core::fmt;
 
 use nom::{
     AsChar, IResult, Parser, branch,
-    bytes::complete::{tag, take_while, take_while1},
+    bytes::complete::{tag, take_till, take_while, take_while1},
     character::complete::space0,
     combinator,
     error::ParseError,
@@ -182,3 +182,65 @@ impl Mapping<'_> {
 pub fn mappings(i: &str) -> IResult<&str, Vec<Mapping<'_>>> {
     combinator::all_consuming(multi::many0(terminated(spaced(Mapping::parse), tag("\n")))).parse(i)
 }
+
+/// A mapping from `/proc/<pid>/smaps`, with how much of it is in memory
+#[derive(Debug)]
+pub struct Usage<'a> {
+    pub mapping: Mapping<'a>,
+    /// The `kB` counters that follow the mapping (`Rss`, `Pss`,
+    /// `Private_Dirty`...), in bytes
+    pub fields: Vec<(&'a str, u64)>,
+}
+
+impl Usage<'_> {
+    /// Returns the counter called `name`, or zero if the kernel doesn't
+    /// report it
+    pub fn field(&self, name: &str) -> u64 {
+        self.fields
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map_or(0, |(_, v)| *v)
+    }
+
+    /// `Private_Dirty` and `Shared_Dirty` together
+    pub fn dirty(&self) -> u64 {
+        self.field("Private_Dirty") + self.field("Shared_Dirty")
+    }
+
+    fn parse(i: &str) -> IResult<&str, Usage<'_>> {
+        fn name(i: &str) -> IResult<&str, &str> {
+            terminated(
+                take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+                tag(":"),
+            )
+            .parse(i)
+        }
+
+        // `Rss:  12 kB`, or something else like `VmFlags: rd mr`, which is
+        // skipped
+        fn field(i: &str) -> IResult<&str, Option<(&str, u64)>> {
+            terminated(
+                branch::alt((
+                    combinator::map((name, spaced(dec_number), tag("kB")), |(name, kib, _)| {
+                        Some((name, kib * 1024))
+                    }),
+                    combinator::value(None, (name, take_till(|c| c == '\n'))),
+                )),
+                tag("\n"),
+            )
+            .parse(i)
+        }
+
+        let (i, (mapping, fields)) = (
+            terminated(spaced(Mapping::parse), tag("\n")),
+            multi::many0(field),
+        )
+            .parse(i)?;
+        let fields = fields.into_iter().flatten().collect();
+        Ok((i, Usage { mapping, fields }))
+    }
+}
+
+pub fn usages(i: &str) -> IResult<&str, Vec<Usage<'_>>> {
+    combinator::all_consuming(multi::many0(Usage::parse)).parse(i)
+}
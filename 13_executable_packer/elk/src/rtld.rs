@@ -0,0 +1,695 @@
+//! The parts of ld.so's interface glibc relies on.
+//!
+//! ld-linux is loaded as a regular object and its startup code never runs, so
+//! the structures it would fill in (`_rtld_global`, `_rtld_global_ro`) are left
+//! as they are in the file, and the functions libc forwards to it walk link
+//! maps that don't exist. elk fills in those structures from its own `Loader`,
+//! and answers `dl_iterate_phdr`, `dladdr` and the `dlopen` family itself.
+//!
+//! Both structures are private to glibc: the offsets below are those of glibc
+//! 2.36 on x86-64, and are only used when libc reports that version and the
+//! symbol sizes match that layout. Other versions get the stubs elk used before it knew any layout: enough
+//! for simple programs, without locales or `__libc_early_init`.
+
+use std::{
+    ffi::{CStr, CString, c_char, c_int, c_void},
+    os::unix::ffi::OsStrExt,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::{
+    gdbstub,
+    name::Name,
+    process::{
+        AuxType, Auxv, Binding, DlError, NamedSym, Object, OpenOptions, ProcessState, SymbolScope,
+        Tls, call_fini, running_loader, running_process, with_host_tls,
+    },
+};
+
+/// The glibc version whose layouts elk knows, as `gnu_get_libc_version` says
+const GLIBC_VERSION: &str = "2.36";
+/// Size of `_rtld_global_ro` in glibc 2.36
+const GLOBAL_RO_SIZE: u64 = 0x380;
+/// Size of `_rtld_global` in glibc 2.36
+const GLOBAL_SIZE: u64 = 0x10f0;
+
+/// Fields of `_rtld_global_ro` that are computed at startup rather than
+/// initialized in the file
+mod global_ro {
+    pub const PLATFORM: u64 = 0x8;
+    pub const PLATFORM_LEN: u64 = 0x10;
+    pub const PAGE_SIZE: u64 = 0x18;
+    pub const MIN_SIG_STACK_SIZE: u64 = 0x20;
+    pub const CLK_TCK: u64 = 0x40;
+    pub const HWCAP: u64 = 0x60;
+    pub const AUXV: u64 = 0x68;
+    pub const TLS_STATIC_SIZE: u64 = 0x2a0;
+    pub const TLS_STATIC_ALIGN: u64 = 0x2a8;
+    pub const HWCAP2: u64 = 0x308;
+    /// libc checks this is set to tell whether ld.so is active (`rtld_active`)
+    pub const INIT_ALL_DIRS: u64 = 0x368;
+}
+
+/// Fields of `_rtld_global`: the first element of `_dl_ns`, the base namespace
+mod global {
+    pub const NS_LOADED: u64 = 0x0;
+    pub const NS_NLOADED: u64 = 0x8;
+}
+
+/// The start of glibc's `struct link_map`: the public part from `<link.h>`,
+/// then the private fields libc looks at
+#[repr(C)]
+struct LinkMap {
+    l_addr: u64,
+    l_name: *const c_char,
+    l_ld: u64,
+    l_next: *mut LinkMap,
+    l_prev: *mut LinkMap,
+    l_real: *mut LinkMap,
+    l_ns: i64,
+    l_libname: u64,
+    /// `l_info` and everything after it. Left zeroed, so that
+    /// `__libc_start_main` finds no initializers to run: elk runs them
+    rest: [u64; 0x100],
+}
+
+/// State backing the structures libc reads, which must outlive the program
+#[derive(Default)]
+pub struct Rtld {
+    /// One per `Loader` object, in the same order. Boxed: libc holds on to
+    /// their addresses
+    #[allow(clippy::vec_box)]
+    link_maps: Vec<Box<LinkMap>>,
+    names: Vec<CString>,
+    auxv: Vec<u64>,
+    /// Pointed to by `_dl_init_all_dirs`, never searched
+    search_dirs: Box<[u64; 2]>,
+    /// `_rtld_global`, whose list of loaded objects follows `dlopen` and `dlclose`
+    global: Option<delf::Addr>,
+    /// Whether `_rtld_global` and `_rtld_global_ro` have the layout above
+    known_layout: bool,
+    /// What the stubbed `__ctype_*_loc` return, for unknown layouts
+    fake_ctype: Option<Box<FakeCtype>>,
+}
+
+impl Rtld {
+    /// Functions of ld.so and libc that elk provides instead
+    pub fn redirects() -> [(&'static str, u64); 8] {
+        [
+            ("dl_iterate_phdr", dl_iterate_phdr as *const () as u64),
+            ("dladdr", dladdr as *const () as u64),
+            ("dladdr1", dladdr1 as *const () as u64),
+            ("dlopen", dlopen as *const () as u64),
+            ("dlsym", dlsym as *const () as u64),
+            ("dlvsym", dlvsym as *const () as u64),
+            ("dlclose", dlclose as *const () as u64),
+            ("dlerror", dlerror as *const () as u64),
+        ]
+    }
+
+    /// Builds link maps for `objects` and fills in `_rtld_global` and
+    /// `_rtld_global_ro`, or stubs out what relies on them if their layout
+    /// is unknown
+    pub fn install(&mut self, objects: &[Object], tls: &Tls) {
+        // sizes alone can stay the same across versions while fields move
+        let known_version = match glibc_version(objects) {
+            Some(version) if version == GLIBC_VERSION => true,
+            Some(version) => {
+                println!(
+                    "Warning: libc is glibc {version}, expected {GLIBC_VERSION}: unknown glibc version, using stubs"
+                );
+                false
+            }
+            None => false,
+        };
+        let global = global_address(objects, "_rtld_global", GLOBAL_SIZE);
+        let global_ro = global_address(objects, "_rtld_global_ro", GLOBAL_RO_SIZE);
+        let (true, Some(global), Some(global_ro)) = (known_version, global, global_ro) else {
+            self.add(objects);
+            self.bootstrap(objects);
+            return;
+        };
+        self.known_layout = true;
+
+        println!("Populating _rtld_global at {global:?}");
+        self.global = Some(global);
+        self.add(objects);
+
+        self.auxv = Auxv::get_known()
+            .iter()
+            .flat_map(|auxv| [auxv.typ as u64, auxv.value])
+            .chain([AuxType::Null as u64, 0])
+            .collect();
+
+        println!("Populating _rtld_global_ro at {global_ro:?}");
+        let aux = |typ: AuxType| Auxv::get(typ).map(|auxv| auxv.value);
+        let set = |offset: u64, value: u64| unsafe { (global_ro + delf::Addr(offset)).set(value) };
+
+        if let Some(platform) = aux(AuxType::Platform) {
+            let len = unsafe { std::ffi::CStr::from_ptr(platform as *const c_char) }.count_bytes();
+            set(global_ro::PLATFORM, platform);
+            set(global_ro::PLATFORM_LEN, len as u64);
+        }
+        set(global_ro::PAGE_SIZE, aux(AuxType::PageSz).unwrap_or(4096));
+        if let Some(size) = aux(AuxType::MinSigStkSz) {
+            set(global_ro::MIN_SIG_STACK_SIZE, size);
+        }
+        if let Some(clk_tck) = aux(AuxType::ClkTck) {
+            unsafe { (global_ro + delf::Addr(global_ro::CLK_TCK)).set(clk_tck as i32) };
+        }
+        set(global_ro::HWCAP, aux(AuxType::HwCap).unwrap_or_default());
+        set(global_ro::HWCAP2, aux(AuxType::HwCap2).unwrap_or_default());
+        set(global_ro::AUXV, self.auxv.as_ptr() as u64);
+        set(global_ro::TLS_STATIC_SIZE, tls.static_size());
+        set(global_ro::TLS_STATIC_ALIGN, tls.static_align());
+        set(global_ro::INIT_ALL_DIRS, self.search_dirs.as_ptr() as u64);
+    }
+
+    /// For glibc versions whose layouts we don't know: brings `_rtld_global`
+    /// into a state where `__libc_start_main` gets through, and stubs out the
+    /// libc functions that would reach deeper into ld.so's state
+    fn bootstrap(&mut self, objects: &[Object]) {
+        let name = Name::owned("_rtld_global");
+        if let Some((obj, sym)) = objects
+            .iter()
+            .find_map(|obj| Some((obj, obj.defined(&name)?)))
+            .filter(|(_, sym)| sym.sym.size != 0)
+        {
+            let addr = obj.base + sym.sym.value;
+            println!("Bootstrapping _rtld_global at {addr:?}");
+            unsafe {
+                // libc uses the first word as a base pointer: make it non-null
+                let first: u64 = std::ptr::read_unaligned(addr.as_ptr());
+                if first == 0 {
+                    addr.set(addr.0);
+                }
+                // function pointers `__libc_start_main` calls if they're set
+                (addr + delf::Addr(0xa0)).set::<u64>(0);
+                (addr + delf::Addr(0x108)).set::<u64>(0);
+            }
+        }
+
+        let Some(libc) = objects
+            .iter()
+            .find(|obj| obj.path.to_string_lossy().contains("/libc.so.6"))
+        else {
+            return;
+        };
+        let ctype = self.fake_ctype.insert(FakeCtype::new());
+        let return_zero = [0x48, 0x31, 0xc0, 0xc3].to_vec(); // xor rax, rax; ret
+        let return_ptr = |ptr: *const *const ()| {
+            let mut code = vec![0x48, 0xb8]; // movabs rax, imm64
+            code.extend((ptr as u64).to_le_bytes());
+            code.push(0xc3); // ret
+            code
+        };
+        let stubs = [
+            // walks the link maps in `_rtld_global`
+            ("_dl_addr", return_zero.clone()),
+            // loading locales hits TLS state ld.so would have set up
+            ("setlocale", return_zero),
+            // without `__libc_early_init`, ctype tables are never set up
+            ("__ctype_b_loc", return_ptr(&raw const ctype.b_ptr as _)),
+            (
+                "__ctype_tolower_loc",
+                return_ptr(&raw const ctype.tolower_ptr as _),
+            ),
+            (
+                "__ctype_toupper_loc",
+                return_ptr(&raw const ctype.toupper_ptr as _),
+            ),
+        ];
+        for (name, code) in stubs {
+            let Some(sym) = libc.defined(&Name::owned(name)) else {
+                continue;
+            };
+            let addr = libc.base + sym.sym.value;
+            println!("Stubbing {addr:?} ({name:?})");
+            unsafe { addr.write(&code) };
+        }
+    }
+
+    /// Builds link maps for the objects loaded since the last call, and
+    /// chains them to the others
+    pub fn add(&mut self, objects: &[Object]) {
+        for (index, obj) in objects.iter().enumerate().skip(self.link_maps.len()) {
+            let name = CString::new(obj.path.as_os_str().as_bytes()).unwrap();
+            let mut map = Box::new(LinkMap {
+                l_addr: obj.base.0,
+                // glibc names the main program ""
+                l_name: if index == 0 {
+                    c"".as_ptr()
+                } else {
+                    name.as_ptr()
+                },
+                l_ld: obj
+                    .file
+                    .segment_of_type(delf::SegmentType::Dynamic)
+                    .map_or(0, |ph| (obj.base + ph.vaddr).0),
+                l_next: std::ptr::null_mut(),
+                l_prev: std::ptr::null_mut(),
+                l_real: std::ptr::null_mut(),
+                l_ns: 0,
+                l_libname: 0,
+                rest: [0; 0x100],
+            });
+            map.l_real = &mut *map;
+            self.names.push(name);
+            self.link_maps.push(map);
+        }
+        self.relink(objects);
+    }
+
+    /// Chains the link maps of the objects still loaded, and publishes them
+    /// in `_rtld_global`
+    pub fn relink(&mut self, objects: &[Object]) {
+        let ptrs: Vec<*mut LinkMap> = self
+            .link_maps
+            .iter_mut()
+            .zip(objects)
+            .filter(|(_, obj)| obj.is_loaded())
+            .map(|(map, _)| &mut **map as *mut LinkMap)
+            .collect();
+        for (i, &map) in ptrs.iter().enumerate() {
+            unsafe {
+                (*map).l_prev = i.checked_sub(1).map_or(std::ptr::null_mut(), |i| ptrs[i]);
+                (*map).l_next = ptrs.get(i + 1).copied().unwrap_or(std::ptr::null_mut());
+            }
+        }
+
+        if let Some(addr) = self.global {
+            unsafe {
+                (addr + delf::Addr(global::NS_LOADED)).set(ptrs[0]);
+                (addr + delf::Addr(global::NS_NLOADED)).set(ptrs.len() as u32);
+            }
+        }
+    }
+
+    /// The `dlopen` handle of an object: its link map, like glibc
+    pub fn handle(&self, index: usize) -> *mut c_void {
+        &*self.link_maps[index] as *const LinkMap as *mut c_void
+    }
+
+    /// The object a `dlopen` handle stands for
+    fn index_of(&self, handle: *mut c_void) -> Option<usize> {
+        self.link_maps
+            .iter()
+            .position(|map| std::ptr::eq(&**map, handle as *const LinkMap))
+    }
+
+    /// Finds libc's `__libc_early_init`, which ld.so calls once everything is
+    /// relocated and before any initializer: it sets up ctype tables and
+    /// threading defaults
+    pub fn early_init(&self, objects: &[Object]) -> Option<extern "C" fn(initial: bool)> {
+        // it reads `_rtld_global_ro`
+        if !self.known_layout {
+            return None;
+        }
+        let name = Name::owned("__libc_early_init");
+        let (obj, sym) = objects
+            .iter()
+            .find_map(|obj| Some((obj, obj.defined(&name)?)))?;
+        Some(unsafe {
+            std::mem::transmute::<delf::Addr, extern "C" fn(bool)>(obj.base + sym.sym.value)
+        })
+    }
+}
+
+/// The glibc version among `objects`, from libc's `gnu_get_libc_version`,
+/// which only returns a string constant: it's safe to call before libc is
+/// initialized
+fn glibc_version(objects: &[Object]) -> Option<String> {
+    let name = Name::owned("gnu_get_libc_version");
+    let (obj, sym) = objects
+        .iter()
+        .find_map(|obj| Some((obj, obj.defined(&name)?)))?;
+    let version = unsafe {
+        let get = std::mem::transmute::<delf::Addr, extern "C" fn() -> *const c_char>(
+            obj.base + sym.sym.value,
+        );
+        CStr::from_ptr(get())
+    };
+    Some(version.to_string_lossy().into_owned())
+}
+
+/// Address of a variable defined by one of `objects`, provided it has the
+/// layout we know about
+fn global_address(objects: &[Object], name: &str, size: u64) -> Option<delf::Addr> {
+    let name = Name::owned(name);
+    let (obj, sym) = objects
+        .iter()
+        .find_map(|obj| Some((obj, obj.defined(&name)?)))?;
+    if sym.sym.size != size {
+        println!(
+            "Warning: {name:?} is {} bytes, expected {size}: unknown glibc version, using stubs",
+            sym.sym.size
+        );
+        return None;
+    }
+    Some(obj.base + sym.sym.value)
+}
+
+/// Stand-ins for glibc's ctype tables, for unknown layouts: every ASCII
+/// character is printable, and `tolower`/`toupper` change nothing
+#[repr(C)]
+struct FakeCtype {
+    b_table: [u16; 256],
+    b_ptr: *const u16,
+    tolower_table: [i32; 256],
+    tolower_ptr: *const i32,
+    toupper_table: [i32; 256],
+    toupper_ptr: *const i32,
+}
+
+impl FakeCtype {
+    fn new() -> Box<Self> {
+        const IS_PRINT: u16 = 0x40;
+        let mut ctype = Box::new(Self {
+            b_table: [0; 256],
+            b_ptr: std::ptr::null(),
+            tolower_table: std::array::from_fn(|c| c as i32),
+            tolower_ptr: std::ptr::null(),
+            toupper_table: std::array::from_fn(|c| c as i32),
+            toupper_ptr: std::ptr::null(),
+        });
+        ctype.b_table[0x20..0x7f].fill(IS_PRINT);
+        ctype.b_ptr = ctype.b_table.as_ptr();
+        ctype.tolower_ptr = ctype.tolower_table.as_ptr();
+        ctype.toupper_ptr = ctype.toupper_table.as_ptr();
+        ctype
+    }
+}
+
+/// `struct dl_phdr_info`, from `<link.h>`
+#[repr(C)]
+struct DlPhdrInfo {
+    addr: u64,
+    name: *const c_char,
+    phdr: u64,
+    phnum: u16,
+    adds: u64,
+    subs: u64,
+    tls_modid: usize,
+    tls_data: *mut c_void,
+}
+
+type DlIterateCallback =
+    unsafe extern "C" fn(info: *mut DlPhdrInfo, size: usize, data: *mut c_void) -> c_int;
+
+unsafe extern "C" fn dl_iterate_phdr(callback: DlIterateCallback, data: *mut c_void) -> c_int {
+    let Some(loader) = running_loader() else {
+        return 0;
+    };
+    let subs = loader.objects.iter().filter(|obj| !obj.is_loaded()).count();
+    for (obj, map) in loader.objects.iter().zip(&loader.rtld.link_maps) {
+        if !obj.is_loaded() {
+            continue;
+        }
+        let mut info = DlPhdrInfo {
+            addr: obj.base.0,
+            name: map.l_name,
+            phdr: obj.program_headers().0,
+            phnum: obj.file.program_headers.len() as u16,
+            adds: loader.objects.len() as u64,
+            subs: subs as u64,
+            tls_modid: obj.tls_module.unwrap_or_default() as usize,
+            tls_data: obj
+                .tls_module
+                .map_or(std::ptr::null_mut(), |module| unsafe {
+                    crate::process::tls_block(module) as *mut c_void
+                }),
+        };
+        let res = unsafe { callback(&mut info, std::mem::size_of::<DlPhdrInfo>(), data) };
+        if res != 0 {
+            return res;
+        }
+    }
+    0
+}
+
+/// `Dl_info`, from `<dlfcn.h>`
+#[repr(C)]
+struct DlInfo {
+    fname: *const c_char,
+    fbase: u64,
+    sname: *const c_char,
+    saddr: u64,
+}
+
+const RTLD_DL_SYMENT: c_int = 1;
+const RTLD_DL_LINKMAP: c_int = 2;
+
+unsafe extern "C" fn dladdr(addr: u64, info: *mut DlInfo) -> c_int {
+    unsafe { dladdr1(addr, info, std::ptr::null_mut(), 0) }
+}
+
+unsafe extern "C" fn dladdr1(
+    addr: u64,
+    info: *mut DlInfo,
+    extra_info: *mut *const c_void,
+    flags: c_int,
+) -> c_int {
+    let Some(loader) = running_loader() else {
+        return 0;
+    };
+    let addr = delf::Addr(addr);
+    let Some((index, obj)) = loader.objects.iter().enumerate().find(|(_, obj)| {
+        obj.is_loaded()
+            && (obj.base + obj.mem_range.start..obj.base + obj.mem_range.end).contains(&addr)
+    }) else {
+        return 0;
+    };
+    let map = &loader.rtld.link_maps[index];
+    let name = &loader.rtld.names[index];
+
+    // the symbol containing `addr`, or else the closest one before it
+    let vaddr = addr - obj.base;
+    let sym = obj
+        .syms
+        .iter()
+        .enumerate()
+        .filter(|(_, sym)| is_addressable(sym) && sym.sym.value <= vaddr)
+        .max_by_key(|(_, sym)| {
+            let contains = vaddr < sym.sym.value + delf::Addr(sym.sym.size);
+            (contains, sym.sym.value)
+        });
+
+    unsafe {
+        *info = DlInfo {
+            fname: name.as_ptr(),
+            fbase: (obj.base + obj.mem_range.start).0,
+            // symbol names point into the mapped string table, so they
+            // are followed by a null terminator
+            sname: sym.map_or(std::ptr::null(), |(_, sym)| {
+                sym.name.as_slice().as_ptr() as *const c_char
+            }),
+            saddr: sym.map_or(0, |(_, sym)| (obj.base + sym.sym.value).0),
+        };
+        match flags {
+            RTLD_DL_LINKMAP => *extra_info = &**map as *const LinkMap as *const c_void,
+            RTLD_DL_SYMENT => {
+                *extra_info = match (sym, obj.file.dynamic_entry(delf::DynamicTag::SymTab)) {
+                    (Some((index, _)), Some(symtab)) => {
+                        let entry_size = delf::Sym::size(obj.file.class) as u64;
+                        (obj.base + symtab + delf::Addr(index as u64 * entry_size)).as_ptr()
+                    }
+                    _ => std::ptr::null(),
+                }
+            }
+            _ => {}
+        }
+    }
+    1
+}
+
+fn is_addressable(sym: &NamedSym) -> bool {
+    !sym.sym.shndx.is_undef()
+        && matches!(
+            sym.sym.r#type,
+            delf::SymType::Func | delf::SymType::Object | delf::SymType::IFunc
+        )
+}
+
+const RTLD_LAZY: c_int = 0x1;
+const RTLD_NOLOAD: c_int = 0x4;
+const RTLD_GLOBAL: c_int = 0x100;
+const RTLD_NODELETE: c_int = 0x1000;
+
+const RTLD_DEFAULT: *mut c_void = std::ptr::null_mut();
+const RTLD_NEXT: *mut c_void = -1_isize as *mut c_void;
+
+/// What `dlerror` reports next, from `CString::into_raw`
+static ERROR: AtomicPtr<c_char> = AtomicPtr::new(std::ptr::null_mut());
+/// What it reported last, freed on the next call
+static REPORTED: AtomicPtr<c_char> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Records the error `dlerror` will report. Allocates: host TLS only
+fn set_error(message: impl std::fmt::Display) {
+    let message = CString::new(message.to_string().replace('\0', "")).unwrap();
+    let old = ERROR.swap(message.into_raw(), Ordering::AcqRel);
+    if !old.is_null() {
+        drop(unsafe { CString::from_raw(old) });
+    }
+}
+
+/// `dlopen` searches its caller's `DT_RPATH` and `DT_RUNPATH`, like `dlsym`
+/// it finds the caller on entry
+#[unsafe(naked)]
+unsafe extern "C" fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void {
+    core::arch::naked_asm!(
+        "mov rdx, [rsp]",
+        "jmp {open}",
+        open = sym open_from,
+    )
+}
+
+unsafe extern "C" fn open_from(filename: *const c_char, flags: c_int, caller: u64) -> *mut c_void {
+    let Some(process) = (unsafe { running_process() }) else {
+        return std::ptr::null_mut();
+    };
+    if filename.is_null() {
+        return process.loader().rtld.handle(0);
+    }
+
+    let opts = OpenOptions {
+        binding: if flags & RTLD_LAZY != 0 {
+            Binding::Lazy
+        } else {
+            Binding::Eager
+        },
+        global: flags & RTLD_GLOBAL != 0,
+        no_load: flags & RTLD_NOLOAD != 0,
+        no_delete: flags & RTLD_NODELETE != 0,
+    };
+    let name = unsafe { CStr::from_ptr(filename) };
+    let opened = unsafe {
+        with_host_tls(|| {
+            process
+                .open(&name.to_string_lossy(), delf::Addr(caller), opts)
+                .inspect_err(|e| set_error(format_args!("{}: {e}", name.to_string_lossy())))
+                .ok()
+        })
+    };
+    let Some((index, initializers)) = opened else {
+        return std::ptr::null_mut();
+    };
+    // before the initializers run, so gdb can stop in them
+    gdbstub::stop(gdbstub::Event::Libraries);
+
+    // initializers run as program code, and may well call `dlopen` themselves:
+    // `process` is not to be used past this point
+    let init_args = process.init_args();
+    #[allow(clippy::needless_range_loop)]
+    for i in 0..initializers.len() {
+        unsafe { init_args.call(initializers[i]) };
+    }
+    unsafe { with_host_tls(|| drop(initializers)) };
+    running_loader().map_or(std::ptr::null_mut(), |loader| loader.rtld.handle(index))
+}
+
+/// `dlsym` looks up `RTLD_NEXT` relative to its caller, whose address is
+/// only available on entry
+#[unsafe(naked)]
+unsafe extern "C" fn dlsym(handle: *mut c_void, name: *const c_char) -> *mut c_void {
+    core::arch::naked_asm!(
+        "xor edx, edx",
+        "mov rcx, [rsp]",
+        "jmp {lookup}",
+        lookup = sym lookup_from,
+    )
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn dlvsym(
+    handle: *mut c_void,
+    name: *const c_char,
+    version: *const c_char,
+) -> *mut c_void {
+    core::arch::naked_asm!(
+        "mov rcx, [rsp]",
+        "jmp {lookup}",
+        lookup = sym lookup_from,
+    )
+}
+
+unsafe extern "C" fn lookup_from(
+    handle: *mut c_void,
+    name: *const c_char,
+    version: *const c_char,
+    caller: u64,
+) -> *mut c_void {
+    let Some(loader) = running_loader() else {
+        return std::ptr::null_mut();
+    };
+    let name = unsafe { CStr::from_ptr(name) }.to_bytes();
+    let version = (!version.is_null()).then(|| unsafe { CStr::from_ptr(version) }.to_bytes());
+
+    let found = unsafe {
+        with_host_tls(|| {
+            let scope = match handle {
+                RTLD_DEFAULT => SymbolScope::Default,
+                RTLD_NEXT => SymbolScope::Next(delf::Addr(caller)),
+                handle => match loader.rtld.index_of(handle) {
+                    Some(index) if loader.objects[index].is_loaded() => SymbolScope::Object(index),
+                    _ => {
+                        set_error("invalid handle");
+                        return None;
+                    }
+                },
+            };
+            let found = loader.find_symbol(scope, name, version);
+            if found.is_none() {
+                set_error(format_args!(
+                    "undefined symbol: {}",
+                    String::from_utf8_lossy(name)
+                ));
+            }
+            found
+        })
+    };
+    found.map_or(std::ptr::null_mut(), |found| unsafe {
+        found.resolve() as *mut c_void
+    })
+}
+
+unsafe extern "C" fn dlclose(handle: *mut c_void) -> c_int {
+    let Some(process) = (unsafe { running_process() }) else {
+        return -1;
+    };
+    let closed = unsafe {
+        with_host_tls(|| {
+            let closed = match process.loader().rtld.index_of(handle) {
+                Some(index) => process.close(index),
+                None => Err(DlError::InvalidHandle),
+            };
+            closed.inspect_err(|e| set_error(e)).ok()
+        })
+    };
+    let Some(finalizers) = closed else {
+        return -1;
+    };
+
+    // like initializers, finalizers run as program code
+    #[allow(clippy::needless_range_loop)]
+    for i in 0..finalizers.len() {
+        unsafe { call_fini(finalizers[i]) };
+    }
+    unsafe { with_host_tls(|| drop(finalizers)) };
+    gdbstub::stop(gdbstub::Event::Libraries);
+    0
+}
+
+unsafe extern "C" fn dlerror() -> *const c_char {
+    unsafe {
+        with_host_tls(|| {
+            let old = REPORTED.swap(
+                ERROR.swap(std::ptr::null_mut(), Ordering::AcqRel),
+                Ordering::AcqRel,
+            );
+            if !old.is_null() {
+                drop(CString::from_raw(old));
+            }
+            REPORTED.load(Ordering::Acquire)
+        })
+    }
+}
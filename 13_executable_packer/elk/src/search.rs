@@ -0,0 +1,267 @@
+//! Where ld.so looks for libraries: search path lists and their dynamic
+//! string tokens, `/etc/ld.so.cache`, and the default directories.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use nom::{
+    IResult, Parser,
+    bytes::complete::{tag, take},
+    multi::count,
+    number::complete::{le_u32, le_u64},
+};
+
+use crate::process::{AuxType, Auxv};
+
+/// Searched last, as glibc does on x86-64 (Debian adds the multiarch ones)
+pub const DEFAULT_DIRS: &[&str] = &[
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+];
+
+/// Splits a colon-separated list of directories, as found in `DT_RPATH`,
+/// `DT_RUNPATH` or `LD_LIBRARY_PATH`, and expands `$ORIGIN`, `$LIB` and
+/// `$PLATFORM`. Empty entries stand for the working directory.
+pub fn expand(list: &[u8], origin: &Path) -> Vec<PathBuf> {
+    let list = String::from_utf8_lossy(list);
+    let origin = origin.to_string_lossy();
+    let lib = lib_dir();
+    let platform = platform();
+
+    list.split(':')
+        .map(|dir| {
+            let mut dir = dir.to_string();
+            for (token, value) in [
+                ("ORIGIN", origin.as_ref()),
+                ("LIB", lib),
+                ("PLATFORM", platform.as_str()),
+            ] {
+                dir = dir
+                    .replace(&format!("${{{token}}}"), value)
+                    .replace(&format!("${token}"), value);
+            }
+            if dir.is_empty() {
+                ".".into()
+            } else {
+                dir.into()
+            }
+        })
+        .collect()
+}
+
+/// What `$LIB` expands to: the multiarch directory on systems that have it
+fn lib_dir() -> &'static str {
+    if Path::new("/lib/x86_64-linux-gnu").is_dir() {
+        "lib/x86_64-linux-gnu"
+    } else {
+        "lib64"
+    }
+}
+
+/// What `$PLATFORM` expands to. glibc may pick a name from CPU features
+/// (`haswell`) instead of `AT_PLATFORM`: elk doesn't.
+fn platform() -> String {
+    Auxv::get(AuxType::Platform)
+        .map(|auxv| unsafe { CStr::from_ptr(auxv.value as *const std::ffi::c_char) })
+        .map(|platform| platform.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "x86_64".into())
+}
+
+/// The libraries listed in `/etc/ld.so.cache`, by soname
+#[derive(Debug, Default)]
+pub struct LdCache {
+    libs: HashMap<Vec<u8>, PathBuf>,
+}
+
+/// The format ldconfig stopped writing with glibc 2.32, which may still
+/// precede the new one
+const OLD_MAGIC: &[u8] = b"ld.so-1.7.0";
+const NEW_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+/// `FLAG_ELF_LIBC6 | FLAG_X8664_LIB64`: entries for x86-64 libraries
+const X8664_LIBC6: u32 = 0x0303;
+
+struct CacheEntry {
+    flags: u32,
+    key: u32,
+    value: u32,
+    hwcap: u64,
+}
+
+impl CacheEntry {
+    fn parse(i: &[u8]) -> IResult<&[u8], Self> {
+        let (i, (flags, key, value, _osversion, hwcap)) =
+            (le_u32, le_u32, le_u32, le_u32, le_u64).parse(i)?;
+        Ok((
+            i,
+            Self {
+                flags,
+                key,
+                value,
+                hwcap,
+            },
+        ))
+    }
+}
+
+impl LdCache {
+    pub fn load() -> Option<Self> {
+        let input = std::fs::read("/etc/ld.so.cache").ok()?;
+        Self::parse(&input)
+    }
+
+    pub fn parse(input: &[u8]) -> Option<Self> {
+        let start = if input.starts_with(OLD_MAGIC) {
+            let (_, (_, nlibs)) = (take(12_usize), le_u32::<_, ()>).parse(input).ok()?;
+            // old entries are three ints each
+            (16 + nlibs as usize * 12).next_multiple_of(8)
+        } else {
+            0
+        };
+        let cache = input.get(start..)?;
+
+        let (i, _) = tag::<_, _, ()>(NEW_MAGIC).parse(cache).ok()?;
+        let (i, nlibs) = le_u32::<_, ()>(i).ok()?;
+        // string table length, flags, extension offset and unused words
+        let (i, _) = take::<_, _, ()>(24_usize).parse(i).ok()?;
+        let (_, entries) = count(CacheEntry::parse, nlibs as usize).parse(i).ok()?;
+
+        // string offsets are relative to the new header. Entries come in
+        // order of preference, keep the first one for each name
+        let string = |offset: u32| CStr::from_bytes_until_nul(cache.get(offset as usize..)?).ok();
+        let mut libs = HashMap::new();
+        for entry in entries {
+            // glibc-hwcaps subdirectories have a non-zero hwcap
+            if entry.flags != X8664_LIBC6 || entry.hwcap != 0 {
+                continue;
+            }
+            if let (Some(key), Some(value)) = (string(entry.key), string(entry.value)) {
+                libs.entry(key.to_bytes().to_vec()).or_insert_with(|| {
+                    PathBuf::from(std::ffi::OsStr::from_bytes(value.to_bytes()))
+                });
+            }
+        }
+        Some(Self { libs })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.libs.get(name.as_bytes()).map(PathBuf::as_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new-format cache with `entries` of (flags, key, value, hwcap),
+    /// its strings after them
+    fn cache(entries: &[(u32, &str, &str, u64)]) -> Vec<u8> {
+        let header = NEW_MAGIC.len() + 4 + 24;
+        let mut strings = Vec::new();
+        let mut table = Vec::new();
+        let strings_start = header + entries.len() * 24;
+        let mut add = |s: &str| {
+            let offset = strings_start + strings.len();
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+            offset as u32
+        };
+        for &(flags, key, value, hwcap) in entries {
+            let (key, value) = (add(key), add(value));
+            table.extend(flags.to_le_bytes());
+            table.extend(key.to_le_bytes());
+            table.extend(value.to_le_bytes());
+            table.extend(0_u32.to_le_bytes());
+            table.extend(hwcap.to_le_bytes());
+        }
+
+        let mut out = NEW_MAGIC.to_vec();
+        out.extend((entries.len() as u32).to_le_bytes());
+        out.extend((strings.len() as u32).to_le_bytes());
+        out.extend([0; 20]);
+        out.extend(table);
+        out.extend(strings);
+        out
+    }
+
+    fn sample() -> Vec<u8> {
+        cache(&[
+            (
+                X8664_LIBC6,
+                "libz.so.1",
+                "/usr/lib/hwcaps/libz.so.1",
+                1 << 62,
+            ),
+            (X8664_LIBC6, "libz.so.1", "/usr/lib/libz.so.1", 0),
+            (X8664_LIBC6, "libz.so.1", "/lib/libz.so.1", 0),
+            // i386
+            (0x0003, "libm.so.6", "/usr/lib32/libm.so.6", 0),
+            (X8664_LIBC6, "libm.so.6", "/usr/lib/libm.so.6", 0),
+        ])
+    }
+
+    #[test]
+    fn parse_handcrafted_cache() {
+        let ld_cache = LdCache::parse(&sample()).unwrap();
+        assert_eq!(ld_cache.libs.len(), 2);
+        assert_eq!(
+            ld_cache.get("libz.so.1"),
+            Some(Path::new("/usr/lib/libz.so.1"))
+        );
+        assert_eq!(
+            ld_cache.get("libm.so.6"),
+            Some(Path::new("/usr/lib/libm.so.6"))
+        );
+        assert_eq!(ld_cache.get("libc.so.6"), None);
+
+        // the same, after an old-format cache of one entry
+        let mut old = OLD_MAGIC.to_vec();
+        old.resize(12, 0);
+        old.extend(1_u32.to_le_bytes());
+        old.extend([0; 12]);
+        old.resize(old.len().next_multiple_of(8), 0);
+        old.extend(sample());
+        let ld_cache = LdCache::parse(&old).unwrap();
+        assert_eq!(
+            ld_cache.get("libz.so.1"),
+            Some(Path::new("/usr/lib/libz.so.1"))
+        );
+    }
+
+    #[test]
+    fn reject_truncated_or_garbage_cache() {
+        let sample = sample();
+        let strings_start = NEW_MAGIC.len() + 4 + 24 + 5 * 24;
+        for len in 0..strings_start {
+            assert!(LdCache::parse(&sample[..len]).is_none(), "{len} bytes");
+        }
+        // cut in the strings: whatever entries are left complete still parse
+        for len in strings_start..sample.len() {
+            LdCache::parse(&sample[..len]).unwrap();
+        }
+
+        assert!(LdCache::parse(b"").is_none());
+        assert!(LdCache::parse(&[0xff; 256]).is_none());
+        // an old cache claiming more entries than there are bytes
+        let mut old = OLD_MAGIC.to_vec();
+        old.resize(12, 0);
+        old.extend(u32::MAX.to_le_bytes());
+        assert!(LdCache::parse(&old).is_none());
+        // a new cache claiming more entries than it has
+        let mut huge = sample.clone();
+        huge[NEW_MAGIC.len()..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(LdCache::parse(&huge).is_none());
+        // string offsets past the end are skipped
+        let mut wild = sample;
+        let key = NEW_MAGIC.len() + 4 + 24 + 4;
+        wild[key..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        LdCache::parse(&wild).unwrap();
+    }
+}
@@ -0,0 +1,25 @@
+//! Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Runs gcc in `dir`. The test programs are all built from C, so a missing
+/// gcc fails the test rather than skipping it
+pub fn gcc(dir: &Path, args: &[&str]) {
+    let status = Command::new("gcc")
+        .current_dir(dir)
+        .args(args)
+        .status()
+        .expect("gcc is needed to build the test programs");
+    assert!(status.success(), "gcc {args:?} failed");
+}
+
+/// A fresh `elk-{name}-{pid}` directory in the system's temporary directory
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("elk-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
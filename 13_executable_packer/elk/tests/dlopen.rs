@@ -0,0 +1,271 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::process::Command;
+
+#[test]
+fn plugins() {
+    let dir = temp_dir("dlopen");
+
+    std::fs::write(dir.join("dep.c"), "int dep_value(void) { return 100; }\n").unwrap();
+    // binds against its own dependency and against the program, has a
+    // constructor and a thread-local
+    let plugin = r#"
+#include <stdio.h>
+int dep_value(void);
+int host_symbol(void);
+int plugin_value = 41;
+__thread int plugin_counter = 5;
+__attribute__((constructor)) static void init(void) { puts("plugin: init"); }
+int answer(void) { return plugin_value + 1 + dep_value() + host_symbol() + plugin_counter++; }
+"#;
+    std::fs::write(dir.join("plugin.c"), plugin).unwrap();
+    let main = r#"
+#include <dlfcn.h>
+#include <stdio.h>
+int host_symbol(void) { return 1000; }
+int main(int argc, char **argv) {
+    void *local = dlopen("./libplugin.so", RTLD_NOW | RTLD_LOCAL);
+    if (!local) {
+        printf("dlopen: %s\n", dlerror());
+        return 1;
+    }
+    int (*answer)(void) = (int (*)(void))dlsym(local, "answer");
+    int first = answer(), second = answer();
+    printf("answer: %d %d\n", first, second);
+    printf("counter: %d\n", *(int *)dlsym(local, "plugin_counter"));
+    printf("local: %s\n", dlsym(RTLD_DEFAULT, "answer") ? "visible" : dlerror());
+
+    void *global = dlopen("./libplugin.so", RTLD_NOW | RTLD_GLOBAL | RTLD_NOLOAD);
+    printf("global: %s %d\n", dlsym(RTLD_DEFAULT, "answer") ? "visible" : "missing", global == local);
+    printf("next: %s\n", dlsym(RTLD_NEXT, "puts") ? "found" : "missing");
+    printf("program: %d\n", dlsym(dlopen(NULL, RTLD_NOW), "host_symbol") == (void *)host_symbol);
+
+    int closed = dlclose(local);
+    printf("still open: %s\n", dlsym(RTLD_DEFAULT, "answer") ? "visible" : "missing");
+    closed |= dlclose(global);
+    printf("closed: %d %s\n", closed, dlsym(RTLD_DEFAULT, "answer") ? "visible" : "missing");
+    printf("again: %d\n", dlclose(global));
+    printf("nope: %d\n", dlopen("./nope.so", RTLD_NOW) == NULL && dlerror() != NULL);
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+
+    gcc(&dir, &["-shared", "-fPIC", "dep.c", "-o", "libdep.so"]);
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            "plugin.c",
+            "-o",
+            "libplugin.so",
+            "-L.",
+            "-ldep",
+            "-Wl,-rpath,$ORIGIN",
+        ],
+    );
+    gcc(&dir, &["-rdynamic", "main.c", "-o", "main"]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "./main"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    let program: Vec<_> = stdout
+        .lines()
+        .skip_while(|line| *line != "plugin: init")
+        .collect();
+    assert_eq!(
+        program,
+        [
+            "plugin: init",
+            "answer: 1147 1148",
+            "counter: 7",
+            "local: undefined symbol: answer",
+            "global: visible 1",
+            "next: found",
+            "program: 1",
+            "still open: visible",
+            "closed: 0 missing",
+            "again: -1",
+            "nope: 1",
+        ],
+        "{stdout}"
+    );
+}
+
+#[test]
+fn failed_open_releases_dependencies() {
+    let dir = temp_dir("dlopen-failure");
+
+    std::fs::write(dir.join("dep.c"), "int dep_value(void) { return 100; }\n").unwrap();
+    std::fs::write(
+        dir.join("plugin.c"),
+        "int dep_value(void);\nint answer(void) { return dep_value() + 1; }\n",
+    )
+    .unwrap();
+    // shares libdep.so with libplugin.so, but can't be relocated
+    std::fs::write(
+        dir.join("broken.c"),
+        "int dep_value(void);\nint nowhere(void);\n__thread int broken_counter;\n\
+         int broken(void) { return dep_value() + nowhere() + broken_counter; }\n",
+    )
+    .unwrap();
+    // opened after libbroken.so failed, gets the TLS module ID it gave back
+    std::fs::write(
+        dir.join("late.c"),
+        "__thread int late_counter = 3;\nint late(void) { return late_counter; }\n",
+    )
+    .unwrap();
+    let main = r#"
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <link.h>
+#include <stdio.h>
+#include <string.h>
+static int print_modid(struct dl_phdr_info *info, size_t size, void *data) {
+    const char *name = strrchr(info->dlpi_name, '/');
+    if (name && !strcmp(name, "/liblate.so"))
+        printf("late module: %zu\n", info->dlpi_tls_modid);
+    return 0;
+}
+int main(void) {
+    // elk's own allocations may land on the heap the program's buffer is in
+    setvbuf(stdout, NULL, _IONBF, 0);
+    void *plugin = dlopen("./libplugin.so", RTLD_NOW | RTLD_LOCAL);
+    if (!plugin) {
+        printf("dlopen: %s\n", dlerror());
+        return 1;
+    }
+    void *broken = dlopen("./libbroken.so", RTLD_NOW | RTLD_GLOBAL);
+    printf("broken: %s\n", broken ? "opened" : "failed");
+    printf("dep: %s\n", dlsym(RTLD_DEFAULT, "dep_value") ? "global" : "local");
+    printf("closed: %d\n", dlclose(plugin));
+    int (*late)(void) = (int (*)(void))dlsym(dlopen("./liblate.so", RTLD_NOW), "late");
+    printf("late: %d\n", late());
+    dl_iterate_phdr(print_modid, NULL);
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+
+    let with_dep = ["-L.", "-ldep", "-Wl,-rpath,$ORIGIN"];
+    gcc(&dir, &["-shared", "-fPIC", "dep.c", "-o", "libdep.so"]);
+    gcc(&dir, &["-shared", "-fPIC", "late.c", "-o", "liblate.so"]);
+    for name in ["plugin", "broken"] {
+        let source = format!("{name}.c");
+        let output = format!("lib{name}.so");
+        let mut args = vec!["-shared", "-fPIC", &source, "-o", &output];
+        args.extend(with_dep);
+        gcc(&dir, &args);
+    }
+    gcc(&dir, &["main.c", "-o", "main"]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "./main"])
+        .output()
+        .unwrap();
+    let dir = dir.canonicalize().unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    let program: Vec<_> = stdout
+        .lines()
+        .filter(|line| {
+            ["broken: ", "dep: ", "closed: ", "late"]
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        })
+        .collect();
+    // libdep.so went back to the local scope, and to one reference, and
+    // libbroken.so's TLS module went to liblate.so: libc has the first one
+    assert_eq!(
+        program,
+        [
+            "broken: failed",
+            "dep: local",
+            "closed: 0",
+            "late: 3",
+            "late module: 2"
+        ],
+        "{stdout}"
+    );
+    let unloaded = format!("unloading {:?}", dir.join("libdep.so"));
+    assert!(stdout.lines().any(|line| line == unloaded), "{stdout}");
+}
+
+#[test]
+fn open_searches_the_callers_runpath() {
+    let dir = temp_dir("dlopen-runpath");
+    std::fs::create_dir_all(dir.join("plugins")).unwrap();
+
+    std::fs::write(dir.join("leaf.c"), "int leaf(void) { return 7; }\n").unwrap();
+    // only libhost.so knows where the plugins are
+    let host = r#"
+#include <dlfcn.h>
+int host_open(void) { return dlopen("libleaf.so", RTLD_NOW) != 0; }
+"#;
+    std::fs::write(dir.join("host.c"), host).unwrap();
+    let main = r#"
+#include <dlfcn.h>
+#include <stdio.h>
+int host_open(void);
+int main(void) {
+    setvbuf(stdout, NULL, _IONBF, 0);
+    printf("from main: %d\n", dlopen("libleaf.so", RTLD_NOW) != 0);
+    printf("from host: %d\n", host_open());
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+
+    gcc(
+        &dir,
+        &["-shared", "-fPIC", "leaf.c", "-o", "plugins/libleaf.so"],
+    );
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            "host.c",
+            "-o",
+            "libhost.so",
+            "-Wl,--enable-new-dtags,-rpath,$ORIGIN/plugins",
+        ],
+    );
+    gcc(
+        &dir,
+        &[
+            "main.c",
+            "-o",
+            "main",
+            "-L.",
+            "-lhost",
+            "-Wl,--enable-new-dtags,-rpath,$ORIGIN",
+        ],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "./main"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    let program: Vec<_> = stdout
+        .lines()
+        .filter(|line| line.starts_with("from "))
+        .collect();
+    assert_eq!(program, ["from main: 0", "from host: 1"], "{stdout}");
+}
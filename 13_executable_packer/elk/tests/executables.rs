@@ -0,0 +1,200 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Runs rustc in `dir`. `echidna` needs `#![feature(thread_local)]`,
+/// whatever the toolchain
+fn rustc(dir: &Path, args: &[&str]) {
+    let status = Command::new("rustc")
+        .current_dir(dir)
+        .env("RUSTC_BOOTSTRAP", "1")
+        .args(args)
+        .status()
+        .expect("rustc is needed to build echidna");
+    assert!(status.success(), "rustc {args:?} failed");
+}
+
+/// Static linking needs glibc's archives, which aren't always installed.
+/// Says so when they aren't, since the static variants are then skipped
+fn has_static_libc() -> bool {
+    let found = Command::new("gcc")
+        .arg("-print-file-name=libc.a")
+        .output()
+        .is_ok_and(|output| output.stdout.starts_with(b"/"));
+    if !found {
+        eprintln!("libc.a isn't installed, skipping the static variants");
+    }
+    found
+}
+
+fn elk(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// The value of ` - {name}: 0x...` in echidna's dump of its auxiliary vector
+fn auxv_entry(stdout: &str, name: &str) -> u64 {
+    let prefix = format!(" - {name}: ");
+    let value = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .unwrap_or_else(|| panic!("no {name} in {stdout}"));
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).unwrap(),
+        None => value.parse().unwrap(),
+    }
+}
+
+#[test]
+fn echidna_matrix() {
+    let dir = temp_dir("echidna");
+    let source: PathBuf = [env!("CARGO_MANIFEST_DIR"), "../samples/echidna/src/main.rs"]
+        .iter()
+        .collect();
+    let source = source.to_str().unwrap();
+    // what echidna's manifest and build script ask for, with memset and
+    // memcmp from libc rather than compiler_builtins
+    let common = [
+        source,
+        "--edition",
+        "2024",
+        "-C",
+        "panic=abort",
+        "-C",
+        "link-arg=-nostartfiles",
+    ];
+    let mut variants = vec![
+        (
+            "non-pie",
+            vec![
+                "-C",
+                "relocation-model=static",
+                "-C",
+                "link-arg=-no-pie",
+                "-C",
+                "link-arg=-lc",
+            ],
+        ),
+        ("pie", vec!["-C", "link-arg=-lc"]),
+    ];
+    // nothing relocates this one but elk, IFUNCs (`IRELATIVE`) included
+    if has_static_libc() {
+        variants.push((
+            "static-pie",
+            vec![
+                "-C",
+                "target-feature=+crt-static",
+                "-C",
+                "link-args=-Wl,--start-group -l:libc.a -l:libgcc.a -l:libgcc_eh.a -Wl,--end-group",
+            ],
+        ));
+    }
+
+    for (name, flags) in variants {
+        let output = format!("echidna-{name}");
+        let mut args = common.to_vec();
+        args.extend(flags);
+        args.extend(["-o", &output]);
+        rustc(&dir, &args);
+        let file =
+            delf::File::parse_or_print_error(std::fs::read(dir.join(&output)).unwrap()).unwrap();
+
+        let program = format!("./{output}");
+        let run = elk(&dir, &["run", &program, "one", "two"]);
+        let stdout = String::from_utf8_lossy(&run.stdout);
+        assert_eq!(run.status.code(), Some(3), "{name}: {run:?}");
+
+        // thread-locals, through the initial-exec model
+        let numbers: Vec<_> = stdout
+            .lines()
+            .filter(|line| ["10", "100", "30", "600"].contains(line))
+            .collect();
+        assert_eq!(numbers, ["10", "100", "30", "600"], "{name}: {stdout}");
+        assert!(
+            stdout.contains(&format!(
+                "received 3 arguments:\n - {program}\n - one\n - two\n"
+            )),
+            "{name}: {stdout}"
+        );
+
+        // the auxiliary vector describes echidna, not elk
+        let entry = auxv_entry(&stdout, "AT_ENTRY");
+        let base = entry - file.entry_point.0;
+        if file.r#type == delf::Type::Exec {
+            assert_eq!(base, 0, "{name}: {stdout}");
+        } else {
+            assert!(base != 0 && base.is_multiple_of(0x1000), "{name}: {stdout}");
+        }
+        assert_eq!(
+            auxv_entry(&stdout, "AT_PHNUM"),
+            file.program_headers.len() as u64,
+            "{name}: {stdout}"
+        );
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn glibc_matrix() {
+    let dir = temp_dir("glibc-matrix");
+    let main = r#"
+#include <stdio.h>
+#include <string.h>
+static __thread int calls = 40;
+int main(int argc, char **argv) {
+    char buf[32];
+    memcpy(buf, argv[1], strlen(argv[1]) + 1);
+    printf("%s %d\n", buf, calls + argc);
+    return 7;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    let mut variants = vec![("non-pie", "-no-pie"), ("pie", "-pie")];
+    // these relocate themselves, and probe the CPU before picking IFUNCs
+    if has_static_libc() {
+        variants.extend([("static", "-static"), ("static-pie", "-static-pie")]);
+    }
+
+    for (name, flag) in variants {
+        gcc(&dir, &[flag, "main.c", "-o", name]);
+        let run = elk(&dir, &["run", &format!("./{name}"), "hello"]);
+        let stdout = String::from_utf8_lossy(&run.stdout);
+        assert_eq!(run.status.code(), Some(7), "{name}: {run:?}");
+        assert!(
+            stdout.lines().any(|line| line == "hello 42"),
+            "{name}: {stdout}"
+        );
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn fixed_address_conflict() {
+    let dir = temp_dir("fixed-conflict");
+    std::fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+    gcc(&dir, &["-no-pie", "main.c", "-o", "main"]);
+    // another copy, linked for the same addresses
+    std::fs::copy(dir.join("main"), dir.join("copy")).unwrap();
+
+    let run = elk(&dir, &["run", "--preload", "./copy", "./main"]);
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let dir = dir.canonicalize().unwrap();
+    let warning = stdout
+        .lines()
+        .find(|line| line.starts_with("Warning: \"./copy\" cannot be preloaded"))
+        .unwrap_or_else(|| panic!("{stdout}"));
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(run.status.success(), "{run:?}");
+    assert!(warning.contains("must be mapped at"), "{warning}");
+    assert!(
+        warning.contains(&format!("from File({:?})", dir.join("main"))),
+        "{warning}"
+    );
+}
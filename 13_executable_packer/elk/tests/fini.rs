@@ -0,0 +1,109 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::process::Command;
+
+#[test]
+fn teardown_order() {
+    let dir = temp_dir("fini");
+
+    let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+    write(
+        "b.c",
+        r#"
+#include <stdio.h>
+__attribute__((constructor)) static void init(void) { printf("init b\n"); }
+__attribute__((destructor)) static void fini(void) { printf("fini b\n"); }
+int b(void) { return 2; }
+"#,
+    );
+    write(
+        "a.c",
+        r#"
+#include <stdio.h>
+int b(void);
+__attribute__((constructor)) static void init(void) { printf("init a\n"); }
+__attribute__((destructor)) static void fini(void) { printf("fini a\n"); }
+int a(void) { return b() + 1; }
+"#,
+    );
+    // its `atexit` handler goes away with it, on `dlclose`
+    write(
+        "plug.c",
+        r#"
+#include <stdio.h>
+#include <stdlib.h>
+static void handler(void) { printf("atexit plug\n"); }
+__attribute__((constructor)) static void init(void) { printf("init plug\n"); atexit(handler); }
+__attribute__((destructor)) static void fini(void) { printf("fini plug\n"); }
+"#,
+    );
+    // the last line has no newline: only `exit` flushes it
+    write(
+        "main.c",
+        r#"
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+int a(void);
+static void handler(void) { printf("atexit main\n"); }
+__attribute__((destructor)) static void fini(void) { printf("fini main\n"); }
+int main(void) {
+    atexit(handler);
+    void *plug = dlopen("./libplug.so", RTLD_NOW);
+    dlclose(plug);
+    printf("a=%d ", a());
+    return 0;
+}
+"#,
+    );
+    gcc(&dir, &["-shared", "-fPIC", "b.c", "-o", "libb.so"]);
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            "a.c",
+            "-o",
+            "liba.so",
+            "-L.",
+            "-lb",
+            "-Wl,-rpath,$ORIGIN",
+        ],
+    );
+    gcc(&dir, &["-shared", "-fPIC", "plug.c", "-o", "libplug.so"]);
+    gcc(
+        &dir,
+        &["main.c", "-o", "main", "-L.", "-la", "-Wl,-rpath,$ORIGIN"],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "./main"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "{output:?}");
+
+    // the program's output goes to a pipe, so it's all buffered until exit
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let program: Vec<_> = stdout
+        .lines()
+        .skip_while(|line| *line != "init b")
+        .collect();
+    assert_eq!(
+        program,
+        [
+            "init b",
+            "init a",
+            "init plug",
+            "fini plug",
+            "atexit plug",
+            "a=3 atexit main",
+            "fini main",
+            "fini a",
+            "fini b",
+        ],
+        "{stdout}"
+    );
+}
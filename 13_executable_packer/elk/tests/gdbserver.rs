@@ -0,0 +1,139 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Command, Stdio},
+};
+
+/// Just enough of gdb's side of the remote protocol
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn query(&mut self, packet: &str) -> String {
+        String::from_utf8_lossy(&self.query_bytes(packet)).into_owned()
+    }
+
+    fn query_bytes(&mut self, packet: &str) -> Vec<u8> {
+        let sum = packet.bytes().fold(0_u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${packet}#{sum:02x}").unwrap();
+        assert_eq!(self.byte(), b'+', "{packet} wasn't acknowledged");
+
+        while self.byte() != b'$' {}
+        let mut reply = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                // binary data
+                b'}' => reply.push(self.byte() ^ 0x20),
+                b => reply.push(b),
+            }
+        }
+        self.byte();
+        self.byte();
+        self.stream.write_all(b"+").unwrap();
+        reply
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+fn le_hex(value: u64) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[test]
+fn breakpoint_and_exit() {
+    let dir = temp_dir("gdbserver");
+    let main = r#"
+#include <stdio.h>
+int twice(int x) { return x * 2; }
+int main(void) {
+    printf("twice=%d\n", twice(21));
+    return 3;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    gcc(&dir, &["main.c", "-o", "main"]);
+    let file = delf::File::parse_or_print_error(std::fs::read(dir.join("main")).unwrap()).unwrap();
+    let symtab = file.section_by_name(b".symtab").unwrap();
+    let twice = file
+        .read_symbols(symtab)
+        .unwrap()
+        .into_iter()
+        .find(|sym| file.symbol_name(symtab, sym) == b"twice")
+        .unwrap()
+        .value;
+
+    let mut elk = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "--gdbserver", "127.0.0.1:0", "./main"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(elk.stdout.take().unwrap());
+    let addr = stdout
+        .by_ref()
+        .lines()
+        .find_map(|line| {
+            line.unwrap()
+                .strip_prefix("Waiting for gdb on ")
+                .map(String::from)
+        })
+        .unwrap();
+    let mut gdb = Client {
+        stream: TcpStream::connect(addr).unwrap(),
+    };
+
+    assert!(
+        gdb.query("qSupported:swbreak+")
+            .contains("qXfer:libraries-svr4:read+")
+    );
+    assert_eq!(gdb.query("?"), "T05thread:1;");
+    let libraries = gdb.query("qXfer:libraries-svr4:read::0,ffff");
+    assert!(libraries.starts_with("l<library-list-svr4"), "{libraries}");
+    assert!(libraries.contains("libc.so.6"), "{libraries}");
+
+    // where the program is: its entry point minus the one in the file
+    let auxv = gdb.query_bytes("qXfer:auxv:read::0,1000");
+    let auxv: Vec<u64> = auxv[1..]
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    let entry = auxv
+        .chunks_exact(2)
+        .find(|pair| pair[0] == 9)
+        .map(|pair| pair[1])
+        .unwrap();
+    let twice = entry - file.entry_point.0 + twice.0;
+
+    assert_eq!(gdb.query(&format!("Z0,{twice:x},1")), "OK");
+    // the breakpoint doesn't show
+    assert_ne!(gdb.query(&format!("m{twice:x},1")), "cc");
+    assert_eq!(gdb.query("vCont;c"), "T05thread:1;swbreak:;");
+    assert_eq!(gdb.query("p10"), le_hex(twice));
+    assert_eq!(gdb.query("p5"), le_hex(21));
+
+    // a single step moves past the breakpoint
+    assert_eq!(gdb.query("vCont;s:1"), "T05thread:1;");
+    assert_ne!(gdb.query("p10"), le_hex(twice));
+    assert_eq!(gdb.query("vCont;c"), "W03");
+
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    let status = elk.wait().unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(status.code(), Some(3));
+    assert!(rest.contains("twice=42"), "{rest}");
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Builds `samples/initorder/{source}` into `dir`, linked against `libs`
+/// from there
+fn build(dir: &Path, source: &str, output: &str, flags: &[&str], libs: &[&str]) {
+    let source: PathBuf = [env!("CARGO_MANIFEST_DIR"), "../samples/initorder", source]
+        .iter()
+        .collect();
+    let libs: Vec<_> = libs.iter().map(|lib| format!("-l{lib}")).collect();
+    let mut args = vec![source.to_str().unwrap(), "-o", output];
+    args.extend(flags);
+    // keep every DT_NEEDED, whether or not a symbol comes from it
+    args.extend(["-L.", "-Wl,--no-as-needed", "-Wl,-rpath,$ORIGIN"]);
+    args.extend(libs.iter().map(String::as_str));
+    gcc(dir, &args)
+}
+
+/// What the program and elk's warnings printed, without elk's progress messages
+fn run(dir: &Path, program: &str) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(dir)
+        .args(["run", program])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| {
+            line.starts_with("Warning: dependency cycle")
+                || line.starts_with("init ")
+                || line.starts_with("preinit ")
+        })
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn dependencies_first() {
+    let dir = temp_dir("init-order");
+    let shared = ["-shared", "-fPIC"];
+    build(&dir, "base.c", "libbase.so", &shared, &[]);
+    build(&dir, "mid.c", "libmid.so", &shared, &["base"]);
+    build(&dir, "left.c", "libleft.so", &shared, &["mid"]);
+    build(&dir, "main.c", "main", &[], &["left", "base"]);
+
+    let lines = run(&dir, "./main");
+    std::fs::remove_dir_all(&dir).ok();
+
+    // libc's initializers run too, but print nothing
+    assert_eq!(
+        lines,
+        [
+            "preinit main",
+            "init base",
+            "init mid",
+            "init left",
+            "init main"
+        ]
+    );
+}
+
+#[test]
+fn cycles_are_reported() {
+    let dir = temp_dir("init-cycle");
+    let ping = ["-shared", "-fPIC", "-DNAME=\"ping\""];
+    let pong = ["-shared", "-fPIC", "-DNAME=\"pong\""];
+    // libping.so is built twice: the first one is only there to link against
+    build(&dir, "cycle.c", "libping.so", &ping, &[]);
+    build(&dir, "cycle.c", "libpong.so", &pong, &["ping"]);
+    build(&dir, "cycle.c", "libping.so", &ping, &["pong"]);
+    build(&dir, "cyclic.c", "cyclic", &[], &["ping"]);
+
+    let lines = run(&dir, "./cyclic");
+    let dir = dir.canonicalize().unwrap();
+    let lines: Vec<_> = lines
+        .iter()
+        .map(|line| line.replace(dir.to_str().unwrap(), "DIR"))
+        .collect();
+    std::fs::remove_dir_all(&dir).ok();
+
+    // glibc picks the same order, silently
+    assert_eq!(
+        lines,
+        [
+            "Warning: dependency cycle DIR/libpong.so -> DIR/libping.so -> DIR/libpong.so: \
+             DIR/libping.so is initialized before DIR/libpong.so, which it needs",
+            "init ping",
+            "init pong",
+            "init main",
+        ]
+    );
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::{path::Path, process::Command};
+
+/// Runs `args` under elk in `dir`, and returns the last line the program printed
+fn elk(dir: &Path, args: &[&str], env: &[(&str, &str)]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(dir)
+        .arg("run")
+        .args(args)
+        .env_remove("LD_PRELOAD")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().last().unwrap_or_default().to_string()
+}
+
+#[test]
+fn preloaded_malloc() {
+    let dir = temp_dir("preload");
+
+    // counts calls, and forwards them to the next definition: libc's
+    let counter = r#"
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <stddef.h>
+static size_t calls;
+void *malloc(size_t size) {
+    static void *(*next)(size_t);
+    if (!next)
+        next = (void *(*)(size_t))dlsym(RTLD_NEXT, "malloc");
+    calls++;
+    return next(size);
+}
+size_t malloc_calls(void) { return calls; }
+"#;
+    let main = r#"
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+int main(void) {
+    size_t (*calls)(void) = (size_t (*)(void))dlsym(RTLD_DEFAULT, "malloc_calls");
+    if (!calls) {
+        puts("not interposed");
+        return 0;
+    }
+    size_t before = calls();
+    void *direct = malloc(10);
+    char *from_libc = strdup("hello");
+    printf("calls: %zu\n", calls() - before);
+    free(direct);
+    free(from_libc);
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("counter.c"), counter).unwrap();
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    gcc(
+        &dir,
+        &["-shared", "-fPIC", "counter.c", "-o", "libcounter.so"],
+    );
+    gcc(&dir, &["main.c", "-o", "main"]);
+
+    let results = [
+        elk(&dir, &["./main"], &[]),
+        elk(&dir, &["--preload", "./libcounter.so", "./main"], &[]),
+        elk(&dir, &["./main"], &[("LD_PRELOAD", "./libcounter.so")]),
+    ];
+    std::fs::remove_dir_all(&dir).ok();
+
+    // both the program's call and libc's own go through the preloaded malloc
+    assert_eq!(results, ["not interposed", "calls: 2", "calls: 2"]);
+}
+
+#[test]
+fn protected_and_symbolic() {
+    let dir = temp_dir("symbolic");
+
+    // the pointers keep relocations against the functions, which the
+    // program defines too
+    let lib = r#"
+__attribute__((visibility("protected"))) int protected_value(void) { return 1; }
+int symbolic_value(void) { return 2; }
+int plain_value(void) { return 3; }
+int (*const protected_ptr)(void) = protected_value;
+int (*const symbolic_ptr)(void) = symbolic_value;
+int (*const plain_ptr)(void) = plain_value;
+int from_lib(void) { return protected_ptr() * 100 + symbolic_ptr() * 10 + plain_ptr(); }
+"#;
+    let main = r#"
+#include <stdio.h>
+int protected_value(void) { return 7; }
+int symbolic_value(void) { return 8; }
+int plain_value(void) { return 9; }
+int from_lib(void);
+int main(void) {
+    printf("from_lib=%d\n", from_lib());
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("lib.c"), lib).unwrap();
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    let link = |lib: &str, program: &str| {
+        let lib = format!("-l{lib}");
+        gcc(
+            &dir,
+            &[
+                "-rdynamic",
+                "main.c",
+                "-o",
+                program,
+                "-L.",
+                &lib,
+                "-Wl,-rpath,$ORIGIN",
+            ],
+        )
+    };
+    gcc(&dir, &["-shared", "-fPIC", "lib.c", "-o", "libplain.so"]);
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            "-Wl,-Bsymbolic",
+            "lib.c",
+            "-o",
+            "libsym.so",
+        ],
+    );
+    link("plain", "plain");
+    link("sym", "symbolic");
+
+    let results = [
+        elk(&dir, &["./plain"], &[]),
+        elk(&dir, &["./symbolic"], &[]),
+    ];
+    std::fs::remove_dir_all(&dir).ok();
+
+    // the program interposes everything but the protected function, unless
+    // the library binds its own references
+    assert_eq!(results, ["from_lib=189", "from_lib=123"]);
+}
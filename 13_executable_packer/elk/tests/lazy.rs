@@ -0,0 +1,110 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::process::Command;
+
+#[test]
+fn lazy_binding() {
+    let dir = temp_dir("lazy");
+
+    // integer and floating-point arguments must both survive the resolver
+    let lazy = r#"
+#include <string.h>
+int add(int a, int b) { return a + b; }
+double scale(double x, double by) { return x * by; }
+size_t measure(const char *s) { return strlen(s); }
+"#;
+    // bound at load time even under --lazy: its GOT is read-only afterwards
+    let now = r#"
+int add(int a, int b);
+int twice(int x) { return add(x, x); }
+"#;
+    let main = r#"
+#include <stdio.h>
+#include <stdlib.h>
+int add(int a, int b);
+double scale(double x, double by);
+size_t measure(const char *s);
+int twice(int x);
+int main(void) {
+    for (int i = 0; i < 2; i++) {
+        printf("add=%d scale=%.1f measure=%zu twice=%d\n", add(i, 40), scale(1.5, i + 1),
+               measure("lazy"), twice(i + 1));
+    }
+    char *buf = malloc(16);
+    free(buf);
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("lazy.c"), lazy).unwrap();
+    std::fs::write(dir.join("now.c"), now).unwrap();
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    let rpath = "-Wl,-rpath,$ORIGIN";
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            "-Wl,-z,lazy",
+            "lazy.c",
+            "-o",
+            "liblazy.so",
+        ],
+    );
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            "-Wl,-z,now",
+            "now.c",
+            "-o",
+            "libnow.so",
+            "-L.",
+            "-llazy",
+            rpath,
+        ],
+    );
+    gcc(
+        &dir,
+        &[
+            "-Wl,-z,lazy",
+            "main.c",
+            "-o",
+            "main",
+            "-L.",
+            "-llazy",
+            "-lnow",
+            rpath,
+        ],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "--lazy", "./main"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+
+    // the first round goes through the resolver, the second through the bound slots
+    let results: Vec<_> = stdout.lines().filter(|l| l.starts_with("add=")).collect();
+    assert_eq!(
+        results,
+        [
+            "add=40 scale=1.5 measure=4 twice=2",
+            "add=41 scale=3.0 measure=4 twice=4",
+        ],
+        "{stdout}"
+    );
+
+    let lazily_bound = |name: &str| {
+        stdout
+            .lines()
+            .any(|l| l.starts_with("Binding ") && l.ends_with(&format!("{name}\"")))
+    };
+    assert!(lazily_bound("main"), "{stdout}");
+    assert!(lazily_bound("liblazy.so"), "{stdout}");
+    assert!(!lazily_bound("libnow.so"), "{stdout}");
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::process::Command;
+
+#[test]
+fn missing_libraries_and_symbols() {
+    let dir = temp_dir("ldd");
+
+    let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+    write(
+        "foo.c",
+        "int foo(void) { return 1; }\nint bar(void) { return 2; }\n",
+    );
+    write("gone.c", "int gone(void) { return 3; }\n");
+    write(
+        "main.c",
+        r#"
+int foo(void);
+int bar(void);
+int gone(void);
+int main(void) { return foo() + bar() + gone(); }
+"#,
+    );
+    gcc(&dir, &["-shared", "-fPIC", "foo.c", "-o", "libfoo.so"]);
+    gcc(&dir, &["-shared", "-fPIC", "gone.c", "-o", "libgone.so"]);
+    gcc(
+        &dir,
+        &[
+            "main.c",
+            "-o",
+            "main",
+            "-L.",
+            "-lfoo",
+            "-lgone",
+            "-Wl,-rpath,$ORIGIN",
+        ],
+    );
+    // the program was linked against libraries that changed since
+    write("foo.c", "int foo(void) { return 1; }\n");
+    gcc(&dir, &["-shared", "-fPIC", "foo.c", "-o", "libfoo.so"]);
+    std::fs::remove_file(dir.join("libgone.so")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["ldd", "--bindings", "./main"])
+        .output()
+        .unwrap();
+    let dir = dir.canonicalize().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).replace(dir.to_str().unwrap(), "DIR");
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "{output:?}");
+
+    let lines: Vec<_> = stdout.lines().collect();
+    let has = |line: &str| lines.contains(&line);
+    assert!(has("DIR/main"), "{stdout}");
+    assert!(has("    libfoo.so => DIR/libfoo.so"), "{stdout}");
+    assert!(has("    libgone.so => not found"), "{stdout}");
+    assert!(has("    DIR/main: foo => DIR/libfoo.so"), "{stdout}");
+    assert!(
+        has("    DIR/main: __gmon_start__ => unresolved (weak)"),
+        "{stdout}"
+    );
+
+    let unresolved: Vec<_> = lines
+        .iter()
+        .skip_while(|line| **line != "unresolved symbols:")
+        .skip(1)
+        .collect();
+    assert_eq!(unresolved.len(), 2, "{stdout}");
+    assert!(unresolved.contains(&&"    DIR/main: bar"), "{stdout}");
+    assert!(unresolved.contains(&&"    DIR/main: gone"), "{stdout}");
+}
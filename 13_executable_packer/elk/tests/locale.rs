@@ -0,0 +1,114 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Builds `source` with gcc in `dir`
+fn compile(dir: &Path, name: &str, source: &str) -> PathBuf {
+    let src = format!("{name}.c");
+    std::fs::write(dir.join(&src), source).unwrap();
+    gcc(dir, &[&src, "-o", name]);
+    dir.join(name)
+}
+
+#[test]
+fn setlocale_and_grouping() {
+    let dir = temp_dir("locale");
+
+    // glibc's locale init, malloc and stdio, flushed by exit, all unpatched
+    let source = r#"
+#include <locale.h>
+#include <stdio.h>
+int main(void) {
+    if (!setlocale(LC_ALL, "")) {
+        puts("setlocale failed");
+        return 1;
+    }
+    printf("grouped: %'d\n", 1234567);
+    return 0;
+}
+"#;
+    let program = compile(&dir, "grouping", source);
+
+    // a locale with thousands separators, which minimal systems don't ship
+    let locale = dir.join("en_US.UTF-8");
+    let status = Command::new("localedef")
+        .args(["-i", "en_US", "-f", "UTF-8"])
+        .arg(&locale)
+        .status()
+        .expect("localedef is needed to build the en_US.UTF-8 locale");
+    assert!(
+        status.success(),
+        "localedef failed, are glibc's locale sources (i18n/locales) installed?"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .arg("run")
+        .arg(&program)
+        .env("LOCPATH", &dir)
+        .env("LC_ALL", "en_US.UTF-8")
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        stdout.lines().any(|line| line == "grouped: 1,234,567"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn glibc_layout_is_recognized() {
+    let version = Command::new("getconf")
+        .arg("GNU_LIBC_VERSION")
+        .output()
+        .expect("getconf is needed to tell the glibc version");
+    let version = String::from_utf8_lossy(&version.stdout);
+    let version = version.trim().trim_start_matches("glibc ");
+    // elk knows 2.36's layout, and falls back to stubs for any other version
+    let known = version == "2.36";
+
+    let dir = temp_dir("layout");
+    let source = r#"
+#include <ctype.h>
+#include <stdio.h>
+int main(void) {
+    printf("upper=%c\n", toupper('a'));
+    return 0;
+}
+"#;
+    let program = compile(&dir, "layout", source);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .arg("run")
+        .arg(&program)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(stdout.lines().any(|line| line == "upper=A"), "{stdout}");
+    // a mismatch on the version elk was written for means the layout is stale
+    assert_eq!(
+        stdout.contains("Populating _rtld_global_ro"),
+        known,
+        "{version}: {stdout}"
+    );
+    assert_eq!(
+        stdout.contains("unknown glibc version"),
+        !known,
+        "{version}: {stdout}"
+    );
+    if !known {
+        assert!(
+            stdout.contains(&format!("libc is glibc {version}, expected 2.36")),
+            "{version}: {stdout}"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::process::Command;
+
+fn elk_maps(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .arg("maps")
+        .arg(std::process::id().to_string())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn this_process() {
+    let exe = std::env::current_exe().unwrap().canonicalize().unwrap();
+    let exe = exe.to_str().unwrap();
+    // this very function is somewhere in the test binary's `.text`
+    let here = this_process as fn() as usize as u64;
+
+    let listing = elk_maps(&[]);
+    assert!(
+        listing
+            .lines()
+            .any(|line| line.starts_with(&format!("{exe} (RSS "))),
+        "{listing}"
+    );
+    assert!(listing.contains(" .text "), "{listing}");
+
+    let json: serde_json::Value = serde_json::from_str(&elk_maps(&["--json"])).unwrap();
+    let objects = json.as_array().unwrap();
+    assert!(objects.iter().any(|object| object["object"] == "[stack]"));
+    let object = objects
+        .iter()
+        .find(|object| object["object"] == exe)
+        .unwrap();
+    assert!(object["rss"].as_u64().unwrap() > 0, "{object}");
+
+    let text = object["mappings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|mapping| mapping["perms"].as_str().unwrap().contains('x'))
+        .flat_map(|mapping| mapping["sections"].as_array().unwrap())
+        .find(|section| section["name"] == ".text")
+        .unwrap();
+    let start = text["addr"].as_u64().unwrap();
+    let end = start + text["size"].as_u64().unwrap();
+    assert!((start..end).contains(&here), "{here:x} isn't in {text}");
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::{path::Path, process::Command};
+
+/// Which copy of `libwhere.so` the program ended up with, under elk
+fn run(dir: &Path, program: &str, library_path: Option<&str>, flag: Option<&str>) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_elk"));
+    command.current_dir(dir).arg("run");
+    if let Some(flag) = flag {
+        command.args(["--library-path", flag]);
+    }
+    command.arg(program).env_remove("LD_LIBRARY_PATH");
+    if let Some(library_path) = library_path {
+        command.env("LD_LIBRARY_PATH", library_path);
+    }
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("where="))
+        .unwrap_or_else(|| panic!("{output:?}"))
+        .to_string()
+}
+
+#[test]
+fn search_order() {
+    let dir = temp_dir("search");
+    // three copies of the same library, telling where they were found
+    for copy in ["a", "b", "c"] {
+        std::fs::create_dir_all(dir.join(copy)).unwrap();
+        let source = format!("const char *where(void) {{ return \"{copy}\"; }}\n");
+        std::fs::write(dir.join(copy).join("where.c"), source).unwrap();
+        gcc(
+            &dir.join(copy),
+            &["-shared", "-fPIC", "where.c", "-o", "libwhere.so"],
+        );
+    }
+    let main = r#"
+#include <stdio.h>
+const char *where(void);
+int main(void) {
+    printf("where=%s\n", where());
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    for (program, tags) in [
+        ("rpath", "--disable-new-dtags"),
+        ("runpath", "--enable-new-dtags"),
+    ] {
+        let flags = format!("-Wl,{tags},-rpath,$ORIGIN/a");
+        gcc(
+            &dir,
+            &["main.c", "-o", program, "-La", "-lwhere", flags.as_str()],
+        );
+    }
+
+    let b = dir.join("b");
+    let b = b.to_str().unwrap();
+    let results = [
+        // RPATH comes before LD_LIBRARY_PATH...
+        run(&dir, "./rpath", None, None),
+        run(&dir, "./rpath", Some(b), None),
+        // ...RUNPATH after it
+        run(&dir, "./runpath", None, None),
+        run(&dir, "./runpath", Some(b), None),
+        // --library-path replaces LD_LIBRARY_PATH, and expands $ORIGIN
+        run(&dir, "./runpath", Some(b), Some("$ORIGIN/c")),
+    ];
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(results, ["a", "a", "a", "b", "c"]);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{gcc, temp_dir};
+use std::process::Command;
+
+#[test]
+fn global_dynamic_tls() {
+    let dir = temp_dir("tls");
+
+    // the library reaches its variables, and those of another library,
+    // through `__tls_get_addr` with DTPMOD64/DTPOFF64 slots in its GOT
+    let other = r#"
+__thread long other_value = 1000;
+"#;
+    let lib = r#"
+__thread int counter = 5;
+__thread char name[16] = "initial";
+static __thread int hidden = 40;
+extern __thread long other_value;
+int bump(void) { return ++counter + hidden; }
+const char *get_name(void) { return name; }
+long get_other(void) { return other_value++; }
+"#;
+    let main = r#"
+#include <stdio.h>
+int bump(void);
+const char *get_name(void);
+long get_other(void);
+extern __thread int counter;
+int main(void) {
+    int first = bump();
+    int second = bump();
+    counter += 10;
+    get_other();
+    printf("bump=%d,%d counter=%d name=%s other=%ld\n", first, second, counter, get_name(),
+           get_other());
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("other.c"), other).unwrap();
+    std::fs::write(dir.join("lib.c"), lib).unwrap();
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    let gd = "-ftls-model=global-dynamic";
+    let rpath = "-Wl,-rpath,$ORIGIN";
+    gcc(
+        &dir,
+        &["-shared", "-fPIC", gd, "other.c", "-o", "libother.so"],
+    );
+    gcc(
+        &dir,
+        &[
+            "-shared",
+            "-fPIC",
+            gd,
+            "lib.c",
+            "-o",
+            "libtls.so",
+            "-L.",
+            "-lother",
+            rpath,
+        ],
+    );
+    gcc(
+        &dir,
+        &["main.c", "-o", "main", "-L.", "-ltls", "-lother", rpath],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_elk"))
+        .current_dir(&dir)
+        .args(["run", "./main"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        stdout.lines().last(),
+        Some("bump=46,47 counter=17 name=initial other=1001"),
+        "{stdout}"
+    );
+}
@@ -0,0 +1,3 @@
+//! minipak is `no_std` and builds with its own nightly toolchain and
+//! `build-std`, so it can't host tests that need `std`. The ones in `tests/`
+//! build it in its own workspace and run the binary.
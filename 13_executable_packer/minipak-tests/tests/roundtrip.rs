@@ -0,0 +1,336 @@
+use std::{
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::OnceLock,
+};
+
+const CODECS: [&str; 4] = ["lz4", "zstd-like", "lzma-like", "none"];
+
+/// Runs gcc in `dir`. The guests are C programs, so a missing gcc fails
+/// the test rather than skipping it
+fn gcc(dir: &Path, args: &[&str]) {
+    let status = Command::new("gcc")
+        .current_dir(dir)
+        .args(args)
+        .status()
+        .expect("gcc is needed to build the guests");
+    assert!(status.success(), "gcc {args:?} failed");
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("minipak-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Why minipak can't be built here, if it can't: its `rust-toolchain.toml`
+/// asks for nightly, and `build-std` needs that toolchain's `rust-src`
+fn missing_toolchain(workspace: &Path) -> Option<String> {
+    let rustc = |args: &[&str]| {
+        Command::new("rustc")
+            .current_dir(workspace)
+            .env_remove("RUSTUP_TOOLCHAIN")
+            .args(args)
+            .output()
+    };
+    let version = match rustc(&["--version"]) {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => return Some(String::from_utf8_lossy(&output.stderr).trim().into()),
+        Err(e) => return Some(format!("cannot run rustc: {e}")),
+    };
+    if !String::from_utf8_lossy(&version).contains("nightly") {
+        return Some(format!(
+            "{} is not a nightly toolchain",
+            String::from_utf8_lossy(&version).trim()
+        ));
+    }
+    let sysroot = rustc(&["--print", "sysroot"]).ok()?;
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&sysroot.stdout).trim());
+    if !sysroot.join("lib/rustlib/src/rust/library/core").is_dir() {
+        return Some("the nightly toolchain has no rust-src component".into());
+    }
+    None
+}
+
+/// Builds minipak in its own workspace, once. None, after saying why, if
+/// the toolchain it needs isn't installed
+fn minipak_path() -> Option<&'static Path> {
+    static MINIPAK: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    let minipak = MINIPAK.get_or_init(|| {
+        let workspace: PathBuf = [env!("CARGO_MANIFEST_DIR"), "../minipak"].iter().collect();
+        if let Some(why) = missing_toolchain(&workspace) {
+            return Err(why);
+        }
+        let status = Command::new("cargo")
+            .current_dir(&workspace)
+            .arg("build")
+            // so that its `rust-toolchain.toml` picks nightly
+            .env_remove("RUSTUP_TOOLCHAIN")
+            .env_remove("CARGO_TARGET_DIR")
+            .status()
+            .unwrap();
+        assert!(status.success(), "building minipak failed");
+        Ok(workspace.join("target/x86_64-unknown-linux-gnu/debug/minipak"))
+    });
+    match minipak {
+        Ok(path) => Some(path),
+        Err(why) => {
+            eprintln!("skipping, minipak can't be built here: {why}");
+            None
+        }
+    }
+}
+
+fn minipak(dir: &Path, args: &[&str]) -> Output {
+    Command::new(minipak_path().unwrap())
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A dynamically-linked program that says which arguments it got
+fn build_guest(dir: &Path) {
+    let main = r#"
+#include <stdio.h>
+int main(int argc, char **argv) {
+    printf("guest got %d: %s\n", argc, argv[1]);
+    return 7;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    gcc(dir, &["main.c", "-o", "guest"]);
+}
+
+#[test]
+fn pack_unpack_round_trip() {
+    if minipak_path().is_none() {
+        return;
+    }
+    let dir = temp_dir("roundtrip");
+    build_guest(&dir);
+    let original = std::fs::read(dir.join("guest")).unwrap();
+
+    for codec in CODECS {
+        let packed = format!("packed-{codec}");
+        let pack = minipak(&dir, &["guest", "-o", &packed, "--codec", codec]);
+        assert!(pack.status.success(), "{codec}: {pack:?}");
+
+        let run = Command::new(dir.join(&packed)).arg("hi").output().unwrap();
+        let stdout = String::from_utf8_lossy(&run.stdout);
+        assert_eq!(run.status.code(), Some(7), "{codec}: {run:?}");
+        assert!(
+            stdout.lines().any(|line| line == "guest got 2: hi"),
+            "{codec}: {stdout}"
+        );
+
+        let unpacked = format!("unpacked-{codec}");
+        let unpack = minipak(&dir, &["--unpack", &packed, "-o", &unpacked]);
+        assert!(unpack.status.success(), "{codec}: {unpack:?}");
+        let round_tripped = std::fs::read(dir.join(&unpacked)).unwrap();
+        assert!(round_tripped == original, "{codec}: unpacked guest differs");
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Deterministic filler with incompressible bytes, text, zeros, and
+/// repeats from further back than zstd-like's (2 MiB) and lzma-like's
+/// (4 MiB) windows. Several megabytes, so many 128 KiB zstd-like blocks
+fn large_blob() -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    };
+    let noise = random(1 << 20);
+    let words = [
+        "elk ", "minipak ", "stage1 ", "stage2 ", "guest\n", "pixie ",
+    ];
+    let text: Vec<u8> = random(1 << 16)
+        .iter()
+        .flat_map(|&b| words[b as usize % words.len()].bytes())
+        .collect();
+
+    let mut blob = noise.clone();
+    blob.extend(&text);
+    blob.extend(vec![0; 256 << 10]);
+    blob.extend(random(5 << 19));
+    // 3 MiB after the first copy, then 4.5 MiB after
+    blob.extend(&text);
+    blob.extend(&noise);
+    blob
+}
+
+/// 64-bit FNV-1a, which the large guest prints for its blob
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A program embedding `large_blob`, which prints its length and hash.
+/// Returns what it should print
+fn build_large_guest(dir: &Path) -> String {
+    let blob = large_blob();
+    std::fs::write(dir.join("blob.bin"), &blob).unwrap();
+    let main = r#"
+#include <stdint.h>
+#include <stdio.h>
+extern const unsigned char blob[], blob_end[];
+__asm__(".section .rodata\n"
+        ".global blob\n.global blob_end\n"
+        "blob: .incbin \"blob.bin\"\n"
+        "blob_end:\n"
+        ".previous\n");
+int main(void) {
+    uint64_t hash = 0xcbf29ce484222325ull;
+    for (const unsigned char *p = blob; p < blob_end; p++) {
+        hash = (hash ^ *p) * 0x100000001b3ull;
+    }
+    printf("blob %zu %016llx\n", (size_t)(blob_end - blob), (unsigned long long)hash);
+    return 0;
+}
+"#;
+    std::fs::write(dir.join("main.c"), main).unwrap();
+    gcc(dir, &["main.c", "-o", "guest"]);
+    format!("blob {} {:016x}", blob.len(), fnv1a(&blob))
+}
+
+/// Where the manifest stores the compressed guest's length, the guest's
+/// offset and its length. The end marker points at the manifest: its magic,
+/// stage2's offset and length, then the guest's
+fn guest_resource(packed: &[u8]) -> (usize, usize, usize) {
+    let word = |at: usize| u64::from_le_bytes(packed[at..][..8].try_into().unwrap()) as usize;
+    let guest = word(packed.len() - 8) + 24;
+    (guest + 8, word(guest), word(guest + 8))
+}
+
+/// Packs a multi-megabyte guest with every codec, runs and unpacks it, then
+/// checks that a truncated or corrupted guest is refused by both `--unpack`
+/// and stage2.
+/// Packing takes a while, so both use the same packed executables
+#[test]
+fn large_guest() {
+    if minipak_path().is_none() {
+        return;
+    }
+    let dir = temp_dir("large");
+    let expected = build_large_guest(&dir);
+    let original = std::fs::read(dir.join("guest")).unwrap();
+
+    for codec in CODECS {
+        let packed = format!("packed-{codec}");
+        let pack = minipak(&dir, &["guest", "-o", &packed, "--codec", codec]);
+        assert!(pack.status.success(), "{codec}: {pack:?}");
+
+        let run = Command::new(dir.join(&packed)).output().unwrap();
+        let stdout = String::from_utf8_lossy(&run.stdout);
+        assert!(run.status.success(), "{codec}: {run:?}");
+        assert!(
+            stdout.lines().any(|line| line == expected),
+            "{codec}: {stdout}"
+        );
+
+        let unpacked = format!("unpacked-{codec}");
+        let unpack = minipak(&dir, &["--unpack", &packed, "-o", &unpacked]);
+        assert!(unpack.status.success(), "{codec}: {unpack:?}");
+        let round_tripped = std::fs::read(dir.join(&unpacked)).unwrap();
+        assert!(round_tripped == original, "{codec}: unpacked guest differs");
+
+        let packed = std::fs::read(dir.join(&packed)).unwrap();
+        let (len_at, offset, len) = guest_resource(&packed);
+        let damaged = |name: &str, packed: Vec<u8>| {
+            std::fs::write(dir.join(name), packed).unwrap();
+            std::fs::set_permissions(dir.join(name), Permissions::from_mode(0o755)).unwrap();
+            let unpack = minipak(&dir, &["--unpack", name, "-o", "out"]);
+            assert_eq!(unpack.status.code(), Some(1), "{codec} {name}: {unpack:?}");
+            let run = Command::new(dir.join(name)).output().unwrap();
+            assert!(!run.status.success(), "{codec} {name}: {run:?}");
+            String::from_utf8_lossy(&unpack.stdout).into_owned()
+        };
+
+        // the stream stops halfway through: the size can't match
+        let mut truncated = packed.clone();
+        truncated[len_at..][..8].copy_from_slice(&(len as u64 / 2).to_le_bytes());
+        let stdout = damaged("truncated", truncated);
+        let error = if codec == "none" {
+            "decompressed guest is"
+        } else {
+            &format!("corrupt {codec} stream")
+        };
+        assert!(stdout.contains(error), "{codec}: {stdout}");
+
+        // flipped bytes may still decode, but not to the same guest
+        let mut corrupt = packed.clone();
+        for b in &mut corrupt[offset + len / 2..][..64] {
+            *b ^= 0x5a;
+        }
+        let stdout = damaged("corrupt", corrupt);
+        assert!(stdout.contains("Error: "), "{codec}: {stdout}");
+
+        // a guest ending past the file, or past the address space
+        let out_of_bounds = format!("resource of {} bytes at offset {offset}", len * 2);
+        let mut past_end = packed.clone();
+        past_end[len_at..][..8].copy_from_slice(&(len as u64 * 2).to_le_bytes());
+        let stdout = damaged("past-end", past_end);
+        assert!(stdout.contains(&out_of_bounds), "{codec}: {stdout}");
+
+        let mut overflowing = packed;
+        overflowing[len_at..][..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let stdout = damaged("overflowing", overflowing);
+        assert!(
+            stdout.contains(&format!(
+                "resource of {} bytes at offset {offset}",
+                u64::MAX
+            )),
+            "{codec}: {stdout}"
+        );
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn inspect() {
+    if minipak_path().is_none() {
+        return;
+    }
+    let dir = temp_dir("inspect");
+    build_guest(&dir);
+    let guest_len = std::fs::metadata(dir.join("guest")).unwrap().len();
+    let pack = minipak(&dir, &["guest", "-o", "packed", "--codec", "lzma-like"]);
+    assert!(pack.status.success(), "{pack:?}");
+
+    let inspect = minipak(&dir, &["--inspect", "packed"]);
+    let stdout = String::from_utf8_lossy(&inspect.stdout);
+    assert!(inspect.status.success(), "{inspect:?}");
+    for part in ["stage1", "stage2", "guest", "manifest", "end marker"] {
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line.trim_start().starts_with(part)),
+            "{part}: {stdout}"
+        );
+    }
+    assert!(
+        stdout.contains(&format!(
+            "Guest: {guest_len} bytes, compressed with lzma-like"
+        )),
+        "{stdout}"
+    );
+    assert!(stdout.contains("interpreter /"), "{stdout}");
+
+    // the guest itself has no manifest
+    let inspect = minipak(&dir, &["--inspect", "guest"]);
+    let stdout = String::from_utf8_lossy(&inspect.stdout);
+    assert_eq!(inspect.status.code(), Some(1), "{inspect:?}");
+    assert!(stdout.contains("no valid manifest found"), "{stdout}");
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -153,6 +153,34 @@ pub unsafe fn fstat(fd: FileDescriptor, buf: *mut Stat) -> u64 {
     rax
 }
 
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct Timespec {
+    pub sec: u64,
+    pub nsec: u64,
+}
+
+/// # Safety
+/// Calls into the kernel.
+#[inline(always)]
+pub unsafe fn clock_gettime(clock: u64, ts: *mut Timespec) -> u64 {
+    let syscall_number: u64 = 228;
+    let mut rax = syscall_number;
+    unsafe {
+        asm!(
+            "syscall",
+            inout("rax") rax,
+            in("rdi") clock,
+            in("rsi") ts,
+            lateout("rcx") _, lateout("r11") _,
+            options(nostack),
+        );
+    }
+    rax
+}
+
 /// # Safety
 /// Calls into the kernel.
 #[inline(always)]
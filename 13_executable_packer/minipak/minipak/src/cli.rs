@@ -1,6 +1,7 @@
 use alloc::borrow::Cow;
 use core::fmt::Display;
 use encore::prelude::*;
+use pixie::Codec;
 
 extern crate alloc;
 
@@ -13,17 +14,42 @@ pub struct Error {
 impl Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         writeln!(f, "Error: {}", self.message)?;
-        writeln!(f, "Usage: {} input -o output", self.program_name)?;
+        writeln!(
+            f,
+            "Usage: {} input -o output [--codec lz4|zstd-like|lzma-like|none] [--stats]",
+            self.program_name
+        )?;
+        writeln!(f, "       {} --unpack packed -o output", self.program_name)?;
+        writeln!(f, "       {} --inspect packed", self.program_name)?;
         Ok(())
     }
 }
 
 #[derive(Debug)]
 pub struct Args {
-    /// The executable to compress
+    /// The executable to compress, or a packed executable
     pub input: &'static str,
-    /// Where to write the compressed executable on disk
-    pub output: &'static str,
+    pub mode: Mode,
+}
+
+#[derive(Debug)]
+pub enum Mode {
+    /// Compress `input` into a packed executable
+    Pack {
+        /// Where to write the compressed executable on disk
+        output: &'static str,
+        /// How to compress the executable
+        codec: Codec,
+        /// Whether to compare every codec on the executable
+        stats: bool,
+    },
+    /// Write the executable packed in `input` back out, as it was
+    Unpack {
+        /// Where to write the decompressed executable on disk
+        output: &'static str,
+    },
+    /// Describe what's in a packed executable
+    Inspect,
 }
 
 impl Args {
@@ -62,10 +88,28 @@ impl Args {
             }
         }
 
-        Ok(Args {
-            input: raw.input.ok_or_else(|| err("Missing input".into()))?,
-            output: raw.output.ok_or_else(|| err("Missing output".into()))?,
-        })
+        let input = raw.input.ok_or_else(|| err("Missing input".into()))?;
+        let output = || raw.output.ok_or_else(|| err("Missing output".into()));
+        if (raw.unpack || raw.inspect) && (raw.codec.is_some() || raw.stats) {
+            return Err(err("--codec and --stats only apply when packing".into()));
+        }
+
+        let mode = match (raw.unpack, raw.inspect) {
+            (true, true) => return Err(err("--unpack and --inspect are exclusive".into())),
+            (true, false) => Mode::Unpack { output: output()? },
+            (false, true) => {
+                if raw.output.is_some() {
+                    return Err(err("--inspect doesn't write anything".into()));
+                }
+                Mode::Inspect
+            }
+            (false, false) => Mode::Pack {
+                output: output()?,
+                codec: raw.codec.unwrap_or(Codec::Lz4),
+                stats: raw.stats,
+            },
+        };
+        Ok(Args { input, mode })
     }
 
     fn parse_flag(
@@ -85,6 +129,27 @@ impl Args {
                 raw.output = Some(output);
                 Ok(())
             }
+            "--codec" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| err("Missing codec name after --codec".into()))?;
+                let codec = Codec::from_name(name)
+                    .ok_or_else(|| err(format!("Unknown codec {name}").into()))?;
+                raw.codec = Some(codec);
+                Ok(())
+            }
+            "--stats" => {
+                raw.stats = true;
+                Ok(())
+            }
+            "--unpack" => {
+                raw.unpack = true;
+                Ok(())
+            }
+            "--inspect" => {
+                raw.inspect = true;
+                Ok(())
+            }
             x => Err(err(format!("Unknown flag {x}").into())),
         }
     }
@@ -94,4 +159,8 @@ impl Args {
 pub struct ArgsRaw {
     pub input: Option<&'static str>,
     pub output: Option<&'static str>,
+    pub codec: Option<Codec>,
+    pub stats: bool,
+    pub unpack: bool,
+    pub inspect: bool,
 }
@@ -1,5 +1,5 @@
 use encore::prelude::*;
-use pixie::{PixieError, deku::DekuError};
+use pixie::{Codec, PixieError, deku::DekuError};
 
 use derive_more::{Debug, Display};
 
@@ -11,6 +11,8 @@ pub enum Error {
     Deku(DekuError),
     #[display("pixie error: `{_0}`")]
     Pixie(PixieError),
+    #[display("{_0} did not round-trip")]
+    RoundTrip(Codec),
 }
 
 impl From<EncoreError> for Error {
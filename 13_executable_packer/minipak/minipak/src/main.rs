@@ -1,19 +1,21 @@
 #![no_std]
 #![no_main]
 
-use core::{arch::naked_asm, ops::Range};
+use core::{arch::naked_asm, ops::Range, time::Duration};
 
 extern crate encore;
 
+use cli::Mode;
 use encore::prelude::*;
 use error::Error;
 use pixie::{
-    align_hull, ElfClass, ElfMachine, ElfType, EndMarker, Endianness, Manifest, MappedObject,
-    Object, ObjectHeader, OsAbi, ProgramHeader, Resource, SegmentType, Writer,
+    Codec, ElfClass, ElfMachine, ElfType, EndMarker, Endianness, Manifest, MappedObject, Object,
+    ObjectHeader, OsAbi, ProgramHeader, Resource, SegmentType, Writer, align_hull, fnv1a,
 };
 
 mod cli;
 mod error;
+mod unpack;
 
 #[unsafe(naked)]
 #[unsafe(no_mangle)]
@@ -25,22 +27,36 @@ unsafe extern "C" fn _start() -> ! {
 unsafe extern "C" fn pre_main(stack_top: *mut u8) -> ! {
     unsafe {
         init_allocator();
-        main(Env::read(stack_top)).unwrap();
+        if let Err(e) = main(Env::read(stack_top)) {
+            println!("Error: {e}");
+            syscall::exit(1);
+        }
         syscall::exit(0);
     }
 }
 
 fn main(env: Env) -> Result<(), Error> {
     let args = cli::Args::parse(&env);
+    match args.mode {
+        Mode::Pack {
+            output,
+            codec,
+            stats,
+        } => pack(args.input, output, codec, stats),
+        Mode::Unpack { output } => unpack::unpack(args.input, output),
+        Mode::Inspect => unpack::inspect(args.input),
+    }
+}
 
-    println!("Packing quest {:?} into {:?}", args.input, args.output);
-    let guest = File::open(args.input)?;
+fn pack(input: &str, output_path: &str, codec: Codec, stats: bool) -> Result<(), Error> {
+    println!("Packing quest {input:?} into {output_path:?}");
+    let guest = File::open(input)?;
     let guest_map = guest.map()?;
     let guest = guest_map.as_ref();
     let guest = Object::new(guest)?;
 
     let guest_hull = guest.segments().load_convex_hull()?;
-    let mut output = Writer::new(args.output, 0o755)?;
+    let mut output = Writer::new(output_path, 0o755)?;
     relink_stage1(guest_hull, &mut output)?;
 
     let stage2_slice = include_bytes!(concat!(
@@ -52,8 +68,8 @@ fn main(env: Env) -> Result<(), Error> {
     output.write_all(stage2_slice)?;
     output.align(0x8)?;
 
-    println!("Compressing guest...");
-    let compressed_guest = lz4_flex::compress_prepend_size(guest_map.as_ref());
+    println!("Compressing guest with {codec}...");
+    let compressed_guest = codec.compress(guest_map.as_ref())?;
     let guest_offset = output.offset();
     println!("copying compressed quest at 0x{guest_offset:x}");
     output.write_all(&compressed_guest)?;
@@ -70,6 +86,9 @@ fn main(env: Env) -> Result<(), Error> {
             offset: guest_offset as _,
             len: compressed_guest.len(),
         },
+        codec,
+        guest_size: guest_map.as_ref().len(),
+        guest_hash: fnv1a(guest_map.as_ref()),
     };
     output.write_deku(&manifest)?;
     output.align(0x8)?;
@@ -80,11 +99,52 @@ fn main(env: Env) -> Result<(), Error> {
     };
     output.write_deku(&end_marker)?;
 
-    println!("Written to ({})", args.output);
+    println!("Written to ({output_path})");
+
+    if stats {
+        print_stats(guest_map.as_ref())?;
+    }
 
     Ok(())
 }
 
+/// Compresses the guest with every codec, to compare sizes and timings
+fn print_stats(guest: &[u8]) -> Result<(), Error> {
+    println!();
+    println!(
+        "{:<10} {:>12} {:>8} {:>14} {:>14}",
+        "codec", "size", "ratio", "compress", "decompress"
+    );
+    for codec in Codec::ALL {
+        let start = now();
+        let compressed = codec.compress(guest)?;
+        let compressed_at = now();
+        let decompressed = codec.decompress(&compressed)?;
+        let decompressed_at = now();
+        if decompressed != guest {
+            return Err(Error::RoundTrip(codec));
+        }
+
+        println!(
+            "{:<10} {:>12} {:>7.2}% {:>11.3} ms {:>11.3} ms",
+            codec.to_string(),
+            compressed.len(),
+            compressed.len() as f64 * 100.0 / guest.len() as f64,
+            (compressed_at - start).as_secs_f64() * 1000.0,
+            (decompressed_at - compressed_at).as_secs_f64() * 1000.0,
+        );
+    }
+    Ok(())
+}
+
+fn now() -> Duration {
+    let mut ts = syscall::Timespec::default();
+    unsafe {
+        syscall::clock_gettime(syscall::CLOCK_MONOTONIC, &mut ts);
+    }
+    Duration::new(ts.sec, ts.nsec as u32)
+}
+
 fn relink_stage1(guest_hull: Range<u64>, writer: &mut Writer) -> Result<(), Error> {
     let obj = Object::new(include_bytes!(concat!(
         env!("OUT_DIR"),
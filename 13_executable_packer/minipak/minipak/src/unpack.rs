@@ -0,0 +1,86 @@
+use encore::prelude::*;
+use pixie::{EndMarker, Manifest, Object, ProgramHeader, SegmentType, Writer};
+
+use crate::error::Error;
+
+/// Writes the guest of a packed executable back out, byte for byte
+pub fn unpack(input: &str, output: &str) -> Result<(), Error> {
+    println!("Unpacking {input:?} into {output:?}");
+    let file = File::open(input)?;
+    let map = file.map()?;
+    let slice = map.as_ref();
+    let manifest = Manifest::read_from_full_slice(slice)?;
+
+    println!("Decompressing guest with {}...", manifest.codec);
+    let guest = manifest.decompress_guest(slice)?;
+    let mut writer = Writer::new(output, 0o755)?;
+    writer.write_all(&guest)?;
+
+    println!("Written {} bytes to ({output})", guest.len());
+    Ok(())
+}
+
+/// Prints the layout of a packed executable and a summary of its guest
+pub fn inspect(input: &str) -> Result<(), Error> {
+    let file = File::open(input)?;
+    let map = file.map()?;
+    let slice = map.as_ref();
+    let end_marker = EndMarker::read_from_full_slice(slice)?;
+    let manifest = Manifest::read_from_full_slice(slice)?;
+    let guest = manifest.decompress_guest(slice)?;
+
+    println!("{input}: {} bytes", slice.len());
+    let end_marker_offset = slice.len() - EndMarker::SIZE;
+    let parts = [
+        ("stage1", 0..manifest.stage2.offset),
+        ("stage2", manifest.stage2.as_range()?),
+        ("guest", manifest.guest.as_range()?),
+        ("manifest", end_marker.manifest_offset..end_marker_offset),
+        ("end marker", end_marker_offset..slice.len()),
+    ];
+    for (name, range) in parts {
+        println!(
+            "  {:<10} 0x{:08x}..0x{:08x} {:>10} bytes",
+            name,
+            range.start,
+            range.end,
+            range.len()
+        );
+    }
+
+    println!(
+        "Guest: {} bytes, compressed with {} to {} bytes ({:.2}%)",
+        guest.len(),
+        manifest.codec,
+        manifest.guest.len,
+        manifest.guest.len as f64 * 100.0 / guest.len() as f64,
+    );
+
+    let object = Object::new(&guest)?;
+    let header = object.header();
+    println!(
+        "  {:?} for {:?}, entry point 0x{:x}",
+        header.typ, header.machine, header.entry_point
+    );
+    if let Ok(interp) = object.segments().find(SegmentType::Interp) {
+        let interp = core::str::from_utf8(interp.slice()).unwrap_or("?");
+        println!("  interpreter {}", interp.trim_end_matches('\0'));
+    }
+    println!("  load hull {:x?}", object.segments().load_convex_hull()?);
+    for segment in object.segments().all() {
+        let ph = segment.header();
+        let flag = |bit, c| if ph.flags & bit != 0 { c } else { '-' };
+        println!(
+            "  {:<12} {}{}{} 0x{:08x}..0x{:08x} file 0x{:x}+0x{:x}",
+            format!("{:?}", ph.typ),
+            flag(ProgramHeader::READ, 'r'),
+            flag(ProgramHeader::WRITE, 'w'),
+            flag(ProgramHeader::EXECUTE, 'x'),
+            ph.vaddr,
+            ph.vaddr + ph.memsz,
+            ph.offset,
+            ph.filesz,
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use encore::prelude::*;
+
+/// Writes bit fields, least significant bit first
+#[derive(Default)]
+pub struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    pub fn new(out: Vec<u8>) -> Self {
+        Self {
+            out,
+            ..Default::default()
+        }
+    }
+
+    /// Appends the `count` low bits of `value`, `count` being at most 32
+    pub fn put(&mut self, value: u32, count: u32) {
+        self.acc |= (value as u64) << self.len;
+        self.len += count;
+        while self.len >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    /// Pads the last byte with zeros
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Reads what `BitWriter` wrote
+pub struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    acc: u64,
+    len: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            pos: 0,
+            acc: 0,
+            len: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        while self.len <= 56 {
+            // past the end reads zeros, see `overran`
+            let byte = self.input.get(self.pos).copied().unwrap_or_default();
+            self.acc |= (byte as u64) << self.len;
+            self.pos += 1;
+            self.len += 8;
+        }
+    }
+
+    /// Returns the next `count` bits without consuming them
+    pub fn peek(&mut self, count: u32) -> u32 {
+        if self.len < count {
+            self.refill();
+        }
+        (self.acc & ((1 << count) - 1)) as u32
+    }
+
+    pub fn consume(&mut self, count: u32) {
+        self.acc >>= count;
+        self.len -= count;
+    }
+
+    /// Reads `count` bits, at most 32
+    pub fn get(&mut self, count: u32) -> u32 {
+        let value = self.peek(count);
+        self.consume(count);
+        value
+    }
+
+    /// Whether more bits were read than the input had
+    pub fn overran(&self) -> bool {
+        self.pos * 8 - self.len as usize > self.input.len() * 8
+    }
+}
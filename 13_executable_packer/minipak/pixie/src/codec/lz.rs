@@ -0,0 +1,186 @@
+use alloc::vec;
+use encore::prelude::*;
+
+/// Shortest match worth encoding: hashes cover this many bytes
+pub const MIN_MATCH: usize = 4;
+
+const HASH_LOG: u32 = 17;
+const NONE: u32 = u32::MAX;
+
+/// A run of literals followed by a match, LZ77 style
+#[derive(Debug, Clone, Copy)]
+pub struct Sequence {
+    /// How many bytes to copy from the input as-is
+    pub literals: u32,
+    /// How many bytes to copy from `offset` bytes back, zero for none (only
+    /// the last sequence has no match)
+    pub len: u32,
+    pub offset: u32,
+}
+
+/// How hard to look for matches
+pub struct Params {
+    /// Matches reach back at most `1 << window_log` bytes
+    pub window_log: u32,
+    /// How many candidates to try per position
+    pub depth: usize,
+    pub max_len: usize,
+    /// Whether to look one byte ahead for a longer match before taking one
+    pub lazy: bool,
+}
+
+/// Finds repeated byte strings with hash chains
+struct MatchFinder<'a> {
+    input: &'a [u8],
+    /// The last position seen for each hash
+    head: Vec<u32>,
+    /// The previous position with the same hash, for positions in the window
+    prev: Vec<u32>,
+    mask: usize,
+    /// Positions below this one are in the chains
+    inserted: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(input: &'a [u8], window_log: u32) -> Self {
+        let window = (1_usize << window_log).min(input.len().next_power_of_two().max(1));
+        Self {
+            input,
+            head: vec![NONE; 1 << HASH_LOG],
+            prev: vec![NONE; window],
+            mask: window - 1,
+            inserted: 0,
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let word = u32::from_le_bytes(self.input[pos..][..4].try_into().unwrap());
+        (word.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+    }
+
+    /// Adds every position up to `pos` (excluded) to the chains
+    fn insert_until(&mut self, pos: usize) {
+        let end = pos.min(self.input.len().saturating_sub(MIN_MATCH - 1));
+        while self.inserted < end {
+            let h = self.hash(self.inserted);
+            self.prev[self.inserted & self.mask] = self.head[h];
+            self.head[h] = self.inserted as u32;
+            self.inserted += 1;
+        }
+    }
+
+    /// Returns the longest match for `pos` as `(offset, len)`
+    fn find(&mut self, pos: usize, params: &Params) -> Option<(usize, usize)> {
+        self.insert_until(pos);
+        let max_len = params.max_len.min(self.input.len() - pos);
+        if max_len < MIN_MATCH {
+            return None;
+        }
+
+        let mut best: Option<(usize, usize)> = None;
+        let mut best_len = MIN_MATCH - 1;
+        let mut candidate = self.head[self.hash(pos)];
+        for _ in 0..params.depth {
+            if candidate == NONE {
+                break;
+            }
+            let candidate_pos = candidate as usize;
+            let offset = pos - candidate_pos;
+            if offset > self.mask {
+                break;
+            }
+            // one byte tells most candidates apart
+            if self.input[candidate_pos + best_len] == self.input[pos + best_len] {
+                let len = match_len(self.input, candidate_pos, pos, max_len);
+                if len > best_len {
+                    best_len = len;
+                    best = Some((offset, len));
+                    if len == max_len {
+                        break;
+                    }
+                }
+            }
+            let next = self.prev[candidate_pos & self.mask];
+            // older entries of the ring were overwritten by newer positions
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        best
+    }
+}
+
+/// How many bytes match at `a` and `b`, up to `max_len`
+fn match_len(input: &[u8], a: usize, b: usize, max_len: usize) -> usize {
+    input[a..]
+        .iter()
+        .zip(&input[b..b + max_len])
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+/// The best match at `pos`: the last offset again is cheaper to encode, so
+/// it wins unless the other one is clearly longer
+fn best_match(
+    finder: &mut MatchFinder<'_>,
+    pos: usize,
+    rep: usize,
+    params: &Params,
+) -> Option<(usize, usize)> {
+    let found = finder.find(pos, params);
+    let max_len = params.max_len.min(finder.input.len() - pos);
+    if rep == 0 || rep > pos || max_len < MIN_MATCH {
+        return found;
+    }
+    let rep_len = match_len(finder.input, pos - rep, pos, max_len);
+    match found {
+        _ if rep_len < MIN_MATCH => found,
+        Some((_, len)) if len > rep_len + 1 => found,
+        _ => Some((rep, rep_len)),
+    }
+}
+
+/// Splits `input` into sequences
+pub fn parse(input: &[u8], params: &Params) -> Vec<Sequence> {
+    let mut finder = MatchFinder::new(input, params.window_log);
+    let mut sequences = Vec::new();
+    let mut literals_start = 0;
+    let mut pos = 0;
+    let mut rep = 0;
+
+    while pos + MIN_MATCH <= input.len() {
+        let Some(mut found) = best_match(&mut finder, pos, rep, params) else {
+            pos += 1;
+            continue;
+        };
+        while params.lazy && pos + 1 + MIN_MATCH <= input.len() {
+            match best_match(&mut finder, pos + 1, rep, params) {
+                Some(next) if next.1 > found.1 => {
+                    pos += 1;
+                    found = next;
+                }
+                _ => break,
+            }
+        }
+
+        let (offset, len) = found;
+        sequences.push(Sequence {
+            literals: (pos - literals_start) as u32,
+            len: len as u32,
+            offset: offset as u32,
+        });
+        rep = offset;
+        pos += len;
+        literals_start = pos;
+    }
+
+    if literals_start < input.len() || sequences.is_empty() {
+        sequences.push(Sequence {
+            literals: (input.len() - literals_start) as u32,
+            len: 0,
+            offset: 0,
+        });
+    }
+    sequences
+}
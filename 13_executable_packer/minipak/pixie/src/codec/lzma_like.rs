@@ -0,0 +1,492 @@
+//! LZ77 sequences, coded bit by bit with an adaptive binary range coder,
+//! the way LZMA does it: same coder, same state machine, same literal,
+//! length and distance models. Unlike LZMA there's only one repeat
+//! distance and no short (one byte) repeats, and matches are at least
+//! `MIN_MATCH` long.
+//!
+//! The stream is the decompressed size (u32 LE) followed by the range
+//! coder's output.
+
+use alloc::vec;
+use encore::prelude::*;
+
+use super::{
+    copy_match,
+    lz::{self, MIN_MATCH, Params},
+    read_size, write_size,
+};
+
+/// Longest match the length model can encode
+const MAX_LEN: usize = MIN_MATCH + 271;
+
+const PARAMS: Params = Params {
+    window_log: 22,
+    depth: 48,
+    max_len: MAX_LEN,
+    lazy: true,
+};
+
+const PROB_BITS: u32 = 11;
+const PROB_INIT: u16 = 1 << (PROB_BITS - 1);
+/// How fast probabilities adapt
+const MOVE_BITS: u32 = 5;
+const TOP: u32 = 1 << 24;
+
+const STATES: usize = 12;
+/// States from here on follow a match, where literals are coded against the
+/// byte at the repeat distance
+const FIRST_MATCH_STATE: usize = 7;
+const POS_STATES: usize = 4;
+/// Literals are coded in the context of the previous byte's top bits
+const LITERAL_CONTEXT_BITS: u32 = 3;
+/// Distance slots below this one have their low bits modeled
+const END_POS_MODEL: u32 = 14;
+const ALIGN_BITS: u32 = 4;
+
+fn next_state_literal(state: usize) -> usize {
+    [0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 4, 5][state]
+}
+
+fn next_state_match(state: usize) -> usize {
+    if state < FIRST_MATCH_STATE { 7 } else { 10 }
+}
+
+fn next_state_rep(state: usize) -> usize {
+    if state < FIRST_MATCH_STATE { 8 } else { 11 }
+}
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let sequences = lz::parse(input, &PARAMS);
+    let mut enc = Encoder::new(write_size(input));
+    let mut model = Model::new();
+    let mut state = 0;
+    let mut rep = 0;
+    let mut pos = 0;
+
+    for seq in sequences {
+        for _ in 0..seq.literals {
+            enc.bit(&mut model.is_match[state][pos % POS_STATES], 0);
+            let previous = if pos > 0 { input[pos - 1] } else { 0 };
+            let probs = model.literal_probs(previous);
+            if state >= FIRST_MATCH_STATE {
+                enc.matched_literal(probs, input[pos], input[pos - rep]);
+            } else {
+                enc.tree(probs, 8, input[pos] as u32);
+            }
+            state = next_state_literal(state);
+            pos += 1;
+        }
+        if seq.len == 0 {
+            continue;
+        }
+
+        let (len, offset) = (seq.len as usize, seq.offset as usize);
+        let pos_state = pos % POS_STATES;
+        enc.bit(&mut model.is_match[state][pos_state], 1);
+        if offset == rep {
+            enc.bit(&mut model.is_rep[state], 1);
+            model.rep_len.encode(&mut enc, len - MIN_MATCH, pos_state);
+            state = next_state_rep(state);
+        } else {
+            enc.bit(&mut model.is_rep[state], 0);
+            model.match_len.encode(&mut enc, len - MIN_MATCH, pos_state);
+            model.encode_distance(&mut enc, (offset - 1) as u32, len - MIN_MATCH);
+            rep = offset;
+            state = next_state_match(state);
+        }
+        pos += len;
+    }
+    enc.finish()
+}
+
+pub fn decompress(input: &[u8]) -> Option<Vec<u8>> {
+    let (size, input) = read_size(input)?;
+    let mut out = Vec::with_capacity(size);
+    let mut dec = Decoder::new(input)?;
+    let mut model = Model::new();
+    let mut state = 0;
+    let mut rep = 0;
+
+    while out.len() < size {
+        let pos_state = out.len() % POS_STATES;
+        if dec.bit(&mut model.is_match[state][pos_state]) == 0 {
+            let previous = out.last().copied().unwrap_or_default();
+            let probs = model.literal_probs(previous);
+            let byte = if state >= FIRST_MATCH_STATE {
+                let match_byte = *out.get(out.len().wrapping_sub(rep))?;
+                dec.matched_literal(probs, match_byte)
+            } else {
+                dec.tree(probs, 8) as u8
+            };
+            out.push(byte);
+            state = next_state_literal(state);
+            continue;
+        }
+
+        let len = if dec.bit(&mut model.is_rep[state]) == 1 {
+            state = next_state_rep(state);
+            model.rep_len.decode(&mut dec, pos_state)
+        } else {
+            let len = model.match_len.decode(&mut dec, pos_state);
+            rep = model.decode_distance(&mut dec, len)? as usize + 1;
+            state = next_state_match(state);
+            len
+        };
+        let len = len + MIN_MATCH;
+        if len > size - out.len() {
+            return None;
+        }
+        copy_match(&mut out, rep, len)?;
+    }
+
+    if dec.overran() {
+        return None;
+    }
+    Some(out)
+}
+
+/// Every adaptive probability in the stream
+struct Model {
+    is_match: [[u16; POS_STATES]; STATES],
+    is_rep: [u16; STATES],
+    /// 0x300 per context: a plain 8-bit tree, then one for each value of the
+    /// match byte's current bit
+    literals: Vec<u16>,
+    match_len: LenModel,
+    rep_len: LenModel,
+    /// The distance's bucket, in the context of (short) match lengths
+    slots: [[u16; 64]; 4],
+    /// The low bits of distances in the smaller buckets
+    special: [[u16; 32]; END_POS_MODEL as usize],
+    /// The low bits of distances in the bigger buckets
+    align: [u16; 1 << ALIGN_BITS],
+}
+
+impl Model {
+    fn new() -> Self {
+        Self {
+            is_match: [[PROB_INIT; POS_STATES]; STATES],
+            is_rep: [PROB_INIT; STATES],
+            literals: vec![PROB_INIT; 0x300 << LITERAL_CONTEXT_BITS],
+            match_len: LenModel::new(),
+            rep_len: LenModel::new(),
+            slots: [[PROB_INIT; 64]; 4],
+            special: [[PROB_INIT; 32]; END_POS_MODEL as usize],
+            align: [PROB_INIT; 1 << ALIGN_BITS],
+        }
+    }
+
+    fn literal_probs(&mut self, previous: u8) -> &mut [u16] {
+        let context = (previous >> (8 - LITERAL_CONTEXT_BITS)) as usize;
+        &mut self.literals[context * 0x300..][..0x300]
+    }
+
+    /// Distances below 4 are their own slot, others get two slots per power
+    /// of two and the rest in extra bits
+    fn encode_distance(&mut self, enc: &mut Encoder, distance: u32, len: usize) {
+        let slot = if distance < 4 {
+            distance
+        } else {
+            let log = 31 - distance.leading_zeros();
+            (log << 1) | ((distance >> (log - 1)) & 1)
+        };
+        enc.tree(&mut self.slots[len.min(3)], 6, slot);
+        if slot < 4 {
+            return;
+        }
+
+        let bits = (slot >> 1) - 1;
+        let extra = distance - ((2 | (slot & 1)) << bits);
+        if slot < END_POS_MODEL {
+            enc.reverse_tree(&mut self.special[slot as usize], bits, extra);
+        } else {
+            enc.direct(extra >> ALIGN_BITS, bits - ALIGN_BITS);
+            enc.reverse_tree(&mut self.align, ALIGN_BITS, extra & ((1 << ALIGN_BITS) - 1));
+        }
+    }
+
+    fn decode_distance(&mut self, dec: &mut Decoder<'_>, len: usize) -> Option<u32> {
+        let slot = dec.tree(&mut self.slots[len.min(3)], 6);
+        if slot < 4 {
+            return Some(slot);
+        }
+
+        let bits = (slot >> 1) - 1;
+        let base = (2 | (slot & 1)).checked_shl(bits)?;
+        let extra = if slot < END_POS_MODEL {
+            dec.reverse_tree(&mut self.special[slot as usize], bits)
+        } else {
+            let high = dec.direct(bits - ALIGN_BITS);
+            (high << ALIGN_BITS) | dec.reverse_tree(&mut self.align, ALIGN_BITS)
+        };
+        base.checked_add(extra)
+    }
+}
+
+/// Lengths 0-7 and 8-15 get a small tree per position state, the rest share
+/// a big one
+struct LenModel {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 8]; POS_STATES],
+    mid: [[u16; 8]; POS_STATES],
+    high: [u16; 256],
+}
+
+impl LenModel {
+    fn new() -> Self {
+        Self {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; 8]; POS_STATES],
+            mid: [[PROB_INIT; 8]; POS_STATES],
+            high: [PROB_INIT; 256],
+        }
+    }
+
+    fn encode(&mut self, enc: &mut Encoder, len: usize, pos_state: usize) {
+        let len = len as u32;
+        if len < 8 {
+            enc.bit(&mut self.choice, 0);
+            enc.tree(&mut self.low[pos_state], 3, len);
+        } else if len < 16 {
+            enc.bit(&mut self.choice, 1);
+            enc.bit(&mut self.choice2, 0);
+            enc.tree(&mut self.mid[pos_state], 3, len - 8);
+        } else {
+            enc.bit(&mut self.choice, 1);
+            enc.bit(&mut self.choice2, 1);
+            enc.tree(&mut self.high, 8, len - 16);
+        }
+    }
+
+    fn decode(&mut self, dec: &mut Decoder<'_>, pos_state: usize) -> usize {
+        let len = if dec.bit(&mut self.choice) == 0 {
+            dec.tree(&mut self.low[pos_state], 3)
+        } else if dec.bit(&mut self.choice2) == 0 {
+            8 + dec.tree(&mut self.mid[pos_state], 3)
+        } else {
+            16 + dec.tree(&mut self.high, 8)
+        };
+        len as usize
+    }
+}
+
+struct Encoder {
+    out: Vec<u8>,
+    /// The low end of the range, with a carry in bit 32
+    low: u64,
+    range: u32,
+    /// The last byte out, held back along with `pending - 1` 0xFF bytes in
+    /// case a carry comes through
+    cache: u8,
+    pending: u64,
+}
+
+impl Encoder {
+    fn new(out: Vec<u8>) -> Self {
+        Self {
+            out,
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            pending: 1,
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xFF00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            while self.pending > 0 {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xFF;
+                self.pending -= 1;
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.pending += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn bit(&mut self, prob: &mut u16, bit: u32) {
+        let bound = (self.range >> PROB_BITS) * *prob as u32;
+        if bit == 0 {
+            self.range = bound;
+            *prob += ((1 << PROB_BITS) - *prob) >> MOVE_BITS;
+        } else {
+            self.low += bound as u64;
+            self.range -= bound;
+            *prob -= *prob >> MOVE_BITS;
+        }
+        self.normalize();
+    }
+
+    /// Bits with a probability of one half, most significant first
+    fn direct(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.range >>= 1;
+            if (value >> i) & 1 == 1 {
+                self.low += self.range as u64;
+            }
+            self.normalize();
+        }
+    }
+
+    /// Most significant bit first, each in the context of the ones before
+    fn tree(&mut self, probs: &mut [u16], count: u32, value: u32) {
+        let mut node = 1;
+        for i in (0..count).rev() {
+            let bit = (value >> i) & 1;
+            self.bit(&mut probs[node], bit);
+            node = (node << 1) | bit as usize;
+        }
+    }
+
+    /// Least significant bit first, each in the context of the ones before
+    fn reverse_tree(&mut self, probs: &mut [u16], count: u32, value: u32) {
+        let mut node = 1;
+        for i in 0..count {
+            let bit = (value >> i) & 1;
+            self.bit(&mut probs[node], bit);
+            node = (node << 1) | bit as usize;
+        }
+    }
+
+    /// A literal after a match is likely to be the byte the match would have
+    /// continued with, so its bits are coded against that byte's until they
+    /// differ
+    fn matched_literal(&mut self, probs: &mut [u16], byte: u8, match_byte: u8) {
+        let mut node = 1;
+        let mut agreed = true;
+        for i in (0..8).rev() {
+            let bit = ((byte >> i) & 1) as usize;
+            if agreed {
+                let match_bit = ((match_byte >> i) & 1) as usize;
+                self.bit(&mut probs[((1 + match_bit) << 8) + node], bit as u32);
+                agreed = bit == match_bit;
+            } else {
+                self.bit(&mut probs[node], bit as u32);
+            }
+            node = (node << 1) | bit;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(input: &'a [u8]) -> Option<Self> {
+        // the encoder always starts with a zero byte
+        let Some((&0, rest)) = input.split_first() else {
+            return None;
+        };
+        let (code, _) = rest.split_first_chunk::<4>()?;
+        Some(Self {
+            input,
+            pos: 5,
+            range: u32::MAX,
+            code: u32::from_be_bytes(*code),
+        })
+    }
+
+    fn normalize(&mut self) {
+        if self.range < TOP {
+            self.range <<= 8;
+            // past the end reads zeros, see `overran`
+            let byte = self.input.get(self.pos).copied().unwrap_or_default();
+            self.code = (self.code << 8) | byte as u32;
+            self.pos += 1;
+        }
+    }
+
+    fn bit(&mut self, prob: &mut u16) -> u32 {
+        let bound = (self.range >> PROB_BITS) * *prob as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            *prob += ((1 << PROB_BITS) - *prob) >> MOVE_BITS;
+            0
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *prob -= *prob >> MOVE_BITS;
+            1
+        };
+        self.normalize();
+        bit
+    }
+
+    fn direct(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit = (self.code >= self.range) as u32;
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            value = (value << 1) | bit;
+            self.normalize();
+        }
+        value
+    }
+
+    fn tree(&mut self, probs: &mut [u16], count: u32) -> u32 {
+        let mut node = 1;
+        for _ in 0..count {
+            node = (node << 1) | self.bit(&mut probs[node as usize]);
+        }
+        node - (1 << count)
+    }
+
+    fn reverse_tree(&mut self, probs: &mut [u16], count: u32) -> u32 {
+        let mut node = 1;
+        let mut value = 0;
+        for i in 0..count {
+            let bit = self.bit(&mut probs[node as usize]);
+            node = (node << 1) | bit;
+            value |= bit << i;
+        }
+        value
+    }
+
+    fn matched_literal(&mut self, probs: &mut [u16], match_byte: u8) -> u8 {
+        let mut node = 1;
+        let mut agreed = true;
+        for i in (0..8).rev() {
+            let bit = if agreed {
+                let match_bit = ((match_byte >> i) & 1) as usize;
+                let bit = self.bit(&mut probs[((1 + match_bit) << 8) + node]);
+                agreed = bit as usize == match_bit;
+                bit
+            } else {
+                self.bit(&mut probs[node])
+            };
+            node = (node << 1) | bit as usize;
+        }
+        node as u8
+    }
+
+    /// Whether more bytes were read than the input had
+    fn overran(&self) -> bool {
+        self.pos > self.input.len()
+    }
+}
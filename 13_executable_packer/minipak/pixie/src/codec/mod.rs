@@ -0,0 +1,100 @@
+use deku::prelude::*;
+use derive_more::Display;
+use encore::prelude::*;
+
+use crate::PixieError;
+
+mod bits;
+mod lz;
+mod lzma_like;
+mod zstd_like;
+
+/// How the guest is compressed. Every codec records the decompressed size in
+/// its own output.
+#[derive(Debug, Display, Copy, Clone, PartialEq, DekuRead, DekuWrite)]
+#[deku(id_type = "u8")]
+pub enum Codec {
+    /// Stored as-is
+    #[display("none")]
+    #[deku(id = "0")]
+    None,
+    /// LZ77 with byte-aligned tokens: fast to decompress, biggest output
+    #[display("lz4")]
+    #[deku(id = "1")]
+    Lz4,
+    /// LZ77 with Huffman-coded literals, lengths and offsets
+    #[display("zstd-like")]
+    #[deku(id = "2")]
+    ZstdLike,
+    /// LZ77 with an adaptive binary range coder: slowest, smallest output
+    #[display("lzma-like")]
+    #[deku(id = "3")]
+    LzmaLike,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 4] = [Codec::Lz4, Codec::ZstdLike, Codec::LzmaLike, Codec::None];
+
+    /// Finds a codec by the name it displays as
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|codec| codec.to_string() == name)
+    }
+
+    /// Fails if the input doesn't fit the 32-bit size header, which every
+    /// codec but `None` writes
+    pub fn compress(self, input: &[u8]) -> Result<Vec<u8>, PixieError> {
+        if self != Codec::None && u32::try_from(input.len()).is_err() {
+            return Err(PixieError::TooLarge(self, input.len()));
+        }
+        Ok(match self {
+            Codec::None => input.to_vec(),
+            Codec::Lz4 => lz4_flex::compress_prepend_size(input),
+            Codec::ZstdLike => zstd_like::compress(input),
+            Codec::LzmaLike => lzma_like::compress(input),
+        })
+    }
+
+    pub fn decompress(self, input: &[u8]) -> Result<Vec<u8>, PixieError> {
+        match self {
+            Codec::None => Ok(input.to_vec()),
+            Codec::Lz4 => {
+                lz4_flex::decompress_size_prepended(input).map_err(|_| PixieError::Corrupt(self))
+            }
+            Codec::ZstdLike => zstd_like::decompress(input).ok_or(PixieError::Corrupt(self)),
+            Codec::LzmaLike => lzma_like::decompress(input).ok_or(PixieError::Corrupt(self)),
+        }
+    }
+}
+
+/// Splits a size header off a compressed stream
+fn read_size(input: &[u8]) -> Option<(usize, &[u8])> {
+    let (size, rest) = input.split_first_chunk::<4>()?;
+    Some((u32::from_le_bytes(*size) as usize, rest))
+}
+
+/// Starts a compressed stream with a size header. `Codec::compress` has
+/// checked that the size fits.
+fn write_size(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    out.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    out
+}
+
+/// Copies `len` bytes from `offset` bytes back at the end of `out`. The
+/// ranges may overlap, which repeats the last `offset` bytes.
+fn copy_match(out: &mut Vec<u8>, offset: usize, len: usize) -> Option<()> {
+    if offset == 0 || offset > out.len() {
+        return None;
+    }
+    let start = out.len() - offset;
+    if offset >= len {
+        out.extend_from_within(start..start + len);
+    } else {
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+    Some(())
+}
@@ -0,0 +1,386 @@
+//! LZ77 sequences, entropy-coded with canonical Huffman codes. Like zstd,
+//! codes are at most 11 bits long and lengths and offsets are coded as a
+//! bucket (Huffman-coded) plus raw extra bits, but every table is stored
+//! as plain code lengths and there is a single repeat offset.
+//!
+//! The stream is the decompressed size (u32 LE) followed by blocks, each
+//! covering about `BLOCK_SIZE` bytes of input:
+//!
+//!   - the number of sequences (32 bits)
+//!   - code lengths (4 bits each) for literals, literal lengths, match
+//!     lengths, then offsets
+//!   - for each sequence: its literal length, its literals, its match length
+//!     and, if there is a match, its offset
+
+use alloc::vec;
+use encore::prelude::*;
+
+use super::{
+    bits::{BitReader, BitWriter},
+    copy_match,
+    lz::{self, MIN_MATCH, Params, Sequence},
+    read_size, write_size,
+};
+
+const PARAMS: Params = Params {
+    window_log: 21,
+    depth: 16,
+    max_len: 1 << 16,
+    lazy: true,
+};
+
+const BLOCK_SIZE: usize = 128 * 1024;
+
+const MAX_BITS: u32 = 11;
+const LITERALS: usize = 256;
+/// Enough buckets for any `u32`, see `length_code`
+const LENGTHS: usize = 72;
+/// The repeat offset, then one bucket per power of two
+const OFFSETS: usize = 33;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let sequences = lz::parse(input, &PARAMS);
+    let mut writer = BitWriter::new(write_size(input));
+    let mut state = State::default();
+
+    let mut rest = &sequences[..];
+    while !rest.is_empty() {
+        let mut covered = 0;
+        let count = rest
+            .iter()
+            .take_while(|seq| {
+                let more = covered < BLOCK_SIZE;
+                covered += (seq.literals + seq.len) as usize;
+                more
+            })
+            .count();
+        let (block, next) = rest.split_at(count);
+        state.encode_block(&mut writer, input, block);
+        rest = next;
+    }
+    writer.finish()
+}
+
+pub fn decompress(input: &[u8]) -> Option<Vec<u8>> {
+    let (size, input) = read_size(input)?;
+    let mut out = Vec::with_capacity(size);
+    let mut reader = BitReader::new(input);
+    let mut rep = 0;
+
+    while out.len() < size {
+        let count = reader.get(32);
+        if count == 0 {
+            return None;
+        }
+        let literals = Decoder::read(&mut reader, LITERALS)?;
+        let literal_lengths = Decoder::read(&mut reader, LENGTHS)?;
+        let match_lengths = Decoder::read(&mut reader, LENGTHS)?;
+        let offsets = Decoder::read(&mut reader, OFFSETS)?;
+
+        for _ in 0..count {
+            let literal_len = read_length(&mut reader, &literal_lengths)?;
+            if literal_len > size - out.len() {
+                return None;
+            }
+            for _ in 0..literal_len {
+                out.push(literals.decode(&mut reader)? as u8);
+            }
+
+            let match_len = read_length(&mut reader, &match_lengths)?;
+            if match_len == 0 {
+                continue;
+            }
+            let len = match_len + MIN_MATCH - 1;
+            let offset = match offsets.decode(&mut reader)? {
+                0 => rep,
+                code => {
+                    let bits = code - 1;
+                    (1 << bits) + reader.get(bits) as usize
+                }
+            };
+            if len > size - out.len() {
+                return None;
+            }
+            copy_match(&mut out, offset, len)?;
+            rep = offset;
+        }
+
+        if reader.overran() {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+#[derive(Default)]
+struct State {
+    /// Where the next block starts in the input
+    pos: usize,
+    /// The offset of the last match
+    rep: u32,
+}
+
+impl State {
+    fn encode_block(&mut self, writer: &mut BitWriter, input: &[u8], block: &[Sequence]) {
+        let mut literal_freqs = [0; LITERALS];
+        let mut literal_len_freqs = [0; LENGTHS];
+        let mut match_len_freqs = [0; LENGTHS];
+        let mut offset_freqs = [0; OFFSETS];
+
+        let mut pos = self.pos;
+        let mut rep = self.rep;
+        for seq in block {
+            for &b in &input[pos..][..seq.literals as usize] {
+                literal_freqs[b as usize] += 1;
+            }
+            literal_len_freqs[length_code(seq.literals).0 as usize] += 1;
+            match_len_freqs[length_code(match_value(seq)).0 as usize] += 1;
+            if seq.len > 0 {
+                offset_freqs[offset_code(seq.offset, rep).0 as usize] += 1;
+                rep = seq.offset;
+            }
+            pos += (seq.literals + seq.len) as usize;
+        }
+
+        let literals = Encoder::new(&literal_freqs);
+        let literal_lengths = Encoder::new(&literal_len_freqs);
+        let match_lengths = Encoder::new(&match_len_freqs);
+        let offsets = Encoder::new(&offset_freqs);
+        writer.put(block.len() as u32, 32);
+        for encoder in [&literals, &literal_lengths, &match_lengths, &offsets] {
+            encoder.write_table(writer);
+        }
+
+        for seq in block {
+            write_length(writer, &literal_lengths, seq.literals);
+            for &b in &input[self.pos..][..seq.literals as usize] {
+                literals.put(writer, b as u32);
+            }
+            write_length(writer, &match_lengths, match_value(seq));
+            if seq.len > 0 {
+                let (code, extra, bits) = offset_code(seq.offset, self.rep);
+                offsets.put(writer, code);
+                writer.put(extra, bits);
+                self.rep = seq.offset;
+            }
+            self.pos += (seq.literals + seq.len) as usize;
+        }
+    }
+}
+
+/// Match lengths are coded so that zero means "no match"
+fn match_value(seq: &Sequence) -> u32 {
+    if seq.len == 0 {
+        0
+    } else {
+        seq.len - MIN_MATCH as u32 + 1
+    }
+}
+
+/// Returns `(code, extra, extra_bits)`. Values below 16 are their own code,
+/// others get two codes per power of two.
+fn length_code(value: u32) -> (u32, u32, u32) {
+    if value < 16 {
+        return (value, 0, 0);
+    }
+    let log = 31 - value.leading_zeros();
+    let half = (value >> (log - 1)) & 1;
+    let bits = log - 1;
+    (16 + 2 * (log - 4) + half, value & ((1 << bits) - 1), bits)
+}
+
+/// Returns `(code, extra, extra_bits)`: code 0 repeats the last offset
+fn offset_code(offset: u32, rep: u32) -> (u32, u32, u32) {
+    if offset == rep {
+        return (0, 0, 0);
+    }
+    let log = 31 - offset.leading_zeros();
+    (1 + log, offset - (1 << log), log)
+}
+
+fn write_length(writer: &mut BitWriter, encoder: &Encoder, value: u32) {
+    let (code, extra, bits) = length_code(value);
+    encoder.put(writer, code);
+    writer.put(extra, bits);
+}
+
+fn read_length(reader: &mut BitReader<'_>, decoder: &Decoder) -> Option<usize> {
+    let code = decoder.decode(reader)?;
+    if code < 16 {
+        return Some(code as usize);
+    }
+    let log = (code - 16) / 2 + 4;
+    let half = (code - 16) & 1;
+    let bits = log - 1;
+    let base = (1_u64 << log) | ((half as u64) << bits);
+    usize::try_from(base + reader.get(bits) as u64).ok()
+}
+
+struct Encoder {
+    lengths: Vec<u8>,
+    /// Codes, bit-reversed since the stream is read least significant bit first
+    codes: Vec<u16>,
+}
+
+impl Encoder {
+    fn new(freqs: &[u32]) -> Self {
+        let lengths = limited_lengths(freqs);
+        let codes = canonical_codes(&lengths);
+        Self { lengths, codes }
+    }
+
+    fn write_table(&self, writer: &mut BitWriter) {
+        for &len in &self.lengths {
+            writer.put(len as u32, 4);
+        }
+    }
+
+    fn put(&self, writer: &mut BitWriter, symbol: u32) {
+        let symbol = symbol as usize;
+        writer.put(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+    }
+}
+
+struct Decoder {
+    /// Indexed by the next `MAX_BITS` bits: the symbol in the high bits, the
+    /// code length in the low 4 (zero for codes that don't exist)
+    table: Vec<u16>,
+}
+
+impl Decoder {
+    fn read(reader: &mut BitReader<'_>, alphabet: usize) -> Option<Self> {
+        let lengths: Vec<u8> = (0..alphabet).map(|_| reader.get(4) as u8).collect();
+        let mut space = 0;
+        for &len in &lengths {
+            if len as u32 > MAX_BITS {
+                return None;
+            }
+            if len > 0 {
+                space += 1 << (MAX_BITS - len as u32);
+            }
+        }
+        // more codes than fit would overwrite each other
+        if space > 1 << MAX_BITS {
+            return None;
+        }
+
+        let codes = canonical_codes(&lengths);
+        let mut table = vec![0; 1 << MAX_BITS];
+        for (symbol, (&len, &code)) in lengths.iter().zip(&codes).enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let entry = ((symbol as u16) << 4) | len as u16;
+            for slot in table.iter_mut().skip(code as usize).step_by(1 << len) {
+                *slot = entry;
+            }
+        }
+        Some(Self { table })
+    }
+
+    fn decode(&self, reader: &mut BitReader<'_>) -> Option<u32> {
+        let entry = self.table[reader.peek(MAX_BITS) as usize];
+        let len = (entry & 0xF) as u32;
+        if len == 0 {
+            return None;
+        }
+        reader.consume(len);
+        Some((entry >> 4) as u32)
+    }
+}
+
+/// Huffman code lengths of at most `MAX_BITS`: rare symbols are made less
+/// rare until the tree is shallow enough
+fn limited_lengths(freqs: &[u32]) -> Vec<u8> {
+    let mut weights = freqs.to_vec();
+    loop {
+        let lengths = huffman_lengths(&weights);
+        if lengths.iter().all(|&len| len as u32 <= MAX_BITS) {
+            return lengths;
+        }
+        for weight in weights.iter_mut().filter(|weight| **weight > 0) {
+            *weight = (*weight >> 1).max(1);
+        }
+    }
+}
+
+/// Code lengths of an optimal prefix code, zero for unused symbols
+fn huffman_lengths(weights: &[u32]) -> Vec<u8> {
+    let mut lengths = vec![0; weights.len()];
+    let mut leaves: Vec<(u64, usize)> = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .map(|(symbol, &weight)| (weight as u64, symbol))
+        .collect();
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            lengths[leaves[0].1] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+    leaves.sort_unstable();
+
+    // leaves first, then internal nodes in the order they're made, which is
+    // also by increasing weight: the two lightest are at the front of either
+    let n = leaves.len();
+    let mut weight: Vec<u64> = leaves.iter().map(|leaf| leaf.0).collect();
+    let mut parent = vec![0; 2 * n - 1];
+    let (mut next_leaf, mut next_node) = (0, n);
+    for node in n..2 * n - 1 {
+        let mut sum = 0;
+        for _ in 0..2 {
+            let child =
+                if next_leaf < n && (next_node == node || weight[next_leaf] <= weight[next_node]) {
+                    next_leaf += 1;
+                    next_leaf - 1
+                } else {
+                    next_node += 1;
+                    next_node - 1
+                };
+            parent[child] = node;
+            sum += weight[child];
+        }
+        weight.push(sum);
+    }
+
+    // parents come after their children, so walk down from the root
+    let mut depth = vec![0_u32; 2 * n - 1];
+    for node in (0..2 * n - 2).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+    for (leaf, &(_, symbol)) in leaves.iter().enumerate() {
+        lengths[symbol] = depth[leaf].min(u8::MAX as u32) as u8;
+    }
+    lengths
+}
+
+/// Canonical codes for the given lengths, bit-reversed
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0_u16; MAX_BITS as usize + 1];
+    for &len in lengths {
+        counts[len as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next = [0_u16; MAX_BITS as usize + 1];
+    let mut code = 0;
+    for bits in 1..=MAX_BITS as usize {
+        code = (code + counts[bits - 1]) << 1;
+        next[bits] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            code.reverse_bits() >> (16 - len)
+        })
+        .collect()
+}
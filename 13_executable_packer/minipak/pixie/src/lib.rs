@@ -10,6 +10,9 @@ use deku::prelude::*;
 use derive_more::Display;
 use encore::prelude::*;
 
+mod codec;
+pub use codec::*;
+
 mod manifest;
 pub use manifest::*;
 
@@ -40,6 +43,18 @@ pub enum PixieError {
     DynamicEntryNotFound(DynamicTagType),
     #[display("unsupported relocation type`{_0:?}`")]
     UnsupportedRela(Rela),
+    #[display("corrupt {_0} stream")]
+    Corrupt(Codec),
+    #[display("{_1} bytes is too large for {_0}, which handles up to 4 GiB")]
+    TooLarge(Codec, usize),
+    #[display("decompressed guest is {_0} bytes, the manifest says {_1}")]
+    GuestSize(usize, usize),
+    #[display("decompressed guest doesn't match the manifest's hash")]
+    GuestHash,
+    #[display("no valid manifest found, is this a packed executable?")]
+    ManifestNotFound,
+    #[display("resource of {_1} bytes at offset {_0} runs past the end of the input")]
+    ResourceOutOfBounds(usize, usize),
 }
 
 impl From<DekuError> for PixieError {
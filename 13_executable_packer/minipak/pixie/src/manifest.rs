@@ -1,7 +1,8 @@
 use core::ops::Range;
 use deku::prelude::*;
+use encore::prelude::*;
 
-use crate::PixieError;
+use crate::{Codec, PixieError};
 
 #[derive(Debug, DekuRead, DekuWrite)]
 #[deku(magic = b"pixoendm")]
@@ -19,23 +20,87 @@ pub struct Resource {
 }
 
 impl Resource {
-    pub fn as_range(&self) -> Range<usize> {
-        self.offset..self.offset + self.len
+    /// Fails if the resource would end past `usize::MAX`
+    pub fn as_range(&self) -> Result<Range<usize>, PixieError> {
+        let end = self
+            .offset
+            .checked_add(self.len)
+            .ok_or_else(|| self.out_of_bounds())?;
+        Ok(self.offset..end)
+    }
+
+    /// The resource's bytes in `slice`, the packed executable
+    pub fn slice<'a>(&self, slice: &'a [u8]) -> Result<&'a [u8], PixieError> {
+        slice
+            .get(self.as_range()?)
+            .ok_or_else(|| self.out_of_bounds())
+    }
+
+    fn out_of_bounds(&self) -> PixieError {
+        PixieError::ResourceOutOfBounds(self.offset, self.len)
     }
 }
 
+/// 64-bit FNV-1a: enough to notice a damaged guest, small enough for stage2
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
 #[derive(Debug, DekuRead, DekuWrite)]
 #[deku(magic = b"piximani")]
 pub struct Manifest {
     pub stage2: Resource,
     pub guest: Resource,
+    /// How `guest` is compressed
+    pub codec: Codec,
+    /// The guest's size once decompressed
+    #[deku(bytes = 8)]
+    pub guest_size: usize,
+    /// The `fnv1a` hash of the decompressed guest
+    pub guest_hash: u64,
+}
+
+impl EndMarker {
+    pub const SIZE: usize = 16;
+
+    /// Reads the end marker from the last bytes of a packed executable
+    pub fn read_from_full_slice(slice: &[u8]) -> Result<Self, PixieError> {
+        let start = slice
+            .len()
+            .checked_sub(Self::SIZE)
+            .ok_or(PixieError::ManifestNotFound)?;
+        let (_, endmarker) = EndMarker::from_bytes((&slice[start..], 0))
+            .map_err(|_| PixieError::ManifestNotFound)?;
+        Ok(endmarker)
+    }
 }
 
 impl Manifest {
     pub fn read_from_full_slice(slice: &[u8]) -> Result<Self, PixieError> {
-        let (_, endmarker) = EndMarker::from_bytes((&slice[slice.len() - 16..], 0)).unwrap();
-        let (_, manifdest) =
-            Manifest::from_bytes((&slice[endmarker.manifest_offset..], 0)).unwrap();
-        Ok(manifdest)
+        let endmarker = EndMarker::read_from_full_slice(slice)?;
+        let rest = slice
+            .get(endmarker.manifest_offset..)
+            .ok_or(PixieError::ManifestNotFound)?;
+        let (_, manifest) =
+            Manifest::from_bytes((rest, 0)).map_err(|_| PixieError::ManifestNotFound)?;
+        for resource in [&manifest.stage2, &manifest.guest] {
+            resource.slice(slice)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Decompresses the guest out of `slice`, the packed executable the
+    /// manifest was read from, and checks its size and hash
+    pub fn decompress_guest(&self, slice: &[u8]) -> Result<Vec<u8>, PixieError> {
+        let guest = self.codec.decompress(self.guest.slice(slice)?)?;
+        if guest.len() != self.guest_size {
+            return Err(PixieError::GuestSize(guest.len(), self.guest_size));
+        }
+        if fnv1a(&guest) != self.guest_hash {
+            return Err(PixieError::GuestHash);
+        }
+        Ok(guest)
     }
 }
@@ -38,7 +38,7 @@ unsafe fn main(stack_top: *mut u8) {
     let slice = map.as_ref();
     let manifest = Manifest::read_from_full_slice(slice).unwrap();
 
-    let s2_slice = &slice[manifest.stage2.as_range()];
+    let s2_slice = manifest.stage2.slice(slice).unwrap();
     let s2_obj = Object::new(s2_slice).unwrap();
     let mut s2_mapped = MappedObject::new(&s2_obj, None).unwrap();
     info!(
@@ -34,8 +34,8 @@ unsafe fn main(stack_top: *mut u8) {
     let slice = map.as_ref();
     let manifest = Manifest::read_from_full_slice(slice).unwrap();
 
-    let compressed_guest = &slice[manifest.guest.as_range()];
-    let guest = lz4_flex::decompress_size_prepended(compressed_guest).unwrap();
+    info!("Decompressing guest ({})...", manifest.codec);
+    let guest = manifest.decompress_guest(slice).unwrap();
     let guest_obj = Object::new(&guest).unwrap();
     let guest_hull = guest_obj.segments().load_convex_hull().unwrap();
 
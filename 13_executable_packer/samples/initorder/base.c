@@ -0,0 +1,4 @@
+// needs nothing, but is loaded before mid, which needs it
+#include "say.h"
+INIT("base")
+int base(void) { return 1; }
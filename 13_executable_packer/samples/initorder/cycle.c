@@ -0,0 +1,3 @@
+// built twice, as libping.so needing libpong.so and the other way around
+#include "say.h"
+INIT(NAME)
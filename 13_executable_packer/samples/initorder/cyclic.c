@@ -0,0 +1,4 @@
+// main needs ping, which needs pong, which needs ping
+#include "say.h"
+INIT("main")
+int main(void) { return 0; }
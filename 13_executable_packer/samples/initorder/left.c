@@ -0,0 +1,5 @@
+// needs mid
+#include "say.h"
+INIT("left")
+int mid(void);
+int left(void) { return mid(); }
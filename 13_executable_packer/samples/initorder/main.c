@@ -0,0 +1,11 @@
+// main needs left and base. Breadth-first, that loads left, base, mid:
+// initializing them backwards would run mid before base.
+#include "say.h"
+
+static void preinit(void) { say("preinit main"); }
+__attribute__((section(".preinit_array"), used)) static void (*preinits[])(void) = {preinit};
+
+INIT("main")
+int left(void);
+int base(void);
+int main(void) { return left() + base() == 3 ? 0 : 1; }
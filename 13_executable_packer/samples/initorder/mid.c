@@ -0,0 +1,5 @@
+// needs base
+#include "say.h"
+INIT("mid")
+int base(void);
+int mid(void) { return base() + 1; }